@@ -29,7 +29,7 @@ use std::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 use analyze::{analyze, monitor, Metrics};
@@ -42,8 +42,12 @@ use foyer_storage::{
         rated_random::RatedRandomAdmissionPolicy, rated_ticket::RatedTicketAdmissionPolicy,
         AdmissionPolicy,
     },
+    checksum::{ChecksumAlgorithm, VerifyMode},
+    compress::Compression,
     device::fs::FsDeviceConfig,
+    encrypt::StaticKeyProvider,
     error::Result,
+    generic::RecoverMode,
     reinsertion::{
         rated_random::RatedRandomReinsertionPolicy, rated_ticket::RatedTicketReinsertionPolicy,
         ReinsertionPolicy,
@@ -112,6 +116,22 @@ pub struct Args {
     #[arg(long, default_value_t = 1024)]
     buffer_pool_size: usize,
 
+    /// (MiB) stage small entries in a ring buffer of the capacity, `0` disables it
+    #[arg(long, default_value_t = 0)]
+    ring_buffer_capacity: usize,
+
+    /// (KiB) entries no larger than it are staged in the ring buffer
+    #[arg(long, default_value_t = 1)]
+    ring_buffer_threshold: usize,
+
+    /// regions reserved for the small-object engine, `0` disables it
+    #[arg(long, default_value_t = 0)]
+    small_object_regions: usize,
+
+    /// (B) entries no larger than it are stored in the small-object engine
+    #[arg(long, default_value_t = 1024)]
+    small_object_threshold: usize,
+
     #[arg(long, default_value_t = 4)]
     flushers: usize,
 
@@ -124,6 +144,14 @@ pub struct Args {
     #[arg(long, default_value_t = 16 * 1024)]
     io_size: usize,
 
+    /// remove existing data under `dir` if it is written with another device geometry
+    #[arg(long, default_value_t = false)]
+    reformat: bool,
+
+    /// preallocate region files on open
+    #[arg(long, default_value_t = false)]
+    preallocate: bool,
+
     #[arg(long, default_value_t = 16)]
     writers: usize,
 
@@ -133,6 +161,30 @@ pub struct Args {
     #[arg(long, default_value_t = 16)]
     recover_concurrency: usize,
 
+    /// skip corrupted entries instead of stopping at them when recovering a region
+    #[arg(long, default_value_t = false)]
+    tolerant_recovery: bool,
+
+    /// catalog checkpoint interval (s), periodic checkpoint is disabled if `checkpoint_interval` = 0
+    #[arg(long, default_value_t = 0)]
+    checkpoint_interval: u64,
+
+    /// entry checksum algorithm: none, xxhash64, xxh3 or crc32c
+    #[arg(long, default_value = "xxhash64")]
+    checksum: String,
+
+    /// when entry checksums are verified: read, recovery, or a sampled ratio of reads in (0, 1]
+    #[arg(long, default_value = "read")]
+    verify: String,
+
+    /// value compression algorithm: none, lz4 or zstd, lz4 and zstd require the features of the same names
+    #[arg(long, default_value = "none")]
+    compression: String,
+
+    /// encrypt entries with a random key
+    #[arg(long, default_value_t = false)]
+    encrypt: bool,
+
     /// enable rated random admission policy if `random_insert_rate_limit` > 0
     /// (MiB/s)
     #[arg(long, default_value_t = 0)]
@@ -161,6 +213,10 @@ pub struct Args {
     #[arg(long, default_value_t = 0)]
     reclaim_rate_limit: usize,
 
+    /// discard the blocks of reclaimed regions on the device
+    #[arg(long, default_value_t = false)]
+    reclaim_discard: bool,
+
     /// (ms)
     #[arg(long, default_value_t = 10)]
     allocation_timeout: usize,
@@ -282,6 +338,13 @@ where
         }
     }
 
+    fn set_expire_at(&mut self, deadline: SystemTime) {
+        match self {
+            BenchStoreWriter::StoreWriter { writer } => writer.set_expire_at(deadline),
+            BenchStoreWriter::RuntimeStoreWriter { writer } => writer.set_expire_at(deadline),
+        }
+    }
+
     async fn finish(self, value: Self::Value) -> Result<bool> {
         match self {
             BenchStoreWriter::StoreWriter { writer } => writer.finish(value).await,
@@ -359,6 +422,13 @@ where
         }
     }
 
+    async fn insert_many(&self, entries: Vec<(Self::Key, Self::Value)>) -> Result<Vec<bool>> {
+        match self {
+            BenchStore::Store { store } => store.insert_many(entries).await,
+            BenchStore::RuntimeStore { store } => store.insert_many(entries).await,
+        }
+    }
+
     fn exists(&self, key: &Self::Key) -> Result<bool> {
         match self {
             BenchStore::Store { store } => store.exists(key),
@@ -373,17 +443,24 @@ where
         }
     }
 
-    fn remove(&self, key: &Self::Key) -> Result<bool> {
+    async fn lookup_many(&self, keys: &[Self::Key]) -> Result<Vec<Option<Self::Value>>> {
+        match self {
+            BenchStore::Store { store } => store.lookup_many(keys).await,
+            BenchStore::RuntimeStore { store } => store.lookup_many(keys).await,
+        }
+    }
+
+    async fn remove(&self, key: &Self::Key) -> Result<bool> {
         match self {
-            BenchStore::Store { store } => store.remove(key),
-            BenchStore::RuntimeStore { store } => store.remove(key),
+            BenchStore::Store { store } => store.remove(key).await,
+            BenchStore::RuntimeStore { store } => store.remove(key).await,
         }
     }
 
-    fn clear(&self) -> Result<()> {
+    async fn clear(&self) -> Result<()> {
         match self {
-            BenchStore::Store { store } => store.clear(),
-            BenchStore::RuntimeStore { store } => store.clear(),
+            BenchStore::Store { store } => store.clear().await,
+            BenchStore::RuntimeStore { store } => store.clear().await,
         }
     }
 }
@@ -515,6 +592,8 @@ async fn main() {
         file_capacity: args.region_size * 1024 * 1024,
         align: args.align,
         io_size: args.io_size,
+        reformat: args.reformat,
+        preallocate: args.preallocate,
     };
 
     let mut admissions: Vec<Arc<dyn AdmissionPolicy<Key = u64, Value = Vec<u8>>>> = vec![];
@@ -557,11 +636,48 @@ async fn main() {
         admissions,
         reinsertions,
         buffer_pool_size: args.buffer_pool_size * 1024 * 1024,
+        ring_buffer_capacity: args.ring_buffer_capacity * 1024 * 1024,
+        ring_buffer_threshold: args.ring_buffer_threshold * 1024,
+        small_object_regions: args.small_object_regions,
+        small_object_threshold: args.small_object_threshold,
         flushers: args.flushers,
         flush_rate_limit: args.flush_rate_limit * 1024 * 1024,
         reclaimers: args.reclaimers,
         reclaim_rate_limit: args.reclaim_rate_limit * 1024 * 1024,
+        reclaim_discard: args.reclaim_discard,
         recover_concurrency: args.recover_concurrency,
+        recover_mode: if args.tolerant_recovery {
+            RecoverMode::Tolerant
+        } else {
+            RecoverMode::Strict
+        },
+        checkpoint_interval: match args.checkpoint_interval {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        },
+        checksum_algorithm: match args.checksum.as_str() {
+            "none" => ChecksumAlgorithm::None,
+            "xxhash64" => ChecksumAlgorithm::XxHash64,
+            "xxh3" => ChecksumAlgorithm::Xxh3,
+            "crc32c" => ChecksumAlgorithm::Crc32c,
+            other => panic!("unknown checksum algorithm: {other}"),
+        },
+        verify_mode: match args.verify.as_str() {
+            "read" => VerifyMode::Read,
+            "recovery" => VerifyMode::Recovery,
+            ratio => VerifyMode::Sampled(ratio.parse().expect("invalid verify mode")),
+        },
+        compression: match args.compression.as_str() {
+            "none" => Compression::None,
+            #[cfg(feature = "lz4")]
+            "lz4" => Compression::Lz4,
+            #[cfg(feature = "zstd")]
+            "zstd" => Compression::Zstd,
+            other => panic!("unknown or disabled compression algorithm: {other}"),
+        },
+        encryption: args
+            .encrypt
+            .then(|| Arc::new(StaticKeyProvider::new(0, OsRng.gen())) as _),
         allocation_timeout: Duration::from_millis(args.allocation_timeout as u64),
         clean_region_threshold,
     };
@@ -0,0 +1,676 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Set-associative engine of small entries.
+//!
+//! The first regions of the device are split into `align` sized buckets. A key is hashed to a bucket, and entries
+//! are packed in the bucket without alignment. When a bucket is full, its oldest entries are replaced.
+//!
+//! Only the key hashes of entries are kept in memory.
+
+use std::{
+    collections::VecDeque,
+    hash::Hasher,
+    marker::PhantomData,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use bytes::{Buf, BufMut};
+use foyer_common::code::{Key, Value};
+use futures::{StreamExt, TryStreamExt};
+use itertools::Itertools;
+use parking_lot::Mutex;
+use tokio::sync::RwLock as AsyncRwLock;
+use twox_hash::XxHash64;
+
+use crate::{
+    catalog::{self, Catalog, ExpireAt, Sequence},
+    checksum::ChecksumAlgorithm,
+    device::Device,
+    encrypt::Encryption,
+    entry::{
+        read_entry, read_entry_raw, write_entry, EntryFlags, EntryHeader, EntryKey, EntryValue,
+        RawValue,
+    },
+    error::Result,
+    metrics::Metrics,
+    region::RegionId,
+};
+
+const BUCKET_MAGIC: u32 = 0x6275_636b;
+
+/// | magic | len | epoch | checksum | checksum algorithm | <reserved> |
+///
+/// `len` is the bytes of entries packed after the header.
+#[derive(Debug)]
+struct BucketHeader {
+    len: u32,
+    epoch: u64,
+    checksum: u64,
+    checksum_algorithm: ChecksumAlgorithm,
+}
+
+impl BucketHeader {
+    const fn serialized_len() -> usize {
+        4 + 4 + 8 + 8 + 1 + 3
+    }
+
+    fn write(&self, mut buf: &mut [u8]) {
+        buf.put_u32(BUCKET_MAGIC);
+        buf.put_u32(self.len);
+        buf.put_u64(self.epoch);
+        buf.put_u64(self.checksum);
+        buf.put_u8(self.checksum_algorithm.to_u8());
+        // reserved
+        buf.put_bytes(0, 3);
+    }
+
+    fn read(mut buf: &[u8]) -> Option<Self> {
+        if buf.get_u32() != BUCKET_MAGIC {
+            return None;
+        }
+        let len = buf.get_u32();
+        let epoch = buf.get_u64();
+        let checksum = buf.get_u64();
+        let checksum_algorithm = ChecksumAlgorithm::from_u8(buf.get_u8())?;
+        Some(Self {
+            len,
+            epoch,
+            checksum,
+            checksum_algorithm,
+        })
+    }
+}
+
+/// Entry packed in a bucket.
+#[derive(Debug)]
+struct BucketEntry<K>
+where
+    K: Key,
+{
+    key: K,
+    sequence: Sequence,
+    expire_at: ExpireAt,
+    /// Range of the serialized entry in the bucket.
+    range: Range<usize>,
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    /// Serializes modifications of the bucket, lookups only read it.
+    lock: AsyncRwLock<()>,
+    /// Key hashes of entries in the bucket.
+    hashes: Mutex<Vec<u64>>,
+}
+
+#[derive(Debug)]
+pub struct BucketStore<K, V, D>
+where
+    K: Key,
+    V: Value,
+    D: Device,
+{
+    device: D,
+
+    /// Regions `0..regions` are split into buckets.
+    regions: usize,
+
+    buckets: Vec<Bucket>,
+
+    /// Entries with a serialized size no larger than the threshold are stored in buckets.
+    threshold: usize,
+
+    checksum_algorithm: ChecksumAlgorithm,
+
+    encryption: Option<Encryption>,
+
+    epoch: AtomicU64,
+
+    metrics: Arc<Metrics>,
+
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V, D> BucketStore<K, V, D>
+where
+    K: Key,
+    V: Value,
+    D: Device,
+{
+    /// Split the first `regions` regions of the device into buckets.
+    pub fn new(
+        device: D,
+        regions: usize,
+        threshold: usize,
+        checksum_algorithm: ChecksumAlgorithm,
+        encryption: Option<Encryption>,
+        epoch: u64,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let buckets = (0..regions * device.region_size() / device.align())
+            .map(|_| Bucket::default())
+            .collect_vec();
+        Self {
+            device,
+            regions,
+            buckets,
+            threshold,
+            checksum_algorithm,
+            encryption,
+            epoch: AtomicU64::new(epoch),
+            metrics,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the max entry size that fits a bucket.
+    pub fn max_entry_size(align: usize) -> usize {
+        align - BucketHeader::serialized_len()
+    }
+
+    pub fn regions(&self) -> usize {
+        self.regions
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Entries of buckets written in other epochs are ignored.
+    ///
+    /// Key hashes in memory are cleared.
+    pub fn set_epoch(&self, epoch: u64) {
+        self.epoch.store(epoch, Ordering::Release);
+        for bucket in self.buckets.iter() {
+            bucket.hashes.lock().clear();
+        }
+    }
+
+    /// Returns `true` if the key may be in the bucket, false positives are possible on hash collisions.
+    pub fn exists(&self, key: &K) -> bool {
+        let hash = self.hash(key);
+        self.bucket(hash).hashes.lock().contains(&hash)
+    }
+
+    /// Store the entry in its bucket, older entries of the key, expired entries and the oldest entries that no longer
+    /// fit are dropped.
+    ///
+    /// Returns `false` if the bucket is on failed media.
+    pub(crate) async fn insert(
+        &self,
+        key: &K,
+        value: &EntryValue<'_, V>,
+        sequence: Sequence,
+        expire_at: ExpireAt,
+    ) -> Result<bool> {
+        let hash = self.hash(key);
+        let index = self.index(hash);
+        let bucket = &self.buckets[index];
+
+        let entry_key = EntryKey::new(key, self.encryption.as_ref());
+        let len = EntryHeader::serialized_len() + entry_key.len() + value.len();
+        let mut entry = vec![0; len];
+        write_entry(
+            &mut entry,
+            &entry_key,
+            value,
+            sequence,
+            expire_at,
+            self.checksum_algorithm,
+        );
+
+        let guard = bucket.lock.write().await;
+
+        let buf = match self.read(index).await {
+            Ok(buf) => buf,
+            Err(_) if !self.is_usable(index) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        let mut entries = self
+            .parse(&buf)
+            .into_iter()
+            .filter(|entry| entry.key != *key && !catalog::is_expired(entry.expire_at))
+            .collect::<VecDeque<_>>();
+        let capacity = Self::max_entry_size(self.device.align());
+        let mut used = entries.iter().map(|entry| entry.range.len()).sum::<usize>() + len;
+        while used > capacity {
+            let evicted = entries.pop_front().unwrap();
+            used -= evicted.range.len();
+            self.metrics
+                .op_bytes_bucket_evict
+                .inc_by(evicted.range.len() as u64);
+        }
+
+        let parts = entries
+            .iter()
+            .map(|entry| &buf[entry.range.clone()])
+            .chain(std::iter::once(&entry[..]));
+        match self.write(index, parts).await {
+            Ok(()) => {}
+            Err(_) if !self.is_usable(index) => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        *bucket.hashes.lock() = entries
+            .iter()
+            .map(|entry| self.hash(&entry.key))
+            .chain(std::iter::once(hash))
+            .collect();
+        drop(guard);
+
+        Ok(true)
+    }
+
+    pub async fn lookup(&self, key: &K, verify: bool) -> Result<Option<V>> {
+        Ok(self.lookup_raw(key, verify).await?.map(|buf| V::read(&buf)))
+    }
+
+    /// Returns the serialized value, buckets are small so it is copied out of the bucket buffer.
+    pub async fn lookup_raw(&self, key: &K, verify: bool) -> Result<Option<Vec<u8>>> {
+        if !self.exists(key) {
+            return Ok(None);
+        }
+        let index = self.index(self.hash(key));
+
+        let guard = self.buckets[index].lock.read().await;
+        let buf = match self.read(index).await {
+            Ok(buf) => buf,
+            Err(_) if !self.is_usable(index) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        drop(guard);
+
+        let value = self
+            .parse(&buf)
+            .into_iter()
+            .find(|entry| entry.key == *key)
+            .filter(|entry| !catalog::is_expired(entry.expire_at))
+            .and_then(|entry| {
+                let entry = &buf[entry.range];
+                let (_key, value) = read_entry_raw::<K>(entry, verify, self.encryption.as_ref())?;
+                Some(match value {
+                    RawValue::Range(range) => entry[range].to_vec(),
+                    RawValue::Decoded(buf) => buf,
+                })
+            });
+        Ok(value)
+    }
+
+    /// Remove the entry of the key if its sequence is smaller than `sequence`.
+    ///
+    /// Returns `true` if the entry is found and removed.
+    pub async fn remove(&self, key: &K, sequence: Sequence) -> Result<bool> {
+        if !self.exists(key) {
+            return Ok(false);
+        }
+        let index = self.index(self.hash(key));
+        let bucket = &self.buckets[index];
+
+        let guard = bucket.lock.write().await;
+
+        let buf = match self.read(index).await {
+            Ok(buf) => buf,
+            Err(_) if !self.is_usable(index) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let entries = self.parse(&buf);
+        let total = entries.len();
+        let entries = entries
+            .into_iter()
+            .filter(|entry| entry.key != *key || entry.sequence >= sequence)
+            .collect_vec();
+        if entries.len() == total {
+            return Ok(false);
+        }
+
+        let parts = entries.iter().map(|entry| &buf[entry.range.clone()]);
+        match self.write(index, parts).await {
+            Ok(()) => {}
+            Err(_) if !self.is_usable(index) => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        *bucket.hashes.lock() = entries.iter().map(|entry| self.hash(&entry.key)).collect();
+        drop(guard);
+
+        Ok(true)
+    }
+
+    /// Load key hashes of all buckets, returns the max sequence of entries.
+    ///
+    /// Must be called after entries in regions are recovered, a key is only kept where its entry is newer.
+    pub async fn recover(
+        &self,
+        concurrency: usize,
+        verify: bool,
+        indices: &Catalog<K>,
+    ) -> Result<Sequence> {
+        futures::stream::iter(0..self.buckets.len())
+            .map(|index| self.recover_bucket(index, verify, indices))
+            .buffer_unordered(concurrency)
+            .try_fold(0, |max, sequence| async move {
+                Ok(std::cmp::max(max, sequence))
+            })
+            .await
+    }
+
+    async fn recover_bucket(
+        &self,
+        index: usize,
+        verify: bool,
+        indices: &Catalog<K>,
+    ) -> Result<Sequence> {
+        let bucket = &self.buckets[index];
+
+        let buf = match self.read(index).await {
+            Ok(buf) => buf,
+            Err(_) if !self.is_usable(index) => return Ok(0),
+            Err(e) => return Err(e),
+        };
+
+        let entries = self.parse(&buf);
+        let total = entries.len();
+        let mut sequence = 0;
+        let entries = entries
+            .into_iter()
+            .filter(|entry| {
+                if verify
+                    && read_entry::<K, V>(&buf[entry.range.clone()], true, self.encryption.as_ref())
+                        .is_none()
+                {
+                    return false;
+                }
+                // The key may be written to both a bucket and a region if the store crashed while it was moved.
+                match indices.lookup(&entry.key) {
+                    Some(info) if info.sequence > entry.sequence => return false,
+                    Some(_) => {
+                        indices.remove(&entry.key);
+                    }
+                    None => {}
+                }
+                sequence = std::cmp::max(sequence, entry.sequence);
+                true
+            })
+            .collect_vec();
+
+        // Drop corrupted and stale entries from the bucket, so they are not seen again.
+        if entries.len() != total {
+            let parts = entries.iter().map(|entry| &buf[entry.range.clone()]);
+            self.write(index, parts).await?;
+        }
+
+        *bucket.hashes.lock() = entries.iter().map(|entry| self.hash(&entry.key)).collect();
+
+        Ok(sequence)
+    }
+
+    /// Returns entries of the bucket from the oldest to the newest, or nothing if the bucket is invalid.
+    fn parse(&self, buf: &[u8]) -> Vec<BucketEntry<K>> {
+        let Some(header) = BucketHeader::read(buf) else {
+            return vec![];
+        };
+        let start = BucketHeader::serialized_len();
+        let end = start + header.len as usize;
+        if header.epoch != self.epoch.load(Ordering::Acquire)
+            || end > buf.len()
+            || header.checksum_algorithm.checksum(&buf[start..end]) != header.checksum
+        {
+            return vec![];
+        }
+
+        let mut entries = vec![];
+        let mut cursor = start;
+        while cursor < end {
+            let Some(h) = (end - cursor >= EntryHeader::serialized_len())
+                .then(|| EntryHeader::read(&buf[cursor..end]))
+                .flatten()
+            else {
+                break;
+            };
+            let len = EntryHeader::serialized_len() + h.value_len as usize + h.key_len as usize;
+            if !h
+                .flags
+                .difference(EntryFlags::COMPRESSED | EntryFlags::ENCRYPTED)
+                .is_empty()
+                || cursor + len > end
+            {
+                break;
+            }
+            let key_start = cursor + EntryHeader::serialized_len() + h.value_len as usize;
+            // Entries that fail to decrypt are dropped with the next modification of the bucket.
+            if let Some(key) = h.read_key(&buf[key_start..cursor + len], self.encryption.as_ref()) {
+                entries.push(BucketEntry {
+                    key,
+                    sequence: h.sequence,
+                    expire_at: h.expire_at,
+                    range: cursor..cursor + len,
+                });
+            }
+            cursor += len;
+        }
+        entries
+    }
+
+    async fn read(&self, index: usize) -> Result<Vec<u8, D::IoBufferAllocator>> {
+        let align = self.device.align();
+        let (region, offset) = self.locate(index);
+        let buf = self.device.io_buffer(align, align);
+        let (res, mut buf) = self.device.read(buf, .., region, offset).await;
+        // A bucket never written is read as empty.
+        if res? != align {
+            buf.fill(0);
+        }
+        Ok(buf)
+    }
+
+    async fn write<'a>(&self, index: usize, entries: impl Iterator<Item = &'a [u8]>) -> Result<()> {
+        let align = self.device.align();
+        let (region, offset) = self.locate(index);
+
+        let mut buf = self.device.io_buffer(align, align);
+        buf.fill(0);
+        let mut cursor = BucketHeader::serialized_len();
+        for entry in entries {
+            buf[cursor..cursor + entry.len()].copy_from_slice(entry);
+            cursor += entry.len();
+        }
+        let header = BucketHeader {
+            len: (cursor - BucketHeader::serialized_len()) as u32,
+            epoch: self.epoch.load(Ordering::Acquire),
+            checksum: self
+                .checksum_algorithm
+                .checksum(&buf[BucketHeader::serialized_len()..cursor]),
+            checksum_algorithm: self.checksum_algorithm,
+        };
+        header.write(&mut buf[..BucketHeader::serialized_len()]);
+
+        let (res, _buf) = self.device.write(buf, .., region, offset).await;
+        res?;
+        Ok(())
+    }
+
+    fn locate(&self, index: usize) -> (RegionId, u64) {
+        let buckets = self.device.region_size() / self.device.align();
+        let region = (index / buckets) as RegionId;
+        let offset = ((index % buckets) * self.device.align()) as u64;
+        (region, offset)
+    }
+
+    fn is_usable(&self, index: usize) -> bool {
+        self.device.is_usable(self.locate(index).0)
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        hash as usize % self.buckets.len()
+    }
+
+    fn bucket(&self, hash: u64) -> &Bucket {
+        &self.buckets[self.index(hash)]
+    }
+
+    fn hash(&self, key: &K) -> u64 {
+        let mut hasher = XxHash64::default();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        catalog::{Index, IndexInfo},
+        device::mem::{MemDevice, MemDeviceConfig, MemDeviceMemory},
+        metrics::METRICS,
+    };
+
+    use super::*;
+
+    const ALIGN: usize = 4 * 1024;
+
+    type TestBucketStore = BucketStore<u64, Vec<u8>, MemDevice>;
+
+    async fn device() -> MemDevice {
+        MemDevice::open(MemDeviceConfig {
+            capacity: 4 * ALIGN,
+            region_size: 2 * ALIGN,
+            align: ALIGN,
+            io_size: ALIGN,
+            memory: MemDeviceMemory::new(),
+        })
+        .await
+        .unwrap()
+    }
+
+    fn store(device: MemDevice, regions: usize) -> TestBucketStore {
+        BucketStore::new(
+            device,
+            regions,
+            TestBucketStore::max_entry_size(ALIGN),
+            ChecksumAlgorithm::default(),
+            None,
+            0,
+            Arc::new(METRICS.foyer("test")),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_bucket_store() {
+        let device = device().await;
+        let store = store(device.clone(), 1);
+        assert_eq!(store.buckets.len(), 2);
+
+        for i in 0..10 {
+            assert!(store
+                .insert(&i, &EntryValue::Value(&vec![i as u8; 100]), i, 0)
+                .await
+                .unwrap());
+        }
+        for i in 0..10 {
+            assert!(store.exists(&i));
+            assert_eq!(
+                store.lookup(&i, true).await.unwrap().unwrap(),
+                vec![i as u8; 100]
+            );
+        }
+        assert!(store.lookup(&10, true).await.unwrap().is_none());
+
+        // Overwrite, and remove with an older sequence is ignored.
+        assert!(store
+            .insert(&3, &EntryValue::Value(&vec![33; 100]), 10, 0)
+            .await
+            .unwrap());
+        assert!(!store.remove(&3, 5).await.unwrap());
+        assert_eq!(
+            store.lookup(&3, true).await.unwrap().unwrap(),
+            vec![33; 100]
+        );
+        assert!(store.remove(&4, 11).await.unwrap());
+        assert!(!store.exists(&4));
+        assert!(store.lookup(&4, true).await.unwrap().is_none());
+
+        // Buckets are recovered from the device, keys with newer entries in regions are dropped.
+        let indices = Catalog::new(device.regions(), 1);
+        indices.insert(
+            5,
+            IndexInfo {
+                sequence: 100,
+                expire_at: 0,
+                index: Index::RingBuffer { offset: 0, len: 0 },
+            },
+        );
+        let store = self::store(device.clone(), 1);
+        assert_eq!(store.recover(2, true, &indices).await.unwrap(), 10);
+        for i in 0..10 {
+            let value = store.lookup(&i, true).await.unwrap();
+            match i {
+                3 => assert_eq!(value.unwrap(), vec![33; 100]),
+                4 | 5 => assert!(value.is_none()),
+                i => assert_eq!(value.unwrap(), vec![i as u8; 100]),
+            }
+        }
+
+        // Buckets of another epoch are empty.
+        store.set_epoch(1);
+        for i in 0..10 {
+            assert!(!store.exists(&i));
+        }
+        let store = self::store(device, 1);
+        store.set_epoch(1);
+        assert_eq!(store.recover(2, true, &indices).await.unwrap(), 0);
+        assert!(store.lookup(&0, true).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bucket_store_fifo() {
+        let store = store(device().await, 1);
+
+        // Keys hashed to the same bucket.
+        let keys = (0..)
+            .filter(|key| store.index(store.hash(key)) == 0)
+            .take(64)
+            .collect_vec();
+
+        // Each entry takes about a quarter of the bucket.
+        let len = 1000;
+        for (sequence, key) in keys.iter().enumerate() {
+            assert!(store
+                .insert(
+                    key,
+                    &EntryValue::Value(&vec![*key as u8; len]),
+                    sequence as Sequence,
+                    0,
+                )
+                .await
+                .unwrap());
+        }
+
+        // Only the newest entries are kept.
+        let kept =
+            TestBucketStore::max_entry_size(ALIGN) / (EntryHeader::serialized_len() + 8 + 8 + len);
+        for (i, key) in keys.iter().enumerate() {
+            let value = store.lookup(key, true).await.unwrap();
+            if i >= keys.len() - kept {
+                assert_eq!(value.unwrap(), vec![*key as u8; len]);
+            } else {
+                assert!(value.is_none());
+                assert!(!store.exists(key));
+            }
+        }
+    }
+}
@@ -16,6 +16,7 @@ use std::{
     collections::btree_map::{BTreeMap, Entry},
     hash::Hasher,
     sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use foyer_common::code::Key;
@@ -27,9 +28,35 @@ use crate::region::{RegionId, Version};
 
 pub type Sequence = u64;
 
+/// Unix timestamp in milliseconds when an entry expires, `0` if it never expires.
+pub type ExpireAt = u64;
+
+/// Convert a deadline to [`ExpireAt`], deadlines before the unix epoch are treated as already expired.
+pub fn expire_at(deadline: SystemTime) -> ExpireAt {
+    let millis = deadline
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64);
+    // `0` is reserved for entries that never expire.
+    std::cmp::max(millis, 1)
+}
+
+pub fn is_expired(expire_at: ExpireAt) -> bool {
+    expire_at != 0
+        && SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(true, |now| now.as_millis() as u64 >= expire_at)
+}
+
 #[derive(Debug, Clone)]
 pub enum Index {
-    RingBuffer {},
+    /// The entry is staged in the ring buffer and not spilled into a region yet.
+    RingBuffer {
+        /// Offset of the entry since the ring buffer is created.
+        offset: usize,
+        /// Serialized length of the entry.
+        len: usize,
+    },
+    /// `offset` and `len` are not aligned if the entry is packed in a batch spilled from the ring buffer.
     Region {
         region: RegionId,
         version: Version,
@@ -38,14 +65,76 @@ pub enum Index {
         key_len: u32,
         value_len: u32,
     },
+    /// The entry is too large for a region, its value is split into chunks written to several regions.
+    Chunks {
+        /// Chunks in order, the value is reassembled from all of them.
+        chunks: Arc<[Chunk]>,
+        key_len: u32,
+        value_len: u32,
+    },
+}
+
+impl Index {
+    /// Returns the regions that hold the entry.
+    pub fn regions(&self) -> impl Iterator<Item = RegionId> + '_ {
+        let (region, chunks) = match self {
+            Index::RingBuffer { .. } => (None, &[][..]),
+            Index::Region { region, .. } => (Some(*region), &[][..]),
+            Index::Chunks { chunks, .. } => (None, &chunks[..]),
+        };
+        region
+            .into_iter()
+            .chain(chunks.iter().map(|chunk| chunk.region))
+    }
+}
+
+/// Location of a chunk of a large entry, see [`Index::Chunks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub region: RegionId,
+    pub version: Version,
+    pub offset: u32,
+    pub len: u32,
 }
 
 #[derive(Debug, Clone)]
 pub struct IndexInfo {
     pub sequence: Sequence,
+    pub expire_at: ExpireAt,
     pub index: Index,
 }
 
+impl IndexInfo {
+    pub fn is_expired(&self) -> bool {
+        is_expired(self.expire_at)
+    }
+}
+
+/// In-memory record of a persisted tombstone.
+///
+/// A tombstone must be kept on device as long as any region that may hold an entry shadowed by it is not reclaimed.
+#[derive(Debug, Clone)]
+pub struct Tombstone {
+    pub sequence: Sequence,
+    /// The region that holds the tombstone record.
+    pub region: RegionId,
+    /// Regions that may hold entries shadowed by the tombstone, with their reclaim generation.
+    pub shadows: Vec<(RegionId, u64)>,
+}
+
+#[derive(Debug)]
+struct RegionIndices<K>
+where
+    K: Key,
+{
+    /// Keys of entries in the region and their sequences.
+    keys: BTreeMap<Arc<K>, Sequence>,
+    /// Keys of tombstones in the region and their sequences.
+    tombstones: BTreeMap<Arc<K>, Sequence>,
+    /// Increased each time the region is released after being reclaimed.
+    generation: u64,
+}
+
 #[derive(Debug)]
 pub struct Catalog<K>
 where
@@ -57,8 +146,11 @@ where
     /// Sharded by key hash.
     infos: Vec<RwLock<BTreeMap<Arc<K>, IndexInfo>>>,
 
+    /// Sharded by key hash.
+    tombstones: Vec<Mutex<BTreeMap<Arc<K>, Tombstone>>>,
+
     /// Sharded by region id.
-    regions: Vec<Mutex<BTreeMap<Arc<K>, u64>>>,
+    regions: Vec<Mutex<RegionIndices<K>>>,
 }
 
 impl<K> Catalog<K>
@@ -69,29 +161,108 @@ where
         let infos = (0..1 << bits)
             .map(|_| RwLock::new(BTreeMap::new()))
             .collect_vec();
-        let regions = (0..regions)
+        let tombstones = (0..1 << bits)
             .map(|_| Mutex::new(BTreeMap::new()))
             .collect_vec();
+        let regions = (0..regions)
+            .map(|_| {
+                Mutex::new(RegionIndices {
+                    keys: BTreeMap::new(),
+                    tombstones: BTreeMap::new(),
+                    generation: 0,
+                })
+            })
+            .collect_vec();
         Self {
             bits,
             infos,
+            tombstones,
             regions,
         }
     }
 
+    /// Insert the index of an entry.
+    ///
+    /// The index is ignored if there is already an index or a tombstone with a larger sequence of the key.
     pub fn insert(&self, key: K, info: IndexInfo) {
-        // TODO(MrCroxx): compare sequence.
         let key = Arc::new(key);
+        let shard = self.shard(&key);
+
+        let mut tombstones = self.tombstones[shard].lock();
+        let mut infos = self.infos[shard].write();
+        self.insert_locked(&mut tombstones, &mut infos, key, info);
+    }
 
-        if let Index::Region { region, .. } = info.index {
+    /// Insert the indices of entries atomically, lookups see either none or all of them.
+    ///
+    /// Each index is ignored the same as [`Catalog::insert`], indices of the same key are inserted in order.
+    pub fn insert_many(&self, entries: Vec<(K, IndexInfo)>) {
+        let entries = entries
+            .into_iter()
+            .map(|(key, info)| {
+                let key = Arc::new(key);
+                (self.shard(&key), key, info)
+            })
+            .collect_vec();
+
+        // Lock shards in order to avoid deadlocks, tombstones are locked before infos the same as `insert`.
+        let shards = entries
+            .iter()
+            .map(|(shard, ..)| *shard)
+            .sorted()
+            .dedup()
+            .collect_vec();
+        let mut tombstones = shards
+            .iter()
+            .map(|shard| (*shard, self.tombstones[*shard].lock()))
+            .collect::<BTreeMap<_, _>>();
+        let mut infos = shards
+            .iter()
+            .map(|shard| (*shard, self.infos[*shard].write()))
+            .collect::<BTreeMap<_, _>>();
+
+        for (shard, key, info) in entries {
+            self.insert_locked(
+                tombstones.get_mut(&shard).unwrap(),
+                infos.get_mut(&shard).unwrap(),
+                key,
+                info,
+            );
+        }
+    }
+
+    fn insert_locked(
+        &self,
+        tombstones: &mut BTreeMap<Arc<K>, Tombstone>,
+        infos: &mut BTreeMap<Arc<K>, IndexInfo>,
+        key: Arc<K>,
+        info: IndexInfo,
+    ) {
+        if let Some(tombstone) = tombstones.get_mut(&key)
+            && tombstone.sequence > info.sequence
+        {
+            // The entry is shadowed by the tombstone, the tombstone must be kept until the region is reclaimed.
+            for region in info.index.regions() {
+                self.shadow(tombstone, region);
+            }
+            return;
+        }
+
+        if let Some(old) = infos.get(&key)
+            && old.sequence > info.sequence
+        {
+            return;
+        }
+
+        // Reclaiming any of the regions invalidates the entry.
+        for region in info.index.regions() {
             self.regions[region as usize]
                 .lock()
+                .keys
                 .insert(key.clone(), info.sequence);
-        };
+        }
 
-        let shard = self.shard(&key);
-        // TODO(MrCroxx): handle old key?
-        let _ = self.infos[shard].write().insert(key.clone(), info);
+        infos.insert(key, info);
     }
 
     pub fn lookup(&self, key: &K) -> Option<IndexInfo> {
@@ -102,15 +273,90 @@ where
     pub fn remove(&self, key: &K) -> Option<IndexInfo> {
         let shard = self.shard(key);
         let info: Option<IndexInfo> = self.infos[shard].write().remove(key);
-        if let Some(info) = &info && let Index::Region { region,..} = info.index {
-            self.regions[region as usize].lock().remove(key);
+        if let Some(info) = &info {
+            for region in info.index.regions() {
+                self.regions[region as usize].lock().keys.remove(key);
+            }
         }
         info
     }
 
+    /// Remove the index if its sequence is smaller than `sequence`.
+    pub fn remove_older(&self, key: &K, sequence: Sequence) -> Option<IndexInfo> {
+        let shard = self.shard(key);
+        let mut infos = self.infos[shard].write();
+        if infos.get(key)?.sequence >= sequence {
+            return None;
+        }
+        let info = infos.remove(key)?;
+        drop(infos);
+        for region in info.index.regions() {
+            self.regions[region as usize].lock().keys.remove(key);
+        }
+        Some(info)
+    }
+
+    /// Insert a tombstone persisted in `region` that shadows entries with smaller sequences of the key.
+    ///
+    /// `shadows` are the regions known to hold shadowed entries.
+    pub fn insert_tombstone(
+        &self,
+        key: K,
+        sequence: Sequence,
+        region: RegionId,
+        shadows: impl IntoIterator<Item = RegionId>,
+    ) {
+        let key = Arc::new(key);
+        let shard = self.shard(&key);
+
+        let mut tombstone = Tombstone {
+            sequence,
+            region,
+            shadows: vec![],
+        };
+        for shadow in shadows {
+            self.shadow(&mut tombstone, shadow);
+        }
+
+        let mut tombstones = self.tombstones[shard].lock();
+
+        // Remove the index that is shadowed by the tombstone.
+        if let Entry::Occupied(o) = self.infos[shard].write().entry(key.clone())
+            && o.get().sequence < sequence
+        {
+            let info = o.remove();
+            for region in info.index.regions() {
+                self.regions[region as usize].lock().keys.remove(&key);
+                self.shadow(&mut tombstone, region);
+            }
+        }
+
+        let tombstone = match tombstones.remove(&key) {
+            Some(mut old) => {
+                // Merge the shadows of both tombstones, the one with the larger sequence is kept.
+                if old.sequence > tombstone.sequence {
+                    std::mem::swap(&mut old, &mut tombstone);
+                }
+                for (region, _) in old.shadows {
+                    self.shadow(&mut tombstone, region);
+                }
+                tombstone
+            }
+            None => tombstone,
+        };
+        self.regions[tombstone.region as usize]
+            .lock()
+            .tombstones
+            .insert(key.clone(), tombstone.sequence);
+        tombstones.insert(key, tombstone);
+    }
+
+    /// Take the indices of entries in the region.
+    ///
+    /// Entries on the region are still shadowed by tombstones until the region is released by
+    /// [`Catalog::release_region`].
     pub fn take_region(&self, region: &RegionId) -> Vec<IndexInfo> {
-        let mut keys = BTreeMap::new();
-        std::mem::swap(&mut *self.regions[*region as usize].lock(), &mut keys);
+        let keys = std::mem::take(&mut self.regions[*region as usize].lock().keys);
 
         let mut infos = Vec::with_capacity(keys.len());
         for (key, sequence) in keys {
@@ -128,15 +374,116 @@ where
         infos
     }
 
+    /// Take the tombstones persisted in the region.
+    ///
+    /// Only tombstones that still shadow entries on unreclaimed regions are returned, they need to be rewritten
+    /// before the region is reused.
+    pub fn take_region_tombstones(&self, region: &RegionId) -> Vec<(K, Tombstone)> {
+        let keys = std::mem::take(&mut self.regions[*region as usize].lock().tombstones);
+
+        let mut tombstones = Vec::with_capacity(keys.len());
+        for (key, sequence) in keys {
+            let shard = self.shard(&key);
+            match self.tombstones[shard].lock().entry(key.clone()) {
+                Entry::Vacant(_) => continue,
+                Entry::Occupied(o) => {
+                    if o.get().region != *region || o.get().sequence != sequence {
+                        continue;
+                    }
+                    let mut tombstone = o.remove();
+                    // Entries shadowed on the region itself are reclaimed together with the tombstone.
+                    tombstone
+                        .shadows
+                        .retain(|shadow| shadow.0 != *region && self.is_alive(shadow));
+                    if !tombstone.shadows.is_empty() {
+                        tombstones.push(((*key).clone(), tombstone));
+                    }
+                }
+            }
+        }
+        tombstones
+    }
+
+    /// Mark the region as released, entries persisted in it are gone and no longer need to be shadowed.
+    ///
+    /// Must be called only after the region is reclaimed and before it is reused.
+    pub fn release_region(&self, region: &RegionId) {
+        self.regions[*region as usize].lock().generation += 1;
+    }
+
+    /// Drop tombstones that no longer shadow any entry on unreclaimed regions.
+    pub fn prune_tombstones(&self) {
+        for shard in self.tombstones.iter() {
+            shard.lock().retain(|key, tombstone| {
+                tombstone.shadows.retain(|shadow| self.is_alive(shadow));
+                if tombstone.shadows.is_empty() {
+                    self.regions[tombstone.region as usize]
+                        .lock()
+                        .tombstones
+                        .remove(key);
+                    return false;
+                }
+                true
+            });
+        }
+    }
+
+    /// Returns all indices and the tombstones that still shadow entries on unreclaimed regions.
+    ///
+    /// Each shard is copied separately, so the snapshot is not atomic among shards.
+    #[expect(clippy::type_complexity)]
+    pub fn snapshot(&self) -> (Vec<(K, IndexInfo)>, Vec<(K, Tombstone)>) {
+        let mut infos = vec![];
+        for shard in self.infos.iter() {
+            infos.extend(
+                shard
+                    .read()
+                    .iter()
+                    .map(|(key, info)| ((**key).clone(), info.clone())),
+            );
+        }
+
+        let mut tombstones = vec![];
+        for shard in self.tombstones.iter() {
+            for (key, tombstone) in shard.lock().iter() {
+                let mut tombstone = tombstone.clone();
+                tombstone.shadows.retain(|shadow| self.is_alive(shadow));
+                if !tombstone.shadows.is_empty() {
+                    tombstones.push(((**key).clone(), tombstone));
+                }
+            }
+        }
+
+        (infos, tombstones)
+    }
+
     pub fn clear(&self) {
         for shard in self.infos.iter() {
             shard.write().clear();
         }
+        for shard in self.tombstones.iter() {
+            shard.lock().clear();
+        }
         for region in self.regions.iter() {
-            region.lock().clear();
+            let mut indices = region.lock();
+            indices.keys.clear();
+            indices.tombstones.clear();
+        }
+    }
+
+    fn shadow(&self, tombstone: &mut Tombstone, region: RegionId) {
+        let generation = self.regions[region as usize].lock().generation;
+        match tombstone.shadows.iter_mut().find(|(r, _)| *r == region) {
+            // Entries shadowed in previous generations of the region are already reclaimed.
+            Some(shadow) => shadow.1 = generation,
+            None => tombstone.shadows.push((region, generation)),
         }
     }
 
+    fn is_alive(&self, (region, generation): &(RegionId, u64)) -> bool {
+        self.regions[*region as usize].lock().generation == *generation
+    }
+
     fn shard(&self, key: &K) -> usize {
         self.hash(key) as usize & ((1 << self.bits) - 1)
     }
@@ -0,0 +1,107 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::collections::BTreeMap;
+
+use foyer_common::code::Key;
+use parking_lot::Mutex;
+
+use crate::catalog::{Catalog, Chunk, ExpireAt, Index, IndexInfo, Sequence};
+
+/// Chunks of large entries found on recovery, a large entry is indexed once all of its chunks are found.
+///
+/// Regions are recovered concurrently, so chunks of an entry are found in any order.
+#[derive(Debug)]
+pub(crate) struct Chains<K>
+where
+    K: Key,
+{
+    partials: Mutex<BTreeMap<(K, Sequence), PartialChain>>,
+}
+
+#[derive(Debug)]
+struct PartialChain {
+    chunks: Vec<Option<Chunk>>,
+    found: usize,
+    value_len: u32,
+}
+
+impl<K> Default for Chains<K>
+where
+    K: Key,
+{
+    fn default() -> Self {
+        Self {
+            partials: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl<K> Chains<K>
+where
+    K: Key,
+{
+    #[expect(clippy::too_many_arguments)]
+    pub(crate) fn add(
+        &self,
+        key: K,
+        sequence: Sequence,
+        expire_at: ExpireAt,
+        index: u32,
+        count: u32,
+        value_len: u32,
+        chunk: Chunk,
+        indices: &Catalog<K>,
+    ) {
+        if index >= count {
+            return;
+        }
+        let mut partials = self.partials.lock();
+        let partial = partials
+            .entry((key.clone(), sequence))
+            .or_insert_with(|| PartialChain {
+                chunks: vec![None; count as usize],
+                found: 0,
+                value_len: 0,
+            });
+        if partial.chunks.len() != count as usize || partial.chunks[index as usize].is_some() {
+            return;
+        }
+        partial.chunks[index as usize] = Some(chunk);
+        partial.found += 1;
+        partial.value_len += value_len;
+        if partial.found < partial.chunks.len() {
+            return;
+        }
+
+        let partial = partials.remove(&(key.clone(), sequence)).unwrap();
+        drop(partials);
+
+        let info = IndexInfo {
+            sequence,
+            expire_at,
+            index: Index::Chunks {
+                chunks: partial.chunks.into_iter().map(Option::unwrap).collect(),
+                key_len: key.serialized_len() as u32,
+                value_len: partial.value_len,
+            },
+        };
+        indices.insert(key, info);
+    }
+
+    /// Returns the count of large entries with missing chunks.
+    pub(crate) fn len(&self) -> usize {
+        self.partials.lock().len()
+    }
+}
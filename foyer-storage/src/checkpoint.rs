@@ -0,0 +1,409 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::hash::Hasher;
+
+use bytes::{Buf, BufMut};
+use foyer_common::code::Key;
+use twox_hash::XxHash64;
+
+use crate::catalog::{Chunk, Index, IndexInfo, Sequence, Tombstone};
+
+pub const CHECKPOINT_MAGIC: u64 = 0x19970501;
+
+/// Layout version of checkpoints, must be bumped on every layout change.
+///
+/// Checkpoints of other versions are ignored, so the store falls back to scanning regions.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// Persisted snapshot of the catalog, used to skip scanning regions that are not rewritten since.
+#[derive(Debug)]
+pub struct Checkpoint<K>
+where
+    K: Key,
+{
+    /// Store epoch when the checkpoint is taken, the checkpoint is stale if the epoch changes.
+    pub epoch: u64,
+    /// Store sequence when the checkpoint is taken.
+    pub sequence: Sequence,
+    /// Regions with a header sequence not less than the watermark may hold entries missing in the checkpoint.
+    pub watermark: Sequence,
+    pub infos: Vec<(K, IndexInfo)>,
+    pub tombstones: Vec<(K, Tombstone)>,
+}
+
+impl<K> Checkpoint<K>
+where
+    K: Key,
+{
+    /// | magic | version | epoch | sequence | watermark | info count | tombstone count | infos | tombstones |
+    /// | chunked count | chunked infos | checksum |
+    ///
+    /// info:         | sequence | expire at | region | offset | len | key len | value len | key |
+    /// tombstone:    | sequence | region | shadow count | shadow regions | key len | key |
+    /// chunked info: | sequence | expire at | chunk count | (region | offset | len) * chunk count | key len |
+    ///               | value len | key |
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        let infos = self
+            .infos
+            .iter()
+            .filter(|(_, info)| matches!(info.index, Index::Region { .. }))
+            .count();
+
+        buf.put_u64(CHECKPOINT_MAGIC);
+        buf.put_u32(CHECKPOINT_VERSION);
+        buf.put_u64(self.epoch);
+        buf.put_u64(self.sequence);
+        buf.put_u64(self.watermark);
+        buf.put_u64(infos as u64);
+        buf.put_u64(self.tombstones.len() as u64);
+
+        for (key, info) in self.infos.iter() {
+            let Index::Region {
+                region,
+                offset,
+                len,
+                key_len,
+                value_len,
+                ..
+            } = info.index
+            else {
+                continue;
+            };
+            buf.put_u64(info.sequence);
+            buf.put_u64(info.expire_at);
+            buf.put_u32(region);
+            buf.put_u32(offset);
+            buf.put_u32(len);
+            buf.put_u32(key_len);
+            buf.put_u32(value_len);
+            write_key(buf, key);
+        }
+
+        for (key, tombstone) in self.tombstones.iter() {
+            buf.put_u64(tombstone.sequence);
+            buf.put_u32(tombstone.region);
+            buf.put_u32(tombstone.shadows.len() as u32);
+            for (region, _) in tombstone.shadows.iter() {
+                buf.put_u32(*region);
+            }
+            buf.put_u32(key.serialized_len() as u32);
+            write_key(buf, key);
+        }
+
+        let chunked = self
+            .infos
+            .iter()
+            .filter(|(_, info)| matches!(info.index, Index::Chunks { .. }))
+            .count();
+        buf.put_u64(chunked as u64);
+
+        for (key, info) in self.infos.iter() {
+            let Index::Chunks {
+                chunks,
+                key_len,
+                value_len,
+            } = &info.index
+            else {
+                continue;
+            };
+            buf.put_u64(info.sequence);
+            buf.put_u64(info.expire_at);
+            buf.put_u32(chunks.len() as u32);
+            for chunk in chunks.iter() {
+                buf.put_u32(chunk.region);
+                buf.put_u32(chunk.offset);
+                buf.put_u32(chunk.len);
+            }
+            buf.put_u32(*key_len);
+            buf.put_u32(*value_len);
+            write_key(buf, key);
+        }
+
+        let checksum = checksum(buf);
+        buf.put_u64(checksum);
+    }
+
+    /// Returns `None` if the checkpoint is corrupted or of another layout version.
+    ///
+    /// Indices are restored with version `0`, and shadows of tombstones with generation `0`.
+    pub fn read(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 8 * 8 + 4 {
+            return None;
+        }
+        let (data, mut footer) = buf.split_at(buf.len() - 8);
+        if checksum(data) != footer.get_u64() {
+            return None;
+        }
+
+        let mut buf = data;
+        if buf.get_u64() != CHECKPOINT_MAGIC {
+            return None;
+        }
+        let version = buf.get_u32();
+        if version != CHECKPOINT_VERSION {
+            tracing::warn!(
+                "checkpoint version mismatches, found: {}, supported: {}",
+                version,
+                CHECKPOINT_VERSION
+            );
+            return None;
+        }
+        let epoch = buf.get_u64();
+        let sequence = buf.get_u64();
+        let watermark = buf.get_u64();
+        let info_count = buf.get_u64() as usize;
+        let tombstone_count = buf.get_u64() as usize;
+
+        let mut infos = Vec::with_capacity(info_count);
+        for _ in 0..info_count {
+            if buf.remaining() < 8 + 8 + 4 * 5 {
+                return None;
+            }
+            let sequence = buf.get_u64();
+            let expire_at = buf.get_u64();
+            let region = buf.get_u32();
+            let offset = buf.get_u32();
+            let len = buf.get_u32();
+            let key_len = buf.get_u32();
+            let value_len = buf.get_u32();
+            let key = read_key(&mut buf, key_len as usize)?;
+            let info = IndexInfo {
+                sequence,
+                expire_at,
+                index: Index::Region {
+                    region,
+                    version: 0,
+                    offset,
+                    len,
+                    key_len,
+                    value_len,
+                },
+            };
+            infos.push((key, info));
+        }
+
+        let mut tombstones = Vec::with_capacity(tombstone_count);
+        for _ in 0..tombstone_count {
+            if buf.remaining() < 8 + 4 + 4 {
+                return None;
+            }
+            let sequence = buf.get_u64();
+            let region = buf.get_u32();
+            let shadow_count = buf.get_u32() as usize;
+            if buf.remaining() < 4 * shadow_count + 4 {
+                return None;
+            }
+            let shadows = (0..shadow_count).map(|_| (buf.get_u32(), 0)).collect();
+            let key_len = buf.get_u32();
+            let key = read_key(&mut buf, key_len as usize)?;
+            let tombstone = Tombstone {
+                sequence,
+                region,
+                shadows,
+            };
+            tombstones.push((key, tombstone));
+        }
+
+        if buf.remaining() < 8 {
+            return None;
+        }
+        let chunked_count = buf.get_u64() as usize;
+        for _ in 0..chunked_count {
+            if buf.remaining() < 8 + 8 + 4 {
+                return None;
+            }
+            let sequence = buf.get_u64();
+            let expire_at = buf.get_u64();
+            let chunk_count = buf.get_u32() as usize;
+            if buf.remaining() < 4 * 3 * chunk_count + 4 * 2 {
+                return None;
+            }
+            let chunks = (0..chunk_count)
+                .map(|_| Chunk {
+                    region: buf.get_u32(),
+                    version: 0,
+                    offset: buf.get_u32(),
+                    len: buf.get_u32(),
+                })
+                .collect();
+            let key_len = buf.get_u32();
+            let value_len = buf.get_u32();
+            let key = read_key(&mut buf, key_len as usize)?;
+            let info = IndexInfo {
+                sequence,
+                expire_at,
+                index: Index::Chunks {
+                    chunks,
+                    key_len,
+                    value_len,
+                },
+            };
+            infos.push((key, info));
+        }
+
+        if buf.has_remaining() {
+            return None;
+        }
+
+        Some(Self {
+            epoch,
+            sequence,
+            watermark,
+            infos,
+            tombstones,
+        })
+    }
+}
+
+fn write_key<K>(buf: &mut Vec<u8>, key: &K)
+where
+    K: Key,
+{
+    let start = buf.len();
+    buf.resize(start + key.serialized_len(), 0);
+    key.write(&mut buf[start..]);
+}
+
+fn read_key<K>(buf: &mut &[u8], len: usize) -> Option<K>
+where
+    K: Key,
+{
+    if buf.remaining() < len {
+        return None;
+    }
+    let key = K::read(&buf[..len]);
+    buf.advance(len);
+    Some(key)
+}
+
+fn checksum(buf: &[u8]) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(buf);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_codec() {
+        let info = |sequence, region, offset| IndexInfo {
+            sequence,
+            expire_at: 0,
+            index: Index::Region {
+                region,
+                version: 0,
+                offset,
+                len: 4096,
+                key_len: 8,
+                value_len: 1024,
+            },
+        };
+        let checkpoint = Checkpoint::<u64> {
+            epoch: 1,
+            sequence: 42,
+            watermark: 40,
+            infos: vec![
+                (1, info(10, 0, 4096)),
+                (2, info(11, 1, 8192)),
+                (
+                    4,
+                    IndexInfo {
+                        sequence: 13,
+                        expire_at: 1700000000000,
+                        index: Index::Chunks {
+                            chunks: vec![
+                                Chunk {
+                                    region: 2,
+                                    version: 0,
+                                    offset: 4096,
+                                    len: 61440,
+                                },
+                                Chunk {
+                                    region: 3,
+                                    version: 0,
+                                    offset: 4096,
+                                    len: 8192,
+                                },
+                            ]
+                            .into(),
+                            key_len: 8,
+                            value_len: 65536,
+                        },
+                    },
+                ),
+            ],
+            tombstones: vec![(
+                3,
+                Tombstone {
+                    sequence: 12,
+                    region: 1,
+                    shadows: vec![(0, 0), (2, 0)],
+                },
+            )],
+        };
+
+        let mut buf = vec![];
+        checkpoint.write(&mut buf);
+
+        let res = Checkpoint::<u64>::read(&buf).unwrap();
+        assert_eq!(res.epoch, 1);
+        assert_eq!(res.sequence, 42);
+        assert_eq!(res.watermark, 40);
+        assert_eq!(
+            res.infos
+                .iter()
+                .map(|(key, info)| (*key, info.sequence))
+                .collect::<Vec<_>>(),
+            vec![(1, 10), (2, 11), (4, 13)]
+        );
+        assert!(matches!(
+            res.infos[1].1.index,
+            Index::Region {
+                region: 1,
+                offset: 8192,
+                ..
+            }
+        ));
+        let Index::Chunks {
+            chunks, value_len, ..
+        } = &res.infos[2].1.index
+        else {
+            panic!("index of large entry must be chunks");
+        };
+        assert_eq!(*value_len, 65536);
+        assert_eq!(res.infos[2].1.expire_at, 1700000000000);
+        assert_eq!(
+            chunks.iter().map(|chunk| chunk.region).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(res.tombstones.len(), 1);
+        assert_eq!(res.tombstones[0].0, 3);
+        assert_eq!(res.tombstones[0].1.region, 1);
+        assert_eq!(res.tombstones[0].1.shadows, vec![(0, 0), (2, 0)]);
+
+        // Checkpoints of other versions are ignored even if the checksum matches.
+        let mut other = buf.clone();
+        other[8..12].copy_from_slice(&(CHECKPOINT_VERSION + 1).to_be_bytes());
+        let len = other.len() - 8;
+        let checksum = checksum(&other[..len]);
+        other[len..].copy_from_slice(&checksum.to_be_bytes());
+        assert!(Checkpoint::<u64>::read(&other).is_none());
+
+        buf[16] ^= 1;
+        assert!(Checkpoint::<u64>::read(&buf).is_none());
+    }
+}
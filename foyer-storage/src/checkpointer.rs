@@ -0,0 +1,79 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::time::Duration;
+
+use crate::{
+    device::Device, error::Result, generic::GenericStore, region_manager::RegionEpItemAdapter,
+};
+use foyer_common::code::{Key, Value};
+use foyer_intrusive::{core::adapter::Link, eviction::EvictionPolicy};
+use tokio::sync::broadcast;
+
+/// Periodically persists the catalog checkpoint of the store.
+#[derive(Debug)]
+pub struct Checkpointer<K, V, D, EP, EL>
+where
+    K: Key,
+    V: Value,
+    D: Device,
+    EP: EvictionPolicy<Adapter = RegionEpItemAdapter<EL>>,
+    EL: Link,
+{
+    interval: Duration,
+
+    store: GenericStore<K, V, D, EP, EL>,
+
+    stop_rx: broadcast::Receiver<()>,
+}
+
+impl<K, V, D, EP, EL> Checkpointer<K, V, D, EP, EL>
+where
+    K: Key,
+    V: Value,
+    D: Device,
+    EP: EvictionPolicy<Adapter = RegionEpItemAdapter<EL>>,
+    EL: Link,
+{
+    pub fn new(
+        interval: Duration,
+        store: GenericStore<K, V, D, EP, EL>,
+        stop_rx: broadcast::Receiver<()>,
+    ) -> Self {
+        Self {
+            interval,
+            store,
+            stop_rx,
+        }
+    }
+
+    pub async fn run(mut self) -> Result<()> {
+        loop {
+            tokio::select! {
+                biased;
+                _ = tokio::time::sleep(self.interval) => {
+                    tracing::info!("[checkpointer] write checkpoint");
+                    // A missed checkpoint only makes the next recovery scan more regions, try again on the next tick.
+                    if let Err(e) = self.store.checkpoint().await {
+                        tracing::warn!("[checkpointer] write checkpoint error: {}", e);
+                    }
+                }
+                _ = self.stop_rx.recv() => {
+                    tracing::info!("[checkpointer] exit");
+                    return Ok(())
+                }
+            }
+        }
+    }
+}
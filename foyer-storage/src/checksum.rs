@@ -0,0 +1,184 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::hash::Hasher;
+
+use rand::Rng;
+use twox_hash::{xxh3, XxHash64};
+
+/// Checksum algorithm of entries, recorded in each entry header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// No checksum, entries are never verified.
+    None,
+    #[default]
+    XxHash64,
+    Xxh3,
+    Crc32c,
+}
+
+impl ChecksumAlgorithm {
+    pub fn checksum(&self, buf: &[u8]) -> u64 {
+        match self {
+            ChecksumAlgorithm::None => 0,
+            ChecksumAlgorithm::XxHash64 => {
+                let mut hasher = XxHash64::with_seed(0);
+                hasher.write(buf);
+                hasher.finish()
+            }
+            ChecksumAlgorithm::Xxh3 => xxh3::hash64(buf),
+            ChecksumAlgorithm::Crc32c => crc32c::crc32c(buf) as u64,
+        }
+    }
+
+    /// Returns a hasher to compute the checksum incrementally.
+    pub(crate) fn hasher(&self) -> ChecksumHasher {
+        match self {
+            ChecksumAlgorithm::None => ChecksumHasher::None,
+            ChecksumAlgorithm::XxHash64 => ChecksumHasher::XxHash64(XxHash64::with_seed(0)),
+            ChecksumAlgorithm::Xxh3 => ChecksumHasher::Xxh3(Box::new(xxh3::Hash64::with_seed(0))),
+            ChecksumAlgorithm::Crc32c => ChecksumHasher::Crc32c(0),
+        }
+    }
+
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            ChecksumAlgorithm::None => 0,
+            ChecksumAlgorithm::XxHash64 => 1,
+            ChecksumAlgorithm::Xxh3 => 2,
+            ChecksumAlgorithm::Crc32c => 3,
+        }
+    }
+
+    pub(crate) fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(ChecksumAlgorithm::None),
+            1 => Some(ChecksumAlgorithm::XxHash64),
+            2 => Some(ChecksumAlgorithm::Xxh3),
+            3 => Some(ChecksumAlgorithm::Crc32c),
+            _ => None,
+        }
+    }
+}
+
+/// Computes the same checksum as [`ChecksumAlgorithm::checksum`] over all bytes written.
+pub(crate) enum ChecksumHasher {
+    None,
+    XxHash64(XxHash64),
+    Xxh3(Box<xxh3::Hash64>),
+    Crc32c(u32),
+}
+
+impl ChecksumHasher {
+    pub(crate) fn write(&mut self, buf: &[u8]) {
+        match self {
+            ChecksumHasher::None => {}
+            ChecksumHasher::XxHash64(hasher) => hasher.write(buf),
+            ChecksumHasher::Xxh3(hasher) => hasher.write(buf),
+            ChecksumHasher::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, buf),
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        match self {
+            ChecksumHasher::None => 0,
+            ChecksumHasher::XxHash64(hasher) => hasher.finish(),
+            ChecksumHasher::Xxh3(hasher) => hasher.finish(),
+            ChecksumHasher::Crc32c(crc) => *crc as u64,
+        }
+    }
+}
+
+/// When entry checksums are verified.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum VerifyMode {
+    /// Verify on every read.
+    #[default]
+    Read,
+    /// Verify only entries scanned during recovery, reads are not verified.
+    Recovery,
+    /// Verify the given ratio (`0.0..=1.0`) of reads randomly.
+    Sampled(f64),
+}
+
+impl VerifyMode {
+    /// Returns if the sampled ratio is finite and within `0.0..=1.0`.
+    pub(crate) fn is_valid(&self) -> bool {
+        match self {
+            VerifyMode::Read | VerifyMode::Recovery => true,
+            VerifyMode::Sampled(ratio) => (0.0..=1.0).contains(ratio),
+        }
+    }
+
+    /// Returns if a read should be verified, the mode must be valid.
+    pub(crate) fn on_read(&self) -> bool {
+        match self {
+            VerifyMode::Read => true,
+            VerifyMode::Recovery => false,
+            VerifyMode::Sampled(ratio) => rand::thread_rng().gen_bool(*ratio),
+        }
+    }
+
+    /// Returns if entries scanned during recovery should be verified.
+    pub(crate) fn on_recovery(&self) -> bool {
+        matches!(self, VerifyMode::Recovery)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum_algorithm() {
+        let data = b"hello, foyer";
+        for algorithm in [
+            ChecksumAlgorithm::None,
+            ChecksumAlgorithm::XxHash64,
+            ChecksumAlgorithm::Xxh3,
+            ChecksumAlgorithm::Crc32c,
+        ] {
+            assert_eq!(
+                ChecksumAlgorithm::from_u8(algorithm.to_u8()),
+                Some(algorithm)
+            );
+            assert_eq!(algorithm.checksum(data), algorithm.checksum(data));
+
+            let data = (0..10000).map(|i| i as u8).collect::<Vec<_>>();
+            let mut hasher = algorithm.hasher();
+            for chunk in data.chunks(333) {
+                hasher.write(chunk);
+            }
+            assert_eq!(hasher.finish(), algorithm.checksum(&data));
+        }
+        assert_eq!(ChecksumAlgorithm::None.checksum(data), 0);
+        assert_eq!(
+            ChecksumAlgorithm::Crc32c.checksum(b"123456789"),
+            0xe306_9283
+        );
+        assert_eq!(ChecksumAlgorithm::from_u8(4), None);
+    }
+
+    #[test]
+    fn test_verify_mode_valid() {
+        assert!(VerifyMode::Read.is_valid());
+        assert!(VerifyMode::Recovery.is_valid());
+        assert!(VerifyMode::Sampled(0.0).is_valid());
+        assert!(VerifyMode::Sampled(1.0).is_valid());
+        assert!(!VerifyMode::Sampled(1.5).is_valid());
+        assert!(!VerifyMode::Sampled(-0.1).is_valid());
+        assert!(!VerifyMode::Sampled(f64::NAN).is_valid());
+        assert!(!VerifyMode::Sampled(f64::INFINITY).is_valid());
+    }
+}
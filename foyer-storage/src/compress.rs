@@ -0,0 +1,133 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+/// Values are stored as serialized if they shrink by more than this ratio, so the uncompressed length recorded in an
+/// entry header is bounded by the stored length, even if the checksum of the entry is not verified.
+pub const MAX_COMPRESSION_RATIO: usize = 256;
+
+/// Compression algorithm of entry values, recorded in each entry header.
+///
+/// Algorithms other than [`Compression::None`] are enabled by the cargo features of the same names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Values are stored as serialized.
+    #[default]
+    None,
+    #[cfg(feature = "lz4")]
+    Lz4,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Returns `None` if the value doesn't shrink, or shrinks beyond [`MAX_COMPRESSION_RATIO`], so it is stored as
+    /// serialized.
+    pub fn compress(&self, buf: &[u8]) -> Option<Vec<u8>> {
+        let compressed: Vec<u8> = match self {
+            Compression::None => None,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4::block::compress(buf, None, false).ok(),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::bulk::compress(buf, zstd::DEFAULT_COMPRESSION_LEVEL).ok(),
+        }?;
+        (compressed.len() < buf.len() && buf.len() <= compressed.len() * MAX_COMPRESSION_RATIO)
+            .then_some(compressed)
+    }
+
+    /// Returns `None` if the value fails to decompress to `len` bytes.
+    ///
+    /// `len` is rejected before any allocation if it exceeds [`MAX_COMPRESSION_RATIO`] times the length of `buf`.
+    pub fn decompress(&self, buf: &[u8], len: usize) -> Option<Vec<u8>> {
+        if len > buf.len().saturating_mul(MAX_COMPRESSION_RATIO) {
+            return None;
+        }
+        let decompressed = match self {
+            Compression::None => buf.to_vec(),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4::block::decompress(buf, Some(len.try_into().ok()?)).ok()?,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::bulk::decompress(buf, len).ok()?,
+        };
+        (decompressed.len() == len).then_some(decompressed)
+    }
+
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => 1,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => 2,
+        }
+    }
+
+    /// Returns `None` for unknown algorithms, or algorithms whose features are not enabled.
+    pub(crate) fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Compression::None),
+            #[cfg(feature = "lz4")]
+            1 => Some(Compression::Lz4),
+            #[cfg(feature = "zstd")]
+            2 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        let data = b"hello, foyer".repeat(64);
+        let algorithms = [
+            Compression::None,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd,
+        ];
+        for algorithm in algorithms {
+            assert_eq!(Compression::from_u8(algorithm.to_u8()), Some(algorithm));
+            if algorithm == Compression::None {
+                assert!(algorithm.compress(&data).is_none());
+                continue;
+            }
+            let compressed = algorithm.compress(&data).unwrap();
+            assert!(compressed.len() < data.len());
+            assert_eq!(algorithm.decompress(&compressed, data.len()).unwrap(), data);
+            assert!(algorithm.decompress(&compressed, data.len() + 1).is_none());
+            // Incompressible values are skipped.
+            assert!(algorithm.compress(&[0x42]).is_none());
+            // Values that shrink beyond the ratio are skipped.
+            let zeros = vec![0; 1 << 20];
+            if let Some(compressed) = algorithm.compress(&zeros) {
+                assert!(zeros.len() <= compressed.len() * MAX_COMPRESSION_RATIO);
+                assert_eq!(
+                    algorithm.decompress(&compressed, zeros.len()).unwrap(),
+                    zeros
+                );
+            }
+            // Lengths beyond the ratio are rejected without decompressing.
+            assert!(algorithm
+                .decompress(&compressed, compressed.len() * MAX_COMPRESSION_RATIO + 1)
+                .is_none());
+            assert!(algorithm
+                .decompress(&compressed, u32::MAX as usize)
+                .is_none());
+        }
+        assert_eq!(Compression::from_u8(3), None);
+    }
+}
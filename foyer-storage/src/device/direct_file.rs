@@ -0,0 +1,833 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::{
+    fs::{File, OpenOptions},
+    hash::Hasher,
+    io::{Seek, SeekFrom},
+    os::{
+        fd::{AsRawFd, BorrowedFd},
+        unix::fs::FileTypeExt,
+    },
+    path::PathBuf,
+    sync::Arc,
+};
+
+use bytes::{Buf, BufMut};
+use foyer_common::{bits, range::RangeBoundsExt};
+use parking_lot::Mutex;
+use twox_hash::XxHash64;
+
+use crate::region::RegionId;
+
+use super::{
+    allocator::AlignedAllocator,
+    asyncify,
+    error::{DeviceError, DeviceResult},
+    Device, IoBuf, IoBufMut, IoRange,
+};
+
+#[derive(Debug, Clone)]
+pub struct DirectFileDeviceConfig {
+    /// path of a regular file or a raw block device
+    ///
+    /// A regular file is created and preallocated if it is not large enough.
+    pub path: PathBuf,
+
+    /// must be multipliers of `align` and `region_size`
+    pub capacity: usize,
+
+    /// must be multipliers of `align`
+    pub region_size: usize,
+
+    /// io block alignment, must be pow of 2
+    pub align: usize,
+
+    /// recommended optimized io block size
+    pub io_size: usize,
+
+    /// capacity of each of the two checkpoint slots, must be multipliers of `align`
+    ///
+    /// Checkpoints larger than it fail to persist.
+    pub checkpoint_capacity: usize,
+
+    /// format the device if its pinned geometry mismatches the config, instead of failing to open
+    pub reformat: bool,
+}
+
+impl DirectFileDeviceConfig {
+    pub fn verify(&self) {
+        assert!(self.align.is_power_of_two());
+        assert_eq!(self.region_size % self.align, 0);
+        assert_eq!(self.capacity % self.region_size, 0);
+        assert_eq!(self.checkpoint_capacity % self.align, 0);
+        assert!(self.checkpoint_capacity > 0);
+    }
+
+    /// Offset of the first region, the first block holds the pinned geometry.
+    ///
+    /// The pin is placed at a fixed offset, so it is found whatever the configured capacity is.
+    fn regions_offset(&self) -> u64 {
+        self.align as u64
+    }
+
+    /// Offset of the metadata area after the regions.
+    fn meta_offset(&self) -> u64 {
+        self.regions_offset() + self.capacity as u64
+    }
+
+    /// Size of the metadata area after the regions.
+    ///
+    /// | manifest slot 0 | manifest slot 1 | checkpoint slot 0 | checkpoint slot 1 |
+    fn meta_len(&self) -> usize {
+        self.align * 2 + self.checkpoint_capacity * 2
+    }
+}
+
+const DIRECT_FILE_DEVICE_MAGIC: u64 = 0x19970617;
+const DIRECT_FILE_DEVICE_FORMAT_VERSION: u32 = 1;
+
+const META_SLOT_MAGIC: u64 = 0x19970618;
+
+/// Geometry and format version pinned in the first block of the device.
+#[derive(Debug, PartialEq, Eq)]
+struct DirectFileDeviceManifest {
+    version: u32,
+    capacity: u64,
+    region_size: u64,
+    align: u64,
+    checkpoint_capacity: u64,
+}
+
+impl DirectFileDeviceManifest {
+    fn new(config: &DirectFileDeviceConfig) -> Self {
+        Self {
+            version: DIRECT_FILE_DEVICE_FORMAT_VERSION,
+            capacity: config.capacity as u64,
+            region_size: config.region_size as u64,
+            align: config.align as u64,
+            checkpoint_capacity: config.checkpoint_capacity as u64,
+        }
+    }
+
+    fn serialized_len() -> usize {
+        8 + 4 + 8 + 8 + 8 + 8 + 8
+    }
+
+    /// | magic | version | capacity | region size | align | checkpoint capacity | checksum |
+    fn write(&self, mut buf: &mut [u8]) {
+        buf.put_u64(DIRECT_FILE_DEVICE_MAGIC);
+        buf.put_u32(self.version);
+        buf.put_u64(self.capacity);
+        buf.put_u64(self.region_size);
+        buf.put_u64(self.align);
+        buf.put_u64(self.checkpoint_capacity);
+    }
+
+    /// Returns `None` if the block is never pinned, `Some(None)` if the pinned manifest is corrupted.
+    fn read(buf: &[u8]) -> Option<Option<Self>> {
+        let mut cursor = buf;
+        if cursor.get_u64() != DIRECT_FILE_DEVICE_MAGIC {
+            return None;
+        }
+        let len = Self::serialized_len();
+        let mut footer = &buf[len - 8..len];
+        if checksum(&buf[..len - 8]) != footer.get_u64() {
+            return Some(None);
+        }
+
+        let version = cursor.get_u32();
+        let capacity = cursor.get_u64();
+        let region_size = cursor.get_u64();
+        let align = cursor.get_u64();
+        let checkpoint_capacity = cursor.get_u64();
+
+        Some(Some(Self {
+            version,
+            capacity,
+            region_size,
+            align,
+            checkpoint_capacity,
+        }))
+    }
+}
+
+fn checksum(buf: &[u8]) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(buf);
+    hasher.finish()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Meta {
+    Manifest,
+    Checkpoint,
+}
+
+/// State of the two slots of a metadata, new data is always written to the slot not holding the latest one, so a
+/// torn write never loses the previous data.
+#[derive(Debug, Default, Clone, Copy)]
+struct Slots {
+    sequence: u64,
+    /// Index of the slot holding the latest valid data.
+    latest: Option<usize>,
+}
+
+#[derive(Debug)]
+struct DirectFileDeviceInner {
+    config: DirectFileDeviceConfig,
+
+    file: File,
+
+    /// Whether the file is a raw block device.
+    #[cfg_attr(not(target_os = "linux"), expect(dead_code))]
+    block: bool,
+
+    /// Slots of manifest and checkpoint.
+    slots: Mutex<[Slots; 2]>,
+
+    io_buffer_allocator: AlignedAllocator,
+}
+
+/// A device that maps all regions into a single file or raw block device with direct io.
+///
+/// The geometry is pinned in the first block, region `r` is placed at `align + r * region_size`, metadata is placed
+/// after all regions.
+#[derive(Debug, Clone)]
+pub struct DirectFileDevice {
+    inner: Arc<DirectFileDeviceInner>,
+}
+
+impl Device for DirectFileDevice {
+    type Config = DirectFileDeviceConfig;
+    type IoBufferAllocator = AlignedAllocator;
+
+    async fn open(config: DirectFileDeviceConfig) -> DeviceResult<Self> {
+        Self::open(config).await
+    }
+
+    async fn write<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let region_size = self.inner.config.region_size;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::len(&range).unwrap();
+
+        assert!(
+            offset as usize + len <= region_size,
+            "offset ({offset}) + len ({len}) <= region size ({region_size})"
+        );
+
+        let fd = self.inner.file.as_raw_fd();
+        let offset =
+            self.inner.config.regions_offset() + region as u64 * region_size as u64 + offset;
+
+        asyncify(move || {
+            let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+            let res = nix::sys::uio::pwrite(fd, &buf.as_ref()[range], offset as i64)
+                .map_err(DeviceError::from);
+            (res, buf)
+        })
+        .await
+    }
+
+    async fn read<B>(
+        &self,
+        mut buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBufMut,
+    {
+        let region_size = self.inner.config.region_size;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::len(&range).unwrap();
+
+        assert!(
+            offset as usize + len <= region_size,
+            "offset ({offset}) + len ({len}) <= region size ({region_size})"
+        );
+
+        let fd = self.inner.file.as_raw_fd();
+        let offset =
+            self.inner.config.regions_offset() + region as u64 * region_size as u64 + offset;
+
+        asyncify(move || {
+            let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+            let res = nix::sys::uio::pread(fd, &mut buf.as_mut()[range], offset as i64)
+                .map_err(DeviceError::from);
+            (res, buf)
+        })
+        .await
+    }
+
+    async fn flush(&self) -> DeviceResult<()> {
+        let fd = self.inner.file.as_raw_fd();
+        asyncify(move || nix::unistd::fdatasync(fd).map_err(DeviceError::from)).await
+    }
+
+    /// Issues `BLKDISCARD` on block devices and punches a hole in regular files.
+    #[cfg(target_os = "linux")]
+    async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+        nix::ioctl_write_ptr_bad!(blkdiscard, nix::request_code_none!(0x12, 119), [u64; 2]);
+
+        let region_size = self.inner.config.region_size as u64;
+        let range = [
+            self.inner.config.regions_offset() + region as u64 * region_size,
+            region_size,
+        ];
+        let fd = self.inner.file.as_raw_fd();
+        let block = self.inner.block;
+
+        asyncify(move || {
+            if block {
+                unsafe { blkdiscard(fd, &range) }?;
+                Ok(())
+            } else {
+                super::fs::punch_hole(fd, range[0] as i64, range[1] as i64)
+            }
+        })
+        .await
+    }
+
+    async fn read_manifest(&self) -> DeviceResult<Option<Vec<u8>>> {
+        self.read_meta(Meta::Manifest).await
+    }
+
+    async fn write_manifest(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        self.write_meta(Meta::Manifest, buf).await
+    }
+
+    async fn read_checkpoint(&self) -> DeviceResult<Option<Vec<u8>>> {
+        self.read_meta(Meta::Checkpoint).await
+    }
+
+    async fn write_checkpoint(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        self.write_meta(Meta::Checkpoint, buf).await
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.config.capacity
+    }
+
+    fn regions(&self) -> usize {
+        self.inner.config.capacity / self.inner.config.region_size
+    }
+
+    fn align(&self) -> usize {
+        self.inner.config.align
+    }
+
+    fn io_size(&self) -> usize {
+        self.inner.config.io_size
+    }
+
+    fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+        &self.inner.io_buffer_allocator
+    }
+
+    fn io_buffer(&self, len: usize, capacity: usize) -> Vec<u8, Self::IoBufferAllocator> {
+        assert!(len <= capacity);
+        let mut buf = Vec::with_capacity_in(capacity, self.inner.io_buffer_allocator);
+        unsafe { buf.set_len(len) };
+        buf
+    }
+}
+
+impl DirectFileDevice {
+    pub async fn open(config: DirectFileDeviceConfig) -> DeviceResult<Self> {
+        config.verify();
+
+        let io_buffer_allocator = AlignedAllocator::new(config.align);
+
+        let c = config.clone();
+        let (file, slots) = asyncify(move || {
+            let file = Self::open_file(&c)?;
+            Self::pin(&c, &file, io_buffer_allocator)?;
+            let slots = [Meta::Manifest, Meta::Checkpoint]
+                .map(|meta| Self::load_slots(&c, &file, io_buffer_allocator, meta));
+            let [manifest, checkpoint] = slots;
+            Ok::<_, DeviceError>((file, [manifest?, checkpoint?]))
+        })
+        .await?;
+
+        let block = file.metadata()?.file_type().is_block_device();
+
+        let inner = DirectFileDeviceInner {
+            config,
+            file,
+            block,
+            slots: Mutex::new(slots),
+            io_buffer_allocator,
+        };
+
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    fn open_file(config: &DirectFileDeviceConfig) -> DeviceResult<File> {
+        #[cfg(target_os = "linux")]
+        use std::os::unix::prelude::OpenOptionsExt;
+
+        let len = config.meta_offset() + config.meta_len() as u64;
+
+        let mut opts = OpenOptions::new();
+        opts.create(true);
+        opts.write(true);
+        opts.read(true);
+        #[cfg(target_os = "linux")]
+        opts.custom_flags(libc::O_DIRECT);
+
+        let mut file = opts.open(&config.path)?;
+
+        if file.metadata()?.file_type().is_block_device() {
+            let size = file.seek(SeekFrom::End(0))?;
+            if size < len {
+                return Err(format!(
+                    "block device {:?} is too small, size: {}, required: {}",
+                    config.path, size, len
+                )
+                .into());
+            }
+        } else if file.metadata()?.len() < len {
+            #[cfg(target_os = "linux")]
+            nix::fcntl::fallocate(
+                file.as_raw_fd(),
+                nix::fcntl::FallocateFlags::empty(),
+                0,
+                len as i64,
+            )?;
+            #[cfg(not(target_os = "linux"))]
+            file.set_len(len)?;
+        }
+
+        Ok(file)
+    }
+
+    /// Validate the geometry pinned on the device, or format the device if it is not pinned yet.
+    ///
+    /// Formatting invalidates all region headers and metadata slots, so data left by a previous user of the device
+    /// is never recovered.
+    fn pin(
+        config: &DirectFileDeviceConfig,
+        file: &File,
+        allocator: AlignedAllocator,
+    ) -> DeviceResult<()> {
+        let align = config.align;
+        let base = config.meta_offset();
+        let manifest = DirectFileDeviceManifest::new(config);
+
+        let mut buf = Vec::with_capacity_in(align, allocator);
+        buf.resize(align, 0);
+        read_exact_at(file, &mut buf, 0)?;
+
+        match DirectFileDeviceManifest::read(&buf) {
+            Some(Some(pinned)) if pinned == manifest => return Ok(()),
+            Some(pinned) if !config.reformat => {
+                return Err(format!(
+                    "direct file device config mismatches the data of {:?}, pinned: {:?}, given: {:?}; \
+                     set `reformat` to format the device",
+                    config.path, pinned, manifest
+                )
+                .into())
+            }
+            Some(_) => tracing::warn!("reformat direct file device {:?}", config.path),
+            None => tracing::info!("format direct file device {:?}", config.path),
+        }
+
+        buf.fill(0);
+        for region in 0..config.capacity / config.region_size {
+            write_all_at(
+                file,
+                &buf,
+                config.regions_offset() + (region * config.region_size) as u64,
+            )?;
+        }
+        for offset in (0..config.meta_len())
+            .step_by(align)
+            .filter(|offset| Self::is_slot_start(config, *offset))
+        {
+            write_all_at(file, &buf, base + offset as u64)?;
+        }
+        file.sync_data()?;
+
+        manifest.write(&mut buf);
+        let len = DirectFileDeviceManifest::serialized_len();
+        let checksum = checksum(&buf[..len - 8]);
+        (&mut buf[len - 8..len]).put_u64(checksum);
+        write_all_at(file, &buf, 0)?;
+        file.sync_data()?;
+
+        Ok(())
+    }
+
+    fn is_slot_start(config: &DirectFileDeviceConfig, offset: usize) -> bool {
+        [Meta::Manifest, Meta::Checkpoint]
+            .into_iter()
+            .flat_map(|meta| [0, 1].map(|slot| Self::slot_offset(config, meta, slot)))
+            .any(|o| o == offset)
+    }
+
+    /// Offset of the slot in the metadata area, and its capacity.
+    fn slot(config: &DirectFileDeviceConfig, meta: Meta, slot: usize) -> (u64, usize) {
+        let offset = Self::slot_offset(config, meta, slot);
+        let capacity = match meta {
+            Meta::Manifest => config.align,
+            Meta::Checkpoint => config.checkpoint_capacity,
+        };
+        (config.meta_offset() + offset as u64, capacity)
+    }
+
+    fn slot_offset(config: &DirectFileDeviceConfig, meta: Meta, slot: usize) -> usize {
+        match meta {
+            Meta::Manifest => config.align * slot,
+            Meta::Checkpoint => config.align * 2 + config.checkpoint_capacity * slot,
+        }
+    }
+
+    fn load_slots(
+        config: &DirectFileDeviceConfig,
+        file: &File,
+        allocator: AlignedAllocator,
+        meta: Meta,
+    ) -> DeviceResult<Slots> {
+        let mut slots = Slots::default();
+        for slot in 0..2 {
+            if let Some((sequence, _)) = Self::read_slot(config, file, allocator, meta, slot)?
+                && (slots.latest.is_none() || sequence > slots.sequence)
+            {
+                slots = Slots {
+                    sequence,
+                    latest: Some(slot),
+                };
+            }
+        }
+        Ok(slots)
+    }
+
+    /// | magic | sequence | len | data | checksum | <padding> |
+    ///
+    /// Returns `None` if the slot is empty or corrupted.
+    fn read_slot(
+        config: &DirectFileDeviceConfig,
+        file: &File,
+        allocator: AlignedAllocator,
+        meta: Meta,
+        slot: usize,
+    ) -> DeviceResult<Option<(u64, Vec<u8>)>> {
+        let align = config.align;
+        let (offset, capacity) = Self::slot(config, meta, slot);
+
+        let mut buf = Vec::with_capacity_in(capacity, allocator);
+        buf.resize(align, 0);
+        read_exact_at(file, &mut buf, offset)?;
+
+        let mut cursor = &buf[..];
+        if cursor.get_u64() != META_SLOT_MAGIC {
+            return Ok(None);
+        }
+        let sequence = cursor.get_u64();
+        let len = cursor.get_u64() as usize;
+        if len > capacity - 8 * 4 {
+            return Ok(None);
+        }
+
+        let total = 8 * 3 + len + 8;
+        buf.resize(bits::align_up(align, total), 0);
+        read_exact_at(file, &mut buf, offset)?;
+
+        let mut footer = &buf[total - 8..total];
+        if checksum(&buf[..total - 8]) != footer.get_u64() {
+            return Ok(None);
+        }
+
+        Ok(Some((sequence, buf[8 * 3..8 * 3 + len].to_vec())))
+    }
+
+    async fn read_meta(&self, meta: Meta) -> DeviceResult<Option<Vec<u8>>> {
+        let inner = self.inner.clone();
+        asyncify(move || {
+            let slots = inner.slots.lock();
+            let Some(slot) = slots[meta as usize].latest else {
+                return Ok(None);
+            };
+            let data = Self::read_slot(
+                &inner.config,
+                &inner.file,
+                inner.io_buffer_allocator,
+                meta,
+                slot,
+            )?;
+            Ok(data.map(|(_, data)| data))
+        })
+        .await
+    }
+
+    async fn write_meta(&self, meta: Meta, data: Vec<u8>) -> DeviceResult<()> {
+        let inner = self.inner.clone();
+        asyncify(move || {
+            let mut slots = inner.slots.lock();
+            let current = slots[meta as usize];
+            let slot = current.latest.map(|slot| 1 - slot).unwrap_or(0);
+            let sequence = current.sequence + 1;
+            let (offset, capacity) = Self::slot(&inner.config, meta, slot);
+
+            let total = 8 * 3 + data.len() + 8;
+            if total > capacity {
+                return Err(format!(
+                    "{:?} of {} bytes exceeds the slot capacity {} of direct file device",
+                    meta,
+                    data.len(),
+                    capacity
+                )
+                .into());
+            }
+
+            let mut encoded = Vec::with_capacity(total);
+            encoded.put_u64(META_SLOT_MAGIC);
+            encoded.put_u64(sequence);
+            encoded.put_u64(data.len() as u64);
+            encoded.put_slice(&data);
+            let checksum = checksum(&encoded);
+            encoded.put_u64(checksum);
+
+            let mut buf = Vec::with_capacity_in(capacity, inner.io_buffer_allocator);
+            buf.extend_from_slice(&encoded);
+            buf.resize(bits::align_up(inner.config.align, total), 0);
+
+            write_all_at(&inner.file, &buf, offset)?;
+            inner.file.sync_data()?;
+
+            slots[meta as usize] = Slots {
+                sequence,
+                latest: Some(slot),
+            };
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> DeviceResult<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buf, offset)?;
+    Ok(())
+}
+
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> DeviceResult<()> {
+    use std::os::unix::fs::FileExt;
+    file.write_all_at(buf, offset)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BufMut;
+
+    use super::*;
+
+    const REGIONS: usize = 8;
+    const REGION_SIZE: usize = 8 * 1024; // 8 KiB
+    const CAPACITY: usize = REGIONS * REGION_SIZE; // 64 KiB
+    const ALIGN: usize = 4 * 1024;
+
+    fn config(path: PathBuf) -> DirectFileDeviceConfig {
+        DirectFileDeviceConfig {
+            path,
+            capacity: CAPACITY,
+            region_size: REGION_SIZE,
+            align: ALIGN,
+            io_size: ALIGN,
+            checkpoint_capacity: 2 * ALIGN,
+            reformat: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_direct_file_device_simple() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foyer-device");
+        let dev = DirectFileDevice::open(config(path.clone())).await.unwrap();
+        assert_eq!(dev.regions(), REGIONS);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len() as usize,
+            ALIGN + CAPACITY + 2 * ALIGN + 2 * 2 * ALIGN
+        );
+
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+        let mut rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut rbuffer[..]).put_slice(&[0; ALIGN]);
+
+        let (res, wbuffer) = dev.write(wbuffer, .., 3, ALIGN as u64).await;
+        res.unwrap();
+        let (res, rbuffer) = dev.read(rbuffer, .., 3, ALIGN as u64).await;
+        res.unwrap();
+        assert_eq!(&wbuffer, &rbuffer);
+
+        // Regions are addressed by `align + region * region_size + offset`.
+        let raw = std::fs::read(&path).unwrap();
+        let start = ALIGN + 3 * REGION_SIZE + ALIGN;
+        assert_eq!(&raw[start..start + ALIGN], &wbuffer[..]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_direct_file_device_discard() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foyer-device");
+        let dev = DirectFileDevice::open(config(path.clone())).await.unwrap();
+        let len = std::fs::metadata(&path).unwrap().len();
+
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+        for region in [2, 3] {
+            let (res, b) = dev.write(wbuffer, .., region, 0).await;
+            res.unwrap();
+            wbuffer = b;
+        }
+
+        // Only the discarded region reads as zeros, the file keeps its size.
+        dev.discard(3).await.unwrap();
+        let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        let (res, rbuffer) = dev.read(rbuffer, .., 3, 0).await;
+        res.unwrap();
+        assert_eq!(&rbuffer[..], &[0; ALIGN]);
+        let (res, rbuffer) = dev.read(rbuffer, .., 2, 0).await;
+        res.unwrap();
+        assert_eq!(&rbuffer, &wbuffer);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+    }
+
+    #[tokio::test]
+    async fn test_direct_file_device_meta() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foyer-device");
+        let config = config(path.clone());
+
+        let dev = DirectFileDevice::open(config.clone()).await.unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), None);
+        assert_eq!(dev.read_checkpoint().await.unwrap(), None);
+
+        dev.write_manifest(vec![1; 16]).await.unwrap();
+        dev.write_manifest(vec![2; 32]).await.unwrap();
+        dev.write_checkpoint(vec![3; 3 * ALIGN / 2]).await.unwrap();
+        assert!(dev.write_checkpoint(vec![4; 2 * ALIGN]).await.is_err());
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![2; 32]));
+        drop(dev);
+
+        let dev = DirectFileDevice::open(config.clone()).await.unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![2; 32]));
+        assert_eq!(
+            dev.read_checkpoint().await.unwrap(),
+            Some(vec![3; 3 * ALIGN / 2])
+        );
+        drop(dev);
+
+        // A torn write of the latest slot falls back to the previous data.
+        let (offset, _) = DirectFileDevice::slot(&config, Meta::Manifest, 1);
+        let mut raw = std::fs::read(&path).unwrap();
+        raw[offset as usize + 8 * 3] ^= 0xff;
+        std::fs::write(&path, raw).unwrap();
+
+        let dev = DirectFileDevice::open(config).await.unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![1; 16]));
+        dev.write_manifest(vec![5; 8]).await.unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![5; 8]));
+    }
+
+    #[tokio::test]
+    async fn test_direct_file_device_pin() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path().join("foyer-device"));
+
+        let dev = DirectFileDevice::open(config.clone()).await.unwrap();
+        dev.write_manifest(vec![b'x'; 16]).await.unwrap();
+        drop(dev);
+
+        // `io_size` is not pinned.
+        let dev = DirectFileDevice::open(DirectFileDeviceConfig {
+            io_size: 2 * ALIGN,
+            ..config.clone()
+        })
+        .await
+        .unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![b'x'; 16]));
+        drop(dev);
+
+        let mismatch = DirectFileDeviceConfig {
+            region_size: REGION_SIZE / 2,
+            ..config.clone()
+        };
+        assert!(DirectFileDevice::open(mismatch.clone()).await.is_err());
+
+        let dev = DirectFileDevice::open(DirectFileDeviceConfig {
+            reformat: true,
+            ..mismatch.clone()
+        })
+        .await
+        .unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), None);
+        assert_eq!(dev.regions(), 2 * REGIONS);
+        drop(dev);
+
+        assert!(DirectFileDevice::open(mismatch).await.is_ok());
+        assert!(DirectFileDevice::open(config.clone()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_direct_file_device_pin_capacity() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path().join("foyer-device"));
+
+        let dev = DirectFileDevice::open(config.clone()).await.unwrap();
+        dev.write_manifest(vec![b'x'; 16]).await.unwrap();
+        drop(dev);
+
+        // The pin is found whatever the capacity is, so a changed capacity is a mismatch instead of a new device.
+        for capacity in [CAPACITY / 2, CAPACITY * 2] {
+            let mismatch = DirectFileDeviceConfig {
+                capacity,
+                ..config.clone()
+            };
+            assert!(DirectFileDevice::open(mismatch).await.is_err());
+        }
+
+        let dev = DirectFileDevice::open(config.clone()).await.unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![b'x'; 16]));
+        drop(dev);
+
+        let dev = DirectFileDevice::open(DirectFileDeviceConfig {
+            capacity: CAPACITY * 2,
+            reformat: true,
+            ..config
+        })
+        .await
+        .unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), None);
+        assert_eq!(dev.regions(), 2 * REGIONS);
+    }
+}
@@ -34,21 +34,27 @@ pub enum DeviceErrorKind {
     Other(#[from] Box<dyn std::error::Error + Send + Sync + 'static>),
 }
 
+impl From<DeviceErrorKind> for DeviceError {
+    fn from(value: DeviceErrorKind) -> Self {
+        Self(Box::new(DeviceErrorInner { source: value }))
+    }
+}
+
 impl From<std::io::Error> for DeviceError {
     fn from(value: std::io::Error) -> Self {
-        value.into()
+        DeviceErrorKind::from(value).into()
     }
 }
 
 impl From<nix::errno::Errno> for DeviceError {
     fn from(value: nix::errno::Errno) -> Self {
-        value.into()
+        DeviceErrorKind::from(value).into()
     }
 }
 
 impl From<String> for DeviceError {
     fn from(value: String) -> Self {
-        value.into()
+        DeviceErrorKind::Other(value.into()).into()
     }
 }
 
@@ -65,4 +71,10 @@ mod tests {
             std::mem::size_of::<usize>()
         );
     }
+
+    #[test]
+    fn test_error_from() {
+        let e: DeviceError = "some error".to_string().into();
+        assert_eq!(e.to_string(), "other error: some error");
+    }
 }
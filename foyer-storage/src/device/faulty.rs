@@ -0,0 +1,354 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::sync::Arc;
+
+use foyer_common::{bits, range::RangeBoundsExt};
+use parking_lot::Mutex;
+
+use crate::region::RegionId;
+
+use super::{error::DeviceResult, Device, IoBuf, IoBufMut, IoRange};
+
+/// A fault to be injected into the next matching io of a [`FaultyDevice`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The next write returns an error without writing anything.
+    WriteError,
+    /// The next read returns an error without reading anything.
+    ReadError,
+    /// The next write only writes the first half (aligned down) of the range, and returns the written length.
+    ShortWrite,
+    /// The next read only reads the first half (aligned down) of the range, and returns the read length.
+    ShortRead,
+    /// The next write that covers `offset` of a region only persists the bytes before `offset` (aligned down),
+    /// but reports success.
+    TornWrite { offset: u64 },
+}
+
+#[derive(Debug)]
+struct Undo {
+    region: RegionId,
+    offset: u64,
+    buf: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct FaultInjectorInner {
+    faults: Vec<Fault>,
+
+    /// Previous data of ranges written since the last flush, in write order.
+    undo: Vec<Undo>,
+
+    /// Increased by each crash, devices opened before are dead since then.
+    generation: u64,
+    crashed: bool,
+}
+
+/// Shared fault controller of [`FaultyDevice`]s opened with it.
+///
+/// The controller outlives the devices, so a crash can be simulated by [`FaultInjector::crash`] and then reopening
+/// the device with the same controller.
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjector {
+    inner: Arc<Mutex<FaultInjectorInner>>,
+}
+
+impl FaultInjector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inject a fault into the next matching io. Faults are matched in the injected order.
+    pub fn inject(&self, fault: Fault) {
+        self.inner.lock().faults.push(fault);
+    }
+
+    /// Returns the count of injected faults that are not triggered yet.
+    pub fn pending(&self) -> usize {
+        self.inner.lock().faults.len()
+    }
+
+    /// Simulate a crash.
+    ///
+    /// Devices opened before never persist anything since then, and all writes that are not flushed by
+    /// [`Device::flush`] are dropped when a device is reopened with the controller.
+    pub fn crash(&self) {
+        let mut inner = self.inner.lock();
+        inner.generation += 1;
+        inner.crashed = true;
+    }
+
+    fn take(&self, f: impl Fn(&Fault) -> bool) -> Option<Fault> {
+        let mut inner = self.inner.lock();
+        let index = inner.faults.iter().position(f)?;
+        Some(inner.faults.remove(index))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FaultyDeviceConfig<C> {
+    /// Config of the underlying device.
+    pub device: C,
+
+    pub injector: FaultInjector,
+}
+
+/// A [`Device`] decorator that injects io faults and simulates crashes, for testing.
+#[derive(Debug, Clone)]
+pub struct FaultyDevice<D>
+where
+    D: Device,
+{
+    device: D,
+    injector: FaultInjector,
+    generation: u64,
+}
+
+impl<D> FaultyDevice<D>
+where
+    D: Device,
+{
+    fn is_dead(&self) -> bool {
+        self.injector.inner.lock().generation != self.generation
+    }
+
+    /// Read the data that is going to be overwritten, unwritten bytes are read as zeros.
+    async fn backup(&self, len: usize, region: RegionId, offset: u64) -> DeviceResult<Vec<u8>> {
+        let buf = self.device.io_buffer(len, len);
+        let (res, buf) = self.device.read(buf, .., region, offset).await;
+        let read = res?;
+        let mut backup = vec![0; len];
+        backup[..read].copy_from_slice(&buf[..read]);
+        Ok(backup)
+    }
+}
+
+impl<D> Device for FaultyDevice<D>
+where
+    D: Device,
+{
+    type Config = FaultyDeviceConfig<D::Config>;
+    type IoBufferAllocator = D::IoBufferAllocator;
+
+    async fn open(config: Self::Config) -> DeviceResult<Self> {
+        let device = D::open(config.device).await?;
+
+        let (undo, generation) = {
+            let mut inner = config.injector.inner.lock();
+            let undo = std::mem::take(&mut inner.undo);
+            let crashed = std::mem::take(&mut inner.crashed);
+            (if crashed { undo } else { vec![] }, inner.generation)
+        };
+
+        // Drop writes that are not flushed before the crash.
+        for undo in undo.into_iter().rev() {
+            let mut buf = device.io_buffer(undo.buf.len(), undo.buf.len());
+            buf.copy_from_slice(&undo.buf);
+            let (res, _) = device.write(buf, .., undo.region, undo.offset).await;
+            res?;
+        }
+
+        Ok(Self {
+            device,
+            injector: config.injector,
+            generation,
+        })
+    }
+
+    async fn write<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::len(&range).unwrap();
+        let align = self.align();
+
+        if self.is_dead() {
+            return (Ok(len), buf);
+        }
+
+        let fault = self.injector.take(|fault| match fault {
+            Fault::WriteError | Fault::ShortWrite => true,
+            Fault::TornWrite { offset: o } => *o >= offset && *o < offset + len as u64,
+            _ => false,
+        });
+
+        let (persisted, reported) = match fault {
+            Some(Fault::WriteError) => {
+                return (Err("injected write error".to_string().into()), buf);
+            }
+            Some(Fault::ShortWrite) => {
+                let persisted = bits::align_down(align, len / 2);
+                (persisted, persisted)
+            }
+            Some(Fault::TornWrite { offset: o }) => {
+                (bits::align_down(align, (o - offset) as usize), len)
+            }
+            _ => (len, len),
+        };
+
+        let backup = match self.backup(len, region, offset).await {
+            Ok(backup) => backup,
+            Err(e) => return (Err(e), buf),
+        };
+        {
+            let mut inner = self.injector.inner.lock();
+            if inner.generation != self.generation {
+                return (Ok(len), buf);
+            }
+            inner.undo.push(Undo {
+                region,
+                offset,
+                buf: backup,
+            });
+        }
+
+        if persisted == 0 {
+            return (Ok(reported), buf);
+        }
+
+        let start = range.start;
+        let (res, buf) = self
+            .device
+            .write(buf, start..start + persisted, region, offset)
+            .await;
+        (res.map(|_| reported), buf)
+    }
+
+    async fn read<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBufMut,
+    {
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::len(&range).unwrap();
+
+        if self.is_dead() {
+            return (Err("device is crashed".to_string().into()), buf);
+        }
+
+        let fault = self
+            .injector
+            .take(|fault| matches!(fault, Fault::ReadError | Fault::ShortRead));
+
+        let len = match fault {
+            Some(Fault::ReadError) => {
+                return (Err("injected read error".to_string().into()), buf);
+            }
+            Some(Fault::ShortRead) => bits::align_down(self.align(), len / 2),
+            _ => len,
+        };
+
+        let start = range.start;
+        self.device
+            .read(buf, start..start + len, region, offset)
+            .await
+    }
+
+    async fn flush(&self) -> DeviceResult<()> {
+        if self.is_dead() {
+            return Ok(());
+        }
+        self.device.flush().await?;
+
+        let mut inner = self.injector.inner.lock();
+        if inner.generation == self.generation {
+            inner.undo.clear();
+        }
+        Ok(())
+    }
+
+    async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+        if self.is_dead() {
+            return Ok(());
+        }
+        self.device.discard(region).await
+    }
+
+    async fn read_manifest(&self) -> DeviceResult<Option<Vec<u8>>> {
+        if self.is_dead() {
+            return Err("device is crashed".to_string().into());
+        }
+        self.device.read_manifest().await
+    }
+
+    async fn write_manifest(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        if self.is_dead() {
+            return Ok(());
+        }
+        self.device.write_manifest(buf).await
+    }
+
+    async fn read_checkpoint(&self) -> DeviceResult<Option<Vec<u8>>> {
+        if self.is_dead() {
+            return Err("device is crashed".to_string().into());
+        }
+        self.device.read_checkpoint().await
+    }
+
+    async fn write_checkpoint(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        if self.is_dead() {
+            return Ok(());
+        }
+        self.device.write_checkpoint(buf).await
+    }
+
+    fn capacity(&self) -> usize {
+        self.device.capacity()
+    }
+
+    fn regions(&self) -> usize {
+        self.device.regions()
+    }
+
+    fn align(&self) -> usize {
+        self.device.align()
+    }
+
+    fn io_size(&self) -> usize {
+        self.device.io_size()
+    }
+
+    fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+        self.device.io_buffer_allocator()
+    }
+
+    fn io_buffer(&self, len: usize, capacity: usize) -> Vec<u8, Self::IoBufferAllocator> {
+        self.device.io_buffer(len, capacity)
+    }
+
+    fn is_usable(&self, region: RegionId) -> bool {
+        self.device.is_usable(region)
+    }
+
+    fn tiers(&self) -> usize {
+        self.device.tiers()
+    }
+
+    fn tier(&self, region: RegionId) -> usize {
+        self.device.tier(region)
+    }
+}
@@ -13,9 +13,11 @@
 //  limitations under the License.
 
 use std::{
-    fs::{create_dir_all, File, OpenOptions},
+    fs::{create_dir_all, read_dir, remove_file, rename, File, OpenOptions},
+    hash::Hasher,
+    io::Write,
     os::fd::{AsRawFd, BorrowedFd, RawFd},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
@@ -27,9 +29,11 @@ use super::{
     error::{DeviceError, DeviceResult},
     Device, IoBuf, IoBufMut, IoRange,
 };
+use bytes::{Buf, BufMut};
 use foyer_common::range::RangeBoundsExt;
 use futures::future::try_join_all;
 use itertools::Itertools;
+use twox_hash::XxHash64;
 
 #[derive(Debug, Clone)]
 pub struct FsDeviceConfig {
@@ -47,6 +51,15 @@ pub struct FsDeviceConfig {
 
     /// recommended optimized io block size
     pub io_size: usize,
+
+    /// remove all data under `dir` if its pinned geometry mismatches the config, instead of failing to open
+    pub reformat: bool,
+
+    /// allocate the blocks of every region file to `file_capacity` on open, so files are not grown on the write
+    /// path and running out of space is reported on open
+    ///
+    /// preallocated regions are never discarded, their blocks stay allocated and keep their data until overwritten
+    pub preallocate: bool,
 }
 
 impl FsDeviceConfig {
@@ -57,6 +70,87 @@ impl FsDeviceConfig {
     }
 }
 
+const FS_DEVICE_MAGIC: u64 = 0x19970616;
+const FS_DEVICE_FORMAT_VERSION: u32 = 1;
+
+/// Geometry and format version of the data under the device dir, pinned on the first open.
+#[derive(Debug, PartialEq, Eq)]
+struct FsDeviceManifest {
+    version: u32,
+    capacity: u64,
+    file_capacity: u64,
+    align: u64,
+}
+
+impl FsDeviceManifest {
+    fn new(config: &FsDeviceConfig) -> Self {
+        Self {
+            version: FS_DEVICE_FORMAT_VERSION,
+            capacity: config.capacity as u64,
+            file_capacity: config.file_capacity as u64,
+            align: config.align as u64,
+        }
+    }
+
+    /// | magic | version | capacity | file capacity | align | checksum |
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.put_u64(FS_DEVICE_MAGIC);
+        buf.put_u32(self.version);
+        buf.put_u64(self.capacity);
+        buf.put_u64(self.file_capacity);
+        buf.put_u64(self.align);
+        let checksum = checksum(buf);
+        buf.put_u64(checksum);
+    }
+
+    /// Returns `None` if the manifest is corrupted.
+    fn read(buf: &[u8]) -> Option<Self> {
+        if buf.len() != 8 + 4 + 8 + 8 + 8 + 8 {
+            return None;
+        }
+        let (data, mut footer) = buf.split_at(buf.len() - 8);
+        if checksum(data) != footer.get_u64() {
+            return None;
+        }
+
+        let mut buf = data;
+        if buf.get_u64() != FS_DEVICE_MAGIC {
+            return None;
+        }
+        let version = buf.get_u32();
+        let capacity = buf.get_u64();
+        let file_capacity = buf.get_u64();
+        let align = buf.get_u64();
+
+        Some(Self {
+            version,
+            capacity,
+            file_capacity,
+            align,
+        })
+    }
+}
+
+fn checksum(buf: &[u8]) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(buf);
+    hasher.finish()
+}
+
+/// Deallocate `[offset, offset + len)` of the file, the range reads as zeros afterwards.
+#[cfg(target_os = "linux")]
+pub(crate) fn punch_hole(fd: RawFd, offset: i64, len: i64) -> DeviceResult<()> {
+    use nix::fcntl::FallocateFlags;
+
+    nix::fcntl::fallocate(
+        fd,
+        FallocateFlags::FALLOC_FL_PUNCH_HOLE | FallocateFlags::FALLOC_FL_KEEP_SIZE,
+        offset,
+        len,
+    )
+    .map_err(DeviceError::from)
+}
+
 #[derive(Debug)]
 struct FsDeviceInner {
     config: FsDeviceConfig,
@@ -163,6 +257,33 @@ impl Device for FsDevice {
         Ok(())
     }
 
+    /// Punches a hole in the region file, unless the region files are preallocated.
+    #[cfg(target_os = "linux")]
+    async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+        if self.inner.config.preallocate {
+            return Ok(());
+        }
+        let fd = self.fd(region);
+        let len = self.inner.config.file_capacity as i64;
+        asyncify(move || punch_hole(fd, 0, len)).await
+    }
+
+    async fn read_manifest(&self) -> DeviceResult<Option<Vec<u8>>> {
+        self.read_meta(Self::MANIFEST_FILENAME).await
+    }
+
+    async fn write_manifest(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        self.write_meta(Self::MANIFEST_FILENAME, buf).await
+    }
+
+    async fn read_checkpoint(&self) -> DeviceResult<Option<Vec<u8>>> {
+        self.read_meta(Self::CHECKPOINT_FILENAME).await
+    }
+
+    async fn write_checkpoint(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        self.write_meta(Self::CHECKPOINT_FILENAME, buf).await
+    }
+
     fn capacity(&self) -> usize {
         self.inner.config.capacity
     }
@@ -192,11 +313,13 @@ impl Device for FsDevice {
 }
 
 impl FsDevice {
+    const DEVICE_FILENAME: &'static str = "foyer-device";
+    const MANIFEST_FILENAME: &'static str = "foyer-manifest";
+    const CHECKPOINT_FILENAME: &'static str = "foyer-checkpoint";
+
     pub async fn open(config: FsDeviceConfig) -> DeviceResult<Self> {
         config.verify();
 
-        // TODO(MrCroxx): write and read config to a manifest file for pinning
-
         let regions = config.capacity / config.file_capacity;
 
         let path = config.dir.clone();
@@ -206,6 +329,9 @@ impl FsDevice {
         })
         .await?;
 
+        Self::pin(&config).await?;
+
+        let (preallocate, file_capacity) = (config.preallocate, config.file_capacity);
         let futures = (0..regions)
             .map(|i| {
                 let path = config.dir.clone().join(Self::filename(i as RegionId));
@@ -220,9 +346,23 @@ impl FsDevice {
                     #[cfg(target_os = "linux")]
                     opts.custom_flags(libc::O_DIRECT);
 
-                    let file = opts.open(path)?;
-
-                    Ok::<_, DeviceError>(file)
+                    let file = opts.open(&path)?;
+
+                    if preallocate {
+                        let len = file_capacity as u64;
+                        asyncify(move || {
+                            Self::preallocate(&file, len).map_err(|e| {
+                                DeviceError::from(format!(
+                                    "fail to preallocate region file {:?}: {}",
+                                    path, e
+                                ))
+                            })?;
+                            Ok::<_, DeviceError>(file)
+                        })
+                        .await
+                    } else {
+                        Ok::<_, DeviceError>(file)
+                    }
                 }
             })
             .collect_vec();
@@ -242,10 +382,112 @@ impl FsDevice {
         })
     }
 
-    fn fd(&self, region: RegionId) -> RawFd {
+    /// Allocate the blocks of `[0, len)` of the file, fails with `ENOSPC` if there is not enough space.
+    fn preallocate(file: &File, len: u64) -> DeviceResult<()> {
+        #[cfg(target_os = "linux")]
+        nix::fcntl::fallocate(
+            file.as_raw_fd(),
+            nix::fcntl::FallocateFlags::empty(),
+            0,
+            len as i64,
+        )?;
+        #[cfg(not(target_os = "linux"))]
+        if file.metadata()?.len() < len {
+            file.set_len(len)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn fd(&self, region: RegionId) -> RawFd {
         self.inner.files[region as usize].as_raw_fd()
     }
 
+    async fn read_meta(&self, filename: &'static str) -> DeviceResult<Option<Vec<u8>>> {
+        let path = self.inner.config.dir.join(filename);
+        asyncify(move || match std::fs::read(path) {
+            Ok(buf) => Ok(Some(buf)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        })
+        .await
+    }
+
+    async fn write_meta(&self, filename: &'static str, buf: Vec<u8>) -> DeviceResult<()> {
+        let dir = self.inner.config.dir.clone();
+        asyncify(move || Ok(Self::write_file(&dir, filename, &buf)?)).await
+    }
+
+    /// Validate the geometry pinned under the device dir, or pin it if the dir is not pinned yet.
+    async fn pin(config: &FsDeviceConfig) -> DeviceResult<()> {
+        let dir = config.dir.clone();
+        let manifest = FsDeviceManifest::new(config);
+        let reformat = config.reformat;
+
+        asyncify(move || {
+            let path = dir.join(Self::DEVICE_FILENAME);
+            let pinned = match std::fs::read(&path) {
+                Ok(buf) => Some(FsDeviceManifest::read(&buf)),
+                // Region files without a pin are left by an unknown config, they are treated as a corrupted pin.
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    Self::has_region_files(&dir)?.then_some(None)
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            match pinned {
+                Some(Some(pinned)) if pinned == manifest => return Ok(()),
+                Some(pinned) if !reformat => {
+                    return Err(format!(
+                    "fs device config mismatches the data under {:?}, pinned: {:?}, given: {:?}; \
+                         set `reformat` to remove the data",
+                    dir, pinned, manifest
+                )
+                    .into())
+                }
+                Some(_) => {
+                    tracing::warn!("reformat fs device under {:?}", dir);
+                    for entry in read_dir(&dir)? {
+                        let entry = entry?;
+                        if entry.file_name().to_string_lossy().starts_with("foyer-") {
+                            remove_file(entry.path())?;
+                        }
+                    }
+                }
+                None => {}
+            }
+
+            let mut buf = vec![];
+            manifest.write(&mut buf);
+            Self::write_file(&dir, Self::DEVICE_FILENAME, &buf)?;
+            Ok(())
+        })
+        .await
+    }
+
+    fn has_region_files(dir: &Path) -> std::io::Result<bool> {
+        for entry in read_dir(dir)? {
+            if entry?
+                .file_name()
+                .to_string_lossy()
+                .starts_with("foyer-cache-")
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Write to a temporary file and rename it, so a crash never leaves a partial file.
+    fn write_file(dir: &Path, filename: &str, buf: &[u8]) -> std::io::Result<()> {
+        let tmp = dir.join(format!("{}.tmp", filename));
+        let mut file = File::create(&tmp)?;
+        file.write_all(buf)?;
+        file.sync_all()?;
+        rename(&tmp, dir.join(filename))?;
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
     fn filename(region: RegionId) -> String {
         format!("foyer-cache-{:08}", region)
     }
@@ -272,6 +514,8 @@ mod tests {
             file_capacity: FILE_CAPACITY,
             align: ALIGN,
             io_size: ALIGN,
+            reformat: false,
+            preallocate: false,
         };
         let dev = FsDevice::open(config).await.unwrap();
 
@@ -290,4 +534,156 @@ mod tests {
         drop(wbuffer);
         drop(rbuffer);
     }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_fs_device_preallocate() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = FsDeviceConfig {
+            dir: PathBuf::from(dir.path()),
+            capacity: CAPACITY,
+            file_capacity: FILE_CAPACITY,
+            align: ALIGN,
+            io_size: ALIGN,
+            reformat: false,
+            preallocate: true,
+        };
+        FsDevice::open(config.clone()).await.unwrap();
+        for region in 0..(CAPACITY / FILE_CAPACITY) as RegionId {
+            let metadata = std::fs::metadata(dir.path().join(FsDevice::filename(region))).unwrap();
+            assert_eq!(metadata.len() as usize, FILE_CAPACITY);
+            assert!(metadata.blocks() as usize * 512 >= FILE_CAPACITY);
+        }
+
+        // Blocks deallocated in the meantime are allocated again on open.
+        let path = dir.path().join(FsDevice::filename(0));
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        punch_hole(file.as_raw_fd(), 0, FILE_CAPACITY as i64).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().blocks(), 0);
+        FsDevice::open(config).await.unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.len() as usize, FILE_CAPACITY);
+        assert!(metadata.blocks() as usize * 512 >= FILE_CAPACITY);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_fs_device_discard() {
+        use std::os::unix::fs::MetadataExt;
+
+        for preallocate in [false, true] {
+            let dir = tempfile::tempdir().unwrap();
+            let config = FsDeviceConfig {
+                dir: PathBuf::from(dir.path()),
+                capacity: CAPACITY,
+                file_capacity: FILE_CAPACITY,
+                align: ALIGN,
+                io_size: ALIGN,
+                reformat: false,
+                preallocate,
+            };
+            let dev = FsDevice::open(config).await.unwrap();
+
+            let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+            (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+            let (res, _) = dev.write(wbuffer, .., 0, ALIGN as u64).await;
+            res.unwrap();
+
+            dev.discard(0).await.unwrap();
+            let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+            let (res, rbuffer) = dev.read(rbuffer, .., 0, ALIGN as u64).await;
+            res.unwrap();
+
+            // Preallocated regions are not discarded, only the data of other regions is dropped.
+            let metadata = std::fs::metadata(dir.path().join(FsDevice::filename(0))).unwrap();
+            if preallocate {
+                assert_eq!(&rbuffer[..], &[b'x'; ALIGN]);
+                assert!(metadata.blocks() as usize * 512 >= FILE_CAPACITY);
+            } else {
+                assert_eq!(&rbuffer[..], &[0; ALIGN]);
+                assert_eq!(metadata.blocks(), 0);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fs_device_pin() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = FsDeviceConfig {
+            dir: PathBuf::from(dir.path()),
+            capacity: CAPACITY,
+            file_capacity: FILE_CAPACITY,
+            align: ALIGN,
+            io_size: ALIGN,
+            reformat: false,
+            preallocate: false,
+        };
+        let dev = FsDevice::open(config.clone()).await.unwrap();
+        dev.write_manifest(vec![b'x'; 16]).await.unwrap();
+        drop(dev);
+
+        // `io_size` is not pinned.
+        let dev = FsDevice::open(FsDeviceConfig {
+            io_size: 2 * ALIGN,
+            ..config.clone()
+        })
+        .await
+        .unwrap();
+        drop(dev);
+
+        let mismatch = FsDeviceConfig {
+            capacity: CAPACITY / 2,
+            file_capacity: FILE_CAPACITY / 2,
+            ..config.clone()
+        };
+        assert!(FsDevice::open(mismatch.clone()).await.is_err());
+
+        let dev = FsDevice::open(FsDeviceConfig {
+            reformat: true,
+            ..mismatch.clone()
+        })
+        .await
+        .unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), None);
+        assert_eq!(dev.regions(), FILES);
+        drop(dev);
+
+        assert!(FsDevice::open(mismatch).await.is_ok());
+        assert!(FsDevice::open(config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fs_device_unpinned() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = FsDeviceConfig {
+            dir: PathBuf::from(dir.path()),
+            capacity: CAPACITY,
+            file_capacity: FILE_CAPACITY,
+            align: ALIGN,
+            io_size: ALIGN,
+            reformat: false,
+            preallocate: false,
+        };
+        let dev = FsDevice::open(config.clone()).await.unwrap();
+        dev.write_manifest(vec![b'x'; 16]).await.unwrap();
+        drop(dev);
+
+        // Region files without a pin are not pinned with the given config.
+        std::fs::remove_file(dir.path().join(FsDevice::DEVICE_FILENAME)).unwrap();
+        assert!(FsDevice::open(config.clone()).await.is_err());
+        assert!(!dir.path().join(FsDevice::DEVICE_FILENAME).exists());
+
+        let dev = FsDevice::open(FsDeviceConfig {
+            reformat: true,
+            ..config.clone()
+        })
+        .await
+        .unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), None);
+        drop(dev);
+
+        assert!(FsDevice::open(config).await.is_ok());
+    }
 }
@@ -0,0 +1,568 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use futures::future::join_all;
+use itertools::Itertools;
+
+use crate::region::RegionId;
+
+use super::{
+    error::{DeviceError, DeviceResult},
+    layout_fingerprint, Device, IoBuf, IoBufMut, IoRange, Metadata, MirroredMetadata,
+};
+
+const JBOD_DEVICE_MAGIC: u32 = 0x4A42_4F44;
+
+/// How regions are spread across the child devices.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JbodStrategy {
+    /// Take one region from each child in turn, children run out of regions in the order of their capacity.
+    #[default]
+    RoundRobin,
+    /// Interleave regions in proportion to the capacity of the children, so all children are filled evenly.
+    Weighted,
+}
+
+impl JbodStrategy {
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::RoundRobin => 0,
+            Self::Weighted => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JbodDeviceConfig<C> {
+    /// Configs of the child devices, all children must share `region_size` and `align`.
+    ///
+    /// The region layout depends on the order and capacity of the children, the store refuses to recover with a
+    /// different layout.
+    pub devices: Vec<C>,
+
+    pub strategy: JbodStrategy,
+
+    /// Mark a child failed on its first io error, instead of only returning the error.
+    pub fail_on_error: bool,
+}
+
+#[derive(Debug)]
+struct JbodDeviceInner<D: Device> {
+    devices: Vec<D>,
+    failed: Vec<AtomicBool>,
+
+    /// Global region id to `(child index, child region id)`.
+    regions: Vec<(usize, RegionId)>,
+
+    /// Metadata mirrored to all healthy children, stored with the fingerprint of the region layout.
+    metadata: MirroredMetadata,
+
+    fail_on_error: bool,
+}
+
+/// A device that spreads regions across several child devices, e.g. one [`super::fs::FsDevice`] per disk.
+///
+/// A child can be marked failed with [`JbodDevice::mark_failed`], or on its first io error if `fail_on_error` is
+/// set. Io to a failed child returns an error immediately and its regions are reported unusable, so the store
+/// drops them instead of failing. Metadata is mirrored to all healthy children, the latest copy is read.
+#[derive(Debug, Clone)]
+pub struct JbodDevice<D: Device> {
+    inner: Arc<JbodDeviceInner<D>>,
+}
+
+impl<D: Device> Device for JbodDevice<D> {
+    type Config = JbodDeviceConfig<D::Config>;
+    type IoBufferAllocator = D::IoBufferAllocator;
+
+    async fn open(config: Self::Config) -> DeviceResult<Self> {
+        Self::open(config).await
+    }
+
+    async fn write<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let (index, region) = self.locate(region);
+        if let Err(e) = self.check(index) {
+            return (Err(e), buf);
+        }
+        let (res, buf) = self.inner.devices[index]
+            .write(buf, range, region, offset)
+            .await;
+        (self.on_result(index, res), buf)
+    }
+
+    async fn read<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBufMut,
+    {
+        let (index, region) = self.locate(region);
+        if let Err(e) = self.check(index) {
+            return (Err(e), buf);
+        }
+        let (res, buf) = self.inner.devices[index]
+            .read(buf, range, region, offset)
+            .await;
+        (self.on_result(index, res), buf)
+    }
+
+    async fn write_chunked<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let (index, region) = self.locate(region);
+        if let Err(e) = self.check(index) {
+            return (Err(e), buf);
+        }
+        let (res, buf) = self.inner.devices[index]
+            .write_chunked(buf, range, region, offset)
+            .await;
+        (self.on_result(index, res), buf)
+    }
+
+    async fn flush(&self) -> DeviceResult<()> {
+        let results = join_all(
+            self.healthy()
+                .map(|index| async move { (index, self.inner.devices[index].flush().await) }),
+        )
+        .await;
+        for (index, res) in results {
+            self.on_result(index, res)?;
+        }
+        Ok(())
+    }
+
+    async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+        let (index, region) = self.locate(region);
+        self.check(index)?;
+        let res = self.inner.devices[index].discard(region).await;
+        self.on_result(index, res)
+    }
+
+    async fn read_manifest(&self) -> DeviceResult<Option<Vec<u8>>> {
+        self.read_metadata(Metadata::Manifest).await
+    }
+
+    async fn write_manifest(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        self.write_metadata(Metadata::Manifest, buf).await
+    }
+
+    async fn read_checkpoint(&self) -> DeviceResult<Option<Vec<u8>>> {
+        self.read_metadata(Metadata::Checkpoint).await
+    }
+
+    async fn write_checkpoint(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        self.write_metadata(Metadata::Checkpoint, buf).await
+    }
+
+    fn capacity(&self) -> usize {
+        self.regions() * self.region_size()
+    }
+
+    fn regions(&self) -> usize {
+        self.inner.regions.len()
+    }
+
+    fn align(&self) -> usize {
+        self.inner.devices[0].align()
+    }
+
+    fn io_size(&self) -> usize {
+        self.inner.devices[0].io_size()
+    }
+
+    fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+        self.inner.devices[0].io_buffer_allocator()
+    }
+
+    fn io_buffer(&self, len: usize, capacity: usize) -> Vec<u8, Self::IoBufferAllocator> {
+        self.inner.devices[0].io_buffer(len, capacity)
+    }
+
+    fn region_size(&self) -> usize {
+        self.inner.devices[0].region_size()
+    }
+
+    fn is_usable(&self, region: RegionId) -> bool {
+        let (index, _) = self.locate(region);
+        !self.is_failed(index)
+    }
+}
+
+impl<D: Device> JbodDevice<D> {
+    pub async fn open(config: JbodDeviceConfig<D::Config>) -> DeviceResult<Self> {
+        assert!(
+            !config.devices.is_empty(),
+            "jbod device needs at least one child"
+        );
+
+        let mut devices = Vec::with_capacity(config.devices.len());
+        for c in config.devices {
+            devices.push(D::open(c).await?);
+        }
+
+        let (region_size, align) = (devices[0].region_size(), devices[0].align());
+        for (index, device) in devices.iter().enumerate() {
+            if device.region_size() != region_size || device.align() != align {
+                return Err(format!(
+                    "jbod child {} mismatches child 0, region size: {} vs {}, align: {} vs {}",
+                    index,
+                    device.region_size(),
+                    region_size,
+                    device.align(),
+                    align
+                )
+                .into());
+            }
+        }
+
+        let counts = devices.iter().map(|device| device.regions()).collect_vec();
+        let regions = match config.strategy {
+            JbodStrategy::RoundRobin => round_robin(&counts),
+            JbodStrategy::Weighted => weighted(&counts),
+        };
+
+        let layout = layout_fingerprint(
+            [config.strategy.to_u8() as usize, region_size]
+                .into_iter()
+                .chain(counts),
+        );
+
+        let failed = devices.iter().map(|_| AtomicBool::new(false)).collect();
+
+        let inner = JbodDeviceInner {
+            devices,
+            failed,
+            regions,
+            metadata: MirroredMetadata::new(JBOD_DEVICE_MAGIC, layout),
+            fail_on_error: config.fail_on_error,
+        };
+
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    pub fn devices(&self) -> &[D] {
+        &self.inner.devices
+    }
+
+    /// Returns the child index and the child region id of a region.
+    pub fn locate(&self, region: RegionId) -> (usize, RegionId) {
+        self.inner.regions[region as usize]
+    }
+
+    /// Mark a child failed, its regions become unusable until the device is reopened.
+    pub fn mark_failed(&self, index: usize) {
+        if !self.inner.failed[index].swap(true, Ordering::AcqRel) {
+            tracing::warn!("jbod child {} is marked failed", index);
+        }
+    }
+
+    pub fn is_failed(&self, index: usize) -> bool {
+        self.inner.failed[index].load(Ordering::Acquire)
+    }
+
+    fn healthy(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.inner.devices.len()).filter(|index| !self.is_failed(*index))
+    }
+
+    fn check(&self, index: usize) -> DeviceResult<()> {
+        if self.is_failed(index) {
+            return Err(format!("jbod child {} is failed", index).into());
+        }
+        Ok(())
+    }
+
+    fn on_result<T>(&self, index: usize, res: DeviceResult<T>) -> DeviceResult<T> {
+        if res.is_err() && self.inner.fail_on_error {
+            self.mark_failed(index);
+        }
+        res
+    }
+
+    async fn read_metadata(&self, metadata: Metadata) -> DeviceResult<Option<Vec<u8>>> {
+        self.inner
+            .metadata
+            .read(metadata, |metadata| self.read_copies(metadata))
+            .await
+    }
+
+    async fn write_metadata(&self, metadata: Metadata, buf: Vec<u8>) -> DeviceResult<()> {
+        self.inner
+            .metadata
+            .write(
+                metadata,
+                buf,
+                |metadata| self.read_copies(metadata),
+                |metadata, buf| self.write_copies(metadata, buf),
+            )
+            .await
+    }
+
+    /// Read the copies of all healthy children.
+    async fn read_copies(&self, metadata: Metadata) -> Vec<(usize, DeviceResult<Option<Vec<u8>>>)> {
+        let mut copies = vec![];
+        for index in self.healthy() {
+            let device = &self.inner.devices[index];
+            let res = match metadata {
+                Metadata::Manifest => device.read_manifest().await,
+                Metadata::Checkpoint => device.read_checkpoint().await,
+            };
+            copies.push((index, self.on_result(index, res)));
+        }
+        copies
+    }
+
+    async fn write_copies(&self, metadata: Metadata, buf: Vec<u8>) -> DeviceResult<()> {
+        let results = join_all(self.healthy().map(|index| {
+            let buf = buf.clone();
+            async move {
+                let device = &self.inner.devices[index];
+                let res = match metadata {
+                    Metadata::Manifest => device.write_manifest(buf).await,
+                    Metadata::Checkpoint => device.write_checkpoint(buf).await,
+                };
+                (index, res)
+            }
+        }))
+        .await;
+        self.on_mirrored_results(results)
+    }
+
+    /// Metadata writes succeed if any child persists them, unless errors are returned as is.
+    fn on_mirrored_results(&self, results: Vec<(usize, DeviceResult<()>)>) -> DeviceResult<()> {
+        let mut written = false;
+        let mut error = None;
+        for (index, res) in results {
+            match self.on_result(index, res) {
+                Ok(()) => written = true,
+                Err(e) if self.inner.fail_on_error => {
+                    tracing::warn!("jbod child {} fails to write metadata: {}", index, e);
+                    error.get_or_insert(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        match (written, error) {
+            (true, _) => Ok(()),
+            (false, Some(e)) => Err(e),
+            (false, None) => Err(DeviceError::from(
+                "all jbod children are failed".to_string(),
+            )),
+        }
+    }
+}
+
+fn round_robin(counts: &[usize]) -> Vec<(usize, RegionId)> {
+    let max = counts.iter().copied().max().unwrap_or(0);
+    (0..max)
+        .flat_map(|round| {
+            counts
+                .iter()
+                .enumerate()
+                .filter(move |(_, count)| round < **count)
+                .map(move |(index, _)| (index, round as RegionId))
+        })
+        .collect()
+}
+
+/// Smooth weighted round robin, weighted by the region count of each child.
+fn weighted(counts: &[usize]) -> Vec<(usize, RegionId)> {
+    let total: usize = counts.iter().sum();
+    let mut current = vec![0isize; counts.len()];
+    let mut next = vec![0; counts.len()];
+    let mut res = Vec::with_capacity(total);
+    for _ in 0..total {
+        for (index, count) in counts.iter().enumerate() {
+            current[index] += *count as isize;
+        }
+        let index = (0..counts.len())
+            .filter(|index| next[*index] < counts[*index])
+            .max_by_key(|index| (current[*index], std::cmp::Reverse(*index)))
+            .unwrap();
+        current[index] -= total as isize;
+        res.push((index, next[index] as RegionId));
+        next[index] += 1;
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BufMut;
+
+    use super::*;
+    use crate::device::mem::{MemDevice, MemDeviceConfig, MemDeviceMemory};
+
+    const REGION_SIZE: usize = 8 * 1024; // 8 KiB
+    const ALIGN: usize = 4 * 1024;
+
+    fn config(regions: &[usize], strategy: JbodStrategy) -> JbodDeviceConfig<MemDeviceConfig> {
+        JbodDeviceConfig {
+            devices: regions
+                .iter()
+                .map(|regions| MemDeviceConfig {
+                    capacity: regions * REGION_SIZE,
+                    region_size: REGION_SIZE,
+                    align: ALIGN,
+                    io_size: ALIGN,
+                    memory: MemDeviceMemory::new(),
+                })
+                .collect(),
+            strategy,
+            fail_on_error: false,
+        }
+    }
+
+    #[test]
+    fn test_jbod_layout() {
+        assert_eq!(
+            round_robin(&[3, 1, 2]),
+            vec![(0, 0), (1, 0), (2, 0), (0, 1), (2, 1), (0, 2)]
+        );
+        assert_eq!(
+            weighted(&[4, 2]),
+            vec![(0, 0), (1, 0), (0, 1), (0, 2), (1, 1), (0, 3)]
+        );
+
+        let regions = weighted(&[5, 3, 1]);
+        assert_eq!(regions.len(), 9);
+        for (index, count) in [5, 3, 1].into_iter().enumerate() {
+            let locals = regions
+                .iter()
+                .filter(|(i, _)| *i == index)
+                .map(|(_, region)| *region)
+                .collect_vec();
+            assert_eq!(locals, (0..count).collect_vec());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jbod_stale_metadata() {
+        let config = config(&[2, 2], JbodStrategy::RoundRobin);
+        let dev = JbodDevice::<MemDevice>::open(config.clone()).await.unwrap();
+
+        dev.write_manifest(vec![b'a'; 16]).await.unwrap();
+        dev.write_checkpoint(vec![b'a'; 16]).await.unwrap();
+
+        // Child 0 misses the mirrored writes, its copies are stale.
+        dev.mark_failed(0);
+        dev.write_manifest(vec![b'b'; 16]).await.unwrap();
+        dev.write_checkpoint(vec![b'b'; 16]).await.unwrap();
+
+        drop(dev);
+        let dev = JbodDevice::<MemDevice>::open(config.clone()).await.unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![b'b'; 16]));
+        assert_eq!(dev.read_checkpoint().await.unwrap(), Some(vec![b'b'; 16]));
+
+        // Writes without a read before supersede the latest copies as well.
+        drop(dev);
+        let dev = JbodDevice::<MemDevice>::open(config.clone()).await.unwrap();
+        dev.write_manifest(vec![b'c'; 16]).await.unwrap();
+        dev.mark_failed(0);
+        dev.write_manifest(vec![b'd'; 16]).await.unwrap();
+
+        drop(dev);
+        let dev = JbodDevice::<MemDevice>::open(config.clone()).await.unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![b'd'; 16]));
+        assert_eq!(dev.read_checkpoint().await.unwrap(), Some(vec![b'b'; 16]));
+
+        // A corrupted copy is skipped, the read fails only if no copy is valid.
+        dev.devices()[1].write_manifest(vec![0; 4]).await.unwrap();
+        drop(dev);
+        let dev = JbodDevice::<MemDevice>::open(config.clone()).await.unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![b'c'; 16]));
+        dev.devices()[0].write_manifest(vec![0; 4]).await.unwrap();
+        drop(dev);
+        let dev = JbodDevice::<MemDevice>::open(config).await.unwrap();
+        assert!(dev.read_manifest().await.is_err());
+        assert_eq!(dev.read_checkpoint().await.unwrap(), Some(vec![b'b'; 16]));
+    }
+
+    #[tokio::test]
+    async fn test_jbod_device_simple() {
+        let config = config(&[4, 2], JbodStrategy::Weighted);
+        let dev = JbodDevice::<MemDevice>::open(config.clone()).await.unwrap();
+        assert_eq!(dev.regions(), 6);
+        assert_eq!(dev.capacity(), 6 * REGION_SIZE);
+        assert_eq!(dev.region_size(), REGION_SIZE);
+
+        // Global region 1 is the first region of child 1.
+        assert_eq!(dev.locate(1), (1, 0));
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+        let (res, wbuffer) = dev.write(wbuffer, .., 1, ALIGN as u64).await;
+        res.unwrap();
+
+        let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        let (res, rbuffer) = dev.devices()[1].read(rbuffer, .., 0, ALIGN as u64).await;
+        res.unwrap();
+        assert_eq!(&wbuffer, &rbuffer);
+
+        // Metadata is mirrored to all children.
+        dev.write_manifest(vec![b'm'; 16]).await.unwrap();
+        for child in dev.devices() {
+            assert!(child.read_manifest().await.unwrap().is_some());
+        }
+
+        dev.mark_failed(0);
+        assert!(dev.is_failed(0));
+        assert!(!dev.is_usable(0));
+        assert!(dev.is_usable(1));
+        let (res, rbuffer) = dev.read(rbuffer, .., 0, 0).await;
+        assert!(res.is_err());
+        let (res, _) = dev.read(rbuffer, .., 1, ALIGN as u64).await;
+        res.unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![b'm'; 16]));
+
+        // Failed children are healthy again after reopen.
+        drop(dev);
+        let dev = JbodDevice::<MemDevice>::open(config.clone()).await.unwrap();
+        assert!(!dev.is_failed(0));
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![b'm'; 16]));
+
+        // Metadata written with another layout is refused.
+        let dev = JbodDevice::<MemDevice>::open(JbodDeviceConfig {
+            strategy: JbodStrategy::RoundRobin,
+            ..config
+        })
+        .await
+        .unwrap();
+        assert!(dev.read_manifest().await.is_err());
+    }
+}
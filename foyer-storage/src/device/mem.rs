@@ -0,0 +1,380 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::sync::Arc;
+
+use foyer_common::range::RangeBoundsExt;
+use parking_lot::{Mutex, RwLock};
+
+use crate::region::RegionId;
+
+use super::{
+    allocator::AlignedAllocator,
+    error::{DeviceError, DeviceResult},
+    Device, IoBuf, IoBufMut, IoRange,
+};
+
+#[derive(Debug, Clone)]
+pub struct MemDeviceConfig {
+    /// must be multipliers of `align` and `region_size`
+    pub capacity: usize,
+
+    /// must be multipliers of `align`
+    pub region_size: usize,
+
+    /// io block alignment, must be pow of 2
+    pub align: usize,
+
+    /// recommended optimized io block size
+    pub io_size: usize,
+
+    /// Memory of the device.
+    ///
+    /// Devices opened with clones of the same config share the memory, so a store can be closed, reopened and
+    /// recovered within a process.
+    pub memory: MemDeviceMemory,
+}
+
+impl MemDeviceConfig {
+    pub fn verify(&self) {
+        assert!(self.align.is_power_of_two());
+        assert_eq!(self.region_size % self.align, 0);
+        assert_eq!(self.capacity % self.region_size, 0);
+    }
+}
+
+/// Data of a region, allocated on the first write.
+type RegionData = Arc<RwLock<Option<Vec<u8, AlignedAllocator>>>>;
+
+#[derive(Debug)]
+struct Geometry {
+    capacity: usize,
+    region_size: usize,
+    align: usize,
+}
+
+#[derive(Debug, Default)]
+struct MemDeviceMemoryInner {
+    /// Geometry pinned on the first open.
+    geometry: Mutex<Option<Geometry>>,
+
+    /// Regions are allocated on the first write, unwritten regions are read as zeros.
+    regions: RwLock<Vec<RegionData>>,
+
+    manifest: Mutex<Option<Vec<u8>>>,
+    checkpoint: Mutex<Option<Vec<u8>>>,
+}
+
+/// Memory that backs [`MemDevice`]s, cloning it shares the memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemDeviceMemory {
+    inner: Arc<MemDeviceMemoryInner>,
+}
+
+impl MemDeviceMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Debug)]
+struct MemDeviceInner {
+    config: MemDeviceConfig,
+
+    regions: Vec<RegionData>,
+
+    io_buffer_allocator: AlignedAllocator,
+}
+
+/// A device that keeps regions in memory.
+///
+/// Like a file opened with direct io, offsets and lengths of reads and writes must be aligned to `align`.
+#[derive(Debug, Clone)]
+pub struct MemDevice {
+    inner: Arc<MemDeviceInner>,
+}
+
+impl Device for MemDevice {
+    type Config = MemDeviceConfig;
+    type IoBufferAllocator = AlignedAllocator;
+
+    async fn open(config: MemDeviceConfig) -> DeviceResult<Self> {
+        Self::open(config).await
+    }
+
+    async fn write<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::len(&range).unwrap();
+
+        if let Err(e) = self.check(offset, len) {
+            return (Err(e), buf);
+        }
+
+        let offset = offset as usize;
+        let mut guard = self.inner.regions[region as usize].write();
+        let data = guard.get_or_insert_with(|| {
+            let mut data = Vec::with_capacity_in(
+                self.inner.config.region_size,
+                self.inner.io_buffer_allocator,
+            );
+            data.resize(self.inner.config.region_size, 0);
+            data
+        });
+        data[offset..offset + len].copy_from_slice(&buf.as_ref()[range]);
+        drop(guard);
+
+        (Ok(len), buf)
+    }
+
+    async fn read<B>(
+        &self,
+        mut buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBufMut,
+    {
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::len(&range).unwrap();
+
+        if let Err(e) = self.check(offset, len) {
+            return (Err(e), buf);
+        }
+
+        let offset = offset as usize;
+        let guard = self.inner.regions[region as usize].read();
+        match guard.as_ref() {
+            Some(data) => buf.as_mut()[range].copy_from_slice(&data[offset..offset + len]),
+            None => buf.as_mut()[range].fill(0),
+        }
+        drop(guard);
+
+        (Ok(len), buf)
+    }
+
+    async fn flush(&self) -> DeviceResult<()> {
+        Ok(())
+    }
+
+    /// Frees the memory of the region, it reads as zeros afterwards.
+    async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+        *self.inner.regions[region as usize].write() = None;
+        Ok(())
+    }
+
+    async fn read_manifest(&self) -> DeviceResult<Option<Vec<u8>>> {
+        Ok(self.inner.config.memory.inner.manifest.lock().clone())
+    }
+
+    async fn write_manifest(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        *self.inner.config.memory.inner.manifest.lock() = Some(buf);
+        Ok(())
+    }
+
+    async fn read_checkpoint(&self) -> DeviceResult<Option<Vec<u8>>> {
+        Ok(self.inner.config.memory.inner.checkpoint.lock().clone())
+    }
+
+    async fn write_checkpoint(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        *self.inner.config.memory.inner.checkpoint.lock() = Some(buf);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.config.capacity
+    }
+
+    fn regions(&self) -> usize {
+        self.inner.regions.len()
+    }
+
+    fn align(&self) -> usize {
+        self.inner.config.align
+    }
+
+    fn io_size(&self) -> usize {
+        self.inner.config.io_size
+    }
+
+    fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+        &self.inner.io_buffer_allocator
+    }
+
+    fn io_buffer(&self, len: usize, capacity: usize) -> Vec<u8, Self::IoBufferAllocator> {
+        assert!(len <= capacity);
+        let mut buf = Vec::with_capacity_in(capacity, self.inner.io_buffer_allocator);
+        unsafe { buf.set_len(len) };
+        buf
+    }
+}
+
+impl MemDevice {
+    pub async fn open(config: MemDeviceConfig) -> DeviceResult<Self> {
+        config.verify();
+
+        let memory = config.memory.inner.clone();
+        {
+            let mut geometry = memory.geometry.lock();
+            match geometry.as_ref() {
+                Some(g)
+                    if g.capacity != config.capacity
+                        || g.region_size != config.region_size
+                        || g.align != config.align =>
+                {
+                    return Err(format!(
+                        "mem device config mismatches the memory, pinned: {:?}, given: {:?}",
+                        g, config
+                    )
+                    .into());
+                }
+                Some(_) => {}
+                None => {
+                    *geometry = Some(Geometry {
+                        capacity: config.capacity,
+                        region_size: config.region_size,
+                        align: config.align,
+                    });
+                    *memory.regions.write() = (0..config.capacity / config.region_size)
+                        .map(|_| Arc::new(RwLock::new(None)))
+                        .collect();
+                }
+            }
+        }
+        let regions = memory.regions.read().clone();
+
+        let io_buffer_allocator = AlignedAllocator::new(config.align);
+
+        let inner = MemDeviceInner {
+            config,
+            regions,
+            io_buffer_allocator,
+        };
+
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    fn check(&self, offset: u64, len: usize) -> DeviceResult<()> {
+        let region_size = self.inner.config.region_size;
+        let align = self.inner.config.align;
+
+        assert!(
+            offset as usize + len <= region_size,
+            "offset ({offset}) + len ({len}) <= region size ({region_size})"
+        );
+
+        if offset as usize % align != 0 || len % align != 0 {
+            return Err(DeviceError::from(nix::errno::Errno::EINVAL));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BufMut;
+
+    use super::*;
+
+    const REGIONS: usize = 8;
+    const REGION_SIZE: usize = 8 * 1024; // 8 KiB
+    const CAPACITY: usize = REGIONS * REGION_SIZE; // 64 KiB
+    const ALIGN: usize = 4 * 1024;
+
+    fn config() -> MemDeviceConfig {
+        MemDeviceConfig {
+            capacity: CAPACITY,
+            region_size: REGION_SIZE,
+            align: ALIGN,
+            io_size: ALIGN,
+            memory: MemDeviceMemory::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mem_device_simple() {
+        let config = config();
+        let dev = MemDevice::open(config.clone()).await.unwrap();
+        assert_eq!(dev.regions(), REGIONS);
+
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+        let mut rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut rbuffer[..]).put_slice(&[b'y'; ALIGN]);
+
+        // Unwritten data is read as zeros.
+        let (res, mut rbuffer) = dev.read(rbuffer, .., 1, ALIGN as u64).await;
+        assert_eq!(res.unwrap(), ALIGN);
+        assert_eq!(&rbuffer[..], &[0; ALIGN]);
+
+        let (res, wbuffer) = dev.write(wbuffer, .., 1, ALIGN as u64).await;
+        assert_eq!(res.unwrap(), ALIGN);
+        rbuffer.fill(0);
+        let (res, rbuffer) = dev.read(rbuffer, .., 1, ALIGN as u64).await;
+        assert_eq!(res.unwrap(), ALIGN);
+        assert_eq!(&wbuffer, &rbuffer);
+
+        // Unaligned io is rejected like direct io.
+        let (res, wbuffer) = dev.write(wbuffer, ..ALIGN / 2, 1, 0).await;
+        assert!(res.is_err());
+        let (res, _) = dev.read(rbuffer, .., 1, 512).await;
+        assert!(res.is_err());
+
+        dev.write_manifest(vec![b'm'; 16]).await.unwrap();
+        drop(dev);
+
+        // Devices opened with the same memory share data.
+        let dev = MemDevice::open(config.clone()).await.unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), Some(vec![b'm'; 16]));
+        let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        let (res, rbuffer) = dev.read(rbuffer, .., 1, ALIGN as u64).await;
+        res.unwrap();
+        assert_eq!(&wbuffer, &rbuffer);
+
+        // Discarded regions are read as zeros.
+        dev.discard(1).await.unwrap();
+        let (res, rbuffer) = dev.read(rbuffer, .., 1, ALIGN as u64).await;
+        res.unwrap();
+        assert_eq!(&rbuffer[..], &[0; ALIGN]);
+        drop(dev);
+
+        assert!(MemDevice::open(MemDeviceConfig {
+            region_size: REGION_SIZE / 2,
+            ..config.clone()
+        })
+        .await
+        .is_err());
+
+        // A new memory is empty.
+        let dev = MemDevice::open(MemDeviceConfig {
+            memory: MemDeviceMemory::new(),
+            ..config
+        })
+        .await
+        .unwrap();
+        assert_eq!(dev.read_manifest().await.unwrap(), None);
+    }
+}
@@ -13,8 +13,15 @@
 //  limitations under the License.
 
 pub mod allocator;
+pub mod direct_file;
 pub mod error;
+pub mod faulty;
 pub mod fs;
+pub mod jbod;
+pub mod mem;
+pub mod tiered;
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+pub mod uring;
 
 use std::{alloc::Allocator, fmt::Debug};
 
@@ -57,9 +64,76 @@ pub trait Device: Sized + Clone + Send + Sync + 'static + Debug {
     where
         B: IoBufMut;
 
+    /// Write `buf[range]` to `region` at `offset` in chunks of at most `io_size` bytes, returns the written bytes.
+    ///
+    /// The default implementation writes the chunks one after another, devices may submit them in a batch.
+    #[must_use]
+    fn write_chunked<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> impl Future<Output = (DeviceResult<usize>, B)> + Send
+    where
+        B: IoBuf,
+    {
+        async move {
+            let range = range.bounds(0..buf.as_ref().len());
+            let io_size = self.io_size();
+
+            let mut buf = buf;
+            let mut written = 0;
+            let mut start = range.start;
+            while start < range.end {
+                let end = std::cmp::min(start + io_size, range.end);
+                let (res, b) = self
+                    .write(
+                        buf,
+                        start..end,
+                        region,
+                        offset + (start - range.start) as u64,
+                    )
+                    .await;
+                buf = b;
+                match res {
+                    Ok(len) => written += len,
+                    Err(e) => return (Err(e), buf),
+                }
+                start = end;
+            }
+            (Ok(written), buf)
+        }
+    }
+
     #[must_use]
     fn flush(&self) -> impl Future<Output = DeviceResult<()>> + Send;
 
+    /// Tell the media that the data of `region` is no longer needed, e.g. by punching a hole or a discard.
+    ///
+    /// The region may read as zeros or as its previous data afterwards. The default implementation does nothing.
+    #[must_use]
+    fn discard(&self, region: RegionId) -> impl Future<Output = DeviceResult<()>> + Send {
+        let _ = region;
+        async move { Ok(()) }
+    }
+
+    /// Read the persisted store manifest, returns `None` if there is no manifest yet.
+    #[must_use]
+    fn read_manifest(&self) -> impl Future<Output = DeviceResult<Option<Vec<u8>>>> + Send;
+
+    /// Persist the store manifest atomically, the previous manifest is replaced.
+    #[must_use]
+    fn write_manifest(&self, buf: Vec<u8>) -> impl Future<Output = DeviceResult<()>> + Send;
+
+    /// Read the persisted catalog checkpoint, returns `None` if there is no checkpoint yet.
+    #[must_use]
+    fn read_checkpoint(&self) -> impl Future<Output = DeviceResult<Option<Vec<u8>>>> + Send;
+
+    /// Persist the catalog checkpoint atomically, the previous checkpoint is replaced.
+    #[must_use]
+    fn write_checkpoint(&self, buf: Vec<u8>) -> impl Future<Output = DeviceResult<()>> + Send;
+
     fn capacity(&self) -> usize;
 
     fn regions(&self) -> usize;
@@ -76,6 +150,203 @@ pub trait Device: Sized + Clone + Send + Sync + 'static + Debug {
         debug_assert!(self.capacity() % self.regions() == 0);
         self.capacity() / self.regions()
     }
+
+    /// Returns `false` if the region is on failed media, the store stops using it and treats its entries as lost.
+    fn is_usable(&self, _region: RegionId) -> bool {
+        true
+    }
+
+    /// Count of media tiers, regions of tier `0` are on the fastest media.
+    fn tiers(&self) -> usize {
+        1
+    }
+
+    fn tier(&self, _region: RegionId) -> usize {
+        0
+    }
+}
+
+const LAYOUT_METADATA_HEADER_LEN: usize = 12;
+
+/// Fingerprint of the region layout of a composite device, built from the geometry of its children.
+pub(crate) fn layout_fingerprint(parts: impl IntoIterator<Item = usize>) -> u64 {
+    use std::hash::Hasher;
+
+    let mut hasher = twox_hash::XxHash64::with_seed(0);
+    for part in parts {
+        hasher.write_usize(part);
+    }
+    hasher.finish()
+}
+
+/// Prefix the metadata persisted by a composite device with its magic and layout fingerprint.
+pub(crate) fn encode_layout_metadata(magic: u32, layout: u64, buf: Vec<u8>) -> Vec<u8> {
+    use bytes::BufMut;
+
+    let mut res = Vec::with_capacity(LAYOUT_METADATA_HEADER_LEN + buf.len());
+    res.put_u32(magic);
+    res.put_u64(layout);
+    res.put_slice(&buf);
+    res
+}
+
+/// Strip the prefix written by [`encode_layout_metadata`].
+///
+/// Metadata persisted with another layout is refused, the regions it refers to are not where they used to be.
+pub(crate) fn decode_layout_metadata(
+    magic: u32,
+    layout: u64,
+    buf: Vec<u8>,
+) -> DeviceResult<Vec<u8>> {
+    use bytes::Buf;
+
+    let mut cursor = &buf[..];
+    if cursor.len() < LAYOUT_METADATA_HEADER_LEN || cursor.get_u32() != magic {
+        return Err(format!("metadata is not written by the device, magic: {:#x}", magic).into());
+    }
+    let persisted = cursor.get_u64();
+    if persisted != layout {
+        return Err(format!(
+            "region layout mismatch, persisted: {:#x}, current: {:#x}",
+            persisted, layout
+        )
+        .into());
+    }
+    Ok(buf[LAYOUT_METADATA_HEADER_LEN..].to_vec())
+}
+
+/// Metadata mirrored to several devices by a composite device.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Metadata {
+    Manifest,
+    Checkpoint,
+}
+
+/// Versions of the metadata mirrored by a composite device.
+///
+/// A device may miss a mirrored write, so its copy is stale. Copies are written with increasing versions after the
+/// prefix of [`encode_layout_metadata`], and the latest valid copy is read.
+#[derive(Debug)]
+pub(crate) struct MirroredMetadata {
+    magic: u32,
+    layout: u64,
+
+    /// Versions of the latest manifest and checkpoint mirrored, `None` until they are read or written.
+    manifest_version: tokio::sync::Mutex<Option<u64>>,
+    checkpoint_version: tokio::sync::Mutex<Option<u64>>,
+}
+
+impl MirroredMetadata {
+    pub(crate) fn new(magic: u32, layout: u64) -> Self {
+        Self {
+            magic,
+            layout,
+            manifest_version: tokio::sync::Mutex::new(None),
+            checkpoint_version: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Read the latest valid copy among the copies returned by `read`, indexed by their devices.
+    pub(crate) async fn read<R>(
+        &self,
+        metadata: Metadata,
+        read: impl FnOnce(Metadata) -> R,
+    ) -> DeviceResult<Option<Vec<u8>>>
+    where
+        R: Future<Output = Vec<(usize, DeviceResult<Option<Vec<u8>>>)>>,
+    {
+        let mut version = self.version(metadata).lock().await;
+        let latest = self.latest(metadata, read(metadata).await)?;
+        if let Some((v, _)) = latest.as_ref() {
+            *version = Some(std::cmp::max(version.unwrap_or(0), *v));
+        }
+        Ok(latest.map(|(_, buf)| buf))
+    }
+
+    /// Write a copy that supersedes all copies to each device with `write`, the copies are read with `read` first if
+    /// the latest version is unknown.
+    pub(crate) async fn write<R, W>(
+        &self,
+        metadata: Metadata,
+        buf: Vec<u8>,
+        read: impl FnOnce(Metadata) -> R,
+        write: impl FnOnce(Metadata, Vec<u8>) -> W,
+    ) -> DeviceResult<()>
+    where
+        R: Future<Output = Vec<(usize, DeviceResult<Option<Vec<u8>>>)>>,
+        W: Future<Output = DeviceResult<()>>,
+    {
+        use bytes::BufMut;
+
+        let mut version = self.version(metadata).lock().await;
+        let next = match *version {
+            Some(v) => v + 1,
+            None => self
+                .latest(metadata, read(metadata).await)?
+                .map_or(0, |(v, _)| v + 1),
+        };
+        // Devices may persist the copy even if the write fails, the next write must supersede it anyway.
+        *version = Some(next);
+
+        let mut versioned = Vec::with_capacity(8 + buf.len());
+        versioned.put_u64(next);
+        versioned.put_slice(&buf);
+        write(
+            metadata,
+            encode_layout_metadata(self.magic, self.layout, versioned),
+        )
+        .await
+    }
+
+    fn version(&self, metadata: Metadata) -> &tokio::sync::Mutex<Option<u64>> {
+        match metadata {
+            Metadata::Manifest => &self.manifest_version,
+            Metadata::Checkpoint => &self.checkpoint_version,
+        }
+    }
+
+    /// Returns the version and the metadata of the latest valid copy.
+    ///
+    /// Copies that fail to read or decode are skipped, an error is returned only if no copy is valid.
+    fn latest(
+        &self,
+        metadata: Metadata,
+        copies: Vec<(usize, DeviceResult<Option<Vec<u8>>>)>,
+    ) -> DeviceResult<Option<(u64, Vec<u8>)>> {
+        use bytes::Buf;
+
+        let mut latest: Option<(u64, Vec<u8>)> = None;
+        let mut error = None;
+        for (index, copy) in copies {
+            let copy = copy.and_then(|buf| {
+                let Some(buf) = buf else {
+                    return Ok(None);
+                };
+                let buf = decode_layout_metadata(self.magic, self.layout, buf)?;
+                if buf.len() < 8 {
+                    return Err(format!("truncated {:?}", metadata).into());
+                }
+                Ok(Some(((&buf[..8]).get_u64(), buf[8..].to_vec())))
+            });
+            match copy {
+                Ok(Some((version, buf))) => {
+                    if latest.as_ref().map_or(true, |(v, _)| version > *v) {
+                        latest = Some((version, buf));
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!("skip copy of {:?} on device {}: {}", metadata, index, e);
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        match (latest, error) {
+            (Some(latest), _) => Ok(Some(latest)),
+            (None, Some(e)) => Err(e),
+            (None, None) => Ok(None),
+        }
+    }
 }
 
 #[cfg(not(madsim))]
@@ -149,6 +420,22 @@ pub mod tests {
             Ok(())
         }
 
+        async fn read_manifest(&self) -> DeviceResult<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        async fn write_manifest(&self, _buf: Vec<u8>) -> DeviceResult<()> {
+            Ok(())
+        }
+
+        async fn read_checkpoint(&self) -> DeviceResult<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        async fn write_checkpoint(&self, _buf: Vec<u8>) -> DeviceResult<()> {
+            Ok(())
+        }
+
         fn capacity(&self) -> usize {
             usize::MAX
         }
@@ -0,0 +1,404 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::sync::Arc;
+
+use crate::region::RegionId;
+
+use super::{
+    error::DeviceResult, layout_fingerprint, Device, IoBuf, IoBufMut, IoRange, Metadata,
+    MirroredMetadata,
+};
+
+const TIERED_DEVICE_MAGIC: u32 = 0x5449_4552;
+
+#[derive(Debug, Clone)]
+pub struct TieredDeviceConfig<FC, SC> {
+    /// Config of the fast device, it must share `region_size` and `align` with the slow device.
+    pub fast: FC,
+
+    /// Config of the slow device.
+    pub slow: SC,
+}
+
+#[derive(Debug)]
+struct TieredDeviceInner<F, S> {
+    fast: F,
+    slow: S,
+
+    /// Metadata mirrored to both devices, stored with the fingerprint of the region layout.
+    metadata: MirroredMetadata,
+}
+
+/// A device with two tiers, regions of the fast device come first and form tier `0`, regions of the slow device
+/// form tier `1`.
+///
+/// The store allocates regions for new entries from the fast tier. Entries of reclaimed fast regions are
+/// reinserted into the slow tier, so entries that outlive their fast region are migrated instead of dropped.
+/// Metadata is mirrored to both devices, the latest copy is read.
+#[derive(Debug, Clone)]
+pub struct TieredDevice<F, S> {
+    inner: Arc<TieredDeviceInner<F, S>>,
+}
+
+/// Dispatch `$f` to the device of the tier that `$region` belongs to, with the region id of that device.
+macro_rules! dispatch {
+    ($self:ident, $region:ident, |$device:ident, $local:ident| $f:expr) => {{
+        let fast = $self.inner.fast.regions() as RegionId;
+        if $region < fast {
+            let ($device, $local) = (&$self.inner.fast, $region);
+            $f
+        } else {
+            let ($device, $local) = (&$self.inner.slow, $region - fast);
+            $f
+        }
+    }};
+}
+
+impl<F, S> Device for TieredDevice<F, S>
+where
+    F: Device,
+    S: Device,
+{
+    type Config = TieredDeviceConfig<F::Config, S::Config>;
+    type IoBufferAllocator = F::IoBufferAllocator;
+
+    async fn open(config: Self::Config) -> DeviceResult<Self> {
+        Self::open(config).await
+    }
+
+    async fn write<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        dispatch!(self, region, |device, region| device
+            .write(buf, range, region, offset)
+            .await)
+    }
+
+    async fn read<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBufMut,
+    {
+        dispatch!(self, region, |device, region| device
+            .read(buf, range, region, offset)
+            .await)
+    }
+
+    async fn write_chunked<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        dispatch!(self, region, |device, region| device
+            .write_chunked(buf, range, region, offset)
+            .await)
+    }
+
+    async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+        dispatch!(self, region, |device, region| device.discard(region).await)
+    }
+
+    async fn flush(&self) -> DeviceResult<()> {
+        let (fast, slow) = futures::join!(self.inner.fast.flush(), self.inner.slow.flush());
+        fast?;
+        slow
+    }
+
+    async fn read_manifest(&self) -> DeviceResult<Option<Vec<u8>>> {
+        self.read_metadata(Metadata::Manifest).await
+    }
+
+    async fn write_manifest(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        self.write_metadata(Metadata::Manifest, buf).await
+    }
+
+    async fn read_checkpoint(&self) -> DeviceResult<Option<Vec<u8>>> {
+        self.read_metadata(Metadata::Checkpoint).await
+    }
+
+    async fn write_checkpoint(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        self.write_metadata(Metadata::Checkpoint, buf).await
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.fast.capacity() + self.inner.slow.capacity()
+    }
+
+    fn regions(&self) -> usize {
+        self.inner.fast.regions() + self.inner.slow.regions()
+    }
+
+    fn align(&self) -> usize {
+        self.inner.fast.align()
+    }
+
+    fn io_size(&self) -> usize {
+        self.inner.fast.io_size()
+    }
+
+    fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+        self.inner.fast.io_buffer_allocator()
+    }
+
+    fn io_buffer(&self, len: usize, capacity: usize) -> Vec<u8, Self::IoBufferAllocator> {
+        self.inner.fast.io_buffer(len, capacity)
+    }
+
+    fn region_size(&self) -> usize {
+        self.inner.fast.region_size()
+    }
+
+    fn is_usable(&self, region: RegionId) -> bool {
+        dispatch!(self, region, |device, region| device.is_usable(region))
+    }
+
+    fn tiers(&self) -> usize {
+        2
+    }
+
+    fn tier(&self, region: RegionId) -> usize {
+        if region < self.inner.fast.regions() as RegionId {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+impl<F, S> TieredDevice<F, S>
+where
+    F: Device,
+    S: Device,
+{
+    pub async fn open(config: TieredDeviceConfig<F::Config, S::Config>) -> DeviceResult<Self> {
+        let fast = F::open(config.fast).await?;
+        let slow = S::open(config.slow).await?;
+
+        if fast.region_size() != slow.region_size() || fast.align() != slow.align() {
+            return Err(format!(
+                "tiered device children mismatch, region size: {} vs {}, align: {} vs {}",
+                fast.region_size(),
+                slow.region_size(),
+                fast.align(),
+                slow.align()
+            )
+            .into());
+        }
+
+        let layout = layout_fingerprint([fast.region_size(), fast.regions(), slow.regions()]);
+
+        let inner = TieredDeviceInner {
+            fast,
+            slow,
+            metadata: MirroredMetadata::new(TIERED_DEVICE_MAGIC, layout),
+        };
+
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    pub fn fast(&self) -> &F {
+        &self.inner.fast
+    }
+
+    pub fn slow(&self) -> &S {
+        &self.inner.slow
+    }
+
+    async fn read_metadata(&self, metadata: Metadata) -> DeviceResult<Option<Vec<u8>>> {
+        self.inner
+            .metadata
+            .read(metadata, |metadata| self.read_copies(metadata))
+            .await
+    }
+
+    async fn write_metadata(&self, metadata: Metadata, buf: Vec<u8>) -> DeviceResult<()> {
+        self.inner
+            .metadata
+            .write(
+                metadata,
+                buf,
+                |metadata| self.read_copies(metadata),
+                |metadata, buf| self.write_copies(metadata, buf),
+            )
+            .await
+    }
+
+    /// Read the copies of both devices, the fast device is indexed `0`.
+    async fn read_copies(&self, metadata: Metadata) -> Vec<(usize, DeviceResult<Option<Vec<u8>>>)> {
+        let (fast, slow) = match metadata {
+            Metadata::Manifest => {
+                futures::join!(
+                    self.inner.fast.read_manifest(),
+                    self.inner.slow.read_manifest()
+                )
+            }
+            Metadata::Checkpoint => futures::join!(
+                self.inner.fast.read_checkpoint(),
+                self.inner.slow.read_checkpoint()
+            ),
+        };
+        vec![(0, fast), (1, slow)]
+    }
+
+    async fn write_copies(&self, metadata: Metadata, buf: Vec<u8>) -> DeviceResult<()> {
+        let (fast, slow) = match metadata {
+            Metadata::Manifest => futures::join!(
+                self.inner.fast.write_manifest(buf.clone()),
+                self.inner.slow.write_manifest(buf)
+            ),
+            Metadata::Checkpoint => futures::join!(
+                self.inner.fast.write_checkpoint(buf.clone()),
+                self.inner.slow.write_checkpoint(buf)
+            ),
+        };
+        fast?;
+        slow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BufMut;
+
+    use super::*;
+    use crate::device::{
+        faulty::{FaultInjector, FaultyDevice, FaultyDeviceConfig},
+        mem::{MemDevice, MemDeviceConfig, MemDeviceMemory},
+    };
+
+    const REGION_SIZE: usize = 8 * 1024; // 8 KiB
+    const ALIGN: usize = 4 * 1024;
+
+    fn mem_config(regions: usize) -> MemDeviceConfig {
+        MemDeviceConfig {
+            capacity: regions * REGION_SIZE,
+            region_size: REGION_SIZE,
+            align: ALIGN,
+            io_size: ALIGN,
+            memory: MemDeviceMemory::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tiered_device_simple() {
+        let config = TieredDeviceConfig {
+            fast: mem_config(2),
+            slow: mem_config(6),
+        };
+        let dev = TieredDevice::<MemDevice, MemDevice>::open(config.clone())
+            .await
+            .unwrap();
+        assert_eq!(dev.regions(), 8);
+        assert_eq!(dev.capacity(), 8 * REGION_SIZE);
+        assert_eq!(dev.tiers(), 2);
+        assert_eq!(
+            (0..8).map(|region| dev.tier(region)).collect::<Vec<_>>(),
+            vec![0, 0, 1, 1, 1, 1, 1, 1]
+        );
+
+        // Global region 3 is region 1 of the slow device.
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+        let (res, wbuffer) = dev.write(wbuffer, .., 3, 0).await;
+        res.unwrap();
+        let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        let (res, rbuffer) = dev.slow().read(rbuffer, .., 1, 0).await;
+        res.unwrap();
+        assert_eq!(&wbuffer, &rbuffer);
+
+        // Metadata is mirrored to both tiers.
+        dev.write_checkpoint(vec![b'c'; 16]).await.unwrap();
+        assert!(dev.fast().read_checkpoint().await.unwrap().is_some());
+        assert!(dev.slow().read_checkpoint().await.unwrap().is_some());
+        assert_eq!(dev.read_checkpoint().await.unwrap(), Some(vec![b'c'; 16]));
+
+        // Metadata written with another layout is refused.
+        let dev = TieredDevice::<MemDevice, MemDevice>::open(TieredDeviceConfig {
+            fast: config.slow.clone(),
+            slow: config.fast.clone(),
+        })
+        .await
+        .unwrap();
+        assert!(dev.read_checkpoint().await.is_err());
+
+        assert!(
+            TieredDevice::<MemDevice, MemDevice>::open(TieredDeviceConfig {
+                fast: config.fast,
+                slow: MemDeviceConfig {
+                    region_size: 2 * REGION_SIZE,
+                    ..mem_config(6)
+                },
+            })
+            .await
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tiered_stale_metadata() {
+        type FaultyMemDevice = FaultyDevice<MemDevice>;
+
+        for crashed in [0, 1] {
+            let injectors = [FaultInjector::new(), FaultInjector::new()];
+            let config = TieredDeviceConfig {
+                fast: FaultyDeviceConfig {
+                    device: mem_config(2),
+                    injector: injectors[0].clone(),
+                },
+                slow: FaultyDeviceConfig {
+                    device: mem_config(6),
+                    injector: injectors[1].clone(),
+                },
+            };
+            let dev = TieredDevice::<FaultyMemDevice, FaultyMemDevice>::open(config.clone())
+                .await
+                .unwrap();
+            dev.write_manifest(vec![b'a'; 16]).await.unwrap();
+
+            // Only the write to one tier lands, the copy of the other tier is stale.
+            injectors[crashed].crash();
+            dev.write_manifest(vec![b'b'; 16]).await.unwrap();
+            drop(dev);
+
+            let dev = TieredDevice::<FaultyMemDevice, FaultyMemDevice>::open(config)
+                .await
+                .unwrap();
+            assert_eq!(
+                dev.read_manifest().await.unwrap(),
+                Some(vec![b'b'; 16]),
+                "crashed: {crashed}"
+            );
+        }
+    }
+}
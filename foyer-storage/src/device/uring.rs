@@ -0,0 +1,835 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::{
+    alloc::{AllocError, Allocator, Layout},
+    any::Any,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr::NonNull,
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+};
+
+use foyer_common::range::RangeBoundsExt;
+use io_uring::{opcode, types, IoUring};
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+use crate::region::RegionId;
+
+use super::{
+    allocator::AlignedAllocator,
+    error::{DeviceError, DeviceResult},
+    fs::{FsDevice, FsDeviceConfig},
+    Device, IoBuf, IoBufMut, IoRange,
+};
+
+#[derive(Debug, Clone)]
+pub struct UringDeviceConfig {
+    /// Config of the underlying fs device, which provides region files and metadata.
+    pub fs: FsDeviceConfig,
+
+    /// Submission queue depth of the io_uring instance.
+    pub queue_depth: u32,
+
+    /// Max count of registered io buffers, `0` disables buffer registration.
+    ///
+    /// Only buffers of at least region size are registered, which are the dirty buffers of the region manager.
+    pub registered_buffers: u32,
+}
+
+/// User data of the eventfd read that wakes up the driver.
+const WAKE: u64 = u64::MAX;
+
+/// Buffers that should be registered to the io_uring instance.
+#[derive(Debug)]
+struct Registry {
+    /// Allocations of at least `threshold` bytes are registered.
+    threshold: usize,
+    capacity: usize,
+    inner: Mutex<RegistryInner>,
+}
+
+#[derive(Debug, Default)]
+struct RegistryInner {
+    /// `(addr, len)` of registered buffers, indexed by the buffer index.
+    slots: Vec<Option<(usize, usize)>>,
+    /// Slots changed since the last sync with the kernel.
+    dirty: Vec<usize>,
+}
+
+impl Registry {
+    fn register(&self, addr: usize, len: usize) {
+        let mut inner = self.inner.lock();
+        let index = match inner.slots.iter().position(|slot| slot.is_none()) {
+            Some(index) => index,
+            None if inner.slots.len() < self.capacity => {
+                inner.slots.push(None);
+                inner.slots.len() - 1
+            }
+            // Buffers beyond the capacity are used without registration.
+            None => return,
+        };
+        inner.slots[index] = Some((addr, len));
+        inner.dirty.push(index);
+    }
+
+    fn unregister(&self, addr: usize) {
+        let mut inner = self.inner.lock();
+        if let Some(index) = inner
+            .slots
+            .iter()
+            .position(|slot| matches!(slot, Some((a, _)) if *a == addr))
+        {
+            inner.slots[index] = None;
+            inner.dirty.push(index);
+        }
+    }
+}
+
+/// [`AlignedAllocator`] that registers large buffers to the io_uring instance of a [`UringDevice`], so io on them
+/// skips page pinning.
+#[derive(Debug, Clone)]
+pub struct UringBufferAllocator {
+    aligned: AlignedAllocator,
+    registry: Arc<Registry>,
+}
+
+unsafe impl Allocator for UringBufferAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = self.aligned.allocate(layout)?;
+        if self.registry.capacity > 0 && layout.size() >= self.registry.threshold {
+            self.registry
+                .register(ptr.as_ptr() as *mut u8 as usize, layout.size());
+        }
+        Ok(ptr)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if self.registry.capacity > 0 && layout.size() >= self.registry.threshold {
+            self.registry.unregister(ptr.as_ptr() as usize);
+        }
+        self.aligned.deallocate(ptr, layout)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum OpKind {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Op {
+    kind: OpKind,
+    region: RegionId,
+    addr: usize,
+    len: usize,
+    offset: u64,
+}
+
+type Buffer = Box<dyn Any + Send>;
+
+/// User data of the `i`-th op of the inflight request at `index`.
+fn user_data(index: usize, i: usize) -> u64 {
+    ((index as u64) << 32) | i as u64
+}
+
+/// Ops submitted in a batch, the buffer is owned by the driver until all ops complete.
+struct Request {
+    ops: Vec<Op>,
+    buf: Buffer,
+    tx: oneshot::Sender<(io::Result<usize>, Buffer)>,
+}
+
+struct Inflight {
+    /// Ops of the request, advanced past the bytes done on short completions.
+    ops: Vec<Op>,
+    remaining: usize,
+    result: io::Result<usize>,
+    buf: Buffer,
+    tx: oneshot::Sender<(io::Result<usize>, Buffer)>,
+}
+
+/// Owns the io_uring instance, submits requests in batches and completes them on a dedicated thread.
+struct Driver {
+    /// Owns the region files ops are submitted to, so they are not closed before the requests queued when the device
+    /// is dropped complete.
+    fs: FsDevice,
+
+    ring: IoUring,
+    rx: Receiver<Request>,
+    eventfd: Arc<OwnedFd>,
+    wake_buf: Box<[u8; 8]>,
+
+    registry: Arc<Registry>,
+    /// Buffers registered to the kernel, indexed by the buffer index.
+    registered: Vec<Option<(usize, usize)>>,
+
+    inflights: Vec<Option<Inflight>>,
+    free: Vec<usize>,
+    count: usize,
+}
+
+impl Driver {
+    fn run(mut self) {
+        self.arm_wake();
+        loop {
+            let mut requests = vec![];
+            let disconnected = loop {
+                match self.rx.try_recv() {
+                    Ok(request) => requests.push(request),
+                    Err(TryRecvError::Empty) => break false,
+                    Err(TryRecvError::Disconnected) => break true,
+                }
+            };
+
+            if !requests.is_empty() {
+                // Buffers of the drained requests are allocated before they are sent, so their registrations are
+                // synced here before their ops are built.
+                self.sync_buffers();
+                for request in requests {
+                    self.push(request);
+                }
+            }
+
+            if disconnected && self.count == 0 {
+                return;
+            }
+
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(e)
+                    if e.raw_os_error() == Some(libc::EINTR)
+                        || e.raw_os_error() == Some(libc::EBUSY) => {}
+                Err(e) => {
+                    tracing::error!("io_uring driver exits on error: {}", e);
+                    self.fail_all(e);
+                    return;
+                }
+            }
+
+            self.reap();
+        }
+    }
+
+    fn arm_wake(&mut self) {
+        let sqe = opcode::Read::new(
+            types::Fd(self.eventfd.as_raw_fd()),
+            self.wake_buf.as_mut_ptr(),
+            8,
+        )
+        .build()
+        .user_data(WAKE);
+        self.push_sqe(&sqe);
+    }
+
+    fn push_sqe(&mut self, sqe: &io_uring::squeue::Entry) {
+        while unsafe { self.ring.submission().push(sqe) }.is_err() {
+            // The submission queue is full, submit the pushed entries to make room.
+            if let Err(e) = self.ring.submit() {
+                tracing::warn!("io_uring submit error: {}", e);
+            }
+        }
+    }
+
+    fn sync_buffers(&mut self) {
+        let updates = {
+            let mut inner = self.registry.inner.lock();
+            let dirty = std::mem::take(&mut inner.dirty);
+            dirty
+                .into_iter()
+                .map(|index| (index, inner.slots[index]))
+                .collect::<Vec<_>>()
+        };
+
+        for (index, slot) in updates {
+            if self.registered.len() <= index {
+                self.registered.resize(index + 1, None);
+            }
+            let iovec = match slot {
+                Some((addr, len)) => libc::iovec {
+                    iov_base: addr as *mut libc::c_void,
+                    iov_len: len,
+                },
+                None => libc::iovec {
+                    iov_base: std::ptr::null_mut(),
+                    iov_len: 0,
+                },
+            };
+            let res = unsafe {
+                self.ring
+                    .submitter()
+                    .register_buffers_update(index as u32, &[iovec], None)
+            };
+            self.registered[index] = match res {
+                Ok(()) => slot,
+                Err(e) => {
+                    tracing::warn!("failed to register io buffer {}: {}", index, e);
+                    None
+                }
+            };
+        }
+    }
+
+    /// Returns the index of the registered buffer that contains the range.
+    fn registered(&self, addr: usize, len: usize) -> Option<u16> {
+        self.registered
+            .iter()
+            .position(|slot| {
+                matches!(slot, Some((start, l)) if *start <= addr && addr + len <= *start + *l)
+            })
+            .map(|index| index as u16)
+    }
+
+    fn push(&mut self, request: Request) {
+        if request.ops.is_empty() {
+            let _ = request.tx.send((Ok(0), request.buf));
+            return;
+        }
+
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.inflights.push(None);
+                self.inflights.len() - 1
+            }
+        };
+
+        let sqes = request
+            .ops
+            .iter()
+            .enumerate()
+            .map(|(i, op)| self.sqe(op, user_data(index, i)))
+            .collect::<Vec<_>>();
+
+        self.inflights[index] = Some(Inflight {
+            ops: request.ops,
+            remaining: sqes.len(),
+            result: Ok(0),
+            buf: request.buf,
+            tx: request.tx,
+        });
+        self.count += 1;
+
+        for sqe in sqes {
+            self.push_sqe(&sqe);
+        }
+    }
+
+    fn sqe(&self, op: &Op, user_data: u64) -> io_uring::squeue::Entry {
+        let fd = types::Fd(self.fs.fd(op.region));
+        let ptr = op.addr as *mut u8;
+        let len = op.len as u32;
+        let sqe = match (op.kind, self.registered(op.addr, op.len)) {
+            (OpKind::Read, Some(buf_index)) => opcode::ReadFixed::new(fd, ptr, len, buf_index)
+                .offset(op.offset)
+                .build(),
+            (OpKind::Read, None) => opcode::Read::new(fd, ptr, len).offset(op.offset).build(),
+            (OpKind::Write, Some(buf_index)) => opcode::WriteFixed::new(fd, ptr, len, buf_index)
+                .offset(op.offset)
+                .build(),
+            (OpKind::Write, None) => opcode::Write::new(fd, ptr, len).offset(op.offset).build(),
+        };
+        sqe.user_data(user_data)
+    }
+
+    fn reap(&mut self) {
+        let cqes = self
+            .ring
+            .completion()
+            .map(|cqe| (cqe.user_data(), cqe.result()))
+            .collect::<Vec<_>>();
+
+        for (user_data, result) in cqes {
+            if user_data == WAKE {
+                self.arm_wake();
+                continue;
+            }
+
+            let (index, i) = ((user_data >> 32) as usize, user_data as u32 as usize);
+            let inflight = self.inflights[index].as_mut().unwrap();
+            let resubmit = match inflight.result {
+                Ok(_) if result < 0 => {
+                    inflight.result = Err(io::Error::from_raw_os_error(-result));
+                    None
+                }
+                Ok(ref mut len) => {
+                    let res = result as usize;
+                    *len += res;
+                    let op = &mut inflight.ops[i];
+                    match (op.kind, res) {
+                        (_, res) if res == op.len => None,
+                        // Reads end short at the end of the file, as reads of the fs device do.
+                        (OpKind::Read, 0) => None,
+                        (OpKind::Write, 0) => {
+                            inflight.result = Err(io::ErrorKind::WriteZero.into());
+                            None
+                        }
+                        // Resubmit the remaining bytes of a short completion.
+                        (_, res) => {
+                            op.addr += res;
+                            op.len -= res;
+                            op.offset += res as u64;
+                            Some(*op)
+                        }
+                    }
+                }
+                Err(_) => None,
+            };
+            if let Some(op) = resubmit {
+                let sqe = self.sqe(&op, user_data);
+                self.push_sqe(&sqe);
+                continue;
+            }
+
+            let inflight = self.inflights[index].as_mut().unwrap();
+            inflight.remaining -= 1;
+
+            if inflight.remaining == 0 {
+                let inflight = self.inflights[index].take().unwrap();
+                self.free.push(index);
+                self.count -= 1;
+                let _ = inflight.tx.send((inflight.result, inflight.buf));
+            }
+        }
+    }
+
+    fn fail_all(&mut self, e: io::Error) {
+        for inflight in self
+            .inflights
+            .iter_mut()
+            .filter_map(|inflight| inflight.take())
+        {
+            let _ = inflight
+                .tx
+                .send((Err(io::Error::new(e.kind(), e.to_string())), inflight.buf));
+        }
+    }
+}
+
+#[derive(Debug)]
+struct UringDeviceInner {
+    fs: FsDevice,
+
+    tx: Option<Sender<Request>>,
+    eventfd: Arc<OwnedFd>,
+
+    io_buffer_allocator: UringBufferAllocator,
+}
+
+impl Drop for UringDeviceInner {
+    fn drop(&mut self) {
+        // Disconnect and wake up the driver, it exits after all inflight requests complete.
+        self.tx.take();
+        let _ = nix::unistd::write(self.eventfd.as_raw_fd(), &1u64.to_ne_bytes());
+    }
+}
+
+/// A device that submits reads and writes of the region files of an [`FsDevice`] with io_uring.
+///
+/// Metadata and flush are delegated to the [`FsDevice`].
+#[derive(Debug, Clone)]
+pub struct UringDevice {
+    inner: Arc<UringDeviceInner>,
+}
+
+impl Device for UringDevice {
+    type Config = UringDeviceConfig;
+    type IoBufferAllocator = UringBufferAllocator;
+
+    async fn open(config: UringDeviceConfig) -> DeviceResult<Self> {
+        Self::open(config).await
+    }
+
+    async fn write<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::len(&range).unwrap();
+        self.check(offset, len);
+
+        let buf = Box::new(buf);
+        let ops = vec![Op {
+            kind: OpKind::Write,
+            region,
+            addr: AsRef::<[u8]>::as_ref(&*buf)[range].as_ptr() as usize,
+            len,
+            offset,
+        }];
+        self.submit(ops, buf).await
+    }
+
+    async fn write_chunked<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::len(&range).unwrap();
+        self.check(offset, len);
+
+        let io_size = self.io_size();
+        let buf = Box::new(buf);
+        let addr = AsRef::<[u8]>::as_ref(&*buf)[range].as_ptr() as usize;
+        let ops = (0..len)
+            .step_by(io_size)
+            .map(|start| Op {
+                kind: OpKind::Write,
+                region,
+                addr: addr + start,
+                len: std::cmp::min(io_size, len - start),
+                offset: offset + start as u64,
+            })
+            .collect();
+        self.submit(ops, buf).await
+    }
+
+    async fn read<B>(
+        &self,
+        buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: u64,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBufMut,
+    {
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::len(&range).unwrap();
+        self.check(offset, len);
+
+        let mut buf = Box::new(buf);
+        let ops = vec![Op {
+            kind: OpKind::Read,
+            region,
+            addr: AsMut::<[u8]>::as_mut(&mut *buf)[range].as_mut_ptr() as usize,
+            len,
+            offset,
+        }];
+        self.submit(ops, buf).await
+    }
+
+    async fn flush(&self) -> DeviceResult<()> {
+        self.inner.fs.flush().await
+    }
+
+    async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+        self.inner.fs.discard(region).await
+    }
+
+    async fn read_manifest(&self) -> DeviceResult<Option<Vec<u8>>> {
+        self.inner.fs.read_manifest().await
+    }
+
+    async fn write_manifest(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        self.inner.fs.write_manifest(buf).await
+    }
+
+    async fn read_checkpoint(&self) -> DeviceResult<Option<Vec<u8>>> {
+        self.inner.fs.read_checkpoint().await
+    }
+
+    async fn write_checkpoint(&self, buf: Vec<u8>) -> DeviceResult<()> {
+        self.inner.fs.write_checkpoint(buf).await
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.fs.capacity()
+    }
+
+    fn regions(&self) -> usize {
+        self.inner.fs.regions()
+    }
+
+    fn align(&self) -> usize {
+        self.inner.fs.align()
+    }
+
+    fn io_size(&self) -> usize {
+        self.inner.fs.io_size()
+    }
+
+    fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+        &self.inner.io_buffer_allocator
+    }
+
+    fn io_buffer(&self, len: usize, capacity: usize) -> Vec<u8, Self::IoBufferAllocator> {
+        assert!(len <= capacity);
+        let mut buf = Vec::with_capacity_in(capacity, self.inner.io_buffer_allocator.clone());
+        unsafe { buf.set_len(len) };
+        buf
+    }
+}
+
+impl UringDevice {
+    pub async fn open(config: UringDeviceConfig) -> DeviceResult<Self> {
+        let fs = FsDevice::open(config.fs.clone()).await?;
+
+        let ring = IoUring::new(config.queue_depth)?;
+
+        let mut capacity = config.registered_buffers as usize;
+        if capacity > 0
+            && let Err(e) = ring.submitter().register_buffers_sparse(config.registered_buffers)
+        {
+            tracing::warn!("io buffer registration is disabled: {}", e);
+            capacity = 0;
+        }
+        let registry = Arc::new(Registry {
+            threshold: fs.region_size(),
+            capacity,
+            inner: Mutex::new(RegistryInner::default()),
+        });
+
+        let eventfd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if eventfd < 0 {
+            return Err(DeviceError::from(io::Error::last_os_error()));
+        }
+        let eventfd = Arc::new(unsafe { OwnedFd::from_raw_fd(eventfd) });
+
+        let (tx, rx) = mpsc::channel();
+        let driver = Driver {
+            fs: fs.clone(),
+            ring,
+            rx,
+            eventfd: eventfd.clone(),
+            wake_buf: Box::new([0; 8]),
+            registry: registry.clone(),
+            registered: vec![],
+            inflights: vec![],
+            free: vec![],
+            count: 0,
+        };
+        std::thread::Builder::new()
+            .name("foyer-uring".to_string())
+            .spawn(move || driver.run())?;
+
+        let io_buffer_allocator = UringBufferAllocator {
+            aligned: AlignedAllocator::new(config.fs.align),
+            registry,
+        };
+
+        let inner = UringDeviceInner {
+            fs,
+            tx: Some(tx),
+            eventfd,
+            io_buffer_allocator,
+        };
+
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    fn check(&self, offset: u64, len: usize) {
+        let region_size = self.region_size();
+        assert!(
+            offset as usize + len <= region_size,
+            "offset ({offset}) + len ({len}) <= region size ({region_size})"
+        );
+    }
+
+    async fn submit<B>(&self, ops: Vec<Op>, buf: Box<B>) -> (DeviceResult<usize>, B)
+    where
+        B: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let request = Request { ops, buf, tx };
+
+        let sent = self.inner.tx.as_ref().unwrap().send(request);
+        let (res, buf) = match sent {
+            Ok(()) => {
+                let _ = nix::unistd::write(self.inner.eventfd.as_raw_fd(), &1u64.to_ne_bytes());
+                rx.await
+                    .expect("io_uring driver exited with inflight request")
+            }
+            Err(mpsc::SendError(request)) => (
+                Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "io_uring driver exited",
+                )),
+                request.buf,
+            ),
+        };
+        let buf = *buf.downcast::<B>().unwrap();
+        (res.map_err(DeviceError::from), buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bytes::BufMut;
+
+    use super::*;
+
+    const FILES: usize = 8;
+    const FILE_CAPACITY: usize = 64 * 1024; // 64 KiB
+    const CAPACITY: usize = FILES * FILE_CAPACITY; // 512 KiB
+    const ALIGN: usize = 4 * 1024;
+
+    fn config(dir: PathBuf, registered_buffers: u32) -> UringDeviceConfig {
+        UringDeviceConfig {
+            fs: FsDeviceConfig {
+                dir,
+                capacity: CAPACITY,
+                file_capacity: FILE_CAPACITY,
+                align: ALIGN,
+                io_size: ALIGN,
+                reformat: false,
+                preallocate: false,
+            },
+            queue_depth: 8,
+            registered_buffers,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_uring_device_simple() {
+        let dir = tempfile::tempdir().unwrap();
+        let dev = UringDevice::open(config(PathBuf::from(dir.path()), 0))
+            .await
+            .unwrap();
+
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+        let mut rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut rbuffer[..]).put_slice(&[0; ALIGN]);
+
+        let (res, wbuffer) = dev.write(wbuffer, .., 0, 0).await;
+        assert_eq!(res.unwrap(), ALIGN);
+        let (res, rbuffer) = dev.read(rbuffer, .., 0, 0).await;
+        assert_eq!(res.unwrap(), ALIGN);
+
+        assert_eq!(&wbuffer, &rbuffer);
+    }
+
+    #[tokio::test]
+    async fn test_uring_device_short_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let dev = UringDevice::open(config(PathBuf::from(dir.path()), 0))
+            .await
+            .unwrap();
+
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        wbuffer.fill(b'x');
+        let (res, _) = dev.write(wbuffer, .., 0, ALIGN as u64).await;
+        assert_eq!(res.unwrap(), ALIGN);
+
+        // The region file is not preallocated, the read ends short at the end of the file, the remaining block is
+        // resubmitted and reads nothing.
+        let rbuffer = dev.io_buffer(3 * ALIGN, 3 * ALIGN);
+        let (res, rbuffer) = dev.read(rbuffer, .., 0, 0).await;
+        assert_eq!(res.unwrap(), 2 * ALIGN);
+        assert!(rbuffer[..ALIGN].iter().all(|b| *b == 0));
+        assert!(rbuffer[ALIGN..2 * ALIGN].iter().all(|b| *b == b'x'));
+    }
+
+    #[tokio::test]
+    async fn test_uring_device_drop_queued() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(PathBuf::from(dir.path()), 0);
+        let dev = UringDevice::open(config.clone()).await.unwrap();
+
+        // The request is queued by the first poll, then the write and the device are dropped before it completes.
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        wbuffer.fill(b'x');
+        let mut write = Box::pin(dev.write(wbuffer, .., 1, 0));
+        let _ = futures::poll!(write.as_mut());
+        drop(write);
+        drop(dev);
+
+        // The driver still writes to the region file.
+        let dev = UringDevice::open(config).await.unwrap();
+        let mut rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        for _ in 0..100 {
+            let (res, buf) = dev.read(rbuffer, .., 1, 0).await;
+            rbuffer = buf;
+            if res.unwrap() == ALIGN && rbuffer.iter().all(|b| *b == b'x') {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("the queued write is lost");
+    }
+
+    #[tokio::test]
+    async fn test_uring_device_registered_chunked() {
+        let dir = tempfile::tempdir().unwrap();
+        let dev = UringDevice::open(config(PathBuf::from(dir.path()), 4))
+            .await
+            .unwrap();
+
+        // Buffers of region size are registered.
+        let mut wbuffer = dev.io_buffer(FILE_CAPACITY, FILE_CAPACITY);
+        for (i, chunk) in wbuffer.chunks_mut(ALIGN).enumerate() {
+            chunk.fill(i as u8);
+        }
+        assert_eq!(
+            dev.io_buffer_allocator()
+                .registry
+                .inner
+                .lock()
+                .slots
+                .iter()
+                .flatten()
+                .count(),
+            1
+        );
+
+        let (res, wbuffer) = dev.write_chunked(wbuffer, .., 3, 0).await;
+        assert_eq!(res.unwrap(), FILE_CAPACITY);
+
+        // A small unregistered buffer reads a part of the chunks written from the registered buffer.
+        let rbuffer = dev.io_buffer(2 * ALIGN, 2 * ALIGN);
+        let (res, rbuffer) = dev.read(rbuffer, .., 3, 5 * ALIGN as u64).await;
+        assert_eq!(res.unwrap(), 2 * ALIGN);
+        assert_eq!(&rbuffer[..], &wbuffer[5 * ALIGN..7 * ALIGN]);
+
+        let rbuffer = dev.io_buffer(FILE_CAPACITY, FILE_CAPACITY);
+        let (res, rbuffer) = dev.read(rbuffer, .., 3, 0).await;
+        assert_eq!(res.unwrap(), FILE_CAPACITY);
+        assert_eq!(&rbuffer, &wbuffer);
+
+        drop(wbuffer);
+        drop(rbuffer);
+        assert_eq!(
+            dev.io_buffer_allocator()
+                .registry
+                .inner
+                .lock()
+                .slots
+                .iter()
+                .flatten()
+                .count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use aes_gcm::{aead::AeadInPlace, Aes256Gcm, KeyInit, Nonce, Tag};
+use bytes::{Buf, BufMut};
+use itertools::Itertools;
+use rand::RngCore;
+
+/// Id of an encryption key, recorded with each encrypted buffer to find the key to decrypt it.
+pub type KeyId = u32;
+
+/// Key of AES-256-GCM.
+pub type EncryptionKey = [u8; 32];
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// Bytes added to each encrypted buffer.
+pub const ENCRYPTION_OVERHEAD: usize = 4 + NONCE_LEN + TAG_LEN;
+
+/// Supplies keys to encrypt and decrypt entries.
+///
+/// Keys are rotated by switching the current key, entries written with older keys are still readable as long as their
+/// keys are provided. A key id must never be reused for another key.
+pub trait KeyProvider: Send + Sync + Debug + 'static {
+    /// Id and key to encrypt newly written entries.
+    fn current(&self) -> (KeyId, EncryptionKey);
+
+    /// Returns `None` if the key is no longer provided, entries encrypted with it are treated as missing.
+    fn key(&self, id: KeyId) -> Option<EncryptionKey>;
+}
+
+/// [`KeyProvider`] with a fixed set of keys.
+#[derive(Clone)]
+pub struct StaticKeyProvider {
+    current: KeyId,
+    keys: HashMap<KeyId, EncryptionKey>,
+}
+
+impl StaticKeyProvider {
+    pub fn new(id: KeyId, key: EncryptionKey) -> Self {
+        Self {
+            current: id,
+            keys: HashMap::from([(id, key)]),
+        }
+    }
+
+    /// Keep a retired key to decrypt entries written with it.
+    pub fn with_retired(mut self, id: KeyId, key: EncryptionKey) -> Self {
+        self.keys.entry(id).or_insert(key);
+        self
+    }
+}
+
+impl Debug for StaticKeyProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the keys.
+        f.debug_struct("StaticKeyProvider")
+            .field("current", &self.current)
+            .field("keys", &self.keys.keys().sorted().collect_vec())
+            .finish()
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn current(&self) -> (KeyId, EncryptionKey) {
+        (self.current, self.keys[&self.current])
+    }
+
+    fn key(&self, id: KeyId) -> Option<EncryptionKey> {
+        self.keys.get(&id).copied()
+    }
+}
+
+/// Authenticated encryption with AES-256-GCM, with keys from a [`KeyProvider`].
+#[derive(Debug, Clone)]
+pub struct Encryption {
+    provider: Arc<dyn KeyProvider>,
+}
+
+impl Encryption {
+    pub fn new(provider: Arc<dyn KeyProvider>) -> Self {
+        Self { provider }
+    }
+
+    pub fn provider(&self) -> &Arc<dyn KeyProvider> {
+        &self.provider
+    }
+
+    /// | key id | nonce | ciphertext | tag |
+    ///
+    /// Encrypted with the current key and a random nonce. `aad` is authenticated but not stored, the same `aad` must
+    /// be given to decrypt.
+    pub fn encrypt(&self, buf: &[u8], aad: &[u8]) -> Vec<u8> {
+        let (id, key) = self.provider.current();
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut out = Vec::with_capacity(buf.len() + ENCRYPTION_OVERHEAD);
+        out.put_u32(id);
+        out.put_slice(&nonce);
+        out.put_slice(buf);
+        let tag = Aes256Gcm::new(&key.into())
+            .encrypt_in_place_detached(Nonce::from_slice(&nonce), aad, &mut out[4 + NONCE_LEN..])
+            .expect("buffers to encrypt never exceed the limit of AES-GCM");
+        out.put_slice(&tag);
+        out
+    }
+
+    /// Returns `None` if the key is not provided, or the authentication of `buf` and `aad` fails.
+    pub fn decrypt(&self, buf: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+        if buf.len() < ENCRYPTION_OVERHEAD {
+            return None;
+        }
+        let (mut head, rest) = buf.split_at(4 + NONCE_LEN);
+        let id = head.get_u32();
+        let key = self.provider.key(id)?;
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+
+        let mut out = ciphertext.to_vec();
+        Aes256Gcm::new(&key.into())
+            .decrypt_in_place_detached(Nonce::from_slice(head), aad, &mut out, Tag::from_slice(tag))
+            .ok()?;
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encryption() {
+        let data = b"hello, foyer".repeat(64);
+
+        let old = Encryption::new(Arc::new(StaticKeyProvider::new(1, [1; 32])));
+        let encrypted = old.encrypt(&data, b"aad");
+        assert_eq!(encrypted.len(), data.len() + ENCRYPTION_OVERHEAD);
+        assert_ne!(
+            &encrypted[4 + NONCE_LEN..encrypted.len() - TAG_LEN],
+            &data[..]
+        );
+        assert_eq!(old.decrypt(&encrypted, b"aad").unwrap(), data);
+        // Nonces are never reused.
+        assert_ne!(old.encrypt(&data, b"aad"), encrypted);
+
+        // Tampered buffers fail the authentication.
+        for i in [0, 4, 4 + NONCE_LEN, encrypted.len() - 1] {
+            let mut tampered = encrypted.clone();
+            tampered[i] ^= 1;
+            assert!(old.decrypt(&tampered, b"aad").is_none());
+        }
+        assert!(old
+            .decrypt(&encrypted[..ENCRYPTION_OVERHEAD - 1], b"aad")
+            .is_none());
+        // So does a different associated data.
+        assert!(old.decrypt(&encrypted, b"aae").is_none());
+        assert!(old.decrypt(&encrypted, b"").is_none());
+
+        // Entries written with a retired key are readable only if the key is still provided.
+        let rotated = Encryption::new(Arc::new(StaticKeyProvider::new(2, [2; 32])));
+        assert!(rotated.decrypt(&encrypted, b"aad").is_none());
+        let rotated = Encryption::new(Arc::new(
+            StaticKeyProvider::new(2, [2; 32]).with_retired(1, [1; 32]),
+        ));
+        assert_eq!(rotated.decrypt(&encrypted, b"aad").unwrap(), data);
+        assert_eq!(old.decrypt(&rotated.encrypt(&data, b"aad"), b"aad"), None);
+
+        // A wrong key of the same id fails the authentication as well.
+        let wrong = Encryption::new(Arc::new(StaticKeyProvider::new(1, [3; 32])));
+        assert!(wrong.decrypt(&encrypted, b"aad").is_none());
+    }
+}
@@ -0,0 +1,725 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::{borrow::Cow, ops::Range};
+
+use bitflags::bitflags;
+use bytes::{Buf, BufMut};
+use foyer_common::code::{Key, Value};
+
+use crate::{
+    catalog::{ExpireAt, Sequence},
+    checksum::ChecksumAlgorithm,
+    compress::Compression,
+    encrypt::{Encryption, ENCRYPTION_OVERHEAD},
+};
+
+const ENTRY_MAGIC: u32 = 0x97_00_00_00;
+const ENTRY_MAGIC_MASK: u32 = 0xFF_00_00_00;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(crate) struct EntryFlags: u16 {
+        /// The entry is a tombstone of its key, it has no value.
+        const TOMBSTONE = 1;
+        /// The entry is a batch of entries packed without alignment, it has no key.
+        const BATCH = 2;
+        /// The entry is a chunk of a large entry, its value is a slice of the serialized value.
+        const CHUNK = 4;
+        /// The value of the entry is compressed, with the algorithm and the uncompressed length in the header.
+        const COMPRESSED = 8;
+        /// The key and the value of the entry are encrypted, bound to the header and the key by the associated data,
+        /// see [`EntryHeader::aad`].
+        const ENCRYPTED = 16;
+    }
+}
+
+/// Length of the chunk index and chunk count written before the payload of a chunk.
+pub(crate) const CHUNK_META_LEN: usize = 4 + 4;
+
+/// `value_len` is the length of the value stored, and `uncompressed_len` the length of the serialized value.
+#[derive(Debug, Clone)]
+pub(crate) struct EntryHeader {
+    pub(crate) key_len: u32,
+    pub(crate) value_len: u32,
+    pub(crate) sequence: Sequence,
+    pub(crate) expire_at: ExpireAt,
+    pub(crate) checksum: u64,
+    pub(crate) uncompressed_len: u32,
+    pub(crate) flags: EntryFlags,
+    pub(crate) checksum_algorithm: ChecksumAlgorithm,
+    pub(crate) compression: Compression,
+}
+
+impl EntryHeader {
+    pub(crate) fn serialized_len() -> usize {
+        4 + 4 + 8 + 8 + 8 + 4 + 2 + 1 + 1
+    }
+
+    fn write(&self, mut buf: &mut [u8]) {
+        buf.put_u32(self.key_len | ENTRY_MAGIC);
+        buf.put_u32(self.value_len);
+        buf.put_u64(self.sequence);
+        buf.put_u64(self.expire_at);
+        buf.put_u64(self.checksum);
+        buf.put_u32(self.uncompressed_len);
+        buf.put_u16(self.flags.bits());
+        buf.put_u8(self.checksum_algorithm.to_u8());
+        buf.put_u8(self.compression.to_u8());
+    }
+
+    pub(crate) fn read(mut buf: &[u8]) -> Option<Self> {
+        let head = buf.get_u32();
+        let magic = head & ENTRY_MAGIC_MASK;
+
+        if magic != ENTRY_MAGIC {
+            return None;
+        }
+
+        let key_len = head ^ ENTRY_MAGIC;
+        let value_len = buf.get_u32();
+        let sequence = buf.get_u64();
+        let expire_at = buf.get_u64();
+        let checksum = buf.get_u64();
+        let uncompressed_len = buf.get_u32();
+        let flags = EntryFlags::from_bits(buf.get_u16())?;
+        let checksum_algorithm = ChecksumAlgorithm::from_u8(buf.get_u8())?;
+        let compression = Compression::from_u8(buf.get_u8())?;
+
+        Some(Self {
+            key_len,
+            value_len,
+            sequence,
+            expire_at,
+            checksum,
+            uncompressed_len,
+            flags,
+            checksum_algorithm,
+            compression,
+        })
+    }
+
+    /// Checksum of the body stored, the uncompressed length of a compressed value is covered as well, so it is verified
+    /// before the value is decompressed to it.
+    pub(crate) fn checksum(&self, body: &[u8]) -> u64 {
+        if !self.flags.contains(EntryFlags::COMPRESSED) {
+            return self.checksum_algorithm.checksum(body);
+        }
+        let mut hasher = self.checksum_algorithm.hasher();
+        hasher.write(body);
+        hasher.write(&self.uncompressed_len.to_be_bytes());
+        hasher.finish()
+    }
+
+    /// Associated data of the encrypted key, the associated data of the encrypted value is followed by the key stored.
+    ///
+    /// The lengths and the checksum differ among the chunks of an entry and are left out, the lengths are authenticated
+    /// with the ciphertexts.
+    fn aad(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(8 + 8 + 4 + 2 + 1);
+        buf.put_u64(self.sequence);
+        buf.put_u64(self.expire_at);
+        buf.put_u32(self.uncompressed_len);
+        buf.put_u16(self.flags.bits());
+        buf.put_u8(self.compression.to_u8());
+        buf
+    }
+
+    /// Returns the serialized value from the value stored, `key` is the key stored of the entry.
+    pub(crate) fn decode<'a>(
+        &self,
+        value: &'a [u8],
+        key: &[u8],
+        encryption: Option<&Encryption>,
+    ) -> Option<Cow<'a, [u8]>> {
+        if !self.is_encrypted_as(encryption) {
+            return None;
+        }
+        let value = match self.flags.contains(EntryFlags::ENCRYPTED) {
+            true => {
+                let mut aad = self.aad();
+                aad.extend_from_slice(key);
+                Cow::Owned(self.decrypt(value, &aad, encryption)?)
+            }
+            false => Cow::Borrowed(value),
+        };
+        if !self.flags.contains(EntryFlags::COMPRESSED) {
+            return Some(value);
+        }
+        let decompressed = self
+            .compression
+            .decompress(&value, self.uncompressed_len as usize);
+        if decompressed.is_none() {
+            tracing::warn!(
+                "value decompression failed, compression: {:?}",
+                self.compression
+            );
+        }
+        decompressed.map(Cow::Owned)
+    }
+
+    /// Returns the key from the key stored, `None` if it fails to decrypt or is not encrypted with encryption enabled.
+    pub(crate) fn read_key<K>(&self, key: &[u8], encryption: Option<&Encryption>) -> Option<K>
+    where
+        K: Key,
+    {
+        if !self.is_encrypted_as(encryption) {
+            return None;
+        }
+        match self.flags.contains(EntryFlags::ENCRYPTED) {
+            true => self
+                .decrypt(key, &self.aad(), encryption)
+                .map(|buf| K::read(&buf)),
+            false => Some(K::read(key)),
+        }
+    }
+
+    /// Entries must be encrypted if encryption is enabled, or they may be forged by anyone who can write the device.
+    fn is_encrypted_as(&self, encryption: Option<&Encryption>) -> bool {
+        if encryption.is_some() && !self.flags.contains(EntryFlags::ENCRYPTED) {
+            tracing::warn!(
+                "unencrypted entry with encryption enabled, sequence: {}",
+                self.sequence
+            );
+            return false;
+        }
+        true
+    }
+
+    fn decrypt(&self, buf: &[u8], aad: &[u8], encryption: Option<&Encryption>) -> Option<Vec<u8>> {
+        let decrypted = encryption.and_then(|encryption| encryption.decrypt(buf, aad));
+        if decrypted.is_none() {
+            tracing::warn!(
+                "entry decryption failed, encryption enabled: {}, sequence: {}",
+                encryption.is_some(),
+                self.sequence
+            );
+        }
+        decrypted
+    }
+}
+
+/// Key of an entry to write, serialized beforehand if encryption is enabled.
+///
+/// The key is encrypted when written, with the header of the entry as the associated data.
+pub(crate) enum EntryKey<'a, K> {
+    Key(&'a K),
+    Encrypted {
+        buf: Vec<u8>,
+        encryption: Encryption,
+    },
+}
+
+impl<'a, K> EntryKey<'a, K>
+where
+    K: Key,
+{
+    pub(crate) fn new(key: &'a K, encryption: Option<&Encryption>) -> Self {
+        match encryption {
+            Some(encryption) => {
+                let mut buf = vec![0; key.serialized_len()];
+                key.write(&mut buf);
+                Self::Encrypted {
+                    buf,
+                    encryption: encryption.clone(),
+                }
+            }
+            None => Self::Key(key),
+        }
+    }
+
+    /// Length of the key stored.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Key(key) => key.serialized_len(),
+            Self::Encrypted { buf, .. } => buf.len() + ENCRYPTION_OVERHEAD,
+        }
+    }
+
+    /// Returns the key stored, `header` must be encoded with [`EntryKey::encode`] and [`EntryValue::encode`].
+    pub(crate) fn bytes(&self, header: &EntryHeader) -> Vec<u8> {
+        match self {
+            Self::Key(key) => {
+                let mut buf = vec![0; key.serialized_len()];
+                key.write(&mut buf);
+                buf
+            }
+            Self::Encrypted { buf, encryption } => encryption.encrypt(buf, &header.aad()),
+        }
+    }
+
+    fn write(&self, buf: &mut [u8], header: &EntryHeader) {
+        match self {
+            Self::Key(key) => key.write(buf),
+            Self::Encrypted { .. } => buf.copy_from_slice(&self.bytes(header)),
+        }
+    }
+
+    pub(crate) fn encode(&self, header: &mut EntryHeader) {
+        if let Self::Encrypted { .. } = self {
+            header.flags |= EntryFlags::ENCRYPTED;
+        }
+    }
+}
+
+/// Value of an entry to write, serialized and compressed beforehand if compression or encryption is enabled.
+///
+/// The value is encrypted when written, with the header and the key stored of the entry as the associated data.
+pub(crate) enum EntryValue<'a, V> {
+    Value(&'a V),
+    Serialized {
+        buf: Vec<u8>,
+        uncompressed_len: usize,
+        compression: Compression,
+        encryption: Option<Encryption>,
+    },
+}
+
+impl<'a, V> EntryValue<'a, V>
+where
+    V: Value,
+{
+    pub(crate) fn new(
+        value: &'a V,
+        compression: Compression,
+        encryption: Option<&Encryption>,
+    ) -> Self {
+        if compression == Compression::None && encryption.is_none() {
+            return Self::Value(value);
+        }
+        let mut buf = vec![0; value.serialized_len()];
+        value.write(&mut buf);
+        let uncompressed_len = buf.len();
+        // Values that don't shrink are stored as serialized.
+        let (buf, compression) = match compression.compress(&buf) {
+            Some(compressed) => (compressed, compression),
+            None => (buf, Compression::None),
+        };
+        Self::Serialized {
+            buf,
+            uncompressed_len,
+            compression,
+            encryption: encryption.cloned(),
+        }
+    }
+
+    /// Length of the value stored.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Value(value) => value.serialized_len(),
+            Self::Serialized {
+                buf, encryption, ..
+            } => match encryption {
+                Some(_) => buf.len() + ENCRYPTION_OVERHEAD,
+                None => buf.len(),
+            },
+        }
+    }
+
+    fn write(&self, buf: &mut [u8], header: &EntryHeader, key: &[u8]) {
+        match self {
+            Self::Value(value) => value.write(buf),
+            Self::Serialized { .. } => buf.copy_from_slice(&self.bytes(header, key)),
+        }
+    }
+
+    /// Returns the value stored, `header` must be encoded with [`EntryKey::encode`] and [`EntryValue::encode`], and
+    /// `key` is the key stored.
+    pub(crate) fn bytes(&self, header: &EntryHeader, key: &[u8]) -> Cow<'_, [u8]> {
+        match self {
+            Self::Value(value) => {
+                let mut buf = vec![0; value.serialized_len()];
+                value.write(&mut buf);
+                Cow::Owned(buf)
+            }
+            Self::Serialized {
+                buf,
+                encryption: Some(encryption),
+                ..
+            } => {
+                let mut aad = header.aad();
+                aad.extend_from_slice(key);
+                Cow::Owned(encryption.encrypt(buf, &aad))
+            }
+            Self::Serialized { buf, .. } => Cow::Borrowed(buf),
+        }
+    }
+
+    /// Fill the compression and encryption fields of the header.
+    pub(crate) fn encode(&self, header: &mut EntryHeader) {
+        let Self::Serialized {
+            uncompressed_len,
+            compression,
+            encryption,
+            ..
+        } = self
+        else {
+            return;
+        };
+        if *compression != Compression::None {
+            header.flags |= EntryFlags::COMPRESSED;
+            header.uncompressed_len = *uncompressed_len as u32;
+            header.compression = *compression;
+        }
+        if encryption.is_some() {
+            header.flags |= EntryFlags::ENCRYPTED;
+        }
+    }
+}
+
+/// | header | value | key | <padding> |
+///
+/// # Safety
+///
+/// `buf.len()` must excatly fit entry size
+pub(crate) fn write_entry<K, V>(
+    buf: &mut [u8],
+    key: &EntryKey<'_, K>,
+    value: &EntryValue<'_, V>,
+    sequence: Sequence,
+    expire_at: ExpireAt,
+    checksum_algorithm: ChecksumAlgorithm,
+) where
+    K: Key,
+    V: Value,
+{
+    let mut header = EntryHeader {
+        key_len: key.len() as u32,
+        value_len: value.len() as u32,
+        sequence,
+        expire_at,
+        checksum: 0,
+        uncompressed_len: value.len() as u32,
+        flags: EntryFlags::empty(),
+        checksum_algorithm,
+        compression: Compression::None,
+    };
+    key.encode(&mut header);
+    value.encode(&mut header);
+
+    // The key is written first, the value is bound to the key stored.
+    let offset = EntryHeader::serialized_len();
+    let end = offset + value.len() + key.len();
+    let (value_buf, key_buf) = buf[offset..end].split_at_mut(value.len());
+    key.write(key_buf, &header);
+    value.write(value_buf, &header, key_buf);
+    header.checksum = header.checksum(&buf[offset..end]);
+    header.write(&mut buf[..EntryHeader::serialized_len()]);
+}
+
+/// | header | key | <padding> |
+///
+/// # Safety
+///
+/// `buf.len()` must excatly fit entry size
+pub(crate) fn write_tombstone<K>(
+    buf: &mut [u8],
+    key: &EntryKey<'_, K>,
+    sequence: Sequence,
+    checksum_algorithm: ChecksumAlgorithm,
+) where
+    K: Key,
+{
+    let mut header = EntryHeader {
+        key_len: key.len() as u32,
+        value_len: 0,
+        sequence,
+        expire_at: 0,
+        checksum: 0,
+        uncompressed_len: 0,
+        flags: EntryFlags::TOMBSTONE,
+        checksum_algorithm,
+        compression: Compression::None,
+    };
+    key.encode(&mut header);
+
+    let offset = EntryHeader::serialized_len();
+    key.write(&mut buf[offset..offset + key.len()], &header);
+    header.checksum = checksum_algorithm.checksum(&buf[offset..offset + key.len()]);
+    header.write(&mut buf[..EntryHeader::serialized_len()]);
+}
+
+/// | header | entry | entry | ... | <padding> |
+///
+/// Entries are serialized with [`write_entry`] and packed without alignment.
+///
+/// # Safety
+///
+/// `buf.len()` must excatly fit batch size
+pub(crate) fn write_batch<'a>(
+    buf: &mut [u8],
+    entries: impl IntoIterator<Item = &'a [u8]>,
+    sequence: Sequence,
+    checksum_algorithm: ChecksumAlgorithm,
+) {
+    let mut offset = EntryHeader::serialized_len();
+    for entry in entries {
+        buf[offset..offset + entry.len()].copy_from_slice(entry);
+        offset += entry.len();
+    }
+    let checksum = checksum_algorithm.checksum(&buf[EntryHeader::serialized_len()..offset]);
+
+    let header = EntryHeader {
+        key_len: 0,
+        value_len: (offset - EntryHeader::serialized_len()) as u32,
+        sequence,
+        expire_at: 0,
+        checksum,
+        uncompressed_len: 0,
+        flags: EntryFlags::BATCH,
+        checksum_algorithm,
+        compression: Compression::None,
+    };
+    header.write(&mut buf[..EntryHeader::serialized_len()]);
+}
+
+/// | header | index | count | payload | key | <padding> |
+///
+/// `payload` is the `index`-th of `count` slices of the value stored, and `key` the key stored shared by all chunks.
+/// `header` carries the fields shared by all chunks of the entry, the lengths and the checksum are filled here.
+///
+/// # Safety
+///
+/// `buf.len()` must excatly fit chunk size
+pub(crate) fn write_chunk(
+    buf: &mut [u8],
+    key: &[u8],
+    payload: &[u8],
+    index: u32,
+    count: u32,
+    mut header: EntryHeader,
+) {
+    let mut offset = EntryHeader::serialized_len();
+    (&mut buf[offset..offset + CHUNK_META_LEN]).put_u32(index);
+    (&mut buf[offset + 4..offset + CHUNK_META_LEN]).put_u32(count);
+    offset += CHUNK_META_LEN;
+    buf[offset..offset + payload.len()].copy_from_slice(payload);
+    offset += payload.len();
+    buf[offset..offset + key.len()].copy_from_slice(key);
+    offset += key.len();
+    header.checksum = header.checksum(&buf[EntryHeader::serialized_len()..offset]);
+    header.key_len = key.len() as u32;
+    header.value_len = (CHUNK_META_LEN + payload.len()) as u32;
+    header.write(&mut buf[..EntryHeader::serialized_len()]);
+}
+
+/// | header | index | count | payload | key | <padding> |
+///
+/// Returns the header, the payload and the key stored of the chunk. The checksum is verified with the algorithm
+/// recorded in the header if `verify` is set.
+pub(crate) fn read_chunk(buf: &[u8], verify: bool) -> Option<(EntryHeader, &[u8], &[u8])> {
+    let header = EntryHeader::read(buf)?;
+
+    if !header.flags.contains(EntryFlags::CHUNK)
+        || (header.value_len as usize) < CHUNK_META_LEN
+        || EntryHeader::serialized_len() + header.value_len as usize + header.key_len as usize
+            > buf.len()
+    {
+        return None;
+    }
+
+    let start = EntryHeader::serialized_len();
+    let end = start + header.value_len as usize;
+
+    if verify {
+        let checksum = header.checksum(&buf[start..end + header.key_len as usize]);
+        if checksum != header.checksum {
+            tracing::warn!(
+                "chunk checksum mismatch, checksum: {}, expected: {}",
+                checksum,
+                header.checksum,
+            );
+            return None;
+        }
+    }
+
+    let key = &buf[end..end + header.key_len as usize];
+    Some((header, &buf[start + CHUNK_META_LEN..end], key))
+}
+
+/// | header | value | key | <padding> |
+///
+/// The checksum is verified with the algorithm recorded in the header if `verify` is set. Returns `None` if the entry
+/// is encrypted and fails to decrypt with `encryption`.
+///
+/// # Safety
+///
+/// `buf.len()` must excatly fit entry size
+pub(crate) fn read_entry<K, V>(
+    buf: &[u8],
+    verify: bool,
+    encryption: Option<&Encryption>,
+) -> Option<(K, V)>
+where
+    K: Key,
+    V: Value,
+{
+    let (key, value) = read_entry_raw(buf, verify, encryption)?;
+    let value = match value {
+        RawValue::Range(range) => V::read(&buf[range]),
+        RawValue::Decoded(buf) => V::read(&buf),
+    };
+    Some((key, value))
+}
+
+/// Serialized value of an entry read by [`read_entry_raw`].
+pub(crate) enum RawValue {
+    /// Range of the value in the buffer read, the value is stored as serialized.
+    Range(Range<usize>),
+    /// The value is decompressed or decrypted from the value stored.
+    Decoded(Vec<u8>),
+}
+
+/// Same as [`read_entry`], but the value is left serialized.
+pub(crate) fn read_entry_raw<K>(
+    buf: &[u8],
+    verify: bool,
+    encryption: Option<&Encryption>,
+) -> Option<(K, RawValue)>
+where
+    K: Key,
+{
+    let header = EntryHeader::read(buf)?;
+
+    if header
+        .flags
+        .intersects(EntryFlags::TOMBSTONE | EntryFlags::BATCH | EntryFlags::CHUNK)
+    {
+        return None;
+    }
+
+    let mut offset = EntryHeader::serialized_len();
+    let value = &buf[offset..offset + header.value_len as usize];
+    offset += header.value_len as usize;
+    let key = &buf[offset..offset + header.key_len as usize];
+    offset += header.key_len as usize;
+
+    if verify {
+        let checksum = header.checksum(&buf[EntryHeader::serialized_len()..offset]);
+        if checksum != header.checksum {
+            tracing::warn!(
+                "checksum mismatch, checksum: {}, expected: {}",
+                checksum,
+                header.checksum,
+            );
+            return None;
+        }
+    }
+
+    let stored = key;
+    let key = header.read_key(stored, encryption)?;
+    let value = match header.decode(value, stored, encryption)? {
+        Cow::Borrowed(value) => RawValue::Range(
+            EntryHeader::serialized_len()..EntryHeader::serialized_len() + value.len(),
+        ),
+        Cow::Owned(value) => RawValue::Decoded(value),
+    };
+
+    Some((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::encrypt::StaticKeyProvider;
+
+    use super::*;
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_compressed_len_checksum() {
+        let key = EntryKey::new(&1u64, None);
+        let value = vec![1; 64 * 1024];
+        let value = EntryValue::new(&value, Compression::Lz4, None);
+        let mut buf = vec![0; EntryHeader::serialized_len() + key.len() + value.len()];
+        write_entry(&mut buf, &key, &value, 1, 0, ChecksumAlgorithm::default());
+        assert_eq!(
+            read_entry::<u64, Vec<u8>>(&buf, true, None),
+            Some((1, vec![1; 64 * 1024]))
+        );
+
+        // The uncompressed length is verified with the checksum, and bounded by the compression ratio without.
+        let mut header = EntryHeader::read(&buf).unwrap();
+        header.uncompressed_len = u32::MAX;
+        header.write(&mut buf[..EntryHeader::serialized_len()]);
+        assert_eq!(read_entry::<u64, Vec<u8>>(&buf, true, None), None);
+        assert_eq!(read_entry::<u64, Vec<u8>>(&buf, false, None), None);
+    }
+
+    #[test]
+    fn test_encryption_binding() {
+        let encryption = Encryption::new(Arc::new(StaticKeyProvider::new(1, [1; 32])));
+        let write = |key: u64, value: Vec<u8>, sequence: Sequence| {
+            let key = EntryKey::new(&key, Some(&encryption));
+            let value = EntryValue::new(&value, Compression::None, Some(&encryption));
+            let mut buf = vec![0; EntryHeader::serialized_len() + key.len() + value.len()];
+            write_entry(
+                &mut buf,
+                &key,
+                &value,
+                sequence,
+                0,
+                ChecksumAlgorithm::default(),
+            );
+            buf
+        };
+        // Checksums are not verified, so tampering is caught only by the authentication.
+        let read = |buf: &[u8]| read_entry::<u64, Vec<u8>>(buf, false, Some(&encryption));
+
+        let a = write(1, vec![1; 100], 1);
+        let b = write(2, vec![2; 100], 2);
+        assert_eq!(read(&a), Some((1, vec![1; 100])));
+        assert_eq!(read(&b), Some((2, vec![2; 100])));
+
+        // The value of an entry can't be spliced to the key of another.
+        let header = EntryHeader::read(&a).unwrap();
+        let value = EntryHeader::serialized_len()
+            ..EntryHeader::serialized_len() + header.value_len as usize;
+        let mut spliced = a.clone();
+        spliced[value.clone()].copy_from_slice(&b[value]);
+        assert_eq!(read(&spliced), None);
+
+        // Neither can the header fields be changed.
+        for tamper in [
+            |header: &mut EntryHeader| header.sequence += 1,
+            |header: &mut EntryHeader| header.expire_at = 42,
+            |header: &mut EntryHeader| header.uncompressed_len += 1,
+        ] {
+            let mut tampered = a.clone();
+            let mut header = header.clone();
+            tamper(&mut header);
+            header.write(&mut tampered[..EntryHeader::serialized_len()]);
+            assert_eq!(read(&tampered), None);
+        }
+
+        // Unencrypted entries are rejected with encryption enabled.
+        let key = EntryKey::new(&1u64, None);
+        let value = vec![1; 100];
+        let value = EntryValue::new(&value, Compression::None, None);
+        let mut plain = vec![0; EntryHeader::serialized_len() + key.len() + value.len()];
+        write_entry(&mut plain, &key, &value, 3, 0, ChecksumAlgorithm::default());
+        assert_eq!(
+            read_entry::<u64, Vec<u8>>(&plain, false, None),
+            Some((1, vec![1; 100]))
+        );
+        assert_eq!(read(&plain), None);
+        let mut tombstone = vec![0; EntryHeader::serialized_len() + key.len()];
+        write_tombstone(&mut tombstone, &key, 4, ChecksumAlgorithm::default());
+        let header = EntryHeader::read(&tombstone).unwrap();
+        let stored = &tombstone[EntryHeader::serialized_len()..];
+        assert_eq!(header.read_key::<u64>(stored, None), Some(1));
+        assert_eq!(header.read_key::<u64>(stored, Some(&encryption)), None);
+    }
+}
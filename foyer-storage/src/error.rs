@@ -30,25 +30,37 @@ struct ErrorInner {
 pub enum ErrorKind {
     #[error("device error: {0}")]
     Device(#[from] DeviceError),
+    /// The entry read is corrupted, e.g. its checksum mismatches.
+    #[error("entry corrupted: {0}")]
+    Corrupted(String),
+    /// The entry is evicted while it is being read.
+    #[error("entry evicted while reading")]
+    Evicted,
     #[error("other error: {0}")]
     Other(#[from] anyhow::Error),
 }
 
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        &self.0.source
+    }
+}
+
 impl From<ErrorKind> for Error {
     fn from(value: ErrorKind) -> Self {
-        value.into()
+        Self(Box::new(ErrorInner { source: value }))
     }
 }
 
 impl From<DeviceError> for Error {
     fn from(value: DeviceError) -> Self {
-        value.into()
+        ErrorKind::from(value).into()
     }
 }
 
 impl From<anyhow::Error> for Error {
     fn from(value: anyhow::Error) -> Self {
-        value.into()
+        ErrorKind::from(value).into()
     }
 }
 
@@ -62,4 +74,10 @@ mod tests {
     fn test_error_size() {
         assert_eq!(std::mem::size_of::<Error>(), std::mem::size_of::<usize>());
     }
+
+    #[test]
+    fn test_error_from() {
+        let e: Error = anyhow::anyhow!("some error").into();
+        assert_eq!(e.to_string(), "other error: some error");
+    }
 }
@@ -21,7 +21,7 @@ use tokio::sync::broadcast;
 
 use crate::{
     device::Device,
-    error::Result,
+    error::{Error, Result},
     metrics::Metrics,
     region::RegionId,
     region_manager::{RegionEpItemAdapter, RegionManager},
@@ -96,30 +96,66 @@ where
             let _ = region.exclusive(false, true, false).await;
         }
 
-        tracing::trace!("[flusher] write region {} back to device", region_id);
+        // complete region header with statistics of the written entries
+        region.seal_header();
 
-        let mut offset = 0;
-        let len = region.device().io_size();
-        while offset < region.device().region_size() {
-            let start = offset;
-            let end = std::cmp::min(offset + len, region.device().region_size());
+        tracing::trace!("[flusher] write region {} back to device", region_id);
 
-            if let Some(limiter) = &self.rate_limiter && let Some(duration) = limiter.consume(len as f64) {
-                tokio::time::sleep(duration).await;
+        let res = async {
+            if let Some(limiter) = &self.rate_limiter {
+                let mut offset = 0;
+                let len = region.device().io_size();
+                while offset < region.device().region_size() {
+                    let start = offset;
+                    let end = std::cmp::min(offset + len, region.device().region_size());
+
+                    if let Some(duration) = limiter.consume(len as f64) {
+                        tokio::time::sleep(duration).await;
+                    }
+                    let (res, s) = region
+                        .device()
+                        .write(
+                            slice.take().unwrap(),
+                            start..end,
+                            region.id(),
+                            offset as u64,
+                        )
+                        .await;
+                    slice = Some(s);
+                    res?;
+                    offset += len;
+                }
+            } else {
+                // Without rate limiting, all chunks are handed to the device at once, so it can batch them.
+                let (res, s) = region
+                    .device()
+                    .write_chunked(
+                        slice.take().unwrap(),
+                        ..region.device().region_size(),
+                        region.id(),
+                        0,
+                    )
+                    .await;
+                slice = Some(s);
+                res?;
             }
-            let (res, s) = region
-                .device()
-                .write(
-                    slice.take().unwrap(),
-                    start..end,
-                    region.id(),
-                    offset as u64,
-                )
-                .await;
-            res?;
-            slice = Some(s);
-            offset += len;
+            Ok::<_, Error>(())
         }
+        .await;
+
+        // A write failure on an unusable region retires it: its entries are lost, but the store keeps going.
+        let usable = match res {
+            Ok(()) => true,
+            Err(e) if !region.device().is_usable(region_id) => {
+                tracing::warn!(
+                    "[flusher] retire unusable region {} after write error: {}",
+                    region_id,
+                    e
+                );
+                false
+            }
+            Err(e) => return Err(e),
+        };
 
         drop(slice);
 
@@ -130,11 +166,15 @@ where
             let mut guard = region.exclusive(false, false, true).await;
             guard.detach_buffer()
         };
+        region.notify_flushed();
 
         tracing::trace!("[flusher] step 3");
 
         // step 3: release buffer
         self.region_manager.buffers().release(buffer);
+        if !usable {
+            return Ok(());
+        }
         self.region_manager.eviction_push(region.id());
 
         tracing::info!("[flusher] finish flush task, region: {}", region_id);
@@ -13,45 +13,75 @@
 //  limitations under the License.
 
 use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, VecDeque},
     fmt::Debug,
     marker::PhantomData,
+    ops::Range,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 use bitmaps::Bitmap;
-use bytes::{Buf, BufMut};
+use bytes::{Buf, Bytes};
 use foyer_common::{bits, rate::RateLimiter};
 use foyer_intrusive::eviction::EvictionPolicy;
-use futures::future::try_join_all;
+use futures::{future::try_join_all, stream, StreamExt};
 use itertools::Itertools;
 use parking_lot::Mutex;
-use tokio::{sync::broadcast, task::JoinHandle};
-use twox_hash::XxHash64;
+use tokio::{
+    sync::{broadcast, Mutex as AsyncMutex},
+    task::JoinHandle,
+};
 
 use crate::{
     admission::AdmissionPolicy,
-    catalog::{Catalog, Index, IndexInfo, Sequence},
-    device::Device,
-    error::Result,
+    bucket::BucketStore,
+    catalog::{self, Catalog, Chunk, ExpireAt, Index, IndexInfo, Sequence},
+    chain::Chains,
+    checkpoint::Checkpoint,
+    checkpointer::Checkpointer,
+    checksum::{ChecksumAlgorithm, VerifyMode},
+    compress::Compression,
+    device::{BufferAllocator, Device},
+    encrypt::{Encryption, KeyProvider},
+    entry::{
+        read_chunk, read_entry, read_entry_raw, write_batch, write_chunk, write_entry,
+        write_tombstone, EntryFlags, EntryHeader, EntryKey, EntryValue, RawValue, CHUNK_META_LEN,
+    },
+    error::{ErrorKind, Result},
     flusher::Flusher,
+    guard::ValueGuard,
     judge::Judges,
+    manifest::{Manifest, FORMAT_VERSION},
     metrics::{Metrics, METRICS},
     reclaimer::Reclaimer,
-    region::{Region, RegionHeader, RegionId, REGION_MAGIC},
+    region::{ReadSlice, Region, RegionHeader, RegionId, Version},
     region_manager::{RegionEpItemAdapter, RegionManager},
     reinsertion::ReinsertionPolicy,
+    staging::{RingBufferStage, StagedEntry, RING_BUFFER_ALIGN},
     storage::{Storage, StorageWriter},
+    stream::{StreamCursor, StreamOpened, ValueStream, ValueStreamState},
 };
 use foyer_common::code::{Key, Value};
 use foyer_intrusive::core::adapter::Link;
-use std::hash::Hasher;
 
 const DEFAULT_BROADCAST_CAPACITY: usize = 4096;
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecoverMode {
+    /// Stop scanning a region at the first corrupted entry, entries after it are lost.
+    #[default]
+    Strict,
+    /// Skip a corrupted entry and resume scanning at the next `align` boundary, only damaged entries are lost.
+    ///
+    /// Only regions with valid bytes recorded on flush are resynchronized.
+    Tolerant,
+}
+
 pub struct GenericStoreConfig<K, V, D, EP>
 where
     K: Key,
@@ -88,6 +118,29 @@ where
     /// Buffer pool size, should be a multiplier of device region size.
     pub buffer_pool_size: usize,
 
+    /// Capacity of the in-memory ring buffer that stages small entries, must be a power of 2 and no less than 4
+    /// times `ring_buffer_threshold`.
+    ///
+    /// Staged entries are served from the ring buffer and spilled into regions in batches, so they don't take an
+    /// aligned region slot each. Entries still staged are lost on crash, but are spilled on close.
+    ///
+    /// `0` disables the ring buffer.
+    pub ring_buffer_capacity: usize,
+
+    /// Entries with a serialized size no larger than the threshold are staged in the ring buffer.
+    pub ring_buffer_threshold: usize,
+
+    /// Count of the first device regions reserved for the small-object engine, `0` disables it.
+    ///
+    /// The regions are split into `align` sized buckets, see [`BucketStore`]. Entries in buckets take neither an
+    /// aligned slot nor an index, only a key hash is kept in memory.
+    pub small_object_regions: usize,
+
+    /// Entries with a serialized size no larger than the threshold are stored in the small-object engine.
+    ///
+    /// Must fit a bucket, which is `align` sized with a header.
+    pub small_object_threshold: usize,
+
     /// Count of flushers.
     pub flushers: usize,
 
@@ -100,6 +153,11 @@ where
     /// Flush rate limits.
     pub reclaim_rate_limit: usize,
 
+    /// Discard the blocks of reclaimed regions on the device, so the media knows they are free.
+    ///
+    /// Discards are rate limited by `reclaim_rate_limit` as well.
+    pub reclaim_discard: bool,
+
     /// Allocation timout for skippable writers.
     pub allocation_timeout: Duration,
 
@@ -110,6 +168,34 @@ where
 
     /// Concurrency of recovery.
     pub recover_concurrency: usize,
+
+    /// How recovery handles corrupted entries in a region.
+    pub recover_mode: RecoverMode,
+
+    /// Interval of persisting the catalog checkpoint, which speeds up recovery.
+    ///
+    /// The checkpoint is always persisted on close. `None` disables periodic checkpoints.
+    pub checkpoint_interval: Option<Duration>,
+
+    /// Checksum algorithm of newly written entries.
+    ///
+    /// The algorithm is recorded in each entry, so entries written with another algorithm are still verified.
+    pub checksum_algorithm: ChecksumAlgorithm,
+
+    /// When entry checksums are verified.
+    pub verify_mode: VerifyMode,
+
+    /// Compression algorithm of newly written values, values that don't shrink are stored uncompressed.
+    ///
+    /// The algorithm is recorded in each entry, so entries written with another algorithm are still readable if its
+    /// feature is enabled.
+    pub compression: Compression,
+
+    /// Provider of the keys to encrypt entries and checkpoints with, `None` disables encryption.
+    ///
+    /// Keys and values are encrypted with authentication, entries that fail to decrypt are treated as missing. Key
+    /// rotations are recorded in the manifest.
+    pub encryption: Option<Arc<dyn KeyProvider>>,
 }
 
 impl<K, V, D, EP> Debug for GenericStoreConfig<K, V, D, EP>
@@ -128,13 +214,24 @@ where
             .field("admissions", &self.admissions)
             .field("reinsertions", &self.reinsertions)
             .field("buffer_pool_size", &self.buffer_pool_size)
+            .field("ring_buffer_capacity", &self.ring_buffer_capacity)
+            .field("ring_buffer_threshold", &self.ring_buffer_threshold)
+            .field("small_object_regions", &self.small_object_regions)
+            .field("small_object_threshold", &self.small_object_threshold)
             .field("flushers", &self.flushers)
             .field("flush_rate_limit", &self.flush_rate_limit)
             .field("reclaimers", &self.reclaimers)
             .field("reclaim_rate_limit", &self.reclaim_rate_limit)
+            .field("reclaim_discard", &self.reclaim_discard)
             .field("allocation_timeout", &self.allocation_timeout)
             .field("clean_region_threshold", &self.clean_region_threshold)
             .field("recover_concurrency", &self.recover_concurrency)
+            .field("recover_mode", &self.recover_mode)
+            .field("checkpoint_interval", &self.checkpoint_interval)
+            .field("checksum_algorithm", &self.checksum_algorithm)
+            .field("verify_mode", &self.verify_mode)
+            .field("compression", &self.compression)
+            .field("encryption", &self.encryption)
             .finish()
     }
 }
@@ -156,13 +253,24 @@ where
             admissions: self.admissions.clone(),
             reinsertions: self.reinsertions.clone(),
             buffer_pool_size: self.buffer_pool_size,
+            ring_buffer_capacity: self.ring_buffer_capacity,
+            ring_buffer_threshold: self.ring_buffer_threshold,
+            small_object_regions: self.small_object_regions,
+            small_object_threshold: self.small_object_threshold,
             flushers: self.flushers,
             flush_rate_limit: self.flush_rate_limit,
             reclaimers: self.reclaimers,
             reclaim_rate_limit: self.reclaim_rate_limit,
+            reclaim_discard: self.reclaim_discard,
             allocation_timeout: self.allocation_timeout,
             clean_region_threshold: self.clean_region_threshold,
             recover_concurrency: self.recover_concurrency,
+            recover_mode: self.recover_mode,
+            checkpoint_interval: self.checkpoint_interval,
+            checksum_algorithm: self.checksum_algorithm,
+            verify_mode: self.verify_mode,
+            compression: self.compression,
+            encryption: self.encryption.clone(),
         }
     }
 }
@@ -203,13 +311,19 @@ where
     EP: EvictionPolicy<Adapter = RegionEpItemAdapter<EL>>,
     EL: Link,
 {
-    sequence: AtomicU64,
+    sequence: Arc<AtomicU64>,
     indices: Arc<Catalog<K>>,
 
     region_manager: Arc<RegionManager<D, EP, EL>>,
 
     device: D,
 
+    manifest: AsyncMutex<Manifest>,
+
+    ring: Option<RingBufferStage<K>>,
+
+    buckets: Option<BucketStore<K, V, D>>,
+
     admissions: Vec<Arc<dyn AdmissionPolicy<Key = K, Value = V>>>,
     reinsertions: Vec<Arc<dyn ReinsertionPolicy<Key = K, Value = V>>>,
 
@@ -219,6 +333,14 @@ where
     reclaimer_handles: Mutex<Vec<JoinHandle<()>>>,
     reclaimers_stop_tx: broadcast::Sender<()>,
 
+    checkpointer_handle: Mutex<Option<JoinHandle<()>>>,
+    checkpointer_stop_tx: broadcast::Sender<()>,
+
+    checksum_algorithm: ChecksumAlgorithm,
+    verify_mode: VerifyMode,
+    compression: Compression,
+    encryption: Option<Encryption>,
+
     metrics: Arc<Metrics>,
 
     _marker: PhantomData<V>,
@@ -235,10 +357,76 @@ where
     async fn open(config: GenericStoreConfig<K, V, D, EP>) -> Result<Self> {
         tracing::info!("open store with config:\n{:#?}", config);
 
+        if !config.verify_mode.is_valid() {
+            return Err(anyhow::anyhow!(
+                "The sampled verify ratio should be within 0.0..=1.0, given: {:?}.",
+                config.verify_mode
+            )
+            .into());
+        }
+
         let metrics = Arc::new(METRICS.foyer(&config.name));
 
         let device = D::open(config.device_config).await?;
 
+        let mut manifest = match device.read_manifest().await? {
+            Some(buf) => {
+                let manifest = Manifest::read(&buf)
+                    .ok_or_else(|| anyhow::anyhow!("The store manifest is corrupted."))?;
+                if manifest.version != FORMAT_VERSION {
+                    return Err(anyhow::anyhow!(
+                        "The store format version mismatches, pinned: {}, supported: {}.",
+                        manifest.version,
+                        FORMAT_VERSION
+                    )
+                    .into());
+                }
+                if manifest.name != config.name {
+                    return Err(anyhow::anyhow!(
+                        "The store name mismatches, pinned: {:?}, given: {:?}.",
+                        manifest.name,
+                        config.name
+                    )
+                    .into());
+                }
+                manifest
+            }
+            None => {
+                // Pin the store name and format version on the first open.
+                let manifest = Manifest::new(config.name.clone());
+                let mut buf = vec![];
+                manifest.write(&mut buf);
+                device.write_manifest(buf).await?;
+                manifest
+            }
+        };
+
+        let encryption = config.encryption.map(Encryption::new);
+        let key_id = encryption
+            .as_ref()
+            .map(|encryption| encryption.provider().current().0);
+        if manifest.rotate(key_id) {
+            let mut buf = vec![];
+            manifest.write(&mut buf);
+            device.write_manifest(buf).await?;
+            tracing::info!(
+                "encryption key rotated, current: {:?}, retired: {:?}",
+                manifest.key_id,
+                manifest.retired_key_ids
+            );
+        }
+        for id in manifest.retired_key_ids.iter() {
+            if encryption
+                .as_ref()
+                .map_or(true, |encryption| encryption.provider().key(*id).is_none())
+            {
+                tracing::warn!(
+                    "retired encryption key is not provided, entries encrypted with it are treated as missing, key id: {}",
+                    id
+                );
+            }
+        }
+
         let buffer_count = config.buffer_pool_size / device.region_size();
 
         if buffer_count < (1 << config.allocator_bits) {
@@ -248,6 +436,53 @@ where
             .into());
         }
 
+        let ring = match config.ring_buffer_capacity {
+            0 => None,
+            capacity => {
+                if !capacity.is_power_of_two()
+                    || capacity < RING_BUFFER_ALIGN
+                    || capacity < config.ring_buffer_threshold * 4
+                {
+                    return Err(anyhow::anyhow!(
+                        "The ring buffer capacity should be a power of 2 and no less than 4 times the threshold."
+                    )
+                    .into());
+                }
+                Some(RingBufferStage::new(capacity, config.ring_buffer_threshold))
+            }
+        };
+
+        let buckets = match config.small_object_regions {
+            0 => None,
+            regions => {
+                // Regions of the fastest tier come first, some of them must be left for other entries.
+                if regions >= device.regions() || device.tier(regions as RegionId) != 0 {
+                    return Err(anyhow::anyhow!(
+                        "The small-object engine should leave regions of the fastest tier."
+                    )
+                    .into());
+                }
+                if config.small_object_threshold
+                    > BucketStore::<K, V, D>::max_entry_size(device.align())
+                {
+                    return Err(
+                        anyhow::anyhow!("The small-object threshold should fit a bucket.").into(),
+                    );
+                }
+                Some(BucketStore::new(
+                    device.clone(),
+                    regions,
+                    config.small_object_threshold,
+                    config.checksum_algorithm,
+                    encryption.clone(),
+                    manifest.epoch,
+                    metrics.clone(),
+                ))
+            }
+        };
+
+        let sequence = Arc::new(AtomicU64::new(0));
+
         let region_manager = Arc::new(RegionManager::new(
             config.allocator_bits,
             buffer_count,
@@ -255,6 +490,8 @@ where
             config.eviction_config,
             device.clone(),
             config.allocation_timeout,
+            manifest.epoch,
+            sequence.clone(),
             metrics.clone(),
         ));
 
@@ -262,25 +499,37 @@ where
 
         let (flushers_stop_tx, _) = broadcast::channel(DEFAULT_BROADCAST_CAPACITY);
         let (reclaimers_stop_tx, _) = broadcast::channel(DEFAULT_BROADCAST_CAPACITY);
+        let (checkpointer_stop_tx, _) = broadcast::channel(DEFAULT_BROADCAST_CAPACITY);
 
         let flusher_stop_rxs = (0..config.flushers)
             .map(|_| flushers_stop_tx.subscribe())
             .collect_vec();
-        let reclaimer_stop_rxs = (0..config.reclaimers)
-            .map(|_| reclaimers_stop_tx.subscribe())
+        // Each device tier has its own reclaimers.
+        let reclaimer_stop_rxs = (0..device.tiers())
+            .flat_map(|tier| (0..config.reclaimers).map(move |_| tier))
+            .map(|tier| (tier, reclaimers_stop_tx.subscribe()))
             .collect_vec();
 
         let inner = GenericStoreInner {
-            sequence: AtomicU64::new(0),
+            sequence,
             indices: indices.clone(),
             region_manager: region_manager.clone(),
             device: device.clone(),
+            manifest: AsyncMutex::new(manifest),
+            ring,
+            buckets,
             admissions: config.admissions,
             reinsertions: config.reinsertions,
             flusher_handles: Mutex::new(vec![]),
             reclaimer_handles: Mutex::new(vec![]),
             flushers_stop_tx,
             reclaimers_stop_tx,
+            checkpointer_handle: Mutex::new(None),
+            checkpointer_stop_tx,
+            checksum_algorithm: config.checksum_algorithm,
+            verify_mode: config.verify_mode,
+            compression: config.compression,
+            encryption,
             metrics: metrics.clone(),
             _marker: PhantomData,
         };
@@ -317,9 +566,11 @@ where
             .collect_vec();
         let reclaimers = reclaimer_stop_rxs
             .into_iter()
-            .map(|stop_rx| {
+            .map(|(tier, stop_rx)| {
                 Reclaimer::new(
                     config.clean_region_threshold,
+                    config.reclaim_discard,
+                    tier,
                     store.clone(),
                     region_manager.clone(),
                     reclaim_rate_limiter.clone(),
@@ -328,8 +579,21 @@ where
                 )
             })
             .collect_vec();
+        let checkpointer = config.checkpoint_interval.map(|interval| {
+            Checkpointer::new(
+                interval,
+                store.clone(),
+                store.inner.checkpointer_stop_tx.subscribe(),
+            )
+        });
 
-        let sequence = store.recover(config.recover_concurrency).await?;
+        let sequence = store
+            .recover(
+                config.recover_concurrency,
+                config.recover_mode,
+                config.verify_mode.on_recovery(),
+            )
+            .await?;
         store.inner.sequence.store(sequence + 1, Ordering::Relaxed);
 
         let flusher_handles = flushers
@@ -344,11 +608,16 @@ where
 
         *store.inner.flusher_handles.lock() = flusher_handles;
         *store.inner.reclaimer_handles.lock() = reclaimer_handles;
+        *store.inner.checkpointer_handle.lock() = checkpointer
+            .map(|checkpointer| tokio::spawn(async move { checkpointer.run().await.unwrap() }));
 
         Ok(store)
     }
 
     async fn close(&self) -> Result<()> {
+        // spill entries staged in the ring buffer, they are lost otherwise
+        self.spill().await;
+
         // seal current dirty buffer and trigger flushing
         self.seal().await;
 
@@ -370,6 +639,16 @@ where
             handle.await.unwrap();
         }
 
+        // stop and wait for checkpointer
+        let handle = self.inner.checkpointer_handle.lock().take();
+        if let Some(handle) = handle {
+            self.inner.checkpointer_stop_tx.send(()).unwrap();
+            handle.await.unwrap();
+        }
+
+        // all dirty regions are flushed, persist the checkpoint for fast recovery
+        self.checkpoint().await?;
+
         Ok(())
     }
 
@@ -379,29 +658,377 @@ where
         GenericStoreWriter::new(self.clone(), key, weight)
     }
 
+    /// Insert `entries` in a batch, returns if each entry is inserted in the order of `entries`.
+    ///
+    /// All entries are judged before any of them is written. Admitted entries that are written to regions as a whole
+    /// share a slice per region and are indexed together before the slice is released, so lookups see either none or
+    /// all of the entries written to a region. Other entries, stored in buckets, staged in the ring buffer or split into chunks, are inserted and
+    /// become visible one by one.
+    #[tracing::instrument(skip(self, entries), fields(entries = entries.len()))]
+    pub async fn insert_many(&self, entries: Vec<(K, V)>) -> Result<Vec<bool>> {
+        let now = Instant::now();
+        let mut results = vec![false; entries.len()];
+
+        let mut writers = vec![];
+        let mut values = vec![];
+        for (pos, (key, value)) in entries.into_iter().enumerate() {
+            let weight = key.serialized_len() + value.serialized_len();
+            let mut writer = self.writer(key, weight);
+            if writer.judge() {
+                writers.push((pos, writer));
+                values.push(value);
+            }
+        }
+
+        let mut admitted = vec![];
+        for ((pos, mut writer), value) in writers.into_iter().zip_eq(values.iter()) {
+            let sequence = self.admit(&mut writer, value);
+            let value = self.encode(value);
+            admitted.push((pos, writer, sequence, value));
+        }
+        let keys = admitted
+            .iter()
+            .map(|(_, writer, ..)| EntryKey::new(&writer.key, self.inner.encryption.as_ref()))
+            .collect_vec();
+
+        // Only entries that `write_admitted` writes to a region as a whole are batched. Writers of the batch are built
+        // here, they write to the first tier and are not skippable.
+        let align = self.inner.device.align();
+        let mut batch = vec![];
+        for (i, ((_, writer, sequence, value), key)) in
+            admitted.iter().zip_eq(keys.iter()).enumerate()
+        {
+            let len = EntryHeader::serialized_len() + key.len() + value.len();
+            let size = bits::align_up(align, len);
+            if self
+                .inner
+                .buckets
+                .as_ref()
+                .is_some_and(|buckets| len <= buckets.threshold())
+                || self
+                    .inner
+                    .ring
+                    .as_ref()
+                    .is_some_and(|ring| len <= ring.threshold)
+                || size > self.inner.device.region_size() - align
+            {
+                continue;
+            }
+            // The key may have an older entry in a bucket.
+            if let Some(buckets) = self.inner.buckets.as_ref() {
+                buckets.remove(&writer.key, *sequence).await?;
+            }
+            batch.push((i, size));
+        }
+
+        let sizes = batch.iter().map(|(_, size)| *size).collect_vec();
+        let mut written = 0;
+        while written < batch.len() {
+            let (mut slice, count) = self
+                .inner
+                .region_manager
+                .allocate_many(0, &sizes[written..], true)
+                .await
+                .expect("allocation must not time out if it must allocate");
+            let region = self.inner.region_manager.region(&slice.region_id());
+
+            let mut indices = Vec::with_capacity(count);
+            let mut offset = 0;
+            for &(i, size) in &batch[written..written + count] {
+                let (pos, writer, sequence, value) = &admitted[i];
+                write_entry(
+                    &mut slice.as_mut()[offset..offset + size],
+                    &keys[i],
+                    value,
+                    *sequence,
+                    writer.expire_at,
+                    self.inner.checksum_algorithm,
+                );
+                region.record_entry(*sequence);
+                self.inner.metrics.op_bytes_insert.inc_by(size as u64);
+
+                let info = IndexInfo {
+                    sequence: *sequence,
+                    expire_at: writer.expire_at,
+                    index: Index::Region {
+                        region: slice.region_id(),
+                        version: slice.version(),
+                        offset: (slice.offset() + offset) as u32,
+                        len: size as u32,
+                        key_len: writer.key.serialized_len() as u32,
+                        value_len: value.len() as u32,
+                    },
+                };
+                indices.push((writer.key.clone(), info));
+                results[*pos] = true;
+                offset += size;
+            }
+            // Publish the entries of the region together before the slice is released, so they are not missed by a
+            // checkpoint taken after the region is flushed.
+            self.inner.indices.insert_many(indices);
+            drop(slice);
+
+            written += count;
+        }
+
+        let batched: BTreeSet<_> = batch.into_iter().map(|(i, _)| i).collect();
+        for &i in &batched {
+            let (_, writer, ..) = &admitted[i];
+            let duration = now.elapsed() + writer.duration;
+            self.inner
+                .metrics
+                .op_duration_insert_inserted
+                .observe(duration.as_secs_f64());
+        }
+
+        drop(keys);
+        for (i, (pos, writer, sequence, value)) in admitted.into_iter().enumerate() {
+            if batched.contains(&i) {
+                continue;
+            }
+            results[pos] = self.write_admitted(writer, sequence, &value, now).await?;
+        }
+
+        Ok(results)
+    }
+
     #[tracing::instrument(skip(self))]
     fn exists(&self, key: &K) -> Result<bool> {
-        Ok(self.inner.indices.lookup(key).is_some())
+        let exists = self
+            .inner
+            .indices
+            .lookup(key)
+            .map_or(false, |info| !info.is_expired())
+            || self
+                .inner
+                .buckets
+                .as_ref()
+                .map_or(false, |buckets| buckets.exists(key));
+        Ok(exists)
     }
 
     #[tracing::instrument(skip(self))]
     async fn lookup(&self, key: &K) -> Result<Option<V>> {
+        Ok(self.lookup_raw(key).await?.map(|guard| guard.value()))
+    }
+
+    /// Lookup all `keys` at once, results are returned in the order of `keys`.
+    ///
+    /// Entries indexed in the same region are read together, their aligned ranges are merged into a single read if
+    /// they overlap or are adjacent. Other entries are looked up one by one.
+    #[tracing::instrument(skip(self, keys), fields(keys = keys.len()))]
+    pub async fn lookup_many(&self, keys: &[K]) -> Result<Vec<Option<V>>> {
         let now = Instant::now();
 
-        let info = match self.inner.indices.lookup(key) {
-            Some(info) => info,
-            None => {
+        let mut groups = BTreeMap::<_, Vec<_>>::new();
+        let mut others = vec![];
+        for (pos, key) in keys.iter().enumerate() {
+            if let Some(info) = self.inner.indices.lookup(key)
+                && !info.is_expired()
+                && let Index::Region {
+                    region,
+                    version,
+                    offset,
+                    len,
+                    ..
+                } = info.index
+            {
+                groups
+                    .entry((region, version))
+                    .or_default()
+                    .push((pos, offset, len));
+            } else {
+                others.push(pos);
+            }
+        }
+
+        let mut results = keys.iter().map(|_| None).collect_vec();
+
+        let hits = try_join_all(groups.into_iter().map(|((region, version), entries)| {
+            self.lookup_region_many(keys, region, version, entries, now)
+        }))
+        .await?;
+        for (pos, value) in hits.into_iter().flatten() {
+            results[pos] = value;
+        }
+
+        let values = try_join_all(others.iter().map(|&pos| self.lookup(&keys[pos]))).await?;
+        for (pos, value) in others.into_iter().zip_eq(values) {
+            results[pos] = value;
+        }
+
+        Ok(results)
+    }
+
+    /// Read `entries` of `(position, offset, len)` of `keys` in the same region with coalesced reads.
+    async fn lookup_region_many(
+        &self,
+        keys: &[K],
+        region_id: RegionId,
+        version: Version,
+        mut entries: Vec<(usize, u32, u32)>,
+        now: Instant,
+    ) -> Result<Vec<(usize, Option<V>)>> {
+        self.inner.region_manager.record_access(&region_id);
+        let region = self.inner.region_manager.region(&region_id);
+        let align = self.inner.device.align();
+
+        // Merge aligned ranges of entries sorted by offset, entries packed in a batch may share blocks.
+        entries.sort_by_key(|(_, offset, _)| *offset);
+        let mut reads: Vec<(Range<usize>, Vec<_>)> = vec![];
+        for (pos, offset, len) in entries {
+            let start = bits::align_down(align, offset as usize);
+            let end = bits::align_up(align, (offset + len) as usize);
+            match reads.last_mut() {
+                Some((range, entries)) if start <= range.end => {
+                    range.end = range.end.max(end);
+                    entries.push((pos, offset as usize));
+                }
+                _ => reads.push((start..end, vec![(pos, offset as usize)])),
+            }
+        }
+
+        let mut results = vec![];
+        for (range, entries) in reads {
+            let slice = match region.load(range.clone(), version).await {
+                Ok(Some(slice)) => slice,
+                Err(e) if self.inner.device.is_usable(region_id) => return Err(e),
+                Ok(None) | Err(_) => {
+                    // Same as `lookup_raw`, remove indices if the region is reclaimed or on failed media.
+                    for (pos, _) in entries {
+                        self.inner.indices.remove(&keys[pos]);
+                        self.inner
+                            .metrics
+                            .op_duration_lookup_miss
+                            .observe(now.elapsed().as_secs_f64());
+                        results.push((pos, None));
+                    }
+                    continue;
+                }
+            };
+            self.inner
+                .metrics
+                .op_bytes_lookup
+                .inc_by(slice.len() as u64);
+
+            for (pos, offset) in entries {
+                let value = match read_entry::<K, V>(
+                    &slice.as_ref()[offset - range.start..],
+                    self.inner.verify_mode.on_read(),
+                    self.inner.encryption.as_ref(),
+                ) {
+                    Some((key, value)) if key == keys[pos] => {
+                        self.inner
+                            .metrics
+                            .op_duration_lookup_hit
+                            .observe(now.elapsed().as_secs_f64());
+                        Some(value)
+                    }
+                    // Same as `lookup_raw`, remove the index if the entry is corrupted or of another key.
+                    _ => {
+                        self.inner.indices.remove(&keys[pos]);
+                        self.inner
+                            .metrics
+                            .op_duration_lookup_miss
+                            .observe(now.elapsed().as_secs_f64());
+                        None
+                    }
+                };
+                results.push((pos, value));
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Lookup the serialized value of `key` without deserializing it.
+    ///
+    /// The value is not copied out of the buffer it is read into unless it is compressed, encrypted or split into
+    /// chunks, so it can be read or handed to IO directly. Buffers and the accounting of region readers are released
+    /// when the guard is dropped.
+    #[tracing::instrument(skip(self))]
+    pub async fn lookup_raw(&self, key: &K) -> Result<Option<ValueGuard<D::IoBufferAllocator>>> {
+        let now = Instant::now();
+
+        let miss = || {
+            self.inner
+                .metrics
+                .op_duration_lookup_miss
+                .observe(now.elapsed().as_secs_f64());
+            Ok(None)
+        };
+
+        let Some(mut info) = self.inner.indices.lookup(key) else {
+            // Entries in buckets are not indexed.
+            if let Some(buckets) = self.inner.buckets.as_ref()
+                && let Some(value) = buckets
+                    .lookup_raw(key, self.inner.verify_mode.on_read())
+                    .await?
+            {
+                self.inner
+                    .metrics
+                    .op_bytes_lookup
+                    .inc_by(self.inner.device.align() as u64);
                 self.inner
                     .metrics
-                    .op_duration_lookup_miss
+                    .op_duration_lookup_hit
                     .observe(now.elapsed().as_secs_f64());
-                return Ok(None);
+                return Ok(Some(ValueGuard::Owned(value)));
             }
+            return miss();
         };
 
+        // Expired entries are dropped lazily on lookup.
+        if info.is_expired() {
+            self.inner.indices.remove(key);
+            return miss();
+        }
+
+        // The staged entry may be spilled into a region after indexed, lookup with its new index then.
+        while let Index::RingBuffer { offset, len } = info.index {
+            if let Some(view) = self
+                .inner
+                .ring
+                .as_ref()
+                .and_then(|ring| ring.lookup(offset))
+            {
+                self.inner.metrics.op_bytes_lookup.inc_by(len as u64);
+                let value = match read_entry_raw::<K>(
+                    &view[..len],
+                    self.inner.verify_mode.on_read(),
+                    self.inner.encryption.as_ref(),
+                ) {
+                    Some((k, value)) if &k == key => value,
+                    _ => {
+                        self.inner.indices.remove(key);
+                        return miss();
+                    }
+                };
+
+                self.inner
+                    .metrics
+                    .op_duration_lookup_hit
+                    .observe(now.elapsed().as_secs_f64());
+                return Ok(Some(match value {
+                    RawValue::Range(range) => ValueGuard::View { view, range },
+                    RawValue::Decoded(buf) => ValueGuard::Owned(buf),
+                }));
+            }
+            match self.inner.indices.lookup(key) {
+                Some(i) if !matches!(i.index, Index::RingBuffer { offset: o, .. } if o == offset) => {
+                    info = i
+                }
+                // The staged entry is dropped by `clear`.
+                _ => return miss(),
+            }
+        }
+
         match info.index {
-            crate::catalog::Index::RingBuffer {} => todo!(),
-            crate::catalog::Index::Region {
+            Index::RingBuffer { .. } => unreachable!(),
+            Index::Chunks {
+                chunks, value_len, ..
+            } => self.lookup_chunks(key, &chunks, value_len, now).await,
+            Index::Region {
                 region,
                 version,
                 offset,
@@ -410,15 +1037,20 @@ where
                 value_len: _,
             } => {
                 self.inner.region_manager.record_access(&region);
+                let region_id = region;
                 let region = self.inner.region_manager.region(&region);
-                let start = offset as usize;
-                let end = start + len as usize;
+                // Entries packed in a batch are not aligned.
+                let align = self.inner.device.align();
+                let start = bits::align_down(align, offset as usize);
+                let end = bits::align_up(align, (offset + len) as usize);
 
                 // TODO(MrCroxx): read value only
-                let slice = match region.load(start..end, version).await? {
-                    Some(slice) => slice,
-                    None => {
-                        // Remove index if the storage layer fails to lookup it (because of region version mismatch).
+                let slice = match region.load(start..end, version).await {
+                    Ok(Some(slice)) => slice,
+                    Err(e) if self.inner.device.is_usable(region_id) => return Err(e),
+                    Ok(None) | Err(_) => {
+                        // Remove index if the storage layer fails to lookup it (because of region version mismatch
+                        // or the region is on failed media).
                         self.inner.indices.remove(key);
                         self.inner
                             .metrics
@@ -432,127 +1064,1009 @@ where
                     .op_bytes_lookup
                     .inc_by(slice.len() as u64);
 
-                let res = match read_entry::<K, V>(slice.as_ref()) {
-                    Some((_key, value)) => Ok(Some(value)),
-                    None => {
-                        // Remove index if the storage layer fails to lookup it (because of entry magic mismatch).
+                let relative = offset as usize - start;
+                let value = match read_entry_raw::<K>(
+                    &slice.as_ref()[relative..],
+                    self.inner.verify_mode.on_read(),
+                    self.inner.encryption.as_ref(),
+                ) {
+                    Some((k, value)) if &k == key => value,
+                    _ => {
+                        // Remove index if the storage layer fails to lookup it (because of entry magic mismatch,
+                        // checksum mismatch, decryption failure or the entry is of another key).
                         self.inner.indices.remove(key);
-                        Ok(None)
+                        return miss();
                     }
                 };
-                drop(slice);
 
                 self.inner
                     .metrics
                     .op_duration_lookup_hit
                     .observe(now.elapsed().as_secs_f64());
 
-                res
+                Ok(Some(match value {
+                    RawValue::Range(range) => ValueGuard::Slice {
+                        slice,
+                        range: relative + range.start..relative + range.end,
+                    },
+                    RawValue::Decoded(buf) => ValueGuard::Owned(buf),
+                }))
             }
         }
     }
 
+    /// Lookup the serialized value of `key` as a stream, see [`ValueStream`].
+    ///
+    /// Values in regions and chunks of large values are read piece by piece. Values staged in the ring buffer, stored
+    /// in buckets, compressed or encrypted are fully read and verified before the first piece is yielded.
     #[tracing::instrument(skip(self))]
-    fn remove(&self, key: &K) -> Result<bool> {
-        let _timer = self.inner.metrics.op_duration_remove.start_timer();
+    pub async fn lookup_stream(&self, key: &K) -> Result<Option<ValueStream>> {
+        let now = Instant::now();
+        let io_size = self.inner.device.io_size();
+
+        let (mut segments, chunked) = match self.inner.indices.lookup(key) {
+            Some(info) if !info.is_expired() => match info.index {
+                Index::Region {
+                    region,
+                    version,
+                    offset,
+                    len,
+                    ..
+                } => (
+                    VecDeque::from([Chunk {
+                        region,
+                        version,
+                        offset,
+                        len,
+                    }]),
+                    false,
+                ),
+                Index::Chunks { chunks, .. } => (chunks.iter().cloned().collect(), true),
+                Index::RingBuffer { .. } => (VecDeque::new(), false),
+            },
+            _ => (VecDeque::new(), false),
+        };
+        let verify = self.inner.verify_mode.on_read();
+
+        let miss = || {
+            // Same as `lookup_raw`, remove the index if the entry is of another key, or the region is reclaimed or on
+            // failed media.
+            self.inner.indices.remove(key);
+            self.inner
+                .metrics
+                .op_duration_lookup_miss
+                .observe(now.elapsed().as_secs_f64());
+            Ok(None)
+        };
 
-        let res = self.inner.indices.remove(key).is_some();
+        let first = match segments.pop_front() {
+            Some(segment) => {
+                self.inner.region_manager.record_access(&segment.region);
+                for segment in segments.iter() {
+                    self.inner.region_manager.record_access(&segment.region);
+                }
+                self.stream_open(&segment, chunked, verify, Some(key)).await
+            }
+            None => Ok(StreamOpened::Whole),
+        };
+        let (cursor, pending) = match first {
+            Ok(StreamOpened::Piece(cursor, bytes)) => (cursor, bytes),
+            Ok(StreamOpened::Mismatch) => return miss(),
+            Err(e) if matches!(e.kind(), ErrorKind::Evicted) => return miss(),
+            Err(e) => return Err(e),
+            // Other entries are left to `lookup_raw`.
+            Ok(StreamOpened::Whole) => {
+                return Ok(self.lookup_raw(key).await?.map(|guard| {
+                    let mut buf = match guard {
+                        ValueGuard::Owned(buf) => Bytes::from(buf),
+                        guard => Bytes::copy_from_slice(&guard),
+                    };
+                    let pieces = std::iter::from_fn(move || {
+                        (!buf.is_empty()).then(|| buf.split_to(std::cmp::min(io_size, buf.len())))
+                    });
+                    stream::iter(pieces.map(Ok)).boxed()
+                }))
+            }
+        };
 
-        Ok(res)
+        self.inner
+            .metrics
+            .op_duration_lookup_hit
+            .observe(now.elapsed().as_secs_f64());
+
+        let state = ValueStreamState {
+            store: self.clone(),
+            segments,
+            cursor: (!cursor.is_done()).then_some(cursor),
+            pending,
+            chunked,
+            verify,
+        };
+        Ok(Some(
+            stream::try_unfold(state, ValueStreamState::next).boxed(),
+        ))
     }
 
-    #[tracing::instrument(skip(self))]
-    fn clear(&self) -> Result<()> {
-        self.inner.indices.clear();
+    /// Read the first piece of the entry or the chunk, returns the cursor and the value bytes in the piece.
+    ///
+    /// The key stored is compared with `key` if given, it is read separately if it is not in the first piece.
+    pub(crate) async fn stream_open(
+        &self,
+        segment: &Chunk,
+        chunked: bool,
+        verify: bool,
+        key: Option<&K>,
+    ) -> Result<StreamOpened> {
+        let align = self.inner.device.align();
+        let header_len = EntryHeader::serialized_len();
+        // Entries packed in a batch are not aligned.
+        let offset = segment.offset as usize;
+        let start = bits::align_down(align, offset);
+        let end = bits::align_up(align, offset + segment.len as usize);
+        let first = std::cmp::max(
+            start + self.inner.device.io_size(),
+            bits::align_up(align, offset + header_len),
+        );
 
-        // TODO(MrCroxx): set all regions as clean?
+        let slice = self
+            .stream_load(
+                segment.region,
+                segment.version,
+                start..std::cmp::min(first, end),
+            )
+            .await?;
+        let Some(header) = EntryHeader::read(&slice.as_ref()[offset - start..]) else {
+            return Err(ErrorKind::Corrupted("entry magic mismatch".to_string()).into());
+        };
+        // Entries are decoded as a whole with encryption enabled, so unencrypted ones are rejected as well.
+        if self.inner.encryption.is_some()
+            || header
+                .flags
+                .intersects(EntryFlags::COMPRESSED | EntryFlags::ENCRYPTED)
+        {
+            return Ok(StreamOpened::Whole);
+        }
 
-        Ok(())
-    }
+        let expected = match chunked {
+            true => EntryFlags::CHUNK,
+            false => EntryFlags::empty(),
+        };
+        let meta_len = if chunked { CHUNK_META_LEN } else { 0 };
+        let body = offset + header_len
+            ..offset + header_len + header.value_len as usize + header.key_len as usize;
+        if header.flags != expected || (header.value_len as usize) < meta_len || body.end > end {
+            return Err(ErrorKind::Corrupted(format!(
+                "unexpected entry, flags: {:?}, value len: {}, key len: {}",
+                header.flags, header.value_len, header.key_len
+            ))
+            .into());
+        }
 
-    pub(crate) fn catalog(&self) -> &Arc<Catalog<K>> {
-        &self.inner.indices
-    }
+        // The key follows the value.
+        if let Some(key) = key {
+            let range = body.start + header.value_len as usize..body.end;
+            let stored = if range.end <= start + slice.len() {
+                K::read(&slice.as_ref()[range.start - start..range.end - start])
+            } else {
+                let (s, e) = (
+                    bits::align_down(align, range.start),
+                    bits::align_up(align, range.end),
+                );
+                let slice = self
+                    .stream_load(segment.region, segment.version, s..e)
+                    .await?;
+                K::read(&slice.as_ref()[range.start - s..range.end - s])
+            };
+            if &stored != key {
+                return Ok(StreamOpened::Mismatch);
+            }
+        }
 
-    pub(crate) fn reinsertions(&self) -> &Vec<Arc<dyn ReinsertionPolicy<Key = K, Value = V>>> {
-        &self.inner.reinsertions
+        let mut cursor = StreamCursor {
+            region: segment.region,
+            version: segment.version,
+            pos: start,
+            end: bits::align_up(align, body.end),
+            value: body.start + meta_len..body.start + header.value_len as usize,
+            hasher: verify.then(|| header.checksum_algorithm.hasher()),
+            checksum: header.checksum,
+            body,
+        };
+        let bytes = cursor.consume(start, slice.as_ref())?;
+        drop(slice);
+
+        Ok(StreamOpened::Piece(cursor, bytes))
     }
 
-    fn serialized_len(&self, key: &K, value: &V) -> usize {
-        let unaligned =
-            EntryHeader::serialized_len() + key.serialized_len() + value.serialized_len();
-        bits::align_up(self.inner.device.align(), unaligned)
+    /// Read the next piece of the entry or the chunk, returns the value bytes in the piece.
+    pub(crate) async fn stream_read(&self, cursor: &mut StreamCursor) -> Result<Bytes> {
+        let start = cursor.pos;
+        let end = std::cmp::min(start + self.inner.device.io_size(), cursor.end);
+        let slice = self
+            .stream_load(cursor.region, cursor.version, start..end)
+            .await?;
+        let bytes = cursor.consume(start, slice.as_ref());
+        drop(slice);
+        bytes
     }
 
-    async fn seal(&self) {
-        self.inner.region_manager.seal().await;
+    pub(crate) async fn stream_load(
+        &self,
+        region: RegionId,
+        version: Version,
+        range: Range<usize>,
+    ) -> Result<ReadSlice<D::IoBufferAllocator>> {
+        let slice = match self
+            .inner
+            .region_manager
+            .region(&region)
+            .load(range, version)
+            .await
+        {
+            Ok(Some(slice)) => slice,
+            Err(e) if self.inner.device.is_usable(region) => return Err(e),
+            // The region is reclaimed or on failed media.
+            Ok(None) | Err(_) => return Err(ErrorKind::Evicted.into()),
+        };
+        self.inner
+            .metrics
+            .op_bytes_lookup
+            .inc_by(slice.len() as u64);
+        Ok(slice)
     }
 
     #[tracing::instrument(skip(self))]
-    async fn recover(&self, concurrency: usize) -> Result<Sequence> {
-        tracing::info!("start store recovery");
+    async fn remove(&self, key: &K) -> Result<bool> {
+        let _timer = self.inner.metrics.op_duration_remove.start_timer();
 
-        let (tx, rx) = async_channel::bounded(concurrency);
+        // Acquire the sequence before removing the index, so the tombstone shadows all entries inserted before.
+        let sequence = self.inner.sequence.fetch_add(1, Ordering::Relaxed);
 
-        let mut handles = vec![];
-        for region_id in 0..self.inner.device.regions() as RegionId {
-            let itx = tx.clone();
-            let irx = rx.clone();
-            let region_manager = self.inner.region_manager.clone();
-            let indices = self.inner.indices.clone();
-            let handle = tokio::spawn(async move {
-                itx.send(()).await.unwrap();
-                let res = Self::recover_region(region_id, region_manager, indices).await;
-                irx.recv().await.unwrap();
-                res
-            });
-            handles.push(handle);
-        }
+        // Entries in buckets are removed in place, they need no tombstones.
+        let removed = match self.inner.buckets.as_ref() {
+            Some(buckets) => buckets.remove(key, sequence).await?,
+            None => false,
+        };
 
-        let mut recovered = 0;
-        let mut sequence = 0;
+        let Some(info) = self.inner.indices.remove(key) else {
+            return Ok(removed);
+        };
 
-        let results = try_join_all(handles).await.map_err(anyhow::Error::from)?;
+        let shadows = info.index.regions().collect();
 
-        for (region_id, result) in results.into_iter().enumerate() {
-            if let Some(seq) = result? {
-                tracing::debug!("region {} is recovered", region_id);
-                recovered += 1;
-                sequence = std::cmp::max(sequence, seq);
-            }
+        // Persist the tombstone, so the removed entry will not be recovered after restart.
+        self.write_tombstone(key.clone(), sequence, shadows, 0, true)
+            .await?;
+
+        Ok(true)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn clear(&self) -> Result<()> {
+        let mut manifest = self.inner.manifest.lock().await;
+
+        // Persist the new epoch first, regions of previous epochs are ignored on recovery since then.
+        let epoch = manifest.epoch + 1;
+        let mut buf = vec![];
+        // Entries encrypted with retired keys are all dropped.
+        Manifest {
+            epoch,
+            retired_key_ids: vec![],
+            ..manifest.clone()
         }
+        .write(&mut buf);
+        self.inner.device.write_manifest(buf).await?;
+        manifest.epoch = epoch;
+        manifest.retired_key_ids.clear();
 
-        tracing::info!("finish store recovery, {} region recovered", recovered);
-        self.inner
-            .metrics
-            .total_bytes
-            .set((recovered * self.inner.device.region_size()) as u64);
+        // Switch allocators to regions of the new epoch.
+        self.inner.region_manager.set_epoch(epoch);
+        self.seal().await;
 
-        // Force trigger reclamation.
-        if recovered == self.inner.device.regions() {
-            self.inner.region_manager.clean_regions().flash();
+        if let Some(buckets) = self.inner.buckets.as_ref() {
+            buckets.set_epoch(epoch);
         }
 
-        Ok(sequence)
+        // Drop staged entries, hold the spill lock so they are not spilled to regions of the new epoch meanwhile.
+        let _guard = match self.inner.ring.as_ref() {
+            Some(ring) => {
+                let guard = ring.spill.lock().await;
+                ring.clear();
+                Some(guard)
+            }
+            None => None,
+        };
+
+        self.inner.indices.clear();
+
+        // TODO(MrCroxx): set all regions as clean?
+
+        Ok(())
+    }
+
+    /// The device the store is opened on, e.g. to mark a child of a [`crate::device::jbod::JbodDevice`] failed.
+    pub fn device(&self) -> &D {
+        &self.inner.device
+    }
+
+    pub(crate) fn catalog(&self) -> &Arc<Catalog<K>> {
+        &self.inner.indices
+    }
+
+    #[cfg(test)]
+    pub(crate) fn region_manager(&self) -> &Arc<RegionManager<D, EP, EL>> {
+        &self.inner.region_manager
+    }
+
+    pub(crate) fn encryption(&self) -> Option<&Encryption> {
+        self.inner.encryption.as_ref()
+    }
+
+    pub(crate) fn reinsertions(&self) -> &Vec<Arc<dyn ReinsertionPolicy<Key = K, Value = V>>> {
+        &self.inner.reinsertions
+    }
+
+    /// Write a tombstone of `key` to a region of `tier` and insert it into the catalog.
+    ///
+    /// Returns the region and its version the tombstone is written to, or `None` if the tombstone is skipped because
+    /// of allocation timeout. Only reachable when `must_allocate` is `false`.
+    pub(crate) async fn write_tombstone(
+        &self,
+        key: K,
+        sequence: Sequence,
+        shadows: Vec<RegionId>,
+        tier: usize,
+        must_allocate: bool,
+    ) -> Result<Option<(RegionId, Version)>> {
+        let entry_key = EntryKey::new(&key, self.inner.encryption.as_ref());
+        let len = bits::align_up(
+            self.inner.device.align(),
+            EntryHeader::serialized_len() + entry_key.len(),
+        );
+
+        let mut slice = match self
+            .inner
+            .region_manager
+            .allocate(tier, len, must_allocate)
+            .await
+        {
+            Some(slice) => slice,
+            None => return Ok(None),
+        };
+
+        write_tombstone(
+            slice.as_mut(),
+            &entry_key,
+            sequence,
+            self.inner.checksum_algorithm,
+        );
+        let region = slice.region_id();
+        let version = slice.version();
+        self.inner
+            .region_manager
+            .region(&region)
+            .record_entry(sequence);
+
+        // Insert the tombstone before the slice is released, so the tombstone is not missed by a checkpoint taken
+        // after the region is flushed.
+        self.inner
+            .indices
+            .insert_tombstone(key, sequence, region, shadows);
+        drop(slice);
+
+        Ok(Some((region, version)))
+    }
+
+    /// Persist a checkpoint of the catalog.
+    pub(crate) async fn checkpoint(&self) -> Result<()> {
+        // Hold the manifest, so the epoch is not changed by `clear` in the meantime.
+        let manifest = self.inner.manifest.lock().await;
+
+        // The watermark must be taken before the snapshot, entries inserted later are on regions not below it.
+        let watermark = self.inner.region_manager.watermark();
+        let sequence = self.inner.sequence.load(Ordering::Relaxed);
+        let (infos, tombstones) = self.inner.indices.snapshot();
+
+        let checkpoint = Checkpoint {
+            epoch: manifest.epoch,
+            sequence,
+            watermark,
+            infos,
+            tombstones,
+        };
+        let mut buf = vec![];
+        checkpoint.write(&mut buf);
+        // The checkpoint holds keys, encrypt it as a whole.
+        if let Some(encryption) = self.inner.encryption.as_ref() {
+            buf = encryption.encrypt(&buf, &[]);
+        }
+
+        // Regions below the watermark must be durable before the checkpoint refers to them.
+        self.inner.device.flush().await?;
+        self.inner.device.write_checkpoint(buf).await?;
+
+        tracing::info!(
+            "checkpoint persisted, watermark: {}, indices: {}, tombstones: {}",
+            watermark,
+            checkpoint.infos.len(),
+            checkpoint.tombstones.len()
+        );
+
+        Ok(())
+    }
+
+    /// Load the checkpoint of the current epoch, returns `None` if it is missing, corrupted, stale or fails to
+    /// decrypt.
+    async fn load_checkpoint(&self) -> Result<Option<Checkpoint<K>>> {
+        let Some(mut buf) = self.inner.device.read_checkpoint().await? else {
+            return Ok(None);
+        };
+        if let Some(encryption) = self.inner.encryption.as_ref() {
+            let Some(decrypted) = encryption.decrypt(&buf, &[]) else {
+                tracing::warn!("checkpoint fails to decrypt, fall back to full scan");
+                return Ok(None);
+            };
+            buf = decrypted;
+        }
+        let Some(checkpoint) = Checkpoint::read(&buf) else {
+            tracing::warn!("checkpoint corrupted, fall back to full scan");
+            return Ok(None);
+        };
+        if checkpoint.epoch != self.inner.region_manager.epoch() {
+            tracing::info!("checkpoint is stale, fall back to full scan");
+            return Ok(None);
+        }
+        let regions = self.inner.device.regions() as RegionId;
+        let in_range = checkpoint
+            .infos
+            .iter()
+            .all(|(_, info)| info.index.regions().all(|region| region < regions))
+            && checkpoint.tombstones.iter().all(|(_, tombstone)| {
+                tombstone.region < regions
+                    && tombstone
+                        .shadows
+                        .iter()
+                        .all(|(region, _)| *region < regions)
+            });
+        if !in_range {
+            tracing::warn!("checkpoint refers to regions out of range, fall back to full scan");
+            return Ok(None);
+        }
+        Ok(Some(checkpoint))
+    }
+
+    /// Spill all entries staged in the ring buffer into regions.
+    async fn spill(&self) {
+        if let Some(ring) = self.inner.ring.as_ref() {
+            let guard = ring.spill.lock().await;
+            self.spill_staged(ring).await;
+            drop(guard);
+        }
+    }
+
+    /// Spill entries staged in the ring buffer if they take more than half of it.
+    ///
+    /// Returns at once if another spill is in progress, it checks the staged bytes again when finished.
+    async fn may_spill(&self, ring: &RingBufferStage<K>) {
+        while ring.bytes() >= ring.ring.capacity() / 2 {
+            let Ok(guard) = ring.spill.try_lock() else {
+                return;
+            };
+            self.spill_staged(ring).await;
+            drop(guard);
+        }
+    }
+
+    /// Spill staged entries into regions in batches, must be called with the spill lock held.
+    async fn spill_staged(&self, ring: &RingBufferStage<K>) {
+        let staged = ring.snapshot();
+
+        // Entries removed or overwritten since staged are not spilled.
+        let entries = staged
+            .iter()
+            .filter(|entry| {
+                matches!(
+                    self.inner.indices.lookup(&entry.key),
+                    Some(IndexInfo { sequence, index: Index::RingBuffer { offset, .. }, .. })
+                        if sequence == entry.sequence && offset == entry.view.offset()
+                )
+            })
+            .collect_vec();
+
+        // Batches are bounded by the io size, so they don't leave too much unused space at the end of regions.
+        let align = self.inner.device.align();
+        let limit = std::cmp::min(
+            std::cmp::max(self.inner.device.io_size(), align),
+            self.inner.device.region_size() - align,
+        ) - EntryHeader::serialized_len();
+        let mut batch: Vec<&StagedEntry<K>> = vec![];
+        let mut payload = 0;
+        for entry in entries {
+            if !batch.is_empty() && payload + entry.len > limit {
+                self.write_batch(&batch).await;
+                batch.clear();
+                payload = 0;
+            }
+            payload += entry.len;
+            batch.push(entry);
+        }
+        if !batch.is_empty() {
+            self.write_batch(&batch).await;
+        }
+
+        self.inner
+            .metrics
+            .op_bytes_spill
+            .inc_by(staged.iter().map(|entry| entry.len as u64).sum());
+        ring.release(staged.iter().map(|entry| entry.view.offset()));
+    }
+
+    /// Write staged entries packed in a batch to a region and index them.
+    async fn write_batch(&self, batch: &[&StagedEntry<K>]) {
+        let payload = batch.iter().map(|entry| entry.len).sum::<usize>();
+        let len = bits::align_up(
+            self.inner.device.align(),
+            EntryHeader::serialized_len() + payload,
+        );
+
+        let Some(mut slice) = self.inner.region_manager.allocate(0, len, true).await else {
+            unreachable!("allocation never fails if `must_allocate` is set");
+        };
+
+        let sequence = batch.iter().map(|entry| entry.sequence).max().unwrap();
+        write_batch(
+            slice.as_mut(),
+            batch.iter().map(|entry| &entry.view[..entry.len]),
+            sequence,
+            self.inner.checksum_algorithm,
+        );
+        let region = self.inner.region_manager.region(&slice.region_id());
+        for entry in batch {
+            region.record_entry(entry.sequence);
+        }
+
+        // Index the entries before the slice is released, the same as writing a single entry.
+        let mut offset = slice.offset() + EntryHeader::serialized_len();
+        for entry in batch {
+            let info = IndexInfo {
+                sequence: entry.sequence,
+                expire_at: entry.expire_at,
+                index: Index::Region {
+                    region: slice.region_id(),
+                    version: slice.version(),
+                    offset: offset as u32,
+                    len: entry.len as u32,
+                    key_len: entry.key_len as u32,
+                    value_len: entry.value_len as u32,
+                },
+            };
+            self.inner.indices.insert(entry.key.clone(), info);
+            offset += entry.len;
+        }
+        drop(slice);
+    }
+
+    /// Reassemble a large entry from its chunks, the index is removed if any chunk fails to load.
+    async fn lookup_chunks(
+        &self,
+        key: &K,
+        chunks: &[Chunk],
+        value_len: u32,
+        now: Instant,
+    ) -> Result<Option<ValueGuard<D::IoBufferAllocator>>> {
+        let miss = || {
+            self.inner.indices.remove(key);
+            self.inner
+                .metrics
+                .op_duration_lookup_miss
+                .observe(now.elapsed().as_secs_f64());
+            Ok(None)
+        };
+
+        for chunk in chunks {
+            self.inner.region_manager.record_access(&chunk.region);
+        }
+
+        let mut buf = Vec::with_capacity(value_len as usize);
+        // All chunks share the header fields and the key stored of the first chunk.
+        let mut first = None;
+        for chunk in chunks {
+            let region = self.inner.region_manager.region(&chunk.region);
+            let range = chunk.offset as usize..(chunk.offset + chunk.len) as usize;
+            let slice = match region.load(range, chunk.version).await {
+                Ok(Some(slice)) => slice,
+                Err(e) if self.inner.device.is_usable(chunk.region) => return Err(e),
+                // The region of the chunk is reclaimed or on failed media, so the whole entry is lost.
+                Ok(None) | Err(_) => return miss(),
+            };
+            self.inner
+                .metrics
+                .op_bytes_lookup
+                .inc_by(slice.len() as u64);
+            match read_chunk(slice.as_ref(), self.inner.verify_mode.on_read()) {
+                Some((h, payload, stored)) => {
+                    // Same as `lookup_raw`, the index is removed if the entry is of another key.
+                    if first.is_none()
+                        && !h
+                            .read_key::<K>(stored, self.inner.encryption.as_ref())
+                            .is_some_and(|k| &k == key)
+                    {
+                        return miss();
+                    }
+                    buf.extend_from_slice(payload);
+                    first.get_or_insert_with(|| (h, stored.to_vec()));
+                }
+                None => return miss(),
+            }
+            drop(slice);
+        }
+        if buf.len() != value_len as usize {
+            return miss();
+        }
+
+        let Some((header, stored)) = first else {
+            return miss();
+        };
+        let value = match header.decode(&buf, &stored, self.inner.encryption.as_ref()) {
+            Some(Cow::Owned(value)) => Some(value),
+            Some(Cow::Borrowed(_)) => None,
+            None => return miss(),
+        };
+        let value = ValueGuard::Owned(value.unwrap_or(buf));
+
+        self.inner
+            .metrics
+            .op_duration_lookup_hit
+            .observe(now.elapsed().as_secs_f64());
+
+        Ok(Some(value))
+    }
+
+    /// Write a large entry in chunks across regions, each chunk fits in a region.
+    ///
+    /// Returns `false` if a chunk is skipped by allocation timeout, chunks written before are left as garbage.
+    async fn write_chunks(
+        &self,
+        key: K,
+        value: &EntryValue<'_, V>,
+        sequence: Sequence,
+        expire_at: ExpireAt,
+        tier: usize,
+        must_allocate: bool,
+    ) -> Result<bool> {
+        let align = self.inner.device.align();
+        // All chunks share the encrypted key.
+        let entry_key = EntryKey::new(&key, self.inner.encryption.as_ref());
+        // The first block of a region is taken by the region header.
+        let max_payload_len = self.inner.device.region_size()
+            - align
+            - EntryHeader::serialized_len()
+            - CHUNK_META_LEN
+            - entry_key.len();
+
+        let mut header = EntryHeader {
+            key_len: 0,
+            value_len: 0,
+            sequence,
+            expire_at,
+            checksum: 0,
+            uncompressed_len: value.len() as u32,
+            flags: EntryFlags::CHUNK,
+            checksum_algorithm: self.inner.checksum_algorithm,
+            compression: Compression::None,
+        };
+        value.encode(&mut header);
+        entry_key.encode(&mut header);
+        let key_buf = entry_key.bytes(&header);
+        let buf = value.bytes(&header, &key_buf);
+
+        let count = (buf.len() + max_payload_len - 1) / max_payload_len;
+        let mut chunks = Vec::with_capacity(count);
+        let mut hold = None;
+        for (index, payload) in buf.chunks(max_payload_len).enumerate() {
+            let len = bits::align_up(
+                align,
+                EntryHeader::serialized_len() + CHUNK_META_LEN + payload.len() + entry_key.len(),
+            );
+
+            // Slices are released once written, holding them would block flushers from freeing buffers for
+            // chunks allocated later.
+            let Some(mut slice) = self
+                .inner
+                .region_manager
+                .allocate(tier, len, must_allocate)
+                .await
+            else {
+                return Ok(false);
+            };
+
+            write_chunk(
+                slice.as_mut(),
+                &key_buf,
+                payload,
+                index as u32,
+                count as u32,
+                header.clone(),
+            );
+            let region = self.inner.region_manager.region(&slice.region_id());
+            region.record_entry(sequence);
+            // Hold the watermark at the first region until the entry is indexed, so the chunks are not missed by a
+            // checkpoint taken after their slices are released. Regions of later chunks take larger sequences.
+            if hold.is_none() {
+                let sequence = region
+                    .buffered_sequence()
+                    .expect("region must be buffered while a slice is alive");
+                hold = Some(self.inner.region_manager.hold(sequence));
+            }
+
+            chunks.push(Chunk {
+                region: slice.region_id(),
+                version: slice.version(),
+                offset: slice.offset() as u32,
+                len: slice.len() as u32,
+            });
+        }
+
+        let info = IndexInfo {
+            sequence,
+            expire_at,
+            index: Index::Chunks {
+                chunks: chunks.into(),
+                key_len: key.serialized_len() as u32,
+                value_len: buf.len() as u32,
+            },
+        };
+        self.inner.indices.insert(key, info);
+        drop(hold);
+
+        Ok(true)
+    }
+
+    fn serialized_len(&self, key: &EntryKey<'_, K>, value: &EntryValue<'_, V>) -> usize {
+        let unaligned = EntryHeader::serialized_len() + key.len() + value.len();
+        bits::align_up(self.inner.device.align(), unaligned)
+    }
+
+    async fn seal(&self) {
+        self.inner.region_manager.seal().await;
+    }
+
+    /// Entries scanned are fully read and verified if `verify` is set, otherwise only keys are read.
+    #[tracing::instrument(skip(self))]
+    async fn recover(
+        &self,
+        concurrency: usize,
+        mode: RecoverMode,
+        verify: bool,
+    ) -> Result<Sequence> {
+        tracing::info!("start store recovery");
+
+        // Restore the catalog from the checkpoint, only regions rewritten since the checkpoint need to be scanned.
+        let (mut sequence, watermark) = match self.load_checkpoint().await? {
+            Some(checkpoint) => {
+                tracing::info!(
+                    "restore catalog from checkpoint, watermark: {}",
+                    checkpoint.watermark
+                );
+                for (key, tombstone) in checkpoint.tombstones {
+                    let shadows = tombstone.shadows.into_iter().map(|(region, _)| region);
+                    self.inner.indices.insert_tombstone(
+                        key,
+                        tombstone.sequence,
+                        tombstone.region,
+                        shadows,
+                    );
+                }
+                for (key, info) in checkpoint.infos {
+                    self.inner.indices.insert(key, info);
+                }
+                (checkpoint.sequence, Some(checkpoint.watermark))
+            }
+            None => (0, None),
+        };
+
+        let (tx, rx) = async_channel::bounded(concurrency);
+
+        let chains = Arc::new(Chains::default());
+
+        // Regions reserved for buckets are recovered by the small-object engine.
+        let reserved = self
+            .inner
+            .buckets
+            .as_ref()
+            .map_or(0, |buckets| buckets.regions());
+
+        let mut handles = vec![];
+        for region_id in reserved as RegionId..self.inner.device.regions() as RegionId {
+            let itx = tx.clone();
+            let irx = rx.clone();
+            let region_manager = self.inner.region_manager.clone();
+            let indices = self.inner.indices.clone();
+            let chains = chains.clone();
+            let metrics = self.inner.metrics.clone();
+            let encryption = self.inner.encryption.clone();
+            let handle = tokio::spawn(async move {
+                itx.send(()).await.unwrap();
+                let res = Self::recover_region(
+                    region_id,
+                    watermark,
+                    mode,
+                    verify,
+                    encryption,
+                    region_manager,
+                    indices,
+                    chains,
+                    metrics,
+                )
+                .await;
+                irx.recv().await.unwrap();
+                res
+            });
+            handles.push(handle);
+        }
+
+        let mut recovered = 0;
+
+        let results = try_join_all(handles).await.map_err(anyhow::Error::from)?;
+
+        let mut retired = 0;
+        for (region_id, result) in results.into_iter().enumerate() {
+            let region_id = (reserved + region_id) as RegionId;
+            match result {
+                Ok(Some(seq)) => {
+                    tracing::debug!("region {} is recovered", region_id);
+                    recovered += 1;
+                    sequence = std::cmp::max(sequence, seq);
+                }
+                Ok(None) if !self.inner.device.is_usable(region_id) => retired += 1,
+                Ok(None) => {}
+                Err(e) if !self.inner.device.is_usable(region_id) => {
+                    tracing::warn!("retire unusable region {} on recovery: {}", region_id, e);
+                    self.inner.indices.take_region(&region_id);
+                    self.inner.indices.release_region(&region_id);
+                    retired += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let incomplete = chains.len();
+        if incomplete > 0 {
+            tracing::warn!(
+                "drop {} large entries with missing chunks on recovery",
+                incomplete
+            );
+        }
+
+        // Buckets are recovered after regions, so keys written to both are resolved by sequences.
+        if let Some(buckets) = self.inner.buckets.as_ref() {
+            let seq = buckets
+                .recover(concurrency, verify, &self.inner.indices)
+                .await?;
+            sequence = std::cmp::max(sequence, seq);
+        }
+
+        // Tombstones are only needed if there are still entries shadowed by them.
+        self.inner.indices.prune_tombstones();
+
+        tracing::info!(
+            "finish store recovery, {} region recovered, {} region retired",
+            recovered,
+            retired
+        );
+        self.inner
+            .metrics
+            .total_bytes
+            .set((recovered * self.inner.device.region_size()) as u64);
+
+        // Force trigger reclamation of tiers without clean regions.
+        for tier in 0..self.inner.region_manager.tiers() {
+            let clean_regions = self.inner.region_manager.clean_regions(tier);
+            if clean_regions.is_empty() {
+                clean_regions.flash();
+            }
+        }
+
+        Ok(sequence)
     }
 
     /// Return `Some(max sequence)` if region is valid, otherwise `None`
+    ///
+    /// Regions with a header sequence below `watermark` are not scanned, their indices are restored from the
+    /// checkpoint.
+    #[expect(clippy::too_many_arguments)]
     async fn recover_region(
         region_id: RegionId,
+        watermark: Option<Sequence>,
+        mode: RecoverMode,
+        verify: bool,
+        encryption: Option<Encryption>,
         region_manager: Arc<RegionManager<D, EP, EL>>,
         indices: Arc<Catalog<K>>,
+        chains: Arc<Chains<K>>,
+        metrics: Arc<Metrics>,
     ) -> Result<Option<Sequence>> {
         let region = region_manager.region(&region_id).clone();
-        let mut sequence = 0;
-        let res = if let Some(mut iter) = RegionEntryIter::<K, V, D>::open(region).await? {
-            while let Some((key, info)) = iter.next().await? {
-                sequence = std::cmp::max(sequence, info.sequence);
-                indices.insert(key, info);
+        if !region.device().is_usable(region_id) {
+            indices.take_region(&region_id);
+            indices.release_region(&region_id);
+            return Ok(None);
+        }
+        let epoch = region_manager.epoch();
+        let res = if let Some(mut iter) = RegionEntryIter::<K, V, D>::open(region, epoch).await? {
+            let header = iter.header();
+            let mut sequence = std::cmp::max(header.sequence, header.max_sequence);
+            if let Some(watermark) = watermark
+                && header.sequence < watermark
+            {
+                region_manager.eviction_push(region_id);
+                return Ok(Some(sequence));
+            }
+
+            // Indices restored from the checkpoint may be stale, the region is rewritten since.
+            indices.take_region(&region_id);
+            indices.release_region(&region_id);
+
+            iter.set_recover_mode(mode);
+            iter.set_verify(verify);
+            iter.set_encryption(encryption);
+
+            while let Some(entry) = iter.next().await? {
+                match entry {
+                    // Expired entries still shadow older entries of their keys.
+                    RegionEntry::Value { key, info } if info.is_expired() => {
+                        sequence = std::cmp::max(sequence, info.sequence);
+                        indices.insert_tombstone(key, info.sequence, region_id, []);
+                    }
+                    RegionEntry::Value { key, info } => {
+                        sequence = std::cmp::max(sequence, info.sequence);
+                        indices.insert(key, info);
+                    }
+                    RegionEntry::Tombstone { key, sequence: seq } => {
+                        sequence = std::cmp::max(sequence, seq);
+                        indices.insert_tombstone(key, seq, region_id, []);
+                    }
+                    RegionEntry::Chunk {
+                        key,
+                        sequence: seq,
+                        expire_at,
+                        ..
+                    } if catalog::is_expired(expire_at) => {
+                        sequence = std::cmp::max(sequence, seq);
+                        indices.insert_tombstone(key, seq, region_id, []);
+                    }
+                    RegionEntry::Chunk {
+                        key,
+                        sequence: seq,
+                        expire_at,
+                        index,
+                        count,
+                        value_len,
+                        chunk,
+                    } => {
+                        sequence = std::cmp::max(sequence, seq);
+                        chains.add(
+                            key, seq, expire_at, index, count, value_len, chunk, &indices,
+                        );
+                    }
+                }
+            }
+            if iter.skipped() > 0 {
+                tracing::warn!(
+                    "region {} recovered with {} corrupted bytes skipped",
+                    region_id,
+                    iter.skipped()
+                );
+                metrics
+                    .op_bytes_recover_skipped
+                    .inc_by(iter.skipped() as u64);
             }
             region_manager.eviction_push(region_id);
             Some(sequence)
         } else {
-            region_manager.clean_regions().release(region_id);
+            indices.take_region(&region_id);
+            indices.release_region(&region_id);
+            region_manager.release_clean_region(region_id);
             None
         };
         Ok(res)
@@ -580,6 +2094,13 @@ where
 
         let now = Instant::now();
 
+        let sequence = self.admit(&mut writer, &value);
+        let value = self.encode(&value);
+        self.write_admitted(writer, sequence, &value, now).await
+    }
+
+    /// Mark the judged writer as inserted and notify admission policies, returns the sequence of the entry.
+    fn admit(&self, writer: &mut GenericStoreWriter<K, V, D, EP, EL>, value: &V) -> Sequence {
         let sequence = if let Some(sequence) = writer.sequence {
             sequence
         } else {
@@ -587,15 +2108,13 @@ where
         };
 
         writer.is_inserted = true;
-        let key = writer.key;
+        let key = &writer.key;
 
         for (i, admission) in self.inner.admissions.iter().enumerate() {
             let judge = writer.judges.get(i);
-            admission.on_insert(&key, writer.weight, &self.inner.metrics, judge);
+            admission.on_insert(key, writer.weight, &self.inner.metrics, judge);
         }
 
-        let serialized_len = self.serialized_len(&key, &value);
-
         if key.serialized_len() + value.serialized_len() != writer.weight {
             tracing::error!(
                 "weight != key.serialized_len() + value.serialized_len(), weight: {}, key size: {}, value size: {}, key: {:?}",
@@ -603,15 +2122,153 @@ where
             );
         }
 
+        sequence
+    }
+
+    /// Serialize the value to write, compressed and encrypted as configured.
+    fn encode<'a>(&self, value: &'a V) -> EntryValue<'a, V> {
+        let value = EntryValue::new(
+            value,
+            self.inner.compression,
+            self.inner.encryption.as_ref(),
+        );
+        if self.inner.compression != Compression::None {
+            let uncompressed_len = match &value {
+                EntryValue::Value(value) => value.serialized_len(),
+                EntryValue::Serialized {
+                    uncompressed_len, ..
+                } => *uncompressed_len,
+            };
+            self.inner
+                .metrics
+                .op_bytes_insert_uncompressed
+                .inc_by(uncompressed_len as u64);
+            self.inner
+                .metrics
+                .op_bytes_insert_compressed
+                .inc_by(value.len() as u64);
+        }
+        value
+    }
+
+    /// Write an admitted entry to a bucket, the ring buffer or regions by its size.
+    async fn write_admitted(
+        &self,
+        writer: GenericStoreWriter<K, V, D, EP, EL>,
+        sequence: Sequence,
+        value: &EntryValue<'_, V>,
+        now: Instant,
+    ) -> Result<bool> {
+        let key = writer.key;
+        let entry_key = EntryKey::new(&key, self.inner.encryption.as_ref());
+
+        let len = EntryHeader::serialized_len() + entry_key.len() + value.len();
+
+        if let Some(buckets) = self.inner.buckets.as_ref()
+            && len <= buckets.threshold()
+        {
+            self.inner.metrics.op_bytes_insert.inc_by(len as u64);
+
+            if !buckets.insert(&key, value, sequence, writer.expire_at).await? {
+                return Ok(false);
+            }
+
+            // Drop the older entry of the key in regions or the ring buffer, persist a tombstone for the former.
+            if let Some(info) = self.inner.indices.remove_older(&key, sequence) {
+                let shadows: Vec<_> = info.index.regions().collect();
+                if !shadows.is_empty() {
+                    self.write_tombstone(key, sequence, shadows, 0, !writer.is_skippable)
+                        .await?;
+                }
+            }
+
+            let duration = now.elapsed() + writer.duration;
+            self.inner
+                .metrics
+                .op_duration_insert_inserted
+                .observe(duration.as_secs_f64());
+
+            return Ok(true);
+        }
+
+        // The key may have an older entry in a bucket.
+        if let Some(buckets) = self.inner.buckets.as_ref() {
+            buckets.remove(&key, sequence).await?;
+        }
+
+        // Skippable writers and migrations bypass the ring buffer, which may block until a spill to the first tier.
+        if let Some(ring) = self.inner.ring.as_ref()
+            && !writer.is_skippable
+            && writer.tier == 0
+            && len <= ring.threshold
+        {
+            self.inner.metrics.op_bytes_insert.inc_by(len as u64);
+
+            let mut view = ring.ring.allocate(len).await;
+            write_entry(
+                &mut view[..len],
+                &entry_key,
+                value,
+                sequence,
+                writer.expire_at,
+                self.inner.checksum_algorithm,
+            );
+            let entry = StagedEntry {
+                key_len: key.serialized_len(),
+                value_len: value.len(),
+                key,
+                sequence,
+                expire_at: writer.expire_at,
+                len,
+                view: view.freeze(),
+            };
+            ring.stage(&self.inner.indices, entry);
+            self.may_spill(ring).await;
+
+            let duration = now.elapsed() + writer.duration;
+            self.inner
+                .metrics
+                .op_duration_insert_inserted
+                .observe(duration.as_secs_f64());
+
+            return Ok(true);
+        }
+
+        let serialized_len = self.serialized_len(&entry_key, value);
+
         self.inner
             .metrics
             .op_bytes_insert
             .inc_by(serialized_len as u64);
 
-        let mut slice = match self
+        // Entries larger than a region are split into chunks.
+        if serialized_len > self.inner.device.region_size() - self.inner.device.align() {
+            let inserted = self
+                .write_chunks(
+                    key,
+                    value,
+                    sequence,
+                    writer.expire_at,
+                    writer.tier,
+                    !writer.is_skippable,
+                )
+                .await?;
+
+            let duration = now.elapsed() + writer.duration;
+            if inserted {
+                self.inner
+                    .metrics
+                    .op_duration_insert_inserted
+                    .observe(duration.as_secs_f64());
+            }
+
+            return Ok(inserted);
+        }
+
+        let mut slice = match self
             .inner
             .region_manager
-            .allocate(serialized_len, !writer.is_skippable)
+            .allocate(writer.tier, serialized_len, !writer.is_skippable)
             .await
         {
             Some(slice) => slice,
@@ -619,22 +2276,35 @@ where
             None => return Ok(false),
         };
 
-        write_entry(slice.as_mut(), &key, &value, sequence);
+        write_entry(
+            slice.as_mut(),
+            &entry_key,
+            value,
+            sequence,
+            writer.expire_at,
+            self.inner.checksum_algorithm,
+        );
+        self.inner
+            .region_manager
+            .region(&slice.region_id())
+            .record_entry(sequence);
 
         let info = IndexInfo {
             sequence,
+            expire_at: writer.expire_at,
             index: Index::Region {
                 region: slice.region_id(),
                 version: slice.version(),
                 offset: slice.offset() as u32,
                 len: slice.len() as u32,
                 key_len: key.serialized_len() as u32,
-                value_len: value.serialized_len() as u32,
+                value_len: value.len() as u32,
             },
         };
-        drop(slice);
-
+        // Insert the index before the slice is released, so the entry is not missed by a checkpoint taken after the
+        // region is flushed.
         self.inner.indices.insert(key, info);
+        drop(slice);
 
         let duration = now.elapsed() + writer.duration;
         self.inner
@@ -668,6 +2338,11 @@ where
 
     is_inserted: bool,
     is_skippable: bool,
+
+    /// Device tier the entry is written to.
+    tier: usize,
+
+    expire_at: ExpireAt,
 }
 
 impl<K, V, D, EP, EL> GenericStoreWriter<K, V, D, EP, EL>
@@ -690,6 +2365,8 @@ where
             duration: Duration::from_nanos(0),
             is_inserted: false,
             is_skippable: false,
+            tier: 0,
+            expire_at: 0,
         }
     }
 
@@ -724,6 +2401,21 @@ where
     pub fn set_sequence(&mut self, sequence: Sequence) {
         self.sequence = Some(sequence);
     }
+
+    /// Write the entry to a slower device tier, e.g. when it is migrated by the reclaimer.
+    pub fn set_tier(&mut self, tier: usize) {
+        self.tier = tier;
+    }
+
+    /// Set the deadline after which the entry is treated as a miss.
+    pub fn set_expire_at(&mut self, deadline: SystemTime) {
+        self.expire_at = catalog::expire_at(deadline);
+    }
+
+    /// Keep the deadline of an entry reinserted by the reclaimer.
+    pub(crate) fn set_expire_at_millis(&mut self, expire_at: ExpireAt) {
+        self.expire_at = expire_at;
+    }
 }
 
 impl<K, V, D, EP, EL> Debug for GenericStoreWriter<K, V, D, EP, EL>
@@ -786,112 +2478,42 @@ where
 //     }
 // }
 
-const ENTRY_MAGIC: u32 = 0x97_00_00_00;
-const ENTRY_MAGIC_MASK: u32 = 0xFF_00_00_00;
-
 #[derive(Debug)]
-struct EntryHeader {
-    key_len: u32,
-    value_len: u32,
-    sequence: Sequence,
-    checksum: u64,
-}
-
-impl EntryHeader {
-    fn serialized_len() -> usize {
-        4 + 4 + 8 + 8
-    }
-
-    fn write(&self, mut buf: &mut [u8]) {
-        buf.put_u32(self.key_len | ENTRY_MAGIC);
-        buf.put_u32(self.value_len);
-        buf.put_u64(self.sequence);
-        buf.put_u64(self.checksum);
-    }
-
-    fn read(mut buf: &[u8]) -> Option<Self> {
-        let head = buf.get_u32();
-        let magic = head & ENTRY_MAGIC_MASK;
-
-        if magic != ENTRY_MAGIC {
-            return None;
-        }
-
-        let key_len = head ^ ENTRY_MAGIC;
-        let value_len = buf.get_u32();
-        let sequence = buf.get_u64();
-        let checksum = buf.get_u64();
-
-        Some(Self {
-            key_len,
-            value_len,
-            sequence,
-            checksum,
-        })
-    }
-}
-
-/// | header | value | key | <padding> |
-///
-/// # Safety
-///
-/// `buf.len()` must excatly fit entry size
-fn write_entry<K, V>(buf: &mut [u8], key: &K, value: &V, sequence: Sequence)
+pub enum RegionEntry<K>
 where
     K: Key,
-    V: Value,
 {
-    let mut offset = EntryHeader::serialized_len();
-    value.write(&mut buf[offset..offset + value.serialized_len()]);
-    offset += value.serialized_len();
-    key.write(&mut buf[offset..offset + key.serialized_len()]);
-    offset += key.serialized_len();
-    let checksum = checksum(&buf[EntryHeader::serialized_len()..offset]);
-
-    let header = EntryHeader {
-        key_len: key.serialized_len() as u32,
-        value_len: value.serialized_len() as u32,
-        sequence,
-        checksum,
-    };
-    header.write(&mut buf[..EntryHeader::serialized_len()]);
+    Value {
+        key: K,
+        info: IndexInfo,
+    },
+    Tombstone {
+        key: K,
+        sequence: Sequence,
+    },
+    /// The `index`-th of `count` chunks of a large entry, with `value_len` bytes of its value.
+    Chunk {
+        key: K,
+        sequence: Sequence,
+        expire_at: ExpireAt,
+        index: u32,
+        count: u32,
+        value_len: u32,
+        chunk: Chunk,
+    },
 }
 
-/// | header | value | key | <padding> |
-///
-/// # Safety
-///
-/// `buf.len()` must excatly fit entry size
-fn read_entry<K, V>(buf: &[u8]) -> Option<(K, V)>
+enum Parsed<K>
 where
     K: Key,
-    V: Value,
 {
-    let header = EntryHeader::read(buf)?;
-
-    let mut offset = EntryHeader::serialized_len();
-    let value = V::read(&buf[offset..offset + header.value_len as usize]);
-    offset += header.value_len as usize;
-    let key = K::read(&buf[offset..offset + header.key_len as usize]);
-    offset += header.key_len as usize;
-
-    let checksum = checksum(&buf[EntryHeader::serialized_len()..offset]);
-    if checksum != header.checksum {
-        tracing::warn!(
-            "checksum mismatch, checksum: {}, expected: {}",
-            checksum,
-            header.checksum,
-        );
-        return None;
-    }
-
-    Some((key, value))
-}
-
-fn checksum(buf: &[u8]) -> u64 {
-    let mut hasher = XxHash64::with_seed(0);
-    hasher.write(buf);
-    hasher.finish()
+    Entry(RegionEntry<K>),
+    /// Entries of a batch are buffered and returned one by one.
+    Batch,
+    /// The entry fails to decrypt, e.g. it is encrypted with a key no longer provided.
+    Skipped,
+    Corrupted,
+    End,
 }
 
 pub struct RegionEntryIter<K, V, D>
@@ -902,8 +2524,21 @@ where
 {
     region: Region<D>,
 
+    header: RegionHeader,
+
     cursor: usize,
 
+    mode: RecoverMode,
+    /// Read and verify whole entries instead of keys only.
+    verify: bool,
+    /// Bytes of corrupted entries skipped or left unscanned.
+    skipped: usize,
+
+    encryption: Option<Encryption>,
+
+    /// Parsed entries of the last batch not returned yet.
+    batch: VecDeque<RegionEntry<K>>,
+
     _marker: PhantomData<(K, V)>,
 }
 
@@ -913,7 +2548,8 @@ where
     V: Value,
     D: Device,
 {
-    pub async fn open(region: Region<D>) -> Result<Option<Self>> {
+    /// Returns `None` if the region is not valid, or is written in another epoch or format version.
+    pub async fn open(region: Region<D>, epoch: u64) -> Result<Option<Self>> {
         let align = region.device().align();
 
         let slice = match region.load(..align, 0).await? {
@@ -924,23 +2560,88 @@ where
         let header = RegionHeader::read(slice.as_ref());
         drop(slice);
 
-        if header.magic != REGION_MAGIC {
+        if !header.is_valid() || header.epoch != epoch {
             return Ok(None);
         }
 
         Ok(Some(Self {
             region,
+            header,
             cursor: align,
+            mode: RecoverMode::default(),
+            verify: false,
+            skipped: 0,
+            encryption: None,
+            batch: VecDeque::new(),
             _marker: PhantomData,
         }))
     }
 
-    pub async fn next(&mut self) -> Result<Option<(K, IndexInfo)>> {
+    pub fn header(&self) -> &RegionHeader {
+        &self.header
+    }
+
+    pub fn set_recover_mode(&mut self, mode: RecoverMode) {
+        self.mode = mode;
+    }
+
+    /// Read and verify the checksum of whole entries while iterating, instead of reading keys only.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Decrypt keys and values of encrypted entries with `encryption`, entries that fail to decrypt are skipped.
+    pub fn set_encryption(&mut self, encryption: Option<Encryption>) {
+        self.encryption = encryption;
+    }
+
+    /// Returns the bytes of corrupted entries skipped or left unscanned.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    pub async fn next(&mut self) -> Result<Option<RegionEntry<K>>> {
+        let align = self.region.device().align();
+        loop {
+            if let Some(entry) = self.batch.pop_front() {
+                return Ok(Some(entry));
+            }
+            match self.parse().await? {
+                Parsed::Entry(entry) => return Ok(Some(entry)),
+                Parsed::Batch | Parsed::Skipped => {}
+                Parsed::End => return Ok(None),
+                // Without the valid bytes recorded on flush, the end of entries cannot be told from corruption.
+                Parsed::Corrupted
+                    if self.mode == RecoverMode::Tolerant && self.header.sealed_at != 0 =>
+                {
+                    tracing::warn!(
+                        "skip corrupted block, region: {}, offset: {}",
+                        self.region.id(),
+                        self.cursor
+                    );
+                    self.skipped += align;
+                    self.cursor += align;
+                }
+                Parsed::Corrupted => {
+                    if self.header.sealed_at != 0 {
+                        self.skipped +=
+                            (self.header.valid_bytes as usize).saturating_sub(self.cursor);
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    async fn parse(&mut self) -> Result<Parsed<K>> {
         let region_size = self.region.device().region_size();
         let align = self.region.device().align();
 
-        if self.cursor + align >= region_size {
-            return Ok(None);
+        // Entries are never written beyond the valid bytes recorded when the region is flushed.
+        if self.cursor + align > region_size
+            || (self.header.sealed_at != 0 && self.cursor >= self.header.valid_bytes as usize)
+        {
+            return Ok(Parsed::End);
         }
 
         let Some(slice) = self
@@ -948,86 +2649,270 @@ where
             .load(self.cursor..self.cursor + align, 0)
             .await?
         else {
-            return Ok(None);
+            return Ok(Parsed::End);
         };
 
         let Some(header) = EntryHeader::read(slice.as_ref()) else {
-            return Ok(None);
+            return Ok(Parsed::Corrupted);
         };
 
+        if header.flags.contains(EntryFlags::BATCH) {
+            drop(slice);
+            return self.parse_batch(header).await;
+        }
+
         let entry_len = bits::align_up(
             align,
-            (header.value_len + header.key_len) as usize + EntryHeader::serialized_len(),
+            header.value_len as usize + header.key_len as usize + EntryHeader::serialized_len(),
         );
 
         let abs_start = self.cursor + EntryHeader::serialized_len() + header.value_len as usize;
-        let abs_end = self.cursor
-            + EntryHeader::serialized_len()
-            + (header.key_len + header.value_len) as usize;
+        let abs_end = abs_start + header.key_len as usize;
 
         if abs_start >= abs_end || abs_end > region_size {
             // Double check wrong entry.
-            return Ok(None);
+            return Ok(Parsed::Corrupted);
         }
 
         let align_start = bits::align_down(align, abs_start);
         let align_end = bits::align_up(align, abs_end);
 
-        let key = if align_start == self.cursor - align && align_end == self.cursor {
+        let is_tombstone = header.flags.contains(EntryFlags::TOMBSTONE);
+        // The chunk index and count follow the header in the same block.
+        let chunk = header.flags.contains(EntryFlags::CHUNK).then(|| {
+            let mut buf = &slice.as_ref()[EntryHeader::serialized_len()..];
+            (buf.get_u32(), buf.get_u32())
+        });
+        if chunk.is_some() && (header.value_len as usize) < CHUNK_META_LEN {
+            return Ok(Parsed::Corrupted);
+        }
+        // Tombstones carry no value, verify its checksum here.
+        //
+        // `None` if the entry is corrupted, `Some(None)` if the key fails to decrypt.
+        let read_key = |buf: &[u8]| {
+            if is_tombstone && header.checksum_algorithm.checksum(buf) != header.checksum {
+                tracing::warn!("tombstone checksum mismatch, region: {}", self.region.id());
+                return None;
+            }
+            Some(header.read_key::<K>(buf, self.encryption.as_ref()))
+        };
+
+        let key = if self.verify && !is_tombstone {
+            drop(slice);
+            let Some(s) = self
+                .region
+                .load(self.cursor..self.cursor + entry_len, 0)
+                .await?
+            else {
+                return Ok(Parsed::End);
+            };
+            let body = &s.as_ref()[EntryHeader::serialized_len()..abs_end - self.cursor];
+            let key =
+                if header.checksum(body) != header.checksum {
+                    tracing::warn!(
+                        "entry checksum mismatch, region: {}, offset: {}",
+                        self.region.id(),
+                        self.cursor
+                    );
+                    None
+                } else {
+                    Some(header.read_key::<K>(
+                        &body[header.value_len as usize..],
+                        self.encryption.as_ref(),
+                    ))
+                };
+            drop(s);
+            key
+        } else if align_start == self.cursor - align && align_end == self.cursor {
             // header and key are in the same block, read directly from slice
             let rel_start = EntryHeader::serialized_len() + header.value_len as usize;
             let rel_end = rel_start + header.key_len as usize;
-            let key = K::read(&slice.as_ref()[rel_start..rel_end]);
+            let key = read_key(&slice.as_ref()[rel_start..rel_end]);
             drop(slice);
             key
         } else {
             drop(slice);
             let Some(s) = self.region.load(align_start..align_end, 0).await? else {
-                return Ok(None);
+                return Ok(Parsed::End);
             };
             let rel_start = abs_start - align_start;
             let rel_end = abs_end - align_start;
 
-            let key = K::read(&s.as_ref()[rel_start..rel_end]);
+            let key = read_key(&s.as_ref()[rel_start..rel_end]);
             drop(s);
             key
         };
+        let Some(key) = key else {
+            return Ok(Parsed::Corrupted);
+        };
+        let Some(key) = key else {
+            self.cursor += entry_len;
+            return Ok(Parsed::Skipped);
+        };
+
+        if is_tombstone {
+            self.cursor += entry_len;
+            return Ok(Parsed::Entry(RegionEntry::Tombstone {
+                key,
+                sequence: header.sequence,
+            }));
+        }
+
+        if let Some((index, count)) = chunk {
+            let chunk = Chunk {
+                region: self.region.id(),
+                version: 0,
+                offset: self.cursor as u32,
+                len: entry_len as u32,
+            };
+            self.cursor += entry_len;
+            return Ok(Parsed::Entry(RegionEntry::Chunk {
+                key,
+                sequence: header.sequence,
+                expire_at: header.expire_at,
+                index,
+                count,
+                value_len: header.value_len - CHUNK_META_LEN as u32,
+                chunk,
+            }));
+        }
 
         let info = IndexInfo {
             sequence: header.sequence,
+            expire_at: header.expire_at,
             index: Index::Region {
                 region: self.region.id(),
                 version: 0,
                 offset: self.cursor as u32,
                 len: entry_len as u32,
-                key_len: header.key_len,
+                key_len: key.serialized_len() as u32,
                 value_len: header.value_len,
             },
         };
 
         self.cursor += entry_len;
 
-        Ok(Some((key, info)))
+        Ok(Parsed::Entry(RegionEntry::Value { key, info }))
+    }
+
+    async fn parse_batch(&mut self, header: EntryHeader) -> Result<Parsed<K>> {
+        let region_size = self.region.device().region_size();
+        let align = self.region.device().align();
+        let header_len = EntryHeader::serialized_len();
+
+        let batch_len = bits::align_up(align, header_len + header.value_len as usize);
+        if self.cursor + batch_len > region_size {
+            return Ok(Parsed::Corrupted);
+        }
+
+        let Some(slice) = self
+            .region
+            .load(self.cursor..self.cursor + batch_len, 0)
+            .await?
+        else {
+            return Ok(Parsed::End);
+        };
+
+        // The whole batch is loaded anyway, always verify it.
+        let payload = &slice.as_ref()[header_len..header_len + header.value_len as usize];
+        if header.checksum_algorithm.checksum(payload) != header.checksum {
+            tracing::warn!(
+                "batch checksum mismatch, region: {}, offset: {}",
+                self.region.id(),
+                self.cursor
+            );
+            return Ok(Parsed::Corrupted);
+        }
+
+        let mut entries = vec![];
+        let mut pos = 0;
+        while pos < payload.len() {
+            let buf = &payload[pos..];
+            let Some(h) = (buf.len() >= header_len)
+                .then(|| EntryHeader::read(buf))
+                .flatten()
+            else {
+                return Ok(Parsed::Corrupted);
+            };
+            let len = header_len + h.value_len as usize + h.key_len as usize;
+            if !h
+                .flags
+                .difference(EntryFlags::COMPRESSED | EntryFlags::ENCRYPTED)
+                .is_empty()
+                || len > buf.len()
+            {
+                return Ok(Parsed::Corrupted);
+            }
+
+            let Some(key) = h.read_key::<K>(
+                &buf[header_len + h.value_len as usize..len],
+                self.encryption.as_ref(),
+            ) else {
+                pos += len;
+                continue;
+            };
+            if self.verify
+                && read_entry::<K, V>(&buf[..len], true, self.encryption.as_ref()).is_none()
+            {
+                return Ok(Parsed::Corrupted);
+            }
+
+            let info = IndexInfo {
+                sequence: h.sequence,
+                expire_at: h.expire_at,
+                index: Index::Region {
+                    region: self.region.id(),
+                    version: 0,
+                    offset: (self.cursor + header_len + pos) as u32,
+                    len: len as u32,
+                    key_len: key.serialized_len() as u32,
+                    value_len: h.value_len,
+                },
+            };
+            entries.push(RegionEntry::Value { key, info });
+            pos += len;
+        }
+        drop(slice);
+
+        self.cursor += batch_len;
+        self.batch.extend(entries);
+
+        Ok(Parsed::Batch)
     }
 
+    /// Returns the next key-value entry, tombstones and chunks of large entries are skipped.
     pub async fn next_kv(&mut self) -> Result<Option<(K, V)>> {
-        let (_, info) = match self.next().await {
-            Ok(Some(res)) => res,
-            Ok(None) => return Ok(None),
-            Err(e) => return Err(e),
+        let info = loop {
+            match self.next().await {
+                Ok(Some(RegionEntry::Value { info, .. })) => break info,
+                Ok(Some(RegionEntry::Tombstone { .. } | RegionEntry::Chunk { .. })) => continue,
+                Ok(None) => return Ok(None),
+                Err(e) => return Err(e),
+            }
         };
 
+        self.read_kv(&info).await
+    }
+
+    /// Read the key-value entry of the given index from the region.
+    pub async fn read_kv(&self, info: &IndexInfo) -> Result<Option<(K, V)>> {
         let Index::Region { offset, len, .. } = info.index else {
             unreachable!("kv loaded from region must have index of region")
         };
 
         // TODO(MrCroxx): Optimize if all key, value and footer are in the same read block.
-        let start = offset as usize;
-        let end = start + len as usize;
+        // Entries packed in a batch are not aligned.
+        let align = self.region.device().align();
+        let start = bits::align_down(align, offset as usize);
+        let end = bits::align_up(align, (offset + len) as usize);
         let Some(slice) = self.region.load(start..end, 0).await? else {
             return Ok(None);
         };
-        let kv = read_entry::<K, V>(slice.as_ref());
+        let kv = read_entry::<K, V>(
+            &slice.as_ref()[offset as usize - start..],
+            true,
+            self.encryption.as_ref(),
+        );
         drop(slice);
 
         Ok(kv)
@@ -1053,189 +2938,2033 @@ where
         self.weight
     }
 
-    fn judge(&mut self) -> bool {
-        self.judge()
+    fn judge(&mut self) -> bool {
+        self.judge()
+    }
+
+    fn force(&mut self) {
+        self.force()
+    }
+
+    fn set_expire_at(&mut self, deadline: SystemTime) {
+        self.set_expire_at(deadline)
+    }
+
+    async fn finish(self, value: Self::Value) -> Result<bool> {
+        self.finish(value).await
+    }
+}
+
+impl<K, V, D, EP, EL> Storage for GenericStore<K, V, D, EP, EL>
+where
+    K: Key,
+    V: Value,
+    D: Device,
+    EP: EvictionPolicy<Adapter = RegionEpItemAdapter<EL>>,
+    EL: Link,
+{
+    type Key = K;
+    type Value = V;
+    type Config = GenericStoreConfig<K, V, D, EP>;
+    type Writer = GenericStoreWriter<K, V, D, EP, EL>;
+
+    async fn open(config: Self::Config) -> Result<Self> {
+        Self::open(config).await
+    }
+
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.close().await
+    }
+
+    fn writer(&self, key: Self::Key, weight: usize) -> Self::Writer {
+        self.writer(key, weight)
+    }
+
+    async fn insert_many(&self, entries: Vec<(Self::Key, Self::Value)>) -> Result<Vec<bool>> {
+        self.insert_many(entries).await
+    }
+
+    fn exists(&self, key: &Self::Key) -> Result<bool> {
+        self.exists(key)
+    }
+
+    async fn lookup(&self, key: &Self::Key) -> Result<Option<Self::Value>> {
+        self.lookup(key).await
+    }
+
+    async fn lookup_many(&self, keys: &[Self::Key]) -> Result<Vec<Option<Self::Value>>> {
+        self.lookup_many(keys).await
+    }
+
+    async fn remove(&self, key: &Self::Key) -> Result<bool> {
+        self.remove(key).await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.clear().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        sync::atomic::AtomicUsize,
+    };
+
+    use foyer_intrusive::eviction::{
+        fifo::{Fifo, FifoConfig, FifoLink},
+        lru::LruConfig,
+    };
+
+    use crate::{
+        device::{
+            error::DeviceResult,
+            fs::{FsDevice, FsDeviceConfig},
+            IoBuf, IoBufMut, IoRange,
+        },
+        encrypt::StaticKeyProvider,
+        region::REGION_FORMAT_VERSION,
+        storage::StorageExt,
+        store::{LruFsStore, LruFsStoreConfig},
+        test_utils::JudgeRecorder,
+    };
+
+    use super::*;
+
+    type TestStore =
+        GenericStore<u64, Vec<u8>, FsDevice, Fifo<RegionEpItemAdapter<FifoLink>>, FifoLink>;
+
+    type TestStoreConfig =
+        GenericStoreConfig<u64, Vec<u8>, FsDevice, Fifo<RegionEpItemAdapter<FifoLink>>>;
+
+    fn test_config(dir: &Path) -> TestStoreConfig {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig,
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(dir),
+                capacity: 16 * MB,
+                file_capacity: 4 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                reformat: false,
+                preallocate: false,
+            },
+            allocator_bits: 0,
+            catalog_bits: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            buffer_pool_size: 8 * MB,
+            ring_buffer_capacity: 0,
+            ring_buffer_threshold: 0,
+            small_object_regions: 0,
+            small_object_threshold: 0,
+            flushers: 1,
+            flush_rate_limit: 0,
+            reclaimers: 1,
+            reclaim_rate_limit: 0,
+            reclaim_discard: false,
+            recover_concurrency: 2,
+            recover_mode: RecoverMode::Strict,
+            checkpoint_interval: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            verify_mode: VerifyMode::default(),
+            compression: Compression::None,
+            encryption: None,
+            allocation_timeout: Duration::from_millis(10),
+            clean_region_threshold: 1,
+        }
+    }
+
+    #[tokio::test]
+    #[expect(clippy::identity_op)]
+    async fn test_recovery() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let recorder = Arc::new(JudgeRecorder::default());
+        let admissions: Vec<Arc<dyn AdmissionPolicy<Key = u64, Value = Vec<u8>>>> =
+            vec![recorder.clone()];
+        let reinsertions: Vec<Arc<dyn ReinsertionPolicy<Key = u64, Value = Vec<u8>>>> =
+            vec![recorder.clone()];
+
+        let config = TestStoreConfig {
+            allocator_bits: 1,
+            admissions,
+            reinsertions,
+            ..test_config(tempdir.path())
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+
+        // files:
+        // [0, 1, 2]
+        // [3, 4, 5]
+        // [6, 7, 8]
+        // [9, 10, 11]
+        for i in 0..20 {
+            store.insert(i, vec![i as u8; 1 * MB]).await.unwrap();
+        }
+
+        store.close().await.unwrap();
+
+        let remains = recorder.remains();
+
+        for i in 0..20 {
+            if remains.contains(&i) {
+                assert_eq!(
+                    store.lookup(&i).await.unwrap().unwrap(),
+                    vec![i as u8; 1 * MB],
+                );
+            } else {
+                assert!(store.lookup(&i).await.unwrap().is_none());
+            }
+        }
+
+        drop(store);
+
+        let config = TestStoreConfig {
+            device_config: FsDeviceConfig {
+                align: 4096,
+                io_size: 4096 * KB,
+                ..test_config(tempdir.path()).device_config
+            },
+            allocator_bits: 1,
+            reclaimers: 0,
+            ..test_config(tempdir.path())
+        };
+        let store = TestStore::open(config).await.unwrap();
+
+        for i in 0..12 {
+            if remains.contains(&i) {
+                assert_eq!(
+                    store.lookup(&i).await.unwrap().unwrap(),
+                    vec![i as u8; 1 * MB],
+                );
+            } else {
+                assert!(store.lookup(&i).await.unwrap().is_none());
+            }
+        }
+
+        store.close().await.unwrap();
+
+        drop(store);
+    }
+
+    #[tokio::test]
+    #[expect(clippy::identity_op)]
+    async fn test_remove_recovery() {
+        const KB: usize = 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            allocator_bits: 1,
+            ..test_config(tempdir.path())
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        for i in 0..20 {
+            store.insert(i, vec![i as u8; 1 * KB]).await.unwrap();
+        }
+        for i in 0..10 {
+            assert!(store.remove(&i).await.unwrap());
+        }
+        assert!(!store.remove(&0).await.unwrap());
+        // Reinsert after removal must win over the tombstone.
+        store.insert(0, vec![42; 1 * KB]).await.unwrap();
+
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config).await.unwrap();
+
+        assert_eq!(store.lookup(&0).await.unwrap().unwrap(), vec![42; 1 * KB]);
+        for i in 1..10 {
+            assert!(store.lookup(&i).await.unwrap().is_none());
+        }
+        for i in 10..20 {
+            assert_eq!(
+                store.lookup(&i).await.unwrap().unwrap(),
+                vec![i as u8; 1 * KB]
+            );
+        }
+
+        store.close().await.unwrap();
+        drop(store);
+    }
+
+    #[tokio::test]
+    #[expect(clippy::identity_op)]
+    async fn test_tombstone_rewrite() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = LruFsStoreConfig {
+            name: "".to_string(),
+            eviction_config: LruConfig {
+                lru_insertion_point_fraction: 0.0,
+            },
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 8 * MB,
+                file_capacity: 1 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                reformat: false,
+                preallocate: false,
+            },
+            allocator_bits: 0,
+            catalog_bits: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            buffer_pool_size: 2 * MB,
+            ring_buffer_capacity: 0,
+            ring_buffer_threshold: 0,
+            small_object_regions: 0,
+            small_object_threshold: 0,
+            flushers: 1,
+            flush_rate_limit: 0,
+            reclaimers: 1,
+            reclaim_rate_limit: 0,
+            reclaim_discard: false,
+            recover_concurrency: 2,
+            recover_mode: RecoverMode::Strict,
+            checkpoint_interval: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            verify_mode: VerifyMode::default(),
+            compression: Compression::None,
+            encryption: None,
+            allocation_timeout: Duration::from_millis(10),
+            clean_region_threshold: 1,
+        };
+
+        let store = LruFsStore::open(config.clone()).await.unwrap();
+
+        // Key 0 and the hot key 1 share the first region, the tombstone of key 0 is written to a later region.
+        for i in 0..400 {
+            store.insert(i, vec![i as u8; 1 * KB]).await.unwrap();
+        }
+        assert!(store.remove(&0).await.unwrap());
+
+        // Keep the first region hot, so the region of the tombstone is reclaimed before it.
+        for i in 400..4000 {
+            store.insert(i, vec![i as u8; 1 * KB]).await.unwrap();
+            if i % 64 == 0 {
+                assert_eq!(store.lookup(&1).await.unwrap().unwrap(), vec![1; 1 * KB]);
+            }
+        }
+        assert_eq!(store.lookup(&1).await.unwrap().unwrap(), vec![1; 1 * KB]);
+        assert!(store.lookup(&400).await.unwrap().is_none());
+
+        store.close().await.unwrap();
+        drop(store);
+
+        // Recover by a full scan, the removed entry is only shadowed by the rewritten tombstone.
+        std::fs::remove_file(tempdir.path().join("foyer-checkpoint")).unwrap();
+        let store = LruFsStore::open(config).await.unwrap();
+
+        assert!(store.lookup(&0).await.unwrap().is_none());
+        assert_eq!(store.lookup(&1).await.unwrap().unwrap(), vec![1; 1 * KB]);
+
+        store.close().await.unwrap();
+        drop(store);
+    }
+
+    #[tokio::test]
+    #[expect(clippy::identity_op)]
+    async fn test_clear_recovery() {
+        const KB: usize = 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            allocator_bits: 1,
+            ..test_config(tempdir.path())
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        for i in 0..20 {
+            store.insert(i, vec![i as u8; 1 * KB]).await.unwrap();
+        }
+        store.clear().await.unwrap();
+        for i in 0..20 {
+            assert!(store.lookup(&i).await.unwrap().is_none());
+        }
+        for i in 10..30 {
+            store.insert(i, vec![i as u8 + 1; 1 * KB]).await.unwrap();
+        }
+
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config).await.unwrap();
+
+        for i in 0..10 {
+            assert!(store.lookup(&i).await.unwrap().is_none());
+        }
+        for i in 10..30 {
+            assert_eq!(
+                store.lookup(&i).await.unwrap().unwrap(),
+                vec![i as u8 + 1; 1 * KB]
+            );
+        }
+
+        store.close().await.unwrap();
+        drop(store);
+    }
+
+    #[tokio::test]
+    #[expect(clippy::identity_op)]
+    async fn test_checkpoint_recovery() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let checkpoint = tempdir.path().join("foyer-checkpoint");
+
+        let config = TestStoreConfig {
+            device_config: FsDeviceConfig {
+                capacity: 32 * MB,
+                ..test_config(tempdir.path()).device_config
+            },
+            ..test_config(tempdir.path())
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..20 {
+            store.insert(i, vec![i as u8; 1 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // Keep the checkpoint to simulate a crash after the following writes are flushed.
+        let stale = std::fs::read(&checkpoint).unwrap();
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..20 {
+            assert_eq!(
+                store.lookup(&i).await.unwrap().unwrap(),
+                vec![i as u8; 1 * KB]
+            );
+        }
+        for i in 0..5 {
+            assert!(store.remove(&i).await.unwrap());
+        }
+        for i in 5..10 {
+            store.insert(i, vec![i as u8 + 1; 1 * KB]).await.unwrap();
+        }
+        for i in 20..40 {
+            store.insert(i, vec![i as u8; 1 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        std::fs::write(&checkpoint, stale).unwrap();
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        let assert_recovered = |store: TestStore| async move {
+            for i in 0..5 {
+                assert!(store.lookup(&i).await.unwrap().is_none());
+            }
+            for i in 5..10 {
+                assert_eq!(
+                    store.lookup(&i).await.unwrap().unwrap(),
+                    vec![i as u8 + 1; 1 * KB]
+                );
+            }
+            for i in 10..40 {
+                assert_eq!(
+                    store.lookup(&i).await.unwrap().unwrap(),
+                    vec![i as u8; 1 * KB]
+                );
+            }
+            store.close().await.unwrap();
+        };
+        assert_recovered(store).await;
+
+        // Fall back to full scan with a corrupted checkpoint.
+        let mut buf = std::fs::read(&checkpoint).unwrap();
+        buf[8] ^= 1;
+        std::fs::write(&checkpoint, buf).unwrap();
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        assert_recovered(store).await;
+
+        // Fall back to full scan with a checkpoint that refers to regions out of range.
+        let regions = (32 * MB / (4 * MB)) as RegionId;
+        let mut buf = std::fs::read(&checkpoint).unwrap();
+        let mut index = Checkpoint::<u64>::read(&buf).unwrap();
+        let mut tombstone = Checkpoint::<u64>::read(&buf).unwrap();
+        let Index::Region { region, .. } = &mut index.infos[0].1.index else {
+            panic!("index of region expected");
+        };
+        *region = regions;
+        tombstone.tombstones[0].1.shadows.push((regions, 0));
+        for checkpoint in [index, tombstone] {
+            buf.clear();
+            checkpoint.write(&mut buf);
+            std::fs::write(tempdir.path().join("foyer-checkpoint"), &buf).unwrap();
+
+            let store = TestStore::open(config.clone()).await.unwrap();
+            assert_recovered(store).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pin_name() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "foyer".to_string(),
+            allocator_bits: 1,
+            ..test_config(tempdir.path())
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        store.close().await.unwrap();
+        drop(store);
+
+        let mut other = config.clone();
+        other.name = "other".to_string();
+        assert!(TestStore::open(other).await.is_err());
+
+        let store = TestStore::open(config).await.unwrap();
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[expect(clippy::identity_op)]
+    async fn test_region_header() {
+        const KB: usize = 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = test_config(tempdir.path());
+
+        let store = TestStore::open(config).await.unwrap();
+
+        for i in 0..10 {
+            store.insert(i, vec![i as u8; 1 * KB]).await.unwrap();
+        }
+        assert!(store.remove(&0).await.unwrap());
+        store.close().await.unwrap();
+
+        let Some(Index::Region { region, .. }) =
+            store.inner.indices.lookup(&1).map(|info| info.index)
+        else {
+            panic!("index of region expected");
+        };
+        let region = store.inner.region_manager.region(&region).clone();
+        let iter = RegionEntryIter::<u64, Vec<u8>, FsDevice>::open(region, 0)
+            .await
+            .unwrap()
+            .unwrap();
+        let header = iter.header();
+
+        assert_eq!(header.version, REGION_FORMAT_VERSION);
+        assert_eq!(header.entries, 11);
+        // The region takes a sequence after the first entry.
+        assert_eq!(header.max_sequence, header.min_sequence + 11);
+        assert_eq!(header.valid_bytes as usize, 4 * KB + 11 * 4 * KB);
+        assert!(header.sealed_at > 0);
+    }
+
+    #[tokio::test]
+    #[expect(clippy::identity_op)]
+    async fn test_recover_tolerant() {
+        const KB: usize = 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let mut config = TestStoreConfig {
+            name: "test_recover_tolerant".to_string(),
+            ..test_config(tempdir.path())
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..10 {
+            store.insert(i, vec![i as u8; 1 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+
+        // Simulate a torn write of the entry of key `3`.
+        let Some(Index::Region { region, offset, .. }) =
+            store.inner.indices.lookup(&3).map(|info| info.index)
+        else {
+            panic!("index of region expected");
+        };
+        drop(store);
+        let path = tempdir.path().join(format!("foyer-cache-{:08}", region));
+        let mut buf = std::fs::read(&path).unwrap();
+        buf[offset as usize..offset as usize + 4 * KB].fill(0xff);
+        std::fs::write(&path, buf).unwrap();
+
+        let checkpoint = tempdir.path().join("foyer-checkpoint");
+
+        std::fs::remove_file(&checkpoint).unwrap();
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..3 {
+            assert!(store.exists(&i).unwrap());
+        }
+        for i in 3..10 {
+            assert!(!store.exists(&i).unwrap());
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        std::fs::remove_file(&checkpoint).unwrap();
+        config.recover_mode = RecoverMode::Tolerant;
+        let store = TestStore::open(config).await.unwrap();
+        let skipped = store.inner.metrics.op_bytes_recover_skipped.get();
+        for i in 0..10 {
+            if i == 3 {
+                assert!(!store.exists(&i).unwrap());
+            } else {
+                assert_eq!(
+                    store.lookup(&i).await.unwrap().unwrap(),
+                    vec![i as u8; 1 * KB]
+                );
+            }
+        }
+        store.close().await.unwrap();
+
+        // Both runs count the corrupted bytes: the strict run for the unscanned tail, the tolerant run for one block.
+        assert_eq!(skipped as usize, 7 * 4 * KB + 4 * KB);
+    }
+
+    #[tokio::test]
+    #[expect(clippy::identity_op)]
+    async fn test_checksum_verify() {
+        const KB: usize = 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let mut config = TestStoreConfig {
+            recover_mode: RecoverMode::Tolerant,
+            checksum_algorithm: ChecksumAlgorithm::Xxh3,
+            verify_mode: VerifyMode::Read,
+            ..test_config(tempdir.path())
+        };
+
+        // Entries written with different algorithms are verified with the algorithm recorded in their headers.
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..5 {
+            store.insert(i, vec![i as u8; 1 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        config.checksum_algorithm = ChecksumAlgorithm::Crc32c;
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 5..10 {
+            store.insert(i, vec![i as u8; 1 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+
+        // Flip a value byte of key `2` and key `7`.
+        for key in [2, 7] {
+            let Some(Index::Region { region, offset, .. }) =
+                store.inner.indices.lookup(&key).map(|info| info.index)
+            else {
+                panic!("index of region expected");
+            };
+            let path = tempdir.path().join(format!("foyer-cache-{:08}", region));
+            let mut buf = std::fs::read(&path).unwrap();
+            buf[offset as usize + EntryHeader::serialized_len() + 10] ^= 0xff;
+            std::fs::write(&path, buf).unwrap();
+        }
+        drop(store);
+
+        // Reads are not verified, the corrupted values are returned.
+        config.verify_mode = VerifyMode::Recovery;
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in [2, 7] {
+            assert_ne!(
+                store.lookup(&i).await.unwrap().unwrap(),
+                vec![i as u8; 1 * KB]
+            );
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // Recovery without verification only reads keys, the corrupted entries are recovered.
+        let checkpoint = tempdir.path().join("foyer-checkpoint");
+        std::fs::remove_file(&checkpoint).unwrap();
+        config.verify_mode = VerifyMode::Read;
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..10 {
+            assert!(store.exists(&i).unwrap());
+        }
+        for i in 0..10 {
+            let value = store.lookup(&i).await.unwrap();
+            if i == 2 || i == 7 {
+                assert!(value.is_none());
+            } else {
+                assert_eq!(value.unwrap(), vec![i as u8; 1 * KB]);
+            }
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // Recovery with verification drops the corrupted entries.
+        std::fs::remove_file(&checkpoint).unwrap();
+        config.verify_mode = VerifyMode::Recovery;
+        let store = TestStore::open(config).await.unwrap();
+        for i in 0..10 {
+            assert_eq!(store.exists(&i).unwrap(), i != 2 && i != 7);
+        }
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ring_buffer() {
+        const KB: usize = 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let mut config = TestStoreConfig {
+            ring_buffer_capacity: 64 * KB,
+            ring_buffer_threshold: KB,
+            ..test_config(tempdir.path())
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        // Small entries are staged and served from the ring buffer.
+        for i in 0..10 {
+            store.insert(i, vec![i as u8; 100]).await.unwrap();
+        }
+        for i in 0..10 {
+            let index = store.inner.indices.lookup(&i).unwrap().index;
+            assert!(matches!(index, Index::RingBuffer { .. }));
+            assert_eq!(store.lookup(&i).await.unwrap().unwrap(), vec![i as u8; 100]);
+        }
+
+        // Large entries are written to regions directly.
+        store.insert(1000, vec![0; 4 * KB]).await.unwrap();
+        let index = store.inner.indices.lookup(&1000).unwrap().index;
+        assert!(matches!(index, Index::Region { .. }));
+
+        // Staged entries are spilled in batches when the ring buffer is half full.
+        for i in 10..200 {
+            store.insert(i, vec![i as u8; 100]).await.unwrap();
+        }
+        store.insert(5, vec![55; 100]).await.unwrap();
+        assert!(store.remove(&3).await.unwrap());
+        let blocks = (0..10)
+            .filter_map(|i| match store.inner.indices.lookup(&i)?.index {
+                Index::Region { region, offset, .. } => Some((region, offset as usize / (4 * KB))),
+                Index::RingBuffer { .. } | Index::Chunks { .. } => None,
+            })
+            .collect::<BTreeSet<_>>();
+        assert!(!blocks.is_empty() && blocks.len() < 9);
+
+        let check = |store: TestStore| async move {
+            for i in 0..200 {
+                let value = store.lookup(&i).await.unwrap();
+                match i {
+                    3 => assert!(value.is_none()),
+                    5 => assert_eq!(value.unwrap(), vec![55; 100]),
+                    i => assert_eq!(value.unwrap(), vec![i as u8; 100]),
+                }
+            }
+            store
+        };
+
+        // Staged entries are spilled on close.
+        store.close().await.unwrap();
+        let store = check(store).await;
+        drop(store);
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        let store = check(store).await;
+        store.close().await.unwrap();
+        drop(store);
+
+        // Entries packed in batches are recovered by a full scan as well.
+        std::fs::remove_file(tempdir.path().join("foyer-checkpoint")).unwrap();
+        config.verify_mode = VerifyMode::Recovery;
+        let store = TestStore::open(config).await.unwrap();
+        let store = check(store).await;
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_small_objects() {
+        const KB: usize = 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            small_object_regions: 1,
+            small_object_threshold: KB,
+            ..test_config(tempdir.path())
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        // Small entries are stored in buckets without indices.
+        for i in 0..100 {
+            store.insert(i, vec![i as u8; 100]).await.unwrap();
+        }
+        for i in 100..110 {
+            store.insert(i, vec![i as u8; 8 * KB]).await.unwrap();
+        }
+        for i in 0..100 {
+            assert!(store.exists(&i).unwrap());
+            assert!(store.inner.indices.lookup(&i).is_none());
+        }
+        for i in 100..110 {
+            assert!(store.inner.indices.lookup(&i).is_some());
+        }
+
+        // Entries move between buckets and regions when their sizes change.
+        store.insert(1, vec![11; 8 * KB]).await.unwrap();
+        store.insert(101, vec![111; 100]).await.unwrap();
+        assert!(!store.inner.buckets.as_ref().unwrap().exists(&1));
+        assert!(store.inner.indices.lookup(&101).is_none());
+        assert!(store.remove(&2).await.unwrap());
+        assert!(store.remove(&102).await.unwrap());
+
+        let check = |store: TestStore| async move {
+            for i in 0..110 {
+                let value = store.lookup(&i).await.unwrap();
+                match i {
+                    1 => assert_eq!(value.unwrap(), vec![11; 8 * KB]),
+                    101 => assert_eq!(value.unwrap(), vec![111; 100]),
+                    2 | 102 => assert!(value.is_none()),
+                    i if i < 100 => assert_eq!(value.unwrap(), vec![i as u8; 100]),
+                    i => assert_eq!(value.unwrap(), vec![i as u8; 8 * KB]),
+                }
+            }
+            store
+        };
+
+        let store = check(store).await;
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        let store = check(store).await;
+        store.close().await.unwrap();
+        drop(store);
+
+        std::fs::remove_file(tempdir.path().join("foyer-checkpoint")).unwrap();
+        let store = TestStore::open(config.clone()).await.unwrap();
+        let store = check(store).await;
+
+        store.clear().await.unwrap();
+        for i in 0..110 {
+            assert!(!store.exists(&i).unwrap());
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config).await.unwrap();
+        for i in 0..110 {
+            assert!(store.lookup(&i).await.unwrap().is_none());
+        }
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_large_objects() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            device_config: FsDeviceConfig {
+                file_capacity: MB,
+                io_size: 64 * KB,
+                ..test_config(tempdir.path()).device_config
+            },
+            buffer_pool_size: 4 * MB,
+            ..test_config(tempdir.path())
+        };
+
+        let value = |i: usize, len: usize| (0..len).map(|j| (i + j) as u8).collect::<Vec<_>>();
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        // Entries larger than a region are chunked across regions.
+        store.insert(1, value(1, 5 * MB / 2)).await.unwrap();
+        store.insert(2, value(2, 3 * MB)).await.unwrap();
+        store.insert(3, value(3, 16 * KB)).await.unwrap();
+        for (key, count) in [(1, 3), (2, 4)] {
+            let Index::Chunks { chunks, .. } = store.inner.indices.lookup(&key).unwrap().index
+            else {
+                panic!("large entry must be chunked");
+            };
+            assert_eq!(chunks.len(), count);
+        }
+        assert!(store.remove(&2).await.unwrap());
+
+        let check = |store: TestStore| async move {
+            assert_eq!(
+                store.lookup(&1).await.unwrap().unwrap(),
+                value(1, 5 * MB / 2)
+            );
+            assert!(store.lookup(&2).await.unwrap().is_none());
+            assert_eq!(store.lookup(&3).await.unwrap().unwrap(), value(3, 16 * KB));
+            store
+        };
+
+        let store = check(store).await;
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        let store = check(store).await;
+        store.close().await.unwrap();
+        drop(store);
+
+        std::fs::remove_file(tempdir.path().join("foyer-checkpoint")).unwrap();
+        let store = TestStore::open(config.clone()).await.unwrap();
+        let store = check(store).await;
+
+        // Reclaiming any chunk invalidates the whole entry.
+        for i in 10..50 {
+            store.insert(i, value(i as usize, 512 * KB)).await.unwrap();
+        }
+        assert!(store.lookup(&1).await.unwrap().is_none());
+        assert!(!store.exists(&1).unwrap());
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ttl() {
+        const KB: usize = 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            small_object_regions: 1,
+            small_object_threshold: KB,
+            ..test_config(tempdir.path())
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        // Entries in regions (0..10) and in buckets (10..20).
+        let value = |i: u64| vec![i as u8; if i < 10 { 4 * KB } else { 100 }];
+        let expired = || SystemTime::now() - Duration::from_secs(1);
+        for i in 0..20 {
+            store.insert(i, value(i)).await.unwrap();
+        }
+        // Overwrite with expired entries, older entries must not be visible either.
+        for i in [1, 11] {
+            let mut writer = store.writer(i, 8 + value(i).len());
+            writer.set_expire_at(expired());
+            assert!(writer.finish(value(i)).await.unwrap());
+        }
+        for i in [2, 12] {
+            store
+                .insert_with_ttl(i, value(i), Duration::from_secs(3600))
+                .await
+                .unwrap();
+        }
+        for i in [3, 13] {
+            store
+                .insert_with_ttl(i, value(i), Duration::from_millis(200))
+                .await
+                .unwrap();
+        }
+        for i in [3, 13] {
+            assert_eq!(store.lookup(&i).await.unwrap().unwrap(), value(i));
+        }
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let check = |store: TestStore| async move {
+            for i in 0..20 {
+                let res = store.lookup(&i).await.unwrap();
+                match i {
+                    1 | 11 | 3 | 13 => assert!(res.is_none(), "key: {}", i),
+                    i => assert_eq!(res.unwrap(), value(i)),
+                }
+            }
+            assert!(!store.exists(&1).unwrap());
+            store
+        };
+
+        let store = check(store).await;
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        let store = check(store).await;
+        store.close().await.unwrap();
+        drop(store);
+
+        // Expired entries found by a full scan shadow older entries of their keys.
+        std::fs::remove_file(tempdir.path().join("foyer-checkpoint")).unwrap();
+        let store = TestStore::open(config).await.unwrap();
+        let store = check(store).await;
+        store.close().await.unwrap();
+    }
+
+    #[cfg(feature = "lz4")]
+    #[tokio::test]
+    async fn test_compression() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            device_config: FsDeviceConfig {
+                file_capacity: MB,
+                io_size: 64 * KB,
+                ..test_config(tempdir.path()).device_config
+            },
+            buffer_pool_size: 4 * MB,
+            small_object_regions: 1,
+            small_object_threshold: KB,
+            verify_mode: VerifyMode::Read,
+            compression: Compression::Lz4,
+            ..test_config(tempdir.path())
+        };
+
+        // Compressible values in buckets (0..10), regions (10..20) and chunks (20), and incompressible values (21..30).
+        let value = |i: u64| -> Vec<u8> {
+            match i {
+                0..=9 => vec![i as u8; 2 * KB],
+                10..=19 => vec![i as u8; 64 * KB],
+                20 => (0..2 * MB).map(|j| (j % 251) as u8).collect(),
+                i => {
+                    use rand::{rngs::StdRng, Rng, SeedableRng};
+                    let mut rng = StdRng::seed_from_u64(i);
+                    (0..8 * KB).map(|_| rng.gen()).collect()
+                }
+            }
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..30 {
+            store.insert(i, value(i)).await.unwrap();
+        }
+        for i in 0..10 {
+            assert!(store.inner.indices.lookup(&i).is_none());
+        }
+        let metrics = &store.inner.metrics;
+        assert!(
+            metrics.op_bytes_insert_uncompressed.get()
+                > 4 * metrics.op_bytes_insert_compressed.get()
+        );
+
+        let check = |store: TestStore| async move {
+            for i in 0..30 {
+                assert_eq!(store.lookup(&i).await.unwrap().unwrap(), value(i));
+            }
+            store
+        };
+
+        let store = check(store).await;
+        store.close().await.unwrap();
+        drop(store);
+
+        std::fs::remove_file(tempdir.path().join("foyer-checkpoint")).unwrap();
+        let store = TestStore::open(TestStoreConfig {
+            compression: Compression::None,
+            ..config
+        })
+        .await
+        .unwrap();
+        let store = check(store).await;
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_encryption() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            device_config: FsDeviceConfig {
+                file_capacity: MB,
+                io_size: 64 * KB,
+                ..test_config(tempdir.path()).device_config
+            },
+            buffer_pool_size: 4 * MB,
+            ring_buffer_capacity: 64 * KB,
+            ring_buffer_threshold: 16 * KB,
+            small_object_regions: 1,
+            small_object_threshold: KB,
+            verify_mode: VerifyMode::Read,
+            encryption: Some(Arc::new(StaticKeyProvider::new(1, [1; 32]))),
+            ..test_config(tempdir.path())
+        };
+
+        // Values in buckets (0..10), the ring buffer (10..20), regions (20..30) and chunks (30).
+        let value = |i: u64| -> Vec<u8> {
+            let len = match i {
+                0..=9 => 100,
+                10..=19 => 4 * KB,
+                20..=29 => 64 * KB,
+                _ => 2 * MB,
+            };
+            b"plaintext".repeat(len / 9)
+        };
+        let removed = [5, 15, 25];
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..31 {
+            store.insert(i, value(i)).await.unwrap();
+        }
+        for i in removed {
+            assert!(store.remove(&i).await.unwrap());
+        }
+
+        let check = |store: TestStore, readable: bool| async move {
+            for i in 0..31 {
+                let expected = (readable && !removed.contains(&i)).then(|| value(i));
+                assert_eq!(store.lookup(&i).await.unwrap(), expected, "key: {}", i);
+            }
+            store
+        };
+
+        let store = check(store, true).await;
+        store.close().await.unwrap();
+        drop(store);
+
+        // Neither values nor the checkpoint are stored in plaintext.
+        for entry in std::fs::read_dir(tempdir.path()).unwrap() {
+            let buf = std::fs::read(entry.unwrap().path()).unwrap();
+            assert!(!buf.windows(9).any(|w| w == b"plaintext"));
+        }
+
+        // Rotate the key, entries written with the retired key are still readable.
+        let store = TestStore::open(TestStoreConfig {
+            encryption: Some(Arc::new(
+                StaticKeyProvider::new(2, [2; 32]).with_retired(1, [1; 32]),
+            )),
+            ..config.clone()
+        })
+        .await
+        .unwrap();
+        {
+            let manifest = store.inner.manifest.lock().await;
+            assert_eq!(manifest.key_id, Some(2));
+            assert_eq!(manifest.retired_key_ids, vec![1]);
+        }
+        let store = check(store, true).await;
+        store.insert(100, value(100)).await.unwrap();
+        store.close().await.unwrap();
+        drop(store);
+
+        // Entries written with a retired key no longer provided are treated as missing.
+        std::fs::remove_file(tempdir.path().join("foyer-checkpoint")).unwrap();
+        let store = TestStore::open(TestStoreConfig {
+            encryption: Some(Arc::new(StaticKeyProvider::new(2, [2; 32]))),
+            ..config.clone()
+        })
+        .await
+        .unwrap();
+        let store = check(store, false).await;
+        assert_eq!(store.lookup(&100).await.unwrap(), Some(value(100)));
+
+        // All entries are dropped by `clear`, so are retired keys.
+        store.clear().await.unwrap();
+        assert!(store.inner.manifest.lock().await.retired_key_ids.is_empty());
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lookup_raw() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            device_config: FsDeviceConfig {
+                file_capacity: MB,
+                io_size: 64 * KB,
+                ..test_config(tempdir.path()).device_config
+            },
+            buffer_pool_size: 4 * MB,
+            ring_buffer_capacity: 64 * KB,
+            ring_buffer_threshold: 16 * KB,
+            small_object_regions: 1,
+            small_object_threshold: KB,
+            verify_mode: VerifyMode::Read,
+            ..test_config(tempdir.path())
+        };
+
+        let value = |i: u64, len: usize| (0..len).map(|j| (i as usize + j) as u8).collect_vec();
+
+        let store = TestStore::open(config).await.unwrap();
+
+        // Staged in the ring buffer.
+        store.insert(1, value(1, 4 * KB)).await.unwrap();
+        let guard = store.lookup_raw(&1).await.unwrap().unwrap();
+        assert!(matches!(guard, ValueGuard::View { .. }));
+        assert_eq!(&guard[..], &value(1, 4 * KB)[..]);
+        drop(guard);
+
+        // Stored in a bucket.
+        store.insert(2, value(2, 100)).await.unwrap();
+        let guard = store.lookup_raw(&2).await.unwrap().unwrap();
+        assert!(matches!(guard, ValueGuard::Owned(_)));
+        assert_eq!(guard.value::<Vec<u8>>(), value(2, 100));
+
+        // Split into chunks.
+        store.insert(3, value(3, 2 * MB)).await.unwrap();
+        let guard = store.lookup_raw(&3).await.unwrap().unwrap();
+        assert!(matches!(guard, ValueGuard::Owned(_)));
+        assert_eq!(&guard[..], &value(3, 2 * MB)[..]);
+
+        // Written to a region, the slice is borrowed until the guard is dropped.
+        store.insert(4, value(4, 64 * KB)).await.unwrap();
+        let guard = store.lookup_raw(&4).await.unwrap().unwrap();
+        assert!(matches!(guard, ValueGuard::Slice { .. }));
+        assert_eq!(&guard[..], &value(4, 64 * KB)[..]);
+
+        let Index::Region { region, .. } = store.inner.indices.lookup(&4).unwrap().index else {
+            panic!("entry should be in a region");
+        };
+        let region = store.inner.region_manager.region(&region).clone();
+        assert!(tokio::time::timeout(
+            Duration::from_millis(100),
+            region.exclusive(true, false, false)
+        )
+        .await
+        .is_err());
+        drop(guard);
+        drop(
+            tokio::time::timeout(
+                Duration::from_millis(100),
+                region.exclusive(true, false, false),
+            )
+            .await
+            .unwrap(),
+        );
+
+        assert!(store.lookup_raw(&5).await.unwrap().is_none());
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lookup_stream() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            device_config: FsDeviceConfig {
+                file_capacity: MB,
+                io_size: 64 * KB,
+                ..test_config(tempdir.path()).device_config
+            },
+            buffer_pool_size: 4 * MB,
+            ring_buffer_capacity: 64 * KB,
+            ring_buffer_threshold: 16 * KB,
+            small_object_regions: 1,
+            small_object_threshold: KB,
+            verify_mode: VerifyMode::Read,
+            ..test_config(tempdir.path())
+        };
+
+        // Values in a bucket (1), the ring buffer (2), a region (3) and chunks (4).
+        let value = |i: u64| {
+            let len = match i {
+                1 => 100,
+                2 => 4 * KB,
+                3 => 300 * KB,
+                _ => 2 * MB + 10,
+            };
+            (0..len).map(|j| (i as usize * 7 + j) as u8).collect_vec()
+        };
+
+        let collect = |stream: ValueStream| async move {
+            let pieces = stream.collect::<Vec<_>>().await;
+            let mut buf = vec![];
+            for piece in pieces {
+                let piece = piece?;
+                assert!(!piece.is_empty() && piece.len() <= 64 * KB);
+                buf.extend_from_slice(&piece);
+            }
+            Ok::<_, crate::error::Error>(buf)
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 1..=4 {
+            store.insert(i, value(i)).await.unwrap();
+        }
+        for i in 1..=4 {
+            let stream = store.lookup_stream(&i).await.unwrap().unwrap();
+            assert_eq!(collect(stream).await.unwrap(), value(i));
+        }
+        assert!(store.lookup_stream(&5).await.unwrap().is_none());
+        store.close().await.unwrap();
+
+        // Flip the last value byte of key `3`, the corruption is reported at the end of the stream.
+        let Some(Index::Region { region, offset, .. }) =
+            store.inner.indices.lookup(&3).map(|info| info.index)
+        else {
+            panic!("index of region expected");
+        };
+        let path = tempdir.path().join(format!("foyer-cache-{:08}", region));
+        let mut buf = std::fs::read(&path).unwrap();
+        buf[offset as usize + EntryHeader::serialized_len() + value(3).len() - 1] ^= 0xff;
+        std::fs::write(&path, buf).unwrap();
+        drop(store);
+
+        let store = TestStore::open(config).await.unwrap();
+        let mut stream = store.lookup_stream(&3).await.unwrap().unwrap();
+        let mut len = 0;
+        let err = loop {
+            match stream.next().await.unwrap() {
+                Ok(piece) => len += piece.len(),
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(err.kind(), ErrorKind::Corrupted(_)));
+        // The piece that fails verification is not yielded.
+        assert!(len < value(3).len());
+        assert!(stream.next().await.is_none());
+
+        // Other values are not affected.
+        for i in [1, 2, 4] {
+            let stream = store.lookup_stream(&i).await.unwrap().unwrap();
+            assert_eq!(collect(stream).await.unwrap(), value(i));
+        }
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_insert_many() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        #[derive(Debug)]
+        struct RejectTens;
+
+        impl AdmissionPolicy for RejectTens {
+            type Key = u64;
+            type Value = Vec<u8>;
+
+            fn judge(&self, key: &u64, _weight: usize, _metrics: &Arc<Metrics>) -> bool {
+                key % 10 != 0
+            }
+
+            fn on_insert(&self, _key: &u64, _weight: usize, _metrics: &Arc<Metrics>, _judge: bool) {
+            }
+
+            fn on_drop(&self, _key: &u64, _weight: usize, _metrics: &Arc<Metrics>, _judge: bool) {}
+        }
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            device_config: FsDeviceConfig {
+                file_capacity: MB,
+                io_size: 64 * KB,
+                ..test_config(tempdir.path()).device_config
+            },
+            catalog_bits: 2,
+            admissions: vec![Arc::new(RejectTens)],
+            buffer_pool_size: 4 * MB,
+            ring_buffer_capacity: 64 * KB,
+            ring_buffer_threshold: 16 * KB,
+            small_object_regions: 1,
+            small_object_threshold: KB,
+            verify_mode: VerifyMode::Read,
+            ..test_config(tempdir.path())
+        };
+
+        // Values in regions except a small one (5), a staged one (6) and a large one in chunks (7).
+        let value = |i: u64| {
+            let len = match i {
+                5 => 100,
+                6 => 4 * KB,
+                7 => MB + MB / 2,
+                _ => 100 * KB,
+            };
+            (0..len).map(|j| (i as usize * 3 + j) as u8).collect_vec()
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        let keys = (0..32).collect_vec();
+        let inserted = store
+            .insert_many(keys.iter().map(|i| (*i, value(*i))).collect())
+            .await
+            .unwrap();
+        assert_eq!(inserted, keys.iter().map(|i| i % 10 != 0).collect_vec());
+
+        let values = store.lookup_many(&keys).await.unwrap();
+        for (i, v) in keys.iter().zip_eq(values) {
+            assert_eq!(v, (i % 10 != 0).then(|| value(*i)), "key: {i}");
+        }
+
+        // Batched entries in the same region are written adjacent in order.
+        let index = |i: u64| match store.inner.indices.lookup(&i).unwrap().index {
+            Index::Region {
+                region,
+                offset,
+                len,
+                ..
+            } => (region, offset, len),
+            _ => panic!("index of region expected"),
+        };
+        let (region, offset, len) = index(1);
+        assert_eq!(index(2), (region, offset + len, len));
+        assert_eq!(index(3), (region, offset + 2 * len, len));
+
+        // Later entries of the same key win.
+        let inserted = store
+            .insert_many(vec![(1, value(2)), (1, value(3))])
+            .await
+            .unwrap();
+        assert_eq!(inserted, vec![true, true]);
+        assert_eq!(store.lookup(&1).await.unwrap(), Some(value(3)));
+
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config).await.unwrap();
+        for i in keys {
+            let expected = match i {
+                1 => Some(value(3)),
+                i if i % 10 == 0 => None,
+                i => Some(value(i)),
+            };
+            assert_eq!(store.lookup(&i).await.unwrap(), expected, "key: {i}");
+        }
+        store.close().await.unwrap();
+    }
+
+    /// Device that counts the reads issued to it.
+    #[derive(Debug, Clone)]
+    struct CountingDevice<D: Device> {
+        device: D,
+        reads: Arc<AtomicUsize>,
     }
 
-    fn force(&mut self) {
-        self.force()
+    #[derive(Debug, Clone)]
+    struct CountingDeviceConfig<C> {
+        device: C,
+        reads: Arc<AtomicUsize>,
     }
 
-    async fn finish(self, value: Self::Value) -> Result<bool> {
-        self.finish(value).await
-    }
-}
+    impl<D: Device> Device for CountingDevice<D> {
+        type IoBufferAllocator = D::IoBufferAllocator;
+        type Config = CountingDeviceConfig<D::Config>;
 
-impl<K, V, D, EP, EL> Storage for GenericStore<K, V, D, EP, EL>
-where
-    K: Key,
-    V: Value,
-    D: Device,
-    EP: EvictionPolicy<Adapter = RegionEpItemAdapter<EL>>,
-    EL: Link,
-{
-    type Key = K;
-    type Value = V;
-    type Config = GenericStoreConfig<K, V, D, EP>;
-    type Writer = GenericStoreWriter<K, V, D, EP, EL>;
+        async fn open(config: Self::Config) -> DeviceResult<Self> {
+            Ok(Self {
+                device: D::open(config.device).await?,
+                reads: config.reads,
+            })
+        }
 
-    async fn open(config: Self::Config) -> Result<Self> {
-        Self::open(config).await
-    }
+        async fn write<B>(
+            &self,
+            buf: B,
+            range: impl IoRange,
+            region: RegionId,
+            offset: u64,
+        ) -> (DeviceResult<usize>, B)
+        where
+            B: IoBuf,
+        {
+            self.device.write(buf, range, region, offset).await
+        }
 
-    fn is_ready(&self) -> bool {
-        true
-    }
+        async fn read<B>(
+            &self,
+            buf: B,
+            range: impl IoRange,
+            region: RegionId,
+            offset: u64,
+        ) -> (DeviceResult<usize>, B)
+        where
+            B: IoBufMut,
+        {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            self.device.read(buf, range, region, offset).await
+        }
 
-    async fn close(&self) -> Result<()> {
-        self.close().await
-    }
+        async fn flush(&self) -> DeviceResult<()> {
+            self.device.flush().await
+        }
 
-    fn writer(&self, key: Self::Key, weight: usize) -> Self::Writer {
-        self.writer(key, weight)
-    }
+        async fn read_manifest(&self) -> DeviceResult<Option<Vec<u8>>> {
+            self.device.read_manifest().await
+        }
 
-    fn exists(&self, key: &Self::Key) -> Result<bool> {
-        self.exists(key)
-    }
+        async fn write_manifest(&self, buf: Vec<u8>) -> DeviceResult<()> {
+            self.device.write_manifest(buf).await
+        }
 
-    async fn lookup(&self, key: &Self::Key) -> Result<Option<Self::Value>> {
-        self.lookup(key).await
-    }
+        async fn read_checkpoint(&self) -> DeviceResult<Option<Vec<u8>>> {
+            self.device.read_checkpoint().await
+        }
 
-    fn remove(&self, key: &Self::Key) -> Result<bool> {
-        self.remove(key)
-    }
+        async fn write_checkpoint(&self, buf: Vec<u8>) -> DeviceResult<()> {
+            self.device.write_checkpoint(buf).await
+        }
 
-    fn clear(&self) -> Result<()> {
-        self.clear()
-    }
-}
+        fn capacity(&self) -> usize {
+            self.device.capacity()
+        }
 
-#[cfg(test)]
-mod tests {
-    use std::path::PathBuf;
+        fn regions(&self) -> usize {
+            self.device.regions()
+        }
 
-    use foyer_intrusive::eviction::fifo::{Fifo, FifoConfig, FifoLink};
+        fn align(&self) -> usize {
+            self.device.align()
+        }
 
-    use crate::{
-        device::fs::{FsDevice, FsDeviceConfig},
-        storage::StorageExt,
-        test_utils::JudgeRecorder,
-    };
+        fn io_size(&self) -> usize {
+            self.device.io_size()
+        }
 
-    use super::*;
+        fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+            self.device.io_buffer_allocator()
+        }
 
-    type TestStore =
-        GenericStore<u64, Vec<u8>, FsDevice, Fifo<RegionEpItemAdapter<FifoLink>>, FifoLink>;
+        fn io_buffer(&self, len: usize, capacity: usize) -> Vec<u8, Self::IoBufferAllocator> {
+            self.device.io_buffer(len, capacity)
+        }
+    }
 
-    type TestStoreConfig =
-        GenericStoreConfig<u64, Vec<u8>, FsDevice, Fifo<RegionEpItemAdapter<FifoLink>>>;
+    type CountingStore = GenericStore<
+        u64,
+        Vec<u8>,
+        CountingDevice<FsDevice>,
+        Fifo<RegionEpItemAdapter<FifoLink>>,
+        FifoLink,
+    >;
+
+    type CountingStoreConfig = GenericStoreConfig<
+        u64,
+        Vec<u8>,
+        CountingDevice<FsDevice>,
+        Fifo<RegionEpItemAdapter<FifoLink>>,
+    >;
 
     #[tokio::test]
-    #[expect(clippy::identity_op)]
-    async fn test_recovery() {
+    async fn test_lookup_many_coalesces_reads() {
         const KB: usize = 1024;
         const MB: usize = 1024 * 1024;
 
         let tempdir = tempfile::tempdir().unwrap();
+        let reads = Arc::new(AtomicUsize::new(0));
 
-        let recorder = Arc::new(JudgeRecorder::default());
-        let admissions: Vec<Arc<dyn AdmissionPolicy<Key = u64, Value = Vec<u8>>>> =
-            vec![recorder.clone()];
-        let reinsertions: Vec<Arc<dyn ReinsertionPolicy<Key = u64, Value = Vec<u8>>>> =
-            vec![recorder.clone()];
-
-        let config = TestStoreConfig {
+        let config = CountingStoreConfig {
             name: "".to_string(),
             eviction_config: FifoConfig,
-            device_config: FsDeviceConfig {
-                dir: PathBuf::from(tempdir.path()),
-                capacity: 16 * MB,
-                file_capacity: 4 * MB,
-                align: 4 * KB,
-                io_size: 4 * KB,
+            device_config: CountingDeviceConfig {
+                device: FsDeviceConfig {
+                    dir: PathBuf::from(tempdir.path()),
+                    capacity: 16 * MB,
+                    file_capacity: MB,
+                    align: 4 * KB,
+                    io_size: 64 * KB,
+                    reformat: false,
+                    preallocate: false,
+                },
+                reads: reads.clone(),
             },
-            allocator_bits: 1,
-            catalog_bits: 1,
-            admissions,
-            reinsertions,
-            buffer_pool_size: 8 * MB,
+            allocator_bits: 0,
+            catalog_bits: 2,
+            admissions: vec![],
+            reinsertions: vec![],
+            buffer_pool_size: 4 * MB,
+            ring_buffer_capacity: 0,
+            ring_buffer_threshold: 0,
+            small_object_regions: 0,
+            small_object_threshold: 0,
             flushers: 1,
             flush_rate_limit: 0,
             reclaimers: 1,
             reclaim_rate_limit: 0,
+            reclaim_discard: false,
             recover_concurrency: 2,
+            recover_mode: RecoverMode::Strict,
+            checkpoint_interval: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            verify_mode: VerifyMode::Read,
+            compression: Compression::None,
+            encryption: None,
             allocation_timeout: Duration::from_millis(10),
             clean_region_threshold: 1,
         };
 
+        // 16 entries aligned to 4 KiB are written adjacent in a region, which fits in a single io.
+        let store = CountingStore::open(config.clone()).await.unwrap();
+        for i in 0..16 {
+            store.insert(i, vec![i as u8; 2 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = CountingStore::open(config).await.unwrap();
+
+        let mut keys = (0..16).rev().collect_vec();
+        keys.insert(4, 100);
+        let expected = |removed: &[u64]| {
+            keys.iter()
+                .map(|i| (*i < 16 && !removed.contains(i)).then(|| vec![*i as u8; 2 * KB]))
+                .collect_vec()
+        };
+
+        let before = reads.load(Ordering::Relaxed);
+        assert_eq!(store.lookup_many(&keys).await.unwrap(), expected(&[]));
+        assert_eq!(reads.load(Ordering::Relaxed) - before, 1);
+
+        // A removed entry leaves a gap, so the entries around it are read separately.
+        assert!(store.remove(&8).await.unwrap());
+        let before = reads.load(Ordering::Relaxed);
+        assert_eq!(store.lookup_many(&keys).await.unwrap(), expected(&[8]));
+        assert_eq!(reads.load(Ordering::Relaxed) - before, 2);
+
+        let before = reads.load(Ordering::Relaxed);
+        for i in 0..16 {
+            store.lookup(&i).await.unwrap();
+        }
+        assert_eq!(reads.load(Ordering::Relaxed) - before, 15);
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lookup_key_mismatch() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            device_config: FsDeviceConfig {
+                file_capacity: MB,
+                io_size: 64 * KB,
+                ..test_config(tempdir.path()).device_config
+            },
+            catalog_bits: 2,
+            buffer_pool_size: 4 * MB,
+            ring_buffer_capacity: 64 * KB,
+            ring_buffer_threshold: 16 * KB,
+            small_object_regions: 1,
+            small_object_threshold: KB,
+            verify_mode: VerifyMode::Read,
+            ..test_config(tempdir.path())
+        };
+
         let store = TestStore::open(config).await.unwrap();
 
-        // files:
-        // [0, 1, 2]
-        // [3, 4, 5]
-        // [6, 7, 8]
-        // [9, 10, 11]
-        for i in 0..20 {
-            store.insert(i, vec![i as u8; 1 * MB]).await.unwrap();
+        let keys = (0..4).collect_vec();
+        for i in keys.iter() {
+            store.insert(*i, vec![*i as u8; 100 * KB]).await.unwrap();
+        }
+        store.insert(4, vec![4; 20 * KB]).await.unwrap();
+        store.insert(7, vec![7; 2 * MB]).await.unwrap();
+
+        // Point the indices of 0, 1, 5, 6 and 8 to the entries of 2, 3, 2, 4 and 7, as if the indices were stale.
+        for (from, to) in [(0, 2), (1, 3), (5, 2), (6, 4), (8, 7)] {
+            let info = store.inner.indices.lookup(&to).unwrap();
+            store.inner.indices.insert(from, info);
         }
 
+        assert!(store.lookup(&0).await.unwrap().is_none());
+        assert!(!store.exists(&0).unwrap());
+        assert_eq!(
+            store.lookup_many(&keys).await.unwrap(),
+            vec![None, None, Some(vec![2; 100 * KB]), Some(vec![3; 100 * KB])]
+        );
+        assert!(!store.exists(&1).unwrap());
+
+        // The key of 2 is not in the first piece of the stream, the key of 4 is.
+        for i in [5, 6] {
+            assert!(store.lookup_stream(&i).await.unwrap().is_none());
+            assert!(!store.exists(&i).unwrap());
+        }
+        // Key 7 is chunked, the key is checked on its first chunk.
+        assert!(store.lookup(&8).await.unwrap().is_none());
+        assert!(!store.exists(&8).unwrap());
+        assert_eq!(store.lookup(&7).await.unwrap().unwrap(), vec![7; 2 * MB]);
+
+        let stream = store.lookup_stream(&4).await.unwrap().unwrap();
+        let value = stream.map(|piece| piece.unwrap().to_vec()).concat().await;
+        assert_eq!(value, vec![4; 20 * KB]);
+
         store.close().await.unwrap();
+    }
 
-        let remains = recorder.remains();
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_insert_many_atomic() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
 
-        for i in 0..20 {
-            if remains.contains(&i) {
-                assert_eq!(
-                    store.lookup(&i).await.unwrap().unwrap(),
-                    vec![i as u8; 1 * MB],
-                );
-            } else {
-                assert!(store.lookup(&i).await.unwrap().is_none());
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            device_config: FsDeviceConfig {
+                file_capacity: MB,
+                io_size: 64 * KB,
+                ..test_config(tempdir.path()).device_config
+            },
+            catalog_bits: 2,
+            buffer_pool_size: 4 * MB,
+            ring_buffer_capacity: 64 * KB,
+            ring_buffer_threshold: 16 * KB,
+            small_object_regions: 1,
+            small_object_threshold: KB,
+            verify_mode: VerifyMode::Read,
+            ..test_config(tempdir.path())
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+
+        // The entries span several regions.
+        let keys = (0..64).collect_vec();
+        let checker = {
+            let store = store.clone();
+            let keys = keys.clone();
+            tokio::spawn(async move {
+                loop {
+                    // Entries are published region by region in order, keys checked after a visible one, which are
+                    // checked in reverse, are visible.
+                    let mut visible = keys
+                        .iter()
+                        .rev()
+                        .map(|key| store.exists(key).unwrap())
+                        .collect_vec();
+                    visible.reverse();
+                    let count = visible.iter().take_while(|visible| **visible).count();
+                    assert!(
+                        visible[count..].iter().all(|visible| !*visible),
+                        "{visible:?}"
+                    );
+                    if count == keys.len() {
+                        return;
+                    }
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+
+        let inserted = store
+            .insert_many(
+                keys.iter()
+                    .map(|i| (*i, vec![*i as u8; 100 * KB]))
+                    .collect(),
+            )
+            .await
+            .unwrap();
+        assert!(inserted.iter().all(|inserted| *inserted));
+        checker.await.unwrap();
+
+        store.close().await.unwrap();
+    }
+
+    /// Holds writes to regions after the first `open` regions written once armed, until it is released.
+    #[derive(Debug)]
+    struct Gate {
+        open: usize,
+        /// Regions written since armed, `None` if not armed.
+        regions: Mutex<Option<Vec<RegionId>>>,
+        held: tokio::sync::Notify,
+        release: tokio::sync::Semaphore,
+    }
+
+    /// Device that holds writes with a [`Gate`].
+    #[derive(Debug, Clone)]
+    struct GatedDevice<D: Device> {
+        device: D,
+        gate: Arc<Gate>,
+    }
+
+    #[derive(Debug, Clone)]
+    struct GatedDeviceConfig<C> {
+        device: C,
+        gate: Arc<Gate>,
+    }
+
+    impl<D: Device> Device for GatedDevice<D> {
+        type IoBufferAllocator = D::IoBufferAllocator;
+        type Config = GatedDeviceConfig<D::Config>;
+
+        async fn open(config: Self::Config) -> DeviceResult<Self> {
+            Ok(Self {
+                device: D::open(config.device).await?,
+                gate: config.gate,
+            })
+        }
+
+        async fn write<B>(
+            &self,
+            buf: B,
+            range: impl IoRange,
+            region: RegionId,
+            offset: u64,
+        ) -> (DeviceResult<usize>, B)
+        where
+            B: IoBuf,
+        {
+            let held = {
+                let mut regions = self.gate.regions.lock();
+                match regions.as_mut() {
+                    Some(regions) => {
+                        if !regions.contains(&region) {
+                            regions.push(region);
+                        }
+                        regions.iter().position(|r| *r == region).unwrap() >= self.gate.open
+                    }
+                    None => false,
+                }
+            };
+            if held {
+                self.gate.held.notify_one();
+                // The semaphore is closed on release.
+                let _ = self.gate.release.acquire().await;
             }
+            self.device.write(buf, range, region, offset).await
         }
 
-        drop(store);
+        async fn read<B>(
+            &self,
+            buf: B,
+            range: impl IoRange,
+            region: RegionId,
+            offset: u64,
+        ) -> (DeviceResult<usize>, B)
+        where
+            B: IoBufMut,
+        {
+            self.device.read(buf, range, region, offset).await
+        }
 
-        let config = TestStoreConfig {
+        async fn flush(&self) -> DeviceResult<()> {
+            self.device.flush().await
+        }
+
+        async fn read_manifest(&self) -> DeviceResult<Option<Vec<u8>>> {
+            self.device.read_manifest().await
+        }
+
+        async fn write_manifest(&self, buf: Vec<u8>) -> DeviceResult<()> {
+            self.device.write_manifest(buf).await
+        }
+
+        async fn read_checkpoint(&self) -> DeviceResult<Option<Vec<u8>>> {
+            self.device.read_checkpoint().await
+        }
+
+        async fn write_checkpoint(&self, buf: Vec<u8>) -> DeviceResult<()> {
+            self.device.write_checkpoint(buf).await
+        }
+
+        fn capacity(&self) -> usize {
+            self.device.capacity()
+        }
+
+        fn regions(&self) -> usize {
+            self.device.regions()
+        }
+
+        fn align(&self) -> usize {
+            self.device.align()
+        }
+
+        fn io_size(&self) -> usize {
+            self.device.io_size()
+        }
+
+        fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+            self.device.io_buffer_allocator()
+        }
+
+        fn io_buffer(&self, len: usize, capacity: usize) -> Vec<u8, Self::IoBufferAllocator> {
+            self.device.io_buffer(len, capacity)
+        }
+    }
+
+    type GatedStore = GenericStore<
+        u64,
+        Vec<u8>,
+        GatedDevice<FsDevice>,
+        Fifo<RegionEpItemAdapter<FifoLink>>,
+        FifoLink,
+    >;
+
+    type GatedStoreConfig = GenericStoreConfig<
+        u64,
+        Vec<u8>,
+        GatedDevice<FsDevice>,
+        Fifo<RegionEpItemAdapter<FifoLink>>,
+    >;
+
+    impl Gate {
+        fn new(open: usize) -> Arc<Self> {
+            Arc::new(Self {
+                open,
+                regions: Mutex::new(None),
+                held: tokio::sync::Notify::new(),
+                release: tokio::sync::Semaphore::new(0),
+            })
+        }
+    }
+
+    /// Two buffers of 1 MiB regions, a writer waits for the flush of the second region held by `gate` once the third
+    /// region is written.
+    fn gated_config(dir: &Path, gate: Arc<Gate>) -> GatedStoreConfig {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        GatedStoreConfig {
             name: "".to_string(),
             eviction_config: FifoConfig,
-            device_config: FsDeviceConfig {
-                dir: PathBuf::from(tempdir.path()),
-                capacity: 16 * MB,
-                file_capacity: 4 * MB,
-                align: 4096,
-                io_size: 4096 * KB,
+            device_config: GatedDeviceConfig {
+                device: FsDeviceConfig {
+                    dir: PathBuf::from(dir),
+                    capacity: 16 * MB,
+                    file_capacity: MB,
+                    align: 4 * KB,
+                    io_size: 64 * KB,
+                    reformat: false,
+                    preallocate: false,
+                },
+                gate,
             },
-            allocator_bits: 1,
-            catalog_bits: 1,
+            allocator_bits: 0,
+            catalog_bits: 2,
             admissions: vec![],
             reinsertions: vec![],
-            buffer_pool_size: 8 * MB,
+            buffer_pool_size: 2 * MB,
+            ring_buffer_capacity: 0,
+            ring_buffer_threshold: 0,
+            small_object_regions: 0,
+            small_object_threshold: 0,
             flushers: 1,
             flush_rate_limit: 0,
-            reclaimers: 0,
+            reclaimers: 1,
             reclaim_rate_limit: 0,
+            reclaim_discard: false,
             recover_concurrency: 2,
+            recover_mode: RecoverMode::Strict,
+            checkpoint_interval: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            verify_mode: VerifyMode::Read,
+            compression: Compression::None,
+            encryption: None,
             allocation_timeout: Duration::from_millis(10),
             clean_region_threshold: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_insert_many_checkpoint() {
+        const KB: usize = 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let gate = Gate::new(1);
+        let config = gated_config(tempdir.path(), gate.clone());
+
+        let store = GatedStore::open(config.clone()).await.unwrap();
+        *gate.regions.lock() = Some(vec![]);
+
+        let keys = (0..64).collect_vec();
+        let insert = {
+            let store = store.clone();
+            let keys = keys.clone();
+            tokio::spawn(async move {
+                store
+                    .insert_many(
+                        keys.iter()
+                            .map(|i| (*i, vec![*i as u8; 100 * KB]))
+                            .collect(),
+                    )
+                    .await
+            })
         };
-        let store = TestStore::open(config).await.unwrap();
 
-        for i in 0..12 {
-            if remains.contains(&i) {
-                assert_eq!(
-                    store.lookup(&i).await.unwrap().unwrap(),
-                    vec![i as u8; 1 * MB],
-                );
-            } else {
-                assert!(store.lookup(&i).await.unwrap().is_none());
-            }
-        }
+        // Take a checkpoint after the first region is flushed, while the batch waits for the second one.
+        gate.held.notified().await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!insert.is_finished());
+        store.checkpoint().await.unwrap();
+        let checkpoint = std::fs::read(tempdir.path().join("foyer-checkpoint")).unwrap();
 
+        gate.release.close();
+        let inserted = insert.await.unwrap().unwrap();
+        assert!(inserted.iter().all(|inserted| *inserted));
+        store.close().await.unwrap();
+        drop(store);
+        *gate.regions.lock() = None;
+
+        // Recover from the checkpoint taken in the middle of the batch.
+        std::fs::write(tempdir.path().join("foyer-checkpoint"), checkpoint).unwrap();
+        let store = GatedStore::open(config).await.unwrap();
+        for i in keys {
+            assert_eq!(
+                store.lookup(&i).await.unwrap(),
+                Some(vec![i as u8; 100 * KB]),
+                "key: {i}"
+            );
+        }
         store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_chunks_checkpoint() {
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let gate = Gate::new(1);
+        let config = gated_config(tempdir.path(), gate.clone());
+
+        let store = GatedStore::open(config.clone()).await.unwrap();
+        *gate.regions.lock() = Some(vec![]);
+
+        // A large entry in 4 chunks.
+        let value = (0..3 * MB + MB / 2).map(|i| i as u8).collect_vec();
+        let insert = {
+            let store = store.clone();
+            let value = value.clone();
+            tokio::spawn(async move { store.insert(1, value).await })
+        };
 
+        // Take a checkpoint after the region of the first chunk is flushed, while the writer waits for the second one.
+        gate.held.notified().await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!insert.is_finished());
+        store.checkpoint().await.unwrap();
+        let checkpoint = std::fs::read(tempdir.path().join("foyer-checkpoint")).unwrap();
+
+        gate.release.close();
+        assert!(insert.await.unwrap().unwrap());
+        store.close().await.unwrap();
         drop(store);
+        *gate.regions.lock() = None;
+
+        // Recover from the checkpoint taken before the entry is indexed.
+        std::fs::write(tempdir.path().join("foyer-checkpoint"), checkpoint).unwrap();
+        let store = GatedStore::open(config).await.unwrap();
+        assert_eq!(store.lookup(&1).await.unwrap(), Some(value));
+        store.close().await.unwrap();
     }
 }
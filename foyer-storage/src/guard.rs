@@ -0,0 +1,103 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::{
+    fmt::Debug,
+    ops::{Deref, Range},
+};
+
+use foyer_common::code::Value;
+
+use crate::{device::BufferAllocator, region::ReadSlice, ring::View};
+
+/// Serialized value of an entry returned by [`GenericStore::lookup_raw`], verified and decoded.
+///
+/// The value is borrowed from the buffer it is read into if it is stored as serialized, the buffer is kept referenced
+/// until the guard is dropped.
+///
+/// [`GenericStore::lookup_raw`]: crate::generic::GenericStore::lookup_raw
+pub enum ValueGuard<A>
+where
+    A: BufferAllocator,
+{
+    /// The value in a slice loaded from a region.
+    Slice {
+        slice: ReadSlice<A>,
+        range: Range<usize>,
+    },
+    /// The value staged in the ring buffer.
+    View { view: View, range: Range<usize> },
+    /// The value read from a bucket, reassembled from chunks, decompressed or decrypted.
+    Owned(Vec<u8>),
+}
+
+impl<A> ValueGuard<A>
+where
+    A: BufferAllocator,
+{
+    /// Deserialize the value.
+    pub fn value<V>(&self) -> V
+    where
+        V: Value,
+    {
+        V::read(self)
+    }
+}
+
+impl<A> Debug for ValueGuard<A>
+where
+    A: BufferAllocator,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Slice { slice, range } => f
+                .debug_struct("ValueGuard::Slice")
+                .field("slice", slice)
+                .field("range", range)
+                .finish(),
+            Self::View { range, .. } => f
+                .debug_struct("ValueGuard::View")
+                .field("range", range)
+                .finish(),
+            Self::Owned(buf) => f
+                .debug_struct("ValueGuard::Owned")
+                .field("len", &buf.len())
+                .finish(),
+        }
+    }
+}
+
+impl<A> Deref for ValueGuard<A>
+where
+    A: BufferAllocator,
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Slice { slice, range } => &slice.as_ref()[range.clone()],
+            Self::View { view, range } => &view[range.clone()],
+            Self::Owned(buf) => buf,
+        }
+    }
+}
+
+impl<A> AsRef<[u8]> for ValueGuard<A>
+where
+    A: BufferAllocator,
+{
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
@@ -12,7 +12,10 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::sync::{Arc, OnceLock};
+use std::{
+    sync::{Arc, OnceLock},
+    time::SystemTime,
+};
 
 use crate::{
     error::Result,
@@ -70,6 +73,13 @@ where
         }
     }
 
+    fn set_expire_at(&mut self, deadline: SystemTime) {
+        match self {
+            LazyStorageWriter::Store { writer } => writer.set_expire_at(deadline),
+            LazyStorageWriter::None { writer } => writer.set_expire_at(deadline),
+        }
+    }
+
     async fn finish(self, value: Self::Value) -> Result<bool> {
         match self {
             LazyStorageWriter::Store { writer } => writer.finish(value).await,
@@ -175,6 +185,13 @@ where
         }
     }
 
+    async fn insert_many(&self, entries: Vec<(Self::Key, Self::Value)>) -> Result<Vec<bool>> {
+        match self.once.get() {
+            Some(store) => store.insert_many(entries).await,
+            None => self.none.insert_many(entries).await,
+        }
+    }
+
     fn exists(&self, key: &Self::Key) -> Result<bool> {
         match self.once.get() {
             Some(store) => store.exists(key),
@@ -189,17 +206,24 @@ where
         }
     }
 
-    fn remove(&self, key: &Self::Key) -> Result<bool> {
+    async fn lookup_many(&self, keys: &[Self::Key]) -> Result<Vec<Option<Self::Value>>> {
+        match self.once.get() {
+            Some(store) => store.lookup_many(keys).await,
+            None => self.none.lookup_many(keys).await,
+        }
+    }
+
+    async fn remove(&self, key: &Self::Key) -> Result<bool> {
         match self.once.get() {
-            Some(store) => store.remove(key),
-            None => self.none.remove(key),
+            Some(store) => store.remove(key).await,
+            None => self.none.remove(key).await,
         }
     }
 
-    fn clear(&self) -> Result<()> {
+    async fn clear(&self) -> Result<()> {
         match self.once.get() {
-            Some(store) => store.clear(),
-            None => self.none.clear(),
+            Some(store) => store.clear().await,
+            None => self.none.clear().await,
         }
     }
 }
@@ -214,7 +238,10 @@ mod tests {
     use foyer_intrusive::eviction::fifo::FifoConfig;
 
     use crate::{
+        checksum::{ChecksumAlgorithm, VerifyMode},
+        compress::Compression,
         device::fs::FsDeviceConfig,
+        generic::RecoverMode,
         storage::StorageExt,
         store::{FifoFsStoreConfig, Store},
     };
@@ -237,17 +264,30 @@ mod tests {
                 file_capacity: 4 * MB,
                 align: 4096,
                 io_size: 4096 * KB,
+                reformat: false,
+                preallocate: false,
             },
             allocator_bits: 1,
             catalog_bits: 1,
             admissions: vec![],
             reinsertions: vec![],
             buffer_pool_size: 8 * MB,
+            ring_buffer_capacity: 0,
+            ring_buffer_threshold: 0,
+            small_object_regions: 0,
+            small_object_threshold: 0,
             flushers: 1,
             flush_rate_limit: 0,
             reclaimers: 1,
             reclaim_rate_limit: 0,
+            reclaim_discard: false,
             recover_concurrency: 2,
+            recover_mode: RecoverMode::Strict,
+            checkpoint_interval: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            verify_mode: VerifyMode::default(),
+            compression: Compression::None,
+            encryption: None,
             allocation_timeout: Duration::from_millis(10),
             clean_region_threshold: 1,
         };
@@ -273,17 +313,30 @@ mod tests {
                 file_capacity: 4 * MB,
                 align: 4096,
                 io_size: 4096 * KB,
+                reformat: false,
+                preallocate: false,
             },
             allocator_bits: 1,
             catalog_bits: 1,
             admissions: vec![],
             reinsertions: vec![],
             buffer_pool_size: 8 * MB,
+            ring_buffer_capacity: 0,
+            ring_buffer_threshold: 0,
+            small_object_regions: 0,
+            small_object_threshold: 0,
             flushers: 1,
             flush_rate_limit: 0,
             reclaimers: 1,
             reclaim_rate_limit: 0,
+            reclaim_discard: false,
             recover_concurrency: 2,
+            recover_mode: RecoverMode::Strict,
+            checkpoint_interval: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            verify_mode: VerifyMode::default(),
+            compression: Compression::None,
+            encryption: None,
             allocation_timeout: Duration::from_millis(10),
             clean_region_threshold: 1,
         };
@@ -25,13 +25,23 @@
 #![feature(associated_type_defaults)]
 
 pub mod admission;
+pub mod bucket;
 pub mod catalog;
+pub mod chain;
+pub mod checkpoint;
+pub mod checkpointer;
+pub mod checksum;
+pub mod compress;
 pub mod device;
+pub mod encrypt;
+pub mod entry;
 pub mod error;
 pub mod flusher;
 pub mod generic;
+pub mod guard;
 pub mod judge;
 pub mod lazy;
+pub mod manifest;
 pub mod metrics;
 pub mod reclaimer;
 pub mod region;
@@ -40,7 +50,9 @@ pub mod reinsertion;
 pub mod ring;
 pub mod runtime;
 pub mod slice;
+pub mod staging;
 pub mod storage;
 pub mod store;
+pub mod stream;
 
 pub mod test_utils;
@@ -0,0 +1,185 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::hash::Hasher;
+
+use bytes::{Buf, BufMut};
+use twox_hash::XxHash64;
+
+use crate::encrypt::KeyId;
+
+pub const MANIFEST_MAGIC: u64 = 0x19970423;
+
+/// Version of the store format, stores written in other versions cannot be opened.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Store-wide metadata persisted on the device.
+///
+/// The manifest is small and is always rewritten as a whole with [`crate::device::Device::write_manifest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// Store format version.
+    pub version: u32,
+
+    /// Store name, pinned on the first open.
+    pub name: String,
+
+    /// Store epoch, increased by each durable `clear`.
+    ///
+    /// Regions written in other epochs are ignored on recovery.
+    pub epoch: u64,
+
+    /// Id of the key encrypting newly written entries, `None` if encryption is disabled.
+    pub key_id: Option<KeyId>,
+
+    /// Keys rotated out since the last `clear`, entries encrypted with them may still be in the store.
+    pub retired_key_ids: Vec<KeyId>,
+}
+
+impl Manifest {
+    pub fn new(name: String) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            name,
+            epoch: 0,
+            key_id: None,
+            retired_key_ids: vec![],
+        }
+    }
+
+    /// Switch to the key of `key_id`, the previous key is retired.
+    ///
+    /// Returns `false` if the key is not changed.
+    pub fn rotate(&mut self, key_id: Option<KeyId>) -> bool {
+        if self.key_id == key_id {
+            return false;
+        }
+        if let Some(id) = self.key_id
+            && !self.retired_key_ids.contains(&id)
+        {
+            self.retired_key_ids.push(id);
+        }
+        self.retired_key_ids.retain(|id| Some(*id) != key_id);
+        self.key_id = key_id;
+        true
+    }
+
+    /// | magic | version | epoch | name len | name | has key | key id | retired count | retired key ids | checksum |
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        buf.put_u64(MANIFEST_MAGIC);
+        buf.put_u32(self.version);
+        buf.put_u64(self.epoch);
+        buf.put_u32(self.name.len() as u32);
+        buf.put_slice(self.name.as_bytes());
+        buf.put_u8(self.key_id.is_some() as u8);
+        buf.put_u32(self.key_id.unwrap_or_default());
+        buf.put_u32(self.retired_key_ids.len() as u32);
+        for id in self.retired_key_ids.iter() {
+            buf.put_u32(*id);
+        }
+        let checksum = checksum(buf);
+        buf.put_u64(checksum);
+    }
+
+    /// Returns `None` if the manifest is corrupted.
+    pub fn read(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 8 + 4 + 8 + 4 + 1 + 4 + 4 + 8 {
+            return None;
+        }
+        let (data, mut footer) = buf.split_at(buf.len() - 8);
+        if checksum(data) != footer.get_u64() {
+            return None;
+        }
+
+        let mut buf = data;
+        if buf.get_u64() != MANIFEST_MAGIC {
+            return None;
+        }
+        let version = buf.get_u32();
+        let epoch = buf.get_u64();
+        let len = buf.get_u32() as usize;
+        if buf.remaining() < len {
+            return None;
+        }
+        let name = String::from_utf8(buf[..len].to_vec()).ok()?;
+        buf.advance(len);
+
+        if buf.remaining() < 1 + 4 + 4 {
+            return None;
+        }
+        let has_key = buf.get_u8() != 0;
+        let id = buf.get_u32();
+        let key_id = has_key.then_some(id);
+        let count = buf.get_u32() as usize;
+        if buf.remaining() != count * 4 {
+            return None;
+        }
+        let retired_key_ids = (0..count).map(|_| buf.get_u32()).collect();
+
+        Some(Self {
+            version,
+            name,
+            epoch,
+            key_id,
+            retired_key_ids,
+        })
+    }
+}
+
+fn checksum(buf: &[u8]) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(buf);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_codec() {
+        let manifest = Manifest {
+            version: FORMAT_VERSION,
+            name: "foyer".to_string(),
+            epoch: 42,
+            key_id: Some(3),
+            retired_key_ids: vec![1, 2],
+        };
+
+        let mut buf = vec![];
+        manifest.write(&mut buf);
+        assert_eq!(Manifest::read(&buf), Some(manifest));
+
+        buf[8] ^= 1;
+        assert_eq!(Manifest::read(&buf), None);
+    }
+
+    #[test]
+    fn test_manifest_rotate() {
+        let mut manifest = Manifest::new("foyer".to_string());
+        assert!(!manifest.rotate(None));
+
+        assert!(manifest.rotate(Some(1)));
+        assert!(!manifest.rotate(Some(1)));
+        assert!(manifest.rotate(Some(2)));
+        assert!(manifest.rotate(None));
+        assert_eq!(manifest.key_id, None);
+        assert_eq!(manifest.retired_key_ids, vec![1, 2]);
+
+        // A retired key back in use is no longer retired.
+        assert!(manifest.rotate(Some(1)));
+        assert_eq!(manifest.key_id, Some(1));
+        assert_eq!(manifest.retired_key_ids, vec![2]);
+    }
+}
@@ -147,10 +147,18 @@ pub struct Metrics {
     pub slow_op_duration_reclaim: Histogram,
 
     pub op_bytes_insert: IntCounter,
+    /// Value bytes before compression, only counted if compression is enabled.
+    pub op_bytes_insert_uncompressed: IntCounter,
+    /// Value bytes after compression, values that don't shrink are counted as is.
+    pub op_bytes_insert_compressed: IntCounter,
     pub op_bytes_lookup: IntCounter,
     pub op_bytes_flush: IntCounter,
     pub op_bytes_reclaim: IntCounter,
     pub op_bytes_reinsert: IntCounter,
+    pub op_bytes_discard: IntCounter,
+    pub op_bytes_spill: IntCounter,
+    pub op_bytes_bucket_evict: IntCounter,
+    pub op_bytes_recover_skipped: IntCounter,
 
     pub total_bytes: UintGauge,
 
@@ -184,10 +192,26 @@ impl Metrics {
             .with_label_values(&[foyer, "reclaim", ""]);
 
         let op_bytes_insert = global.op_bytes.with_label_values(&[foyer, "insert", ""]);
+        let op_bytes_insert_uncompressed =
+            global
+                .op_bytes
+                .with_label_values(&[foyer, "insert", "uncompressed"]);
+        let op_bytes_insert_compressed =
+            global
+                .op_bytes
+                .with_label_values(&[foyer, "insert", "compressed"]);
         let op_bytes_lookup = global.op_bytes.with_label_values(&[foyer, "lookup", ""]);
         let op_bytes_flush = global.op_bytes.with_label_values(&[foyer, "flush", ""]);
         let op_bytes_reclaim = global.op_bytes.with_label_values(&[foyer, "reclaim", ""]);
         let op_bytes_reinsert = global.op_bytes.with_label_values(&[foyer, "reinsert", ""]);
+        let op_bytes_discard = global.op_bytes.with_label_values(&[foyer, "discard", ""]);
+        let op_bytes_spill = global.op_bytes.with_label_values(&[foyer, "spill", ""]);
+        let op_bytes_bucket_evict = global
+            .op_bytes
+            .with_label_values(&[foyer, "bucket_evict", ""]);
+        let op_bytes_recover_skipped = global
+            .op_bytes
+            .with_label_values(&[foyer, "recover", "skipped"]);
 
         let total_bytes = global.total_bytes.with_label_values(&[foyer]);
 
@@ -211,10 +235,16 @@ impl Metrics {
             slow_op_duration_reclaim,
 
             op_bytes_insert,
+            op_bytes_insert_uncompressed,
+            op_bytes_insert_compressed,
             op_bytes_lookup,
             op_bytes_flush,
             op_bytes_reclaim,
             op_bytes_reinsert,
+            op_bytes_discard,
+            op_bytes_spill,
+            op_bytes_bucket_evict,
+            op_bytes_recover_skipped,
 
             total_bytes,
 
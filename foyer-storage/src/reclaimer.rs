@@ -12,14 +12,20 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::Duration,
+};
 
 use crate::{
+    catalog::{Index, Tombstone},
     device::Device,
     error::Result,
-    generic::{GenericStore, RegionEntryIter},
+    generic::{GenericStore, RegionEntry, RegionEntryIter},
     judge::Judges,
     metrics::Metrics,
+    region::RegionId,
     region_manager::{RegionEpItemAdapter, RegionManager},
     storage::Storage,
 };
@@ -29,8 +35,20 @@ use foyer_common::{
     rate::RateLimiter,
 };
 use foyer_intrusive::{core::adapter::Link, eviction::EvictionPolicy};
+use futures::future::join_all;
+use itertools::Itertools;
 use tokio::sync::broadcast;
 
+#[derive(Debug, PartialEq, Eq)]
+enum Reclaimed {
+    /// A clean region is released, or there is no need to reclaim.
+    Done,
+    /// No clean region is released, reclaim again without waiting for a change of the clean regions.
+    Again,
+    /// The reclaimer is stopped while reclaiming.
+    Stopped,
+}
+
 #[derive(Debug)]
 pub struct Reclaimer<K, V, D, EP, EL>
 where
@@ -42,6 +60,12 @@ where
 {
     threshold: usize,
 
+    /// Discard reclaimed regions on the device.
+    discard: bool,
+
+    /// Device tier to reclaim regions of, entries reinserted from a tier are migrated to the next slower tier.
+    tier: usize,
+
     store: GenericStore<K, V, D, EP, EL>,
 
     region_manager: Arc<RegionManager<D, EP, EL>>,
@@ -61,8 +85,11 @@ where
     EP: EvictionPolicy<Adapter = RegionEpItemAdapter<EL>>,
     EL: Link,
 {
+    #[expect(clippy::too_many_arguments)]
     pub fn new(
         threshold: usize,
+        discard: bool,
+        tier: usize,
         store: GenericStore<K, V, D, EP, EL>,
         region_manager: Arc<RegionManager<D, EP, EL>>,
         rate_limiter: Option<Arc<RateLimiter>>,
@@ -71,6 +98,8 @@ where
     ) -> Self {
         Self {
             threshold,
+            discard,
+            tier,
             store,
             region_manager,
             rate_limiter,
@@ -80,12 +109,21 @@ where
     }
 
     pub async fn run(mut self) -> Result<()> {
-        let mut watch = self.region_manager.clean_regions().watch();
+        let mut watch = self.region_manager.clean_regions(self.tier).watch();
         loop {
             tokio::select! {
                 biased;
                 Ok(()) = watch.changed() => {
-                    self.handle().await?;
+                    loop {
+                        match self.handle().await? {
+                            Reclaimed::Done => break,
+                            Reclaimed::Again => continue,
+                            Reclaimed::Stopped => {
+                                tracing::info!("[reclaimer] exit");
+                                return Ok(());
+                            }
+                        }
+                    }
                 }
                 _ = self.stop_rx.recv() => {
                     tracing::info!("[reclaimer] exit");
@@ -95,14 +133,16 @@ where
         }
     }
 
-    async fn handle(&self) -> Result<()> {
-        if self.region_manager.clean_regions().len() >= self.threshold {
-            return Ok(());
+    async fn handle(&mut self) -> Result<Reclaimed> {
+        if self.region_manager.clean_regions(self.tier).len() >= self.threshold {
+            return Ok(Reclaimed::Done);
         }
 
+        let target = std::cmp::min(self.tier + 1, self.region_manager.tiers() - 1);
+
         // TODO(MrCroxx): subscribe evictable region changes.
         let region_id = loop {
-            match self.region_manager.eviction_pop() {
+            match self.region_manager.eviction_pop(self.tier) {
                 Some(id) => break id,
                 None => tokio::time::sleep(Duration::from_millis(100)).await,
             }
@@ -113,7 +153,8 @@ where
         let region = self.region_manager.region(&region_id);
 
         // step 1: drop indices
-        let _indices = self.store.catalog().take_region(&region_id);
+        let indices = self.store.catalog().take_region(&region_id);
+        let tombstones = self.store.catalog().take_region_tombstones(&region_id);
 
         // after drop indices and acquire exclusive lock, no writers or readers are supposed to access the region
         {
@@ -134,17 +175,45 @@ where
             let metrics = self.metrics.clone();
             let rate = self.rate_limiter.clone();
             let reinsertions = self.store.reinsertions().clone();
+            let region_manager = &self.region_manager;
+            let store = &self.store;
+            // Only entries that are still indexed can be reinserted, removed, overwritten or expired entries are
+            // skipped.
+            let offsets: BTreeSet<u32> = indices
+                .iter()
+                .filter(|info| !info.is_expired())
+                .filter_map(|info| match info.index {
+                    Index::Region { offset, .. } => Some(offset),
+                    // Large entries are not reinserted, reclaiming any of their chunks invalidates them.
+                    Index::RingBuffer { .. } | Index::Chunks { .. } => None,
+                })
+                .collect();
 
             tracing::info!("[reclaimer] begin reinsertion, region: {}", region_id);
 
             async move {
-                let mut iter = match RegionEntryIter::<K, V, D>::open(region).await {
+                let epoch = region_manager.epoch();
+                let mut iter = match RegionEntryIter::<K, V, D>::open(region, epoch).await {
                     Ok(Some(iter)) => iter,
                     Ok(None) => return Ok(true),
                     Err(e) => return Err(e),
                 };
+                iter.set_encryption(store.encryption().cloned());
+
+                while let Some(entry) = iter.next().await? {
+                    let RegionEntry::Value { info, .. } = entry else {
+                        continue;
+                    };
+                    let Index::Region { offset, .. } = info.index else {
+                        unreachable!("entry loaded from region must have index of region")
+                    };
+                    if !offsets.contains(&offset) {
+                        continue;
+                    }
+                    let Some((key, value)) = iter.read_kv(&info).await? else {
+                        break;
+                    };
 
-                while let Some((key, value)) = iter.next_kv().await? {
                     let weight = key.serialized_len() + value.serialized_len();
 
                     let mut judges = Judges::new(reinsertions.len());
@@ -165,8 +234,10 @@ where
                         tokio::time::sleep(wait).await;
                     }
 
-                    let mut writer = self.store.writer(key.clone(), weight);
+                    let mut writer = store.writer(key.clone(), weight);
                     writer.set_skippable();
+                    writer.set_tier(target);
+                    writer.set_expire_at_millis(info.expire_at);
 
                     if !writer.judge() {
                         continue;
@@ -197,7 +268,8 @@ where
             }
         };
 
-        if !self.store.reinsertions().is_empty() {
+        // Entries are always migrated to the slower tier, unless rejected by the reinsertion policies.
+        if !self.store.reinsertions().is_empty() || target != self.tier {
             match reinsert().await {
                 Ok(true) => {
                     tracing::info!("[reclaimer] reinsertion finish, region: {}", region_id)
@@ -209,15 +281,91 @@ where
             }
         }
 
-        // step 3: set region last block zero
-        let align = region.device().align();
-        let mut buf = region.device().io_buffer(align, align);
-        (&mut buf[..]).put_slice(&vec![0; align]);
-        let (res, _buf) = region.device().write(buf, .., region_id, 0).await;
-        res?;
+        // step 3: rewrite tombstones that still shadow entries on other regions
+        //
+        // Tombstones are rewritten to the same tier, whose current regions are filled and flushed by the writers of
+        // the tier. The header of the region must not be zeroed before they are flushed, otherwise the shadowed
+        // entries are recovered after a crash in the meantime.
+        let mut rewritten = BTreeMap::new();
+        for (key, tombstone) in tombstones.iter() {
+            let shadows = tombstone
+                .shadows
+                .iter()
+                .map(|(region, _)| *region)
+                .collect_vec();
+            match self
+                .store
+                .write_tombstone(key.clone(), tombstone.sequence, shadows, self.tier, false)
+                .await?
+            {
+                Some((region, version)) => {
+                    rewritten.insert(region, version);
+                }
+                None => {
+                    // Writers may be waiting for a clean region with the allocator locked, the reclaimer must not
+                    // wait for the allocation. Keep the region with its tombstones and reclaim it later, and let the
+                    // writers go with the reserved region, otherwise regions that all hold tombstones are never
+                    // reclaimed.
+                    let reserved = self.region_manager.release_reserved(self.tier);
+                    tracing::info!(
+                        "[reclaimer] rewrite tombstone skipped, region: {}, reserved region released: {}, retry later",
+                        region_id,
+                        reserved
+                    );
+                    self.restore_tombstones(region_id, tombstones);
+                    self.region_manager.eviction_push(region_id);
+                    return Ok(Reclaimed::Again);
+                }
+            }
+        }
+        if !rewritten.is_empty() {
+            let flushed = join_all(rewritten.iter().map(|(region, version)| {
+                self.region_manager.region(region).wait_flushed(*version)
+            }));
+            tokio::select! {
+                _ = flushed => {}
+                _ = self.stop_rx.recv() => {
+                    // The rewritten tombstones may never be flushed, keep the region unreclaimed.
+                    self.restore_tombstones(region_id, tombstones);
+                    return Ok(Reclaimed::Stopped);
+                }
+            }
+            region.device().flush().await?;
+        }
 
-        // step 4: send clean region
-        self.region_manager.clean_regions().release(region_id);
+        // step 4: discard the region, so the media knows its blocks are free
+        if self.discard && region.device().is_usable(region_id) {
+            let size = region.device().region_size();
+            if let Some(rate) = self.rate_limiter.as_ref() && let Some(wait) = rate.consume(size as f64) {
+                tokio::time::sleep(wait).await;
+            }
+            match region.device().discard(region_id).await {
+                Ok(()) => self.metrics.op_bytes_discard.inc_by(size as u64),
+                Err(e) => tracing::warn!("[reclaimer] discard region {} error: {}", region_id, e),
+            }
+        }
+
+        // step 5: set region last block zero
+        let usable = region.device().is_usable(region_id) && {
+            let align = region.device().align();
+            let mut buf = region.device().io_buffer(align, align);
+            (&mut buf[..]).put_slice(&vec![0; align]);
+            let (res, _buf) = region.device().write(buf, .., region_id, 0).await;
+            match res {
+                Ok(_) => true,
+                Err(_) if !region.device().is_usable(region_id) => false,
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        // step 6: send clean region, or refill the reserved region, unusable regions are retired
+        //
+        // Tombstones stop shadowing the region only now, it is no longer recovered after a crash.
+        self.store.catalog().release_region(&region_id);
+        let released = usable && self.region_manager.release_clean_region(region_id);
+        if !usable {
+            tracing::warn!("[reclaimer] retire unusable region: {}", region_id);
+        }
 
         tracing::info!("[reclaimer] finish reclaim task, region: {}", region_id);
 
@@ -228,6 +376,250 @@ where
             .total_bytes
             .sub(region.device().region_size() as u64);
 
-        Ok(())
+        // Retired or reserved regions don't change the clean regions.
+        Ok(if released {
+            Reclaimed::Done
+        } else {
+            Reclaimed::Again
+        })
+    }
+
+    /// Insert the tombstones back to the catalog on the region they are taken from, so they are kept until the
+    /// region is reclaimed.
+    fn restore_tombstones(&self, region_id: RegionId, tombstones: Vec<(K, Tombstone)>) {
+        for (key, tombstone) in tombstones {
+            self.store.catalog().insert_tombstone(
+                key,
+                tombstone.sequence,
+                region_id,
+                tombstone.shadows.into_iter().map(|(region, _)| region),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use foyer_intrusive::eviction::fifo::{Fifo, FifoConfig, FifoLink};
+
+    use crate::{
+        checksum::{ChecksumAlgorithm, VerifyMode},
+        compress::Compression,
+        device::fs::{FsDevice, FsDeviceConfig},
+        generic::{GenericStoreConfig, RecoverMode},
+        metrics::METRICS,
+        storage::StorageExt,
+    };
+
+    use super::*;
+
+    type TestStore =
+        GenericStore<u64, Vec<u8>, FsDevice, Fifo<RegionEpItemAdapter<FifoLink>>, FifoLink>;
+
+    type TestReclaimer =
+        Reclaimer<u64, Vec<u8>, FsDevice, Fifo<RegionEpItemAdapter<FifoLink>>, FifoLink>;
+
+    type TestStoreConfig =
+        GenericStoreConfig<u64, Vec<u8>, FsDevice, Fifo<RegionEpItemAdapter<FifoLink>>>;
+
+    fn region_of(store: &TestStore, key: u64) -> RegionId {
+        match store.catalog().lookup(&key).unwrap().index {
+            Index::Region { region, .. } => region,
+            _ => unreachable!(),
+        }
+    }
+
+    const KB: usize = 1024;
+    const MB: usize = 1024 * 1024;
+
+    /// 5 regions of 1 MiB, one of them is reserved.
+    fn config(dir: &Path) -> TestStoreConfig {
+        TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig,
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(dir),
+                capacity: 5 * MB,
+                file_capacity: MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                reformat: false,
+                preallocate: false,
+            },
+            allocator_bits: 0,
+            catalog_bits: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            buffer_pool_size: 4 * MB,
+            ring_buffer_capacity: 0,
+            ring_buffer_threshold: 0,
+            small_object_regions: 0,
+            small_object_threshold: 0,
+            flushers: 1,
+            flush_rate_limit: 0,
+            reclaimers: 0,
+            reclaim_rate_limit: 0,
+            reclaim_discard: false,
+            recover_concurrency: 2,
+            recover_mode: RecoverMode::Strict,
+            checkpoint_interval: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            verify_mode: VerifyMode::default(),
+            compression: Compression::None,
+            encryption: None,
+            allocation_timeout: Duration::from_millis(10),
+            clean_region_threshold: 1,
+        }
+    }
+
+    fn reclaimer(store: &TestStore, stop_rx: broadcast::Receiver<()>) -> TestReclaimer {
+        Reclaimer::new(
+            1,
+            false,
+            0,
+            store.clone(),
+            store.region_manager().clone(),
+            None,
+            Arc::new(METRICS.foyer("")),
+            stop_rx,
+        )
+    }
+
+    /// Take the flushed regions from the eviction, and make `regions` evictable in order.
+    async fn evictable(store: &TestStore, flushed: usize, regions: &[RegionId]) {
+        let region_manager = store.region_manager();
+        let mut evictable = BTreeSet::new();
+        while evictable.len() < flushed {
+            match region_manager.eviction_pop(0) {
+                Some(region) => {
+                    evictable.insert(region);
+                }
+                None => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        }
+        for region in regions {
+            assert!(evictable.contains(region));
+            region_manager.eviction_push(*region);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reclaim_again_keeps_shadows() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let store = TestStore::open(config(tempdir.path())).await.unwrap();
+        let region_manager = store.region_manager().clone();
+
+        // region a: [1, 100]
+        // region b: [tombstone 1, 2, 101]
+        // region c: [tombstone 2, 102]
+        // region d: [103]
+        store.insert(1, vec![1; KB]).await.unwrap();
+        store.insert(100, vec![100; 700 * KB]).await.unwrap();
+        store.insert(101, vec![101; 700 * KB]).await.unwrap();
+        assert!(store.remove(&1).await.unwrap());
+        store.insert(2, vec![2; KB]).await.unwrap();
+        let b = region_of(&store, 2);
+        store.insert(102, vec![102; 700 * KB]).await.unwrap();
+        assert!(store.remove(&2).await.unwrap());
+        let c = region_of(&store, 102);
+        store.insert(103, vec![103; 700 * KB]).await.unwrap();
+
+        // Wait for the regions to be flushed, then make region b the next to reclaim.
+        evictable(&store, 3, &[b, c]).await;
+
+        // The writer holds the allocator while waiting for a clean region, so the tombstone of key 1 can't be
+        // rewritten.
+        let writer = tokio::spawn({
+            let store = store.clone();
+            async move { store.insert(104, vec![104; 700 * KB]).await.unwrap() }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let (_stop_tx, stop_rx) = broadcast::channel(1);
+        let mut reclaimer = reclaimer(&store, stop_rx);
+        assert_eq!(reclaimer.handle().await.unwrap(), Reclaimed::Again);
+
+        // Region b is not released, the tombstone of key 2 must still shadow the entry on it.
+        store.catalog().prune_tombstones();
+        let (_, tombstones) = store.catalog().snapshot();
+        let keys = tombstones
+            .iter()
+            .map(|(key, tombstone)| (*key, tombstone.region))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(keys, BTreeSet::from([(1, b), (2, c)]));
+
+        // The writer goes with the reserved region.
+        writer.await.unwrap();
+        assert!(!region_manager.release_reserved(0));
+
+        store.close().await.unwrap();
+        drop(store);
+    }
+
+    #[tokio::test]
+    async fn test_reclaim_tombstone_regions() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let store = TestStore::open(config(tempdir.path())).await.unwrap();
+        let region_manager = store.region_manager().clone();
+
+        // region a: [1, 100]
+        // region b: [tombstone 1, 2, 101]
+        // region c: [tombstone 2, 3, 102]
+        // region d: [tombstone 3, 103]
+        store.insert(1, vec![1; KB]).await.unwrap();
+        store.insert(100, vec![100; 700 * KB]).await.unwrap();
+        store.insert(101, vec![101; 700 * KB]).await.unwrap();
+        assert!(store.remove(&1).await.unwrap());
+        store.insert(2, vec![2; KB]).await.unwrap();
+        let b = region_of(&store, 2);
+        store.insert(102, vec![102; 700 * KB]).await.unwrap();
+        assert!(store.remove(&2).await.unwrap());
+        store.insert(3, vec![3; KB]).await.unwrap();
+        let c = region_of(&store, 3);
+        store.insert(103, vec![103; 700 * KB]).await.unwrap();
+        assert!(store.remove(&3).await.unwrap());
+        let d = region_of(&store, 103);
+
+        // The writer seals region d and holds the allocator while waiting for a clean region.
+        let writer = tokio::spawn({
+            let store = store.clone();
+            async move { store.insert(104, vec![104; 700 * KB]).await.unwrap() }
+        });
+
+        // Only regions holding tombstones that still shadow entries are evictable.
+        evictable(&store, 4, &[b, c, d]).await;
+
+        let (stop_tx, stop_rx) = broadcast::channel(1);
+        let handle = tokio::spawn(reclaimer(&store, stop_rx).run());
+        region_manager.clean_regions(0).flash();
+
+        tokio::time::timeout(Duration::from_secs(10), writer)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Seal the region the tombstones are rewritten to, the reclaimer releases a clean region for the next writer
+        // once they are flushed.
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            store.insert(105, vec![105; 700 * KB]),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        for key in [1, 2, 3] {
+            assert!(store.lookup(&key).await.unwrap().is_none());
+        }
+        assert_eq!(store.lookup(&105).await.unwrap(), Some(vec![105; 700 * KB]));
+
+        stop_tx.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+        store.close().await.unwrap();
+        drop(store);
     }
 }
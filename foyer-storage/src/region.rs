@@ -20,11 +20,13 @@ use std::{
     fmt::Debug,
     ops::RangeBounds,
     sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
 };
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, Notify};
 use tracing::instrument;
 
 use crate::{
+    catalog::Sequence,
     device::{BufferAllocator, Device},
     error::Result,
     slice::{Slice, SliceMut},
@@ -50,21 +52,77 @@ impl AllocateResult {
 }
 
 pub const REGION_MAGIC: u64 = 0x19970327;
+pub const REGION_FORMAT_VERSION: u32 = 4;
 
-#[derive(Debug)]
+/// Header in the first block of a region.
+///
+/// The header is written when the region is allocated, and is completed with the region statistics when the region
+/// is flushed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct RegionHeader {
     /// magic number to decide a valid region
     pub magic: u64,
+    /// region format version
+    pub version: u32,
+    /// store epoch when the region is written
+    pub epoch: u64,
+    /// store sequence when the region is allocated
+    pub sequence: Sequence,
+    /// minimal sequence of entries in the region, `0` if there is no entry
+    pub min_sequence: Sequence,
+    /// maximal sequence of entries in the region, `0` if there is no entry
+    pub max_sequence: Sequence,
+    /// count of entries (including tombstones) in the region
+    pub entries: u32,
+    /// length of the written prefix of the region, including the header block
+    pub valid_bytes: u64,
+    /// milliseconds since unix epoch when the region is flushed, `0` if not flushed
+    pub sealed_at: u64,
 }
 
 impl RegionHeader {
-    pub fn write(&self, buf: &mut [u8]) {
-        (&mut buf[..]).put_u64(self.magic);
+    pub fn serialized_len() -> usize {
+        8 + 4 + 8 + 8 + 8 + 8 + 4 + 8 + 8
+    }
+
+    pub fn write(&self, mut buf: &mut [u8]) {
+        buf.put_u64(self.magic);
+        buf.put_u32(self.version);
+        buf.put_u64(self.epoch);
+        buf.put_u64(self.sequence);
+        buf.put_u64(self.min_sequence);
+        buf.put_u64(self.max_sequence);
+        buf.put_u32(self.entries);
+        buf.put_u64(self.valid_bytes);
+        buf.put_u64(self.sealed_at);
+    }
+
+    pub fn read(mut buf: &[u8]) -> Self {
+        let magic = buf.get_u64();
+        let version = buf.get_u32();
+        let epoch = buf.get_u64();
+        let sequence = buf.get_u64();
+        let min_sequence = buf.get_u64();
+        let max_sequence = buf.get_u64();
+        let entries = buf.get_u32();
+        let valid_bytes = buf.get_u64();
+        let sealed_at = buf.get_u64();
+        Self {
+            magic,
+            version,
+            epoch,
+            sequence,
+            min_sequence,
+            max_sequence,
+            entries,
+            valid_bytes,
+            sealed_at,
+        }
     }
 
-    pub fn read(buf: &[u8]) -> Self {
-        let magic = (&buf[..]).get_u64();
-        Self { magic }
+    /// Returns `true` if the header is written by the current region format.
+    pub fn is_valid(&self) -> bool {
+        self.magic == REGION_MAGIC && self.version == REGION_FORMAT_VERSION
     }
 }
 
@@ -74,9 +132,13 @@ where
     A: BufferAllocator,
 {
     version: Version,
+    /// Header of the attached buffer.
+    header: RegionHeader,
 
     buffer: Option<Vec<u8, A>>,
     len: usize,
+    /// End of the last allocated slice that fits.
+    valid: usize,
     capacity: usize,
 
     writers: usize,
@@ -113,6 +175,9 @@ where
 
     inner: ErwLock<RegionInner<D::IoBufferAllocator>>,
 
+    /// Notified after the dirty buffer is flushed and detached.
+    flushed: Arc<Notify>,
+
     device: D,
 }
 
@@ -135,9 +200,11 @@ where
     pub fn new(id: RegionId, device: D) -> Self {
         let inner = RegionInner {
             version: 0,
+            header: RegionHeader::default(),
 
             buffer: None,
             len: 0,
+            valid: 0,
             capacity: device.region_size(),
 
             writers: 0,
@@ -149,10 +216,17 @@ where
         Self {
             id,
             inner: ErwLock::new(inner),
+            flushed: Arc::new(Notify::new()),
             device,
         }
     }
 
+    /// Returns the bytes that can still be allocated from the attached buffer.
+    pub fn remaining(&self) -> usize {
+        let inner = self.inner.read();
+        inner.capacity - inner.len
+    }
+
     /// If there is enough buffer, return `AllocateResult::Ok(slice)``.
     /// Else, return `AllocateResult::NotEnough(slice)`. `slice` is the remaining buffer.
     #[tracing::instrument(skip(self))]
@@ -189,6 +263,7 @@ where
             AllocateResult::NotEnough(slice)
         } else {
             inner.len += size;
+            inner.valid = inner.len;
 
             let buffer = inner.buffer.as_mut().unwrap();
             let slice = unsafe { SliceMut::new(&mut buffer[offset..offset + size]) };
@@ -346,19 +421,59 @@ where
         }))
     }
 
-    pub async fn attach_buffer(&self, buf: Vec<u8, D::IoBufferAllocator>) {
+    pub async fn attach_buffer(
+        &self,
+        buf: Vec<u8, D::IoBufferAllocator>,
+        epoch: u64,
+        sequence: Sequence,
+    ) {
         let mut inner = self.inner.write();
 
         assert_eq!(inner.writers, 0);
         assert_eq!(inner.buffered_readers, 0);
 
         inner.attach_buffer(buf);
-        let buffer = inner.buffer.as_deref_mut().unwrap();
-        let header = RegionHeader {
+        inner.header = RegionHeader {
             magic: REGION_MAGIC,
+            version: REGION_FORMAT_VERSION,
+            epoch,
+            sequence,
+            ..Default::default()
         };
-        header.write(buffer);
+        let header = inner.header.clone();
+        header.write(inner.buffer.as_deref_mut().unwrap());
         inner.len = self.device.align();
+        inner.valid = inner.len;
+    }
+
+    /// Record an entry written to the attached buffer.
+    ///
+    /// Must be called before the [`WriteSlice`] of the entry is dropped, so the entry is counted when flushing.
+    pub fn record_entry(&self, sequence: Sequence) {
+        let mut inner = self.inner.write();
+        let header = &mut inner.header;
+        header.min_sequence = match header.entries {
+            0 => sequence,
+            _ => std::cmp::min(header.min_sequence, sequence),
+        };
+        header.max_sequence = std::cmp::max(header.max_sequence, sequence);
+        header.entries += 1;
+    }
+
+    /// Complete the header of the attached buffer with the region statistics before flushing.
+    ///
+    /// Must be called after all writers are done.
+    pub fn seal_header(&self) {
+        let mut inner = self.inner.write();
+        debug_assert_eq!(inner.writers, 0);
+
+        inner.header.valid_bytes = inner.valid as u64;
+        inner.header.sealed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let header = inner.header.clone();
+        header.write(inner.buffer.as_deref_mut().unwrap());
     }
 
     pub async fn detach_buffer(&self) -> Vec<u8, D::IoBufferAllocator> {
@@ -367,11 +482,38 @@ where
         inner.detach_buffer()
     }
 
+    /// Wake the waiters of [`Region::wait_flushed`], must be called after the flushed buffer is detached.
+    pub fn notify_flushed(&self) {
+        self.flushed.notify_waiters();
+    }
+
+    /// Wait until the buffer attached at `version` is flushed, returns immediately if it is already flushed.
+    pub async fn wait_flushed(&self, version: Version) {
+        loop {
+            let notified = self.flushed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            {
+                let inner = self.inner.read();
+                if inner.version != version || !inner.has_buffer() {
+                    return;
+                }
+            }
+            notified.await;
+        }
+    }
+
     pub async fn has_buffer(&self) -> bool {
         let inner = self.inner.read();
         inner.has_buffer()
     }
 
+    /// Returns the header sequence if the region has a dirty buffer that is not flushed yet.
+    pub fn buffered_sequence(&self) -> Option<Sequence> {
+        let inner = self.inner.read();
+        inner.has_buffer().then_some(inner.header.sequence)
+    }
+
     #[instrument(skip(self))]
     pub async fn exclusive(
         &self,
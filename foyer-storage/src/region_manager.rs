@@ -14,7 +14,7 @@
 
 use std::{
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
     time::Duration,
@@ -27,11 +27,12 @@ use foyer_intrusive::{
     intrusive_adapter, key_adapter,
 };
 use itertools::Itertools;
-use parking_lot::RwLock;
-use tokio::sync::Mutex as AsyncMutex;
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::{Mutex as AsyncMutex, MutexGuard as AsyncMutexGuard};
 use tracing::Instrument;
 
 use crate::{
+    catalog::Sequence,
     device::Device,
     metrics::Metrics,
     region::{AllocateResult, Region, RegionId, WriteSlice},
@@ -49,11 +50,39 @@ where
 intrusive_adapter! { pub RegionEpItemAdapter<L> = Arc<RegionEpItem<L>>: RegionEpItem<L> { link: L } where L: Link }
 key_adapter! { RegionEpItemAdapter<L> = RegionEpItem<L> { id: RegionId } where L: Link }
 
+/// Allocators, clean regions and eviction of the regions in a device tier, see [`Device::tier`].
+#[derive(Debug)]
+struct RegionTier<D, EP>
+where
+    D: Device,
+{
+    allocators: Vec<AsyncMutex<Option<Region<D>>>>,
+    allocated: AtomicUsize,
+
+    /// A lower bound of the header sequence of the region each allocator is switching to, `Sequence::MAX` if the
+    /// allocator is not switching.
+    ///
+    /// The region takes its header sequence before its buffer is attached, see [`RegionManager::watermark`].
+    watermarks: Vec<AtomicU64>,
+
+    /// Empty regions.
+    clean_regions: AsyncQueue<RegionId>,
+
+    /// An empty region kept from the writers, see [`RegionManager::release_reserved`].
+    reserved: Mutex<Option<RegionId>>,
+
+    /// Eviction policy.
+    eviction: RwLock<EP>,
+}
+
 /// Manager of regions and buffer pools.
 ///
 /// # Region Lifetime
 ///
 /// `clean` ==(allocate)=> `dirty` ==(flush)=> `evictable` ==(reclaim)=> `clean`
+///
+/// Each device tier has its own clean regions and eviction, regions never move between tiers. One clean region of each
+/// tier is reserved, see [`RegionManager::release_reserved`].
 #[derive(Debug)]
 pub struct RegionManager<D, EP, EL>
 where
@@ -61,36 +90,55 @@ where
     EP: EvictionPolicy<Adapter = RegionEpItemAdapter<EL>>,
     EL: Link,
 {
-    allocators: Vec<AsyncMutex<Option<Region<D>>>>,
+    tiers: Vec<RegionTier<D, EP>>,
     allocator_bits: usize,
-    allocated: AtomicUsize,
 
-    /// Buffer pool for dirty buffers.
+    /// Buffer pool for dirty buffers, shared by all tiers.
     buffers: AsyncQueue<Vec<u8, D::IoBufferAllocator>>,
 
-    /// Empty regions.
-    clean_regions: AsyncQueue<RegionId>,
-
     /// Regions with dirty buffer waiting for flushing.
     dirty_regions: AsyncQueue<RegionId>,
 
     regions: Vec<Region<D>>,
     items: Vec<Arc<RegionEpItem<EL>>>,
 
-    /// Eviction policy.
-    eviction: RwLock<EP>,
-
     allocation_timeout: Duration,
 
+    /// Store epoch written into the header of newly allocated regions.
+    epoch: AtomicU64,
+
+    /// Store sequence, shared with the store. Newly allocated regions take a sequence for their headers.
+    sequence: Arc<AtomicU64>,
+
+    /// Header sequences the watermark is held at, see [`RegionManager::hold`].
+    holds: Mutex<Vec<Sequence>>,
+
     metrics: Arc<Metrics>,
 }
 
+/// Holds the watermark of a [`RegionManager`] until dropped, see [`RegionManager::hold`].
+#[derive(Debug)]
+pub struct WatermarkHold<'a> {
+    holds: &'a Mutex<Vec<Sequence>>,
+    sequence: Sequence,
+}
+
+impl<'a> Drop for WatermarkHold<'a> {
+    fn drop(&mut self) {
+        let mut holds = self.holds.lock();
+        if let Some(pos) = holds.iter().position(|sequence| *sequence == self.sequence) {
+            holds.swap_remove(pos);
+        }
+    }
+}
+
 impl<D, EP, EL> RegionManager<D, EP, EL>
 where
     D: Device,
     EP: EvictionPolicy<Adapter = RegionEpItemAdapter<EL>>,
     EL: Link,
 {
+    #[expect(clippy::too_many_arguments)]
     pub fn new(
         allocator_bits: usize,
         buffer_count: usize,
@@ -98,6 +146,8 @@ where
         eviction_config: EP::Config,
         device: D,
         allocation_timeout: Duration,
+        epoch: u64,
+        sequence: Arc<AtomicU64>,
         metrics: Arc<Metrics>,
     ) -> Self {
         let buffers = AsyncQueue::new();
@@ -107,8 +157,20 @@ where
             buffers.release(buffer);
         }
 
-        let eviction = EP::new(eviction_config);
-        let clean_regions = AsyncQueue::new();
+        let tiers = (0..device.tiers())
+            .map(|_| RegionTier {
+                allocators: (0..(1 << allocator_bits))
+                    .map(|_| AsyncMutex::new(None))
+                    .collect_vec(),
+                allocated: AtomicUsize::new(0),
+                watermarks: (0..(1 << allocator_bits))
+                    .map(|_| AtomicU64::new(Sequence::MAX))
+                    .collect_vec(),
+                clean_regions: AsyncQueue::new(),
+                reserved: Mutex::new(None),
+                eviction: RwLock::new(EP::new(eviction_config.clone())),
+            })
+            .collect_vec();
         let dirty_regions = AsyncQueue::new();
 
         let mut regions = Vec::with_capacity(region_count);
@@ -125,39 +187,30 @@ where
             items.push(item);
         }
 
-        let allocators = (0..(1 << allocator_bits))
-            .map(|_| AsyncMutex::new(None))
-            .collect_vec();
-
         Self {
-            allocators,
-            allocated: AtomicUsize::new(0),
+            tiers,
             allocator_bits,
             buffers,
-            clean_regions,
             dirty_regions,
             regions,
             items,
-            eviction: RwLock::new(eviction),
             allocation_timeout,
+            epoch: AtomicU64::new(epoch),
+            sequence,
+            holds: Mutex::new(vec![]),
             metrics,
         }
     }
 
+    /// Allocate `size` bytes from a region of `tier`.
     #[tracing::instrument(skip(self))]
-    pub async fn allocate(&self, size: usize, must_allocate: bool) -> Option<WriteSlice> {
-        let allocated = self.allocated.fetch_add(1, Ordering::Relaxed);
-        let index = allocated & ((1 << self.allocator_bits) - 1);
-        let allocator = &self.allocators[index];
-
-        let mut current = if must_allocate {
-            allocator.lock().await
-        } else {
-            match tokio::time::timeout(self.allocation_timeout, allocator.lock()).await {
-                Ok(current) => current,
-                Err(_) => return None,
-            }
-        };
+    pub async fn allocate(
+        &self,
+        tier: usize,
+        size: usize,
+        must_allocate: bool,
+    ) -> Option<WriteSlice> {
+        let (index, mut current) = self.lock_allocator(tier, must_allocate).await?;
 
         loop {
             if let Some(region) = current.as_ref() {
@@ -170,15 +223,86 @@ where
                 }
             }
 
-            assert!(current.is_none());
+            self.switch_region(tier, index, &mut current).await;
+        }
+    }
+
+    /// Allocate a contiguous slice for the longest prefix of `sizes` that fits in a region of `tier`, returns the slice
+    /// with the count of sizes it holds.
+    ///
+    /// The allocator switches to a clean region only if the first size doesn't fit in the current one, so a batch
+    /// takes a slice per region and never holds a slice while waiting for a clean region.
+    #[tracing::instrument(skip(self, sizes), fields(sizes = sizes.len()))]
+    pub async fn allocate_many(
+        &self,
+        tier: usize,
+        sizes: &[usize],
+        must_allocate: bool,
+    ) -> Option<(WriteSlice, usize)> {
+        assert!(!sizes.is_empty());
+
+        let (index, mut current) = self.lock_allocator(tier, must_allocate).await?;
 
+        loop {
+            if let Some(region) = current.as_ref() {
+                let remaining = region.remaining();
+                let mut total = 0;
+                let count = sizes
+                    .iter()
+                    .take_while(|size| {
+                        total += **size;
+                        total <= remaining
+                    })
+                    .count();
+                // Allocate the first size alone if none fits, so the region is sealed the same as `allocate`.
+                let count = std::cmp::max(count, 1);
+                match region.allocate(sizes[..count].iter().sum()) {
+                    AllocateResult::Ok(slice) => return Some((slice, count)),
+                    AllocateResult::NotEnough { .. } => {
+                        self.dirty_regions.release(region.id());
+                        *current = None;
+                    }
+                }
+            }
+
+            self.switch_region(tier, index, &mut current).await;
+        }
+    }
+
+    /// Pick an allocator of `tier` and lock it, returns `None` if timeout unless `must_allocate` is set.
+    async fn lock_allocator(
+        &self,
+        tier: usize,
+        must_allocate: bool,
+    ) -> Option<(usize, AsyncMutexGuard<'_, Option<Region<D>>>)> {
+        let tier = &self.tiers[tier];
+        let allocated = tier.allocated.fetch_add(1, Ordering::Relaxed);
+        let index = allocated & ((1 << self.allocator_bits) - 1);
+        let allocator = &tier.allocators[index];
+
+        let current = if must_allocate {
+            allocator.lock().await
+        } else {
+            tokio::time::timeout(self.allocation_timeout, allocator.lock())
+                .await
+                .ok()?
+        };
+        Some((index, current))
+    }
+
+    /// Switch the allocator `index` of `tier` to a clean region, the current region must be released before.
+    async fn switch_region(&self, tier: usize, index: usize, current: &mut Option<Region<D>>) {
+        assert!(current.is_none());
+
+        let tier = &self.tiers[tier];
+        loop {
             // Wait a clean region to be released.
             let region_id = {
                 let timer = self
                     .metrics
                     .inner_op_duration_acquire_clean_region
                     .start_timer();
-                let region_id = self
+                let region_id = tier
                     .clean_regions
                     .acquire()
                     .instrument(tracing::debug_span!("acquire_clean_region"))
@@ -187,9 +311,14 @@ where
                 region_id
             };
 
+            let region = self.region(&region_id);
+            if !region.device().is_usable(region_id) {
+                tracing::warn!("allocator {} drop unusable region: {}", index, region_id);
+                continue;
+            }
+
             tracing::info!("allocator {} switch to clean region: {}", index, region_id);
 
-            let region = self.region(&region_id);
             region.advance().await;
 
             let buffer = {
@@ -205,14 +334,21 @@ where
                 drop(timer);
                 buffer
             };
-            region.attach_buffer(buffer).await;
+            let watermark = &tier.watermarks[index];
+            watermark.store(self.sequence.load(Ordering::SeqCst), Ordering::SeqCst);
+            let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
+            region
+                .attach_buffer(buffer, self.epoch.load(Ordering::Acquire), sequence)
+                .await;
+            watermark.store(Sequence::MAX, Ordering::SeqCst);
 
             *current = Some(region.clone());
+            return;
         }
     }
 
     pub async fn seal(&self) {
-        for allocator in self.allocators.iter() {
+        for allocator in self.tiers.iter().flat_map(|tier| tier.allocators.iter()) {
             let mut guard = allocator.lock().await;
             if let Some(region) = guard.as_ref() {
                 self.dirty_regions.release(region.id());
@@ -221,13 +357,73 @@ where
         }
     }
 
+    /// Returns the minimal header sequence of regions that may still receive entries not persisted yet.
+    ///
+    /// Regions with a smaller header sequence are all flushed, regions allocated later take larger sequences.
+    ///
+    /// Allocators are not locked, writers waiting for clean regions don't block the watermark.
+    pub fn watermark(&self) -> Sequence {
+        // Regions that take a sequence after it is loaded are above the watermark. A region that takes a smaller
+        // sequence is either covered by the watermark of its allocator, or has its buffer attached already.
+        let sequence = self.sequence.load(Ordering::SeqCst);
+        let switching = self
+            .tiers
+            .iter()
+            .flat_map(|tier| tier.watermarks.iter())
+            .map(|watermark| watermark.load(Ordering::SeqCst))
+            .collect_vec();
+        let buffered = self
+            .regions
+            .iter()
+            .filter_map(|region| region.buffered_sequence())
+            .collect_vec();
+        // A hold is taken while its region is buffered, so holds are loaded after the regions.
+        let held = self.holds.lock().iter().copied().min();
+        buffered
+            .into_iter()
+            .chain(switching)
+            .chain(held)
+            .fold(sequence, std::cmp::min)
+    }
+
+    /// Hold the watermark at `sequence` until the returned guard is dropped.
+    ///
+    /// Writers that release slices before their entry is indexed hold the header sequence of the first region they
+    /// write, which must be buffered when the hold is taken.
+    pub fn hold(&self, sequence: Sequence) -> WatermarkHold<'_> {
+        self.holds.lock().push(sequence);
+        WatermarkHold {
+            holds: &self.holds,
+            sequence,
+        }
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// Regions allocated after `set_epoch` are written with the new epoch.
+    ///
+    /// Call `seal` to make the current regions stop accepting writes of the previous epoch.
+    pub fn set_epoch(&self, epoch: u64) {
+        self.epoch.store(epoch, Ordering::Release);
+    }
+
     pub fn region(&self, id: &RegionId) -> &Region<D> {
         &self.regions[*id as usize]
     }
 
+    pub fn tiers(&self) -> usize {
+        self.tiers.len()
+    }
+
+    pub fn tier(&self, id: &RegionId) -> usize {
+        self.region(id).device().tier(*id)
+    }
+
     #[tracing::instrument(skip(self))]
     pub fn record_access(&self, id: &RegionId) {
-        let mut eviction = self.eviction.write();
+        let mut eviction = self.tiers[self.tier(id)].eviction.write();
         let item = &self.items[*id as usize];
         if item.link.is_linked() {
             eviction.access(&self.items[*id as usize]);
@@ -238,8 +434,41 @@ where
         &self.buffers
     }
 
-    pub fn clean_regions(&self) -> &AsyncQueue<RegionId> {
-        &self.clean_regions
+    pub fn clean_regions(&self, tier: usize) -> &AsyncQueue<RegionId> {
+        &self.tiers[tier].clean_regions
+    }
+
+    /// Release a clean region to the writers of its tier, or keep it as the reserved region of the tier if there is
+    /// none or the reserved one is no longer usable.
+    ///
+    /// Returns `false` if the region is kept.
+    pub fn release_clean_region(&self, region_id: RegionId) -> bool {
+        let tier = &self.tiers[self.tier(&region_id)];
+        {
+            let mut reserved = tier.reserved.lock();
+            if reserved.map_or(true, |id| !self.region(&id).device().is_usable(id)) {
+                if let Some(id) = reserved.replace(region_id) {
+                    tracing::warn!("retire unusable reserved region: {}", id);
+                }
+                return false;
+            }
+        }
+        tier.clean_regions.release(region_id);
+        true
+    }
+
+    /// Release the reserved clean region of `tier` to the writers, returns `false` if there is none.
+    ///
+    /// A writer waiting for a clean region holds the allocator, so the reclaimer can't rewrite the tombstones of the
+    /// region it reclaims, and can't release the region before they are rewritten. The reserved region lets the writer
+    /// go, it is refilled by the next clean region released.
+    pub fn release_reserved(&self, tier: usize) -> bool {
+        let tier = &self.tiers[tier];
+        let Some(region_id) = tier.reserved.lock().take() else {
+            return false;
+        };
+        tier.clean_regions.release(region_id);
+        true
     }
 
     pub fn dirty_regions(&self) -> &AsyncQueue<RegionId> {
@@ -247,12 +476,13 @@ where
     }
 
     pub fn eviction_push(&self, region_id: RegionId) {
-        self.eviction
+        self.tiers[self.tier(&region_id)]
+            .eviction
             .write()
             .push(self.items[region_id as usize].clone());
     }
 
-    pub fn eviction_pop(&self) -> Option<RegionId> {
-        self.eviction.write().pop().map(|item| item.id)
+    pub fn eviction_pop(&self, tier: usize) -> Option<RegionId> {
+        self.tiers[tier].eviction.write().pop().map(|item| item.id)
     }
 }
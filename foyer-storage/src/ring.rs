@@ -12,7 +12,7 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use crate::{catalog::Sequence, device::BufferAllocator};
+use crate::device::BufferAllocator;
 use foyer_common::{bits::align_up, continuum::ContinuumUsize};
 use itertools::Itertools;
 use std::{
@@ -93,19 +93,19 @@ where
     /// Returns `None` when the allocated buffer cross the boundary.
     ///
     /// When all views from an allocation are dropped, the buffer will be released.
-    pub async fn allocate(self: &Arc<Self>, len: usize, sequence: Sequence) -> ViewMut<A> {
+    pub async fn allocate(self: &Arc<Self>, len: usize) -> ViewMut<A> {
         loop {
-            if let Some(view) = self.allocate_inner(len, sequence).await {
+            if let Some(view) = self.allocate_inner(len).await {
                 return view;
             }
         }
     }
 
-    async fn allocate_inner(
-        self: &Arc<Self>,
-        len: usize,
-        sequence: Sequence,
-    ) -> Option<ViewMut<A>> {
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    async fn allocate_inner(self: &Arc<Self>, len: usize) -> Option<ViewMut<A>> {
         let len = align_up(self.align, len);
         let offset = self.allocated.fetch_add(len, Ordering::SeqCst);
 
@@ -127,12 +127,13 @@ where
             return None;
         }
 
-        let refs = self.refs(sequence);
+        let refs = self.refs(offset);
         Some(ViewMut::new(self, offset, len, refs))
     }
 
-    fn refs(&self, sequence: Sequence) -> &Arc<AtomicUsize> {
-        &self.refs[sequence as usize & (self.blocks - 1)]
+    /// Allocations in use never share the start block, so their reference counts never collide.
+    fn refs(&self, offset: usize) -> &Arc<AtomicUsize> {
+        &self.refs[(offset / self.align) & (self.blocks - 1)]
     }
 }
 
@@ -166,6 +167,11 @@ where
         }
     }
 
+    /// Offset of the view since the ring buffer is created, it is never reused by another allocation.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
     pub fn freeze(self) -> View<A> {
         View::from(self)
     }
@@ -228,6 +234,16 @@ where
     }
 }
 
+impl<A> View<A>
+where
+    A: BufferAllocator,
+{
+    /// Offset of the view since the ring buffer is created, it is never reused by another allocation.
+    pub fn offset(&self) -> usize {
+        self.view.offset
+    }
+}
+
 impl<A> Deref for View<A>
 where
     A: BufferAllocator,
@@ -290,7 +306,7 @@ mod tests {
         for i in 0..15 {
             let seq = sequence.fetch_add(1, Ordering::Relaxed);
             let view = ring
-                .allocate_inner(1024 * 1024 - 3000 /* ~ 1 MiB */, seq)
+                .allocate_inner(1024 * 1024 - 3000 /* ~ 1 MiB */)
                 .await
                 .unwrap();
             assert_eq!(view.offset, i * 1024 * 1024);
@@ -299,13 +315,13 @@ mod tests {
         }
         let seq = sequence.fetch_add(1, Ordering::Relaxed);
 
-        let mut future = pin!(ring.allocate_inner(2 * 1024 * 1024 - 3000 /* ~ 2 MiB */, seq));
+        let mut future = pin!(ring.allocate_inner(2 * 1024 * 1024 - 3000 /* ~ 2 MiB */));
         assert!(matches! { poll_fn(|cx| Poll::Ready(future.as_mut().poll(cx))).await, Pending });
         views.remove(&0).unwrap();
         let res = future.await;
         assert!(res.is_none());
 
-        let mut future = pin!(ring.allocate_inner(2 * 1024 * 1024 - 3000 /* ~ 2 MiB */, seq));
+        let mut future = pin!(ring.allocate_inner(2 * 1024 * 1024 - 3000 /* ~ 2 MiB */));
         assert!(matches! { poll_fn(|cx| Poll::Ready(future.as_mut().poll(cx))).await, Pending });
         views.remove(&2).unwrap();
         views.remove(&1).unwrap();
@@ -322,17 +338,14 @@ mod tests {
         const SIZE: Range<usize> = 16 * 1024..256 * 1024; // 16 KiB ~ 128 KiB
 
         let ring = Arc::new(RingBuffer::new(ALIGN, blocks));
-        let sequence = Arc::new(AtomicU64::default());
 
         let tasks = (0..concurrency)
             .map(|_| {
                 let ring = ring.clone();
-                let sequence = sequence.clone();
                 async move {
                     for i in 0..loops {
-                        let seq = sequence.fetch_add(1, Ordering::Relaxed);
                         let size = OsRng.gen_range(SIZE);
-                        let mut view = ring.allocate(size, seq).await;
+                        let mut view = ring.allocate(size).await;
                         tokio::time::sleep(Duration::from_millis(OsRng.gen_range(0..10))).await;
                         let data = vec![i as u8; size];
                         view.as_mut().put_slice(&data);
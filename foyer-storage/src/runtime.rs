@@ -12,7 +12,7 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::SystemTime};
 
 use foyer_common::{
     code::{Key, Value},
@@ -93,6 +93,10 @@ where
         self.writer.force()
     }
 
+    fn set_expire_at(&mut self, deadline: SystemTime) {
+        self.writer.set_expire_at(deadline)
+    }
+
     async fn finish(self, value: Self::Value) -> Result<bool> {
         self.runtime
             .spawn(async move { self.writer.finish(value).await })
@@ -175,6 +179,14 @@ where
         }
     }
 
+    async fn insert_many(&self, entries: Vec<(Self::Key, Self::Value)>) -> Result<Vec<bool>> {
+        let store = self.store.clone();
+        self.runtime
+            .spawn(async move { store.insert_many(entries).await })
+            .await
+            .unwrap()
+    }
+
     fn exists(&self, key: &Self::Key) -> crate::error::Result<bool> {
         self.store.exists(key)
     }
@@ -188,12 +200,30 @@ where
             .unwrap()
     }
 
-    fn remove(&self, key: &Self::Key) -> crate::error::Result<bool> {
-        self.store.remove(key)
+    async fn lookup_many(&self, keys: &[Self::Key]) -> Result<Vec<Option<Self::Value>>> {
+        let store = self.store.clone();
+        let keys = keys.to_vec();
+        self.runtime
+            .spawn(async move { store.lookup_many(&keys).await })
+            .await
+            .unwrap()
     }
 
-    fn clear(&self) -> crate::error::Result<()> {
-        self.store.clear()
+    async fn remove(&self, key: &Self::Key) -> Result<bool> {
+        let store = self.store.clone();
+        let key = key.clone();
+        self.runtime
+            .spawn(async move { store.remove(&key).await })
+            .await
+            .unwrap()
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let store = self.store.clone();
+        self.runtime
+            .spawn(async move { store.clear().await })
+            .await
+            .unwrap()
     }
 }
 
@@ -0,0 +1,151 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use foyer_common::code::Key;
+use itertools::Itertools;
+use parking_lot::Mutex;
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::{
+    catalog::{Catalog, ExpireAt, Index, IndexInfo, Sequence},
+    ring::{RingBuffer, View},
+};
+
+/// Alignment of entries staged in the ring buffer.
+pub(crate) const RING_BUFFER_ALIGN: usize = 512;
+
+/// Entry staged in the ring buffer, serialized the same as in a region.
+#[derive(Debug, Clone)]
+pub(crate) struct StagedEntry<K>
+where
+    K: Key,
+{
+    pub(crate) key: K,
+    pub(crate) sequence: Sequence,
+    pub(crate) expire_at: ExpireAt,
+    pub(crate) len: usize,
+    pub(crate) key_len: usize,
+    pub(crate) value_len: usize,
+    pub(crate) view: View,
+}
+
+pub(crate) struct RingBufferStage<K>
+where
+    K: Key,
+{
+    pub(crate) ring: Arc<RingBuffer>,
+    pub(crate) threshold: usize,
+
+    /// Staged entries by their offsets in the ring buffer.
+    entries: Mutex<BTreeMap<usize, StagedEntry<K>>>,
+    /// Ring buffer bytes taken by staged entries.
+    bytes: AtomicUsize,
+
+    pub(crate) spill: AsyncMutex<()>,
+}
+
+impl<K> Debug for RingBufferStage<K>
+where
+    K: Key,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RingBufferStage")
+            .field("capacity", &self.ring.capacity())
+            .field("threshold", &self.threshold)
+            .field("bytes", &self.bytes())
+            .finish()
+    }
+}
+
+impl<K> RingBufferStage<K>
+where
+    K: Key,
+{
+    pub(crate) fn new(capacity: usize, threshold: usize) -> Self {
+        Self {
+            ring: Arc::new(RingBuffer::new(
+                RING_BUFFER_ALIGN,
+                capacity / RING_BUFFER_ALIGN,
+            )),
+            threshold,
+            entries: Mutex::new(BTreeMap::new()),
+            bytes: AtomicUsize::new(0),
+            spill: AsyncMutex::new(()),
+        }
+    }
+
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Acquire)
+    }
+
+    /// Stage the entry and index it.
+    pub(crate) fn stage(&self, indices: &Catalog<K>, entry: StagedEntry<K>) {
+        let offset = entry.view.offset();
+        let bytes = entry.view.len();
+        let key = entry.key.clone();
+        let info = IndexInfo {
+            sequence: entry.sequence,
+            expire_at: entry.expire_at,
+            index: Index::RingBuffer {
+                offset,
+                len: entry.len,
+            },
+        };
+
+        // Index the entry with staged entries locked, so a concurrent spill never releases it before indexed.
+        let mut entries = self.entries.lock();
+        entries.insert(offset, entry);
+        indices.insert(key, info);
+        drop(entries);
+
+        self.bytes.fetch_add(bytes, Ordering::AcqRel);
+    }
+
+    pub(crate) fn lookup(&self, offset: usize) -> Option<View> {
+        self.entries
+            .lock()
+            .get(&offset)
+            .map(|entry| entry.view.clone())
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<StagedEntry<K>> {
+        self.entries.lock().values().cloned().collect_vec()
+    }
+
+    /// Release the ring buffer of spilled entries.
+    pub(crate) fn release(&self, offsets: impl IntoIterator<Item = usize>) {
+        let mut entries = self.entries.lock();
+        for offset in offsets {
+            if let Some(entry) = entries.remove(&offset) {
+                self.bytes.fetch_sub(entry.view.len(), Ordering::AcqRel);
+            }
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut entries = self.entries.lock();
+        for (_, entry) in std::mem::take(&mut *entries) {
+            self.bytes.fetch_sub(entry.view.len(), Ordering::AcqRel);
+        }
+    }
+}
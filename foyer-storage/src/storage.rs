@@ -12,7 +12,10 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    time::{Duration, SystemTime},
+};
 
 use foyer_common::code::{Key, Value};
 
@@ -33,6 +36,14 @@ pub trait StorageWriter: Send + Sync + Debug {
 
     fn force(&mut self);
 
+    /// Set the deadline after which the entry is treated as a miss.
+    fn set_expire_at(&mut self, deadline: SystemTime);
+
+    /// Set the time to live of the entry from now.
+    fn set_ttl(&mut self, ttl: Duration) {
+        self.set_expire_at(SystemTime::now() + ttl)
+    }
+
     fn finish(self, value: Self::Value) -> impl Future<Output = Result<bool>> + Send;
 }
 
@@ -52,14 +63,36 @@ pub trait Storage: Send + Sync + Debug + Clone + 'static {
 
     fn writer(&self, key: Self::Key, weight: usize) -> Self::Writer;
 
+    /// Insert all `entries` at once, returns if each entry is inserted in the order of `entries`.
+    ///
+    /// The batch is not atomic as a whole. Stores may publish part of the entries together, e.g. [`GenericStore`]
+    /// publishes the entries written to the same region as a whole at once, entries stored in buckets, staged in the
+    /// ring buffer or split into chunks become visible one by one.
+    ///
+    /// [`GenericStore`]: crate::generic::GenericStore
+    #[must_use]
+    fn insert_many(
+        &self,
+        entries: Vec<(Self::Key, Self::Value)>,
+    ) -> impl Future<Output = Result<Vec<bool>>> + Send;
+
     fn exists(&self, key: &Self::Key) -> Result<bool>;
 
     #[must_use]
     fn lookup(&self, key: &Self::Key) -> impl Future<Output = Result<Option<Self::Value>>> + Send;
 
-    fn remove(&self, key: &Self::Key) -> Result<bool>;
+    /// Lookup all `keys` at once, results are returned in the order of `keys`.
+    #[must_use]
+    fn lookup_many(
+        &self,
+        keys: &[Self::Key],
+    ) -> impl Future<Output = Result<Vec<Option<Self::Value>>>> + Send;
+
+    #[must_use]
+    fn remove(&self, key: &Self::Key) -> impl Future<Output = Result<bool>> + Send;
 
-    fn clear(&self) -> Result<()>;
+    #[must_use]
+    fn clear(&self) -> impl Future<Output = Result<()>> + Send;
 }
 
 pub trait StorageExt: Storage {
@@ -74,6 +107,21 @@ pub trait StorageExt: Storage {
         self.writer(key, weight).finish(value)
     }
 
+    /// Insert the entry, it is treated as a miss after `ttl`.
+    #[must_use]
+    #[tracing::instrument(skip(self, value))]
+    fn insert_with_ttl(
+        &self,
+        key: Self::Key,
+        value: Self::Value,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<bool>> + Send {
+        let weight = key.serialized_len() + value.serialized_len();
+        let mut writer = self.writer(key, weight);
+        writer.set_ttl(ttl);
+        writer.finish(value)
+    }
+
     #[must_use]
     #[tracing::instrument(skip(self, value))]
     fn insert_if_not_exists(
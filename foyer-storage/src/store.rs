@@ -12,7 +12,7 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::marker::PhantomData;
+use std::{marker::PhantomData, time::SystemTime};
 
 use foyer_common::code::{Key, Value};
 use foyer_intrusive::eviction::{
@@ -91,6 +91,8 @@ impl<K: Key, V: Value> StorageWriter for NoneStoreWriter<K, V> {
 
     fn force(&mut self) {}
 
+    fn set_expire_at(&mut self, _: SystemTime) {}
+
     async fn finish(self, _: Self::Value) -> Result<bool> {
         Ok(false)
     }
@@ -134,6 +136,10 @@ impl<K: Key, V: Value> Storage for NoneStore<K, V> {
         NoneStoreWriter::new(key, weight)
     }
 
+    async fn insert_many(&self, entries: Vec<(Self::Key, Self::Value)>) -> Result<Vec<bool>> {
+        Ok(vec![false; entries.len()])
+    }
+
     fn exists(&self, _: &Self::Key) -> Result<bool> {
         Ok(false)
     }
@@ -142,11 +148,15 @@ impl<K: Key, V: Value> Storage for NoneStore<K, V> {
         Ok(None)
     }
 
-    fn remove(&self, _: &Self::Key) -> Result<bool> {
+    async fn lookup_many(&self, keys: &[Self::Key]) -> Result<Vec<Option<Self::Value>>> {
+        Ok(keys.iter().map(|_| None).collect())
+    }
+
+    async fn remove(&self, _: &Self::Key) -> Result<bool> {
         Ok(false)
     }
 
-    fn clear(&self) -> Result<()> {
+    async fn clear(&self) -> Result<()> {
         Ok(())
     }
 }
@@ -345,6 +355,15 @@ where
         }
     }
 
+    fn set_expire_at(&mut self, deadline: SystemTime) {
+        match self {
+            StoreWriter::LruFsStorWriter { writer } => writer.set_expire_at(deadline),
+            StoreWriter::LfuFsStorWriter { writer } => writer.set_expire_at(deadline),
+            StoreWriter::FifoFsStoreWriter { writer } => writer.set_expire_at(deadline),
+            StoreWriter::NoneStoreWriter { writer } => writer.set_expire_at(deadline),
+        }
+    }
+
     async fn finish(self, value: Self::Value) -> Result<bool> {
         match self {
             StoreWriter::LruFsStorWriter { writer } => writer.finish(value).await,
@@ -413,6 +432,15 @@ where
         }
     }
 
+    async fn insert_many(&self, entries: Vec<(Self::Key, Self::Value)>) -> Result<Vec<bool>> {
+        match self {
+            Store::LruFsStore { store } => store.insert_many(entries).await,
+            Store::LfuFsStore { store } => store.insert_many(entries).await,
+            Store::FifoFsStore { store } => store.insert_many(entries).await,
+            Store::NoneStore { store } => store.insert_many(entries).await,
+        }
+    }
+
     fn exists(&self, key: &Self::Key) -> Result<bool> {
         match self {
             Store::LruFsStore { store } => store.exists(key),
@@ -431,21 +459,30 @@ where
         }
     }
 
-    fn remove(&self, key: &Self::Key) -> Result<bool> {
+    async fn lookup_many(&self, keys: &[Self::Key]) -> Result<Vec<Option<Self::Value>>> {
+        match self {
+            Store::LruFsStore { store } => store.lookup_many(keys).await,
+            Store::LfuFsStore { store } => store.lookup_many(keys).await,
+            Store::FifoFsStore { store } => store.lookup_many(keys).await,
+            Store::NoneStore { store } => store.lookup_many(keys).await,
+        }
+    }
+
+    async fn remove(&self, key: &Self::Key) -> Result<bool> {
         match self {
-            Store::LruFsStore { store } => store.remove(key),
-            Store::LfuFsStore { store } => store.remove(key),
-            Store::FifoFsStore { store } => store.remove(key),
-            Store::NoneStore { store } => store.remove(key),
+            Store::LruFsStore { store } => store.remove(key).await,
+            Store::LfuFsStore { store } => store.remove(key).await,
+            Store::FifoFsStore { store } => store.remove(key).await,
+            Store::NoneStore { store } => store.remove(key).await,
         }
     }
 
-    fn clear(&self) -> Result<()> {
+    async fn clear(&self) -> Result<()> {
         match self {
-            Store::LruFsStore { store } => store.clear(),
-            Store::LfuFsStore { store } => store.clear(),
-            Store::FifoFsStore { store } => store.clear(),
-            Store::NoneStore { store } => store.clear(),
+            Store::LruFsStore { store } => store.clear().await,
+            Store::LfuFsStore { store } => store.clear().await,
+            Store::FifoFsStore { store } => store.clear().await,
+            Store::NoneStore { store } => store.clear().await,
         }
     }
 }
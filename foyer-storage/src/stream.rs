@@ -0,0 +1,178 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::{collections::VecDeque, ops::Range};
+
+use bytes::Bytes;
+use foyer_common::code::{Key, Value};
+use foyer_intrusive::{core::adapter::Link, eviction::EvictionPolicy};
+use futures::stream::BoxStream;
+
+use crate::{
+    catalog::Chunk,
+    checksum::ChecksumHasher,
+    device::Device,
+    error::{ErrorKind, Result},
+    generic::GenericStore,
+    region::{RegionId, Version},
+    region_manager::RegionEpItemAdapter,
+};
+
+/// Serialized value of an entry streamed by [`GenericStore::lookup_stream`], in pieces of at most `io_size` bytes.
+///
+/// Pieces are yielded before the checksum of the entry is verified at its end, or at the end of each chunk of a large
+/// entry. If the entry turns out corrupted or is evicted midway, the stream ends with an error of
+/// [`ErrorKind::Corrupted`] or [`ErrorKind::Evicted`], and the pieces yielded before must be discarded.
+///
+/// [`GenericStore::lookup_stream`]: crate::generic::GenericStore::lookup_stream
+pub type ValueStream = BoxStream<'static, Result<Bytes>>;
+
+/// An entry or a chunk opened by [`GenericStore::stream_open`].
+pub(crate) enum StreamOpened {
+    /// The cursor of the entry or the chunk, with the value bytes in the first piece.
+    Piece(StreamCursor, Bytes),
+    /// The value is compressed or encrypted, which can only be decoded as a whole.
+    Whole,
+    /// The entry is of another key.
+    Mismatch,
+}
+
+/// Position of a [`ValueStream`] in an entry or a chunk, positions and ranges are in the region.
+pub(crate) struct StreamCursor {
+    pub(crate) region: RegionId,
+    pub(crate) version: Version,
+    /// Position of the next piece to read.
+    pub(crate) pos: usize,
+    /// End of the last piece to read.
+    pub(crate) end: usize,
+    /// Bytes covered by the checksum, the value and the key, with the index and count of a chunk.
+    pub(crate) body: Range<usize>,
+    pub(crate) value: Range<usize>,
+    /// `None` if the checksum is not verified.
+    pub(crate) hasher: Option<ChecksumHasher>,
+    pub(crate) checksum: u64,
+}
+
+impl StreamCursor {
+    /// Consume the piece read at `start`, returns the value bytes in it.
+    ///
+    /// The checksum is verified once the whole body is consumed.
+    pub(crate) fn consume(&mut self, start: usize, piece: &[u8]) -> Result<Bytes> {
+        let end = start + piece.len();
+        self.pos = end;
+
+        let slice = |range: &Range<usize>| {
+            let (s, e) = (
+                std::cmp::max(start, range.start),
+                std::cmp::min(end, range.end),
+            );
+            (s < e).then(|| &piece[s - start..e - start])
+        };
+        if let Some(hasher) = self.hasher.as_mut()
+            && let Some(buf) = slice(&self.body)
+        {
+            hasher.write(buf);
+        }
+        let bytes = slice(&self.value).map_or_else(Bytes::new, Bytes::copy_from_slice);
+
+        if self.is_done()
+            && let Some(hasher) = self.hasher.take()
+        {
+            let checksum = hasher.finish();
+            if checksum != self.checksum {
+                tracing::warn!(
+                    "checksum mismatch, checksum: {}, expected: {}",
+                    checksum,
+                    self.checksum,
+                );
+                return Err(ErrorKind::Corrupted(format!(
+                    "checksum mismatch, checksum: {}, expected: {}",
+                    checksum, self.checksum
+                ))
+                .into());
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.pos >= self.body.end
+    }
+}
+
+/// State of a [`ValueStream`] over an entry or the chunks of a large entry in regions.
+pub(crate) struct ValueStreamState<K, V, D, EP, EL>
+where
+    K: Key,
+    V: Value,
+    D: Device,
+    EP: EvictionPolicy<Adapter = RegionEpItemAdapter<EL>>,
+    EL: Link,
+{
+    pub(crate) store: GenericStore<K, V, D, EP, EL>,
+    /// Chunks not opened yet.
+    pub(crate) segments: VecDeque<Chunk>,
+    pub(crate) cursor: Option<StreamCursor>,
+    /// Value bytes read but not yielded yet.
+    pub(crate) pending: Bytes,
+    pub(crate) chunked: bool,
+    pub(crate) verify: bool,
+}
+
+impl<K, V, D, EP, EL> ValueStreamState<K, V, D, EP, EL>
+where
+    K: Key,
+    V: Value,
+    D: Device,
+    EP: EvictionPolicy<Adapter = RegionEpItemAdapter<EL>>,
+    EL: Link,
+{
+    pub(crate) async fn next(mut self) -> Result<Option<(Bytes, Self)>> {
+        loop {
+            if !self.pending.is_empty() {
+                let bytes = std::mem::take(&mut self.pending);
+                return Ok(Some((bytes, self)));
+            }
+
+            let (cursor, bytes) = match self.cursor.take() {
+                Some(mut cursor) => {
+                    let bytes = self.store.stream_read(&mut cursor).await?;
+                    (cursor, bytes)
+                }
+                None => {
+                    let Some(segment) = self.segments.pop_front() else {
+                        return Ok(None);
+                    };
+                    // All chunks share the flags of the entry, the first chunk is not compressed or encrypted.
+                    match self
+                        .store
+                        .stream_open(&segment, self.chunked, self.verify, None)
+                        .await?
+                    {
+                        StreamOpened::Piece(cursor, bytes) => (cursor, bytes),
+                        StreamOpened::Whole => {
+                            return Err(
+                                ErrorKind::Corrupted("chunk flags mismatch".to_string()).into()
+                            );
+                        }
+                        StreamOpened::Mismatch => unreachable!("only the first chunk is checked"),
+                    }
+                }
+            };
+            self.cursor = (!cursor.is_done()).then_some(cursor);
+            self.pending = bytes;
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//  Copyright 2023 MrCroxx
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+#![feature(lint_reasons)]
+#![expect(clippy::identity_op)]
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use foyer_intrusive::eviction::fifo::{Fifo, FifoConfig, FifoLink};
+use foyer_storage::{
+    checksum::{ChecksumAlgorithm, VerifyMode},
+    compress::Compression,
+    device::{
+        faulty::{Fault, FaultInjector, FaultyDevice, FaultyDeviceConfig},
+        fs::{FsDevice, FsDeviceConfig},
+    },
+    generic::{GenericStore, GenericStoreConfig, RecoverMode},
+    region_manager::RegionEpItemAdapter,
+    storage::{Storage, StorageExt},
+    test_utils::JudgeRecorder,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const KB: usize = 1024;
+const MB: usize = 1024 * 1024;
+
+const KEYS: u64 = 64;
+const OPS: usize = 400;
+
+type FaultyStore = GenericStore<
+    u64,
+    Vec<u8>,
+    FaultyDevice<FsDevice>,
+    Fifo<RegionEpItemAdapter<FifoLink>>,
+    FifoLink,
+>;
+
+type FaultyStoreConfig =
+    GenericStoreConfig<u64, Vec<u8>, FaultyDevice<FsDevice>, Fifo<RegionEpItemAdapter<FifoLink>>>;
+
+/// Model of the expected store state, `None` means absent.
+type Model = BTreeMap<u64, Option<u64>>;
+
+fn config(
+    dir: impl Into<PathBuf>,
+    injector: FaultInjector,
+    checkpoint_interval: Option<Duration>,
+    recover_mode: RecoverMode,
+) -> FaultyStoreConfig {
+    let recorder = Arc::new(JudgeRecorder::default());
+    // Large enough to never evict anything, so recovered state is decided only by the faults.
+    FaultyStoreConfig {
+        name: "".to_string(),
+        eviction_config: FifoConfig,
+        device_config: FaultyDeviceConfig {
+            device: FsDeviceConfig {
+                dir: dir.into(),
+                capacity: 16 * MB,
+                file_capacity: 1 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                reformat: false,
+                preallocate: false,
+            },
+            injector,
+        },
+        allocator_bits: 0,
+        catalog_bits: 1,
+        admissions: vec![recorder.clone()],
+        reinsertions: vec![recorder],
+        buffer_pool_size: 4 * MB,
+        ring_buffer_capacity: 0,
+        ring_buffer_threshold: 0,
+        small_object_regions: 0,
+        small_object_threshold: 0,
+        flushers: 1,
+        flush_rate_limit: 0,
+        reclaimers: 1,
+        reclaim_rate_limit: 0,
+        reclaim_discard: false,
+        recover_concurrency: 2,
+        recover_mode,
+        checkpoint_interval,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        verify_mode: VerifyMode::default(),
+        compression: Compression::None,
+        encryption: None,
+        allocation_timeout: Duration::from_millis(10),
+        clean_region_threshold: 1,
+    }
+}
+
+fn value(key: u64, version: u64) -> Vec<u8> {
+    let mut value = vec![0; 1 * KB];
+    value[0..8].copy_from_slice(&key.to_le_bytes());
+    value[8..16].copy_from_slice(&version.to_le_bytes());
+    value
+}
+
+fn version(key: u64, value: &[u8]) -> u64 {
+    assert_eq!(u64::from_le_bytes(value[0..8].try_into().unwrap()), key);
+    u64::from_le_bytes(value[8..16].try_into().unwrap())
+}
+
+async fn open(config: FaultyStoreConfig) -> FaultyStore {
+    let store = FaultyStore::open(config).await.unwrap();
+    while !store.is_ready() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    store
+}
+
+/// Run random inserts and removes, record every state each key has been in.
+async fn workload(
+    store: &FaultyStore,
+    rng: &mut StdRng,
+    version: &mut u64,
+    model: &mut Model,
+    history: &mut BTreeMap<u64, BTreeSet<Option<u64>>>,
+) {
+    for _ in 0..OPS {
+        let key = rng.gen_range(0..KEYS);
+        let state = if rng.gen_bool(0.8) {
+            *version += 1;
+            assert!(store.insert(key, value(key, *version)).await.unwrap());
+            Some(*version)
+        } else {
+            store.remove(&key).await.unwrap();
+            None
+        };
+        model.insert(key, state);
+        history.entry(key).or_default().insert(state);
+    }
+}
+
+async fn lookup(store: &FaultyStore, key: u64) -> Option<u64> {
+    store
+        .lookup(&key)
+        .await
+        .unwrap()
+        .map(|value| version(key, &value))
+}
+
+async fn assert_model(store: &FaultyStore, model: &Model) {
+    for key in 0..KEYS {
+        assert_eq!(
+            lookup(store, key).await,
+            model.get(&key).copied().flatten(),
+            "key: {key}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_crash_after_close() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let injector = FaultInjector::new();
+    let config = config(tempdir.path(), injector.clone(), None, RecoverMode::Strict);
+
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut version = 0;
+    let mut model = Model::new();
+    let mut history = BTreeMap::new();
+
+    for _ in 0..3 {
+        let store = open(config.clone()).await;
+        assert_model(&store, &model).await;
+        workload(&store, &mut rng, &mut version, &mut model, &mut history).await;
+        store.close().await.unwrap();
+        drop(store);
+
+        injector.crash();
+    }
+
+    let store = open(config).await;
+    assert_model(&store, &model).await;
+}
+
+#[tokio::test]
+async fn test_crash_drops_unflushed_writes() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let injector = FaultInjector::new();
+    let config = config(tempdir.path(), injector.clone(), None, RecoverMode::Strict);
+
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut version = 0;
+    let mut model = Model::new();
+    let mut history = BTreeMap::new();
+
+    let store = open(config.clone()).await;
+    workload(&store, &mut rng, &mut version, &mut model, &mut history).await;
+    store.close().await.unwrap();
+    drop(store);
+
+    // Nothing is synced before the crash, so the store rolls back to the last close.
+    let store = open(config.clone()).await;
+    let mut dropped = model.clone();
+    workload(&store, &mut rng, &mut version, &mut dropped, &mut history).await;
+    // Let the flusher write full regions back, they are still not synced.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    injector.crash();
+    drop(store);
+
+    let store = open(config).await;
+    assert_model(&store, &model).await;
+}
+
+#[tokio::test]
+async fn test_crash_with_checkpoints() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let injector = FaultInjector::new();
+    let config = config(
+        tempdir.path(),
+        injector.clone(),
+        Some(Duration::from_millis(5)),
+        RecoverMode::Tolerant,
+    );
+
+    let mut rng = StdRng::seed_from_u64(1024);
+    let mut version = 0;
+    let mut model = Model::new();
+
+    let store = open(config.clone()).await;
+    let mut history = BTreeMap::new();
+    workload(&store, &mut rng, &mut version, &mut model, &mut history).await;
+    store.close().await.unwrap();
+    drop(store);
+
+    for round in 0..3 {
+        let store = open(config.clone()).await;
+        let mut history: BTreeMap<u64, BTreeSet<Option<u64>>> = model
+            .iter()
+            .map(|(key, state)| (*key, BTreeSet::from_iter([*state])))
+            .collect();
+        for _ in 0..4 {
+            workload(&store, &mut rng, &mut version, &mut model, &mut history).await;
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        injector.crash();
+        drop(store);
+
+        // Any state a key has been in since the last close may survive the crash, but never a torn value.
+        let store = open(config.clone()).await;
+        for key in 0..KEYS {
+            let recovered = lookup(&store, key).await;
+            let states = history.get(&key).cloned().unwrap_or_default();
+            assert!(
+                recovered.is_none() || states.contains(&recovered),
+                "round: {round}, key: {key}, recovered: {recovered:?}, states: {states:?}"
+            );
+            model.insert(key, recovered);
+        }
+        store.close().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_torn_write() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let injector = FaultInjector::new();
+    let config = config(
+        tempdir.path(),
+        injector.clone(),
+        None,
+        RecoverMode::Tolerant,
+    );
+
+    let store = open(config.clone()).await;
+    for key in 0..10 {
+        assert!(store.insert(key, value(key, key)).await.unwrap());
+    }
+    // Each entry takes an aligned 4 KiB block after the region header, the 5th entry is torn.
+    injector.inject(Fault::TornWrite {
+        offset: 20 * KB as u64,
+    });
+    store.close().await.unwrap();
+    assert_eq!(injector.pending(), 0);
+    drop(store);
+
+    let store = open(config).await;
+    for key in 0..10 {
+        let expected = if key == 4 { None } else { Some(key) };
+        assert_eq!(lookup(&store, key).await, expected, "key: {key}");
+    }
+}
+
+#[tokio::test]
+async fn test_read_faults() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let injector = FaultInjector::new();
+    let config = config(tempdir.path(), injector.clone(), None, RecoverMode::Strict);
+
+    let store = open(config.clone()).await;
+    for key in 0..10 {
+        assert!(store.insert(key, value(key, key)).await.unwrap());
+    }
+    store.close().await.unwrap();
+    drop(store);
+
+    let store = open(config).await;
+
+    injector.inject(Fault::ReadError);
+    assert!(store.lookup(&0).await.is_err());
+    assert_eq!(lookup(&store, 0).await, Some(0));
+
+    // A short read can not be parsed as an entry, the entry is treated as missing.
+    injector.inject(Fault::ShortRead);
+    assert_eq!(lookup(&store, 1).await, None);
+    assert_eq!(lookup(&store, 2).await, Some(2));
+}
+
+#[tokio::test]
+async fn test_write_faults() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let injector = FaultInjector::new();
+    let config = config(tempdir.path(), injector.clone(), None, RecoverMode::Strict);
+
+    // Manifest and checkpoint bypass the faults, only region writes are affected.
+    let store = open(config.clone()).await;
+    injector.inject(Fault::ShortWrite);
+    for key in 0..10 {
+        assert!(store.insert(key, value(key, key)).await.unwrap());
+    }
+    store.close().await.unwrap();
+    assert_eq!(injector.pending(), 0);
+    drop(store);
+
+    // Half of the 4 KiB region header block is aligned down to nothing, so the whole region is dropped.
+    let store = open(config).await;
+    for key in 0..10 {
+        assert_eq!(lookup(&store, key).await, None, "key: {key}");
+    }
+}
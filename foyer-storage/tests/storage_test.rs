@@ -15,10 +15,17 @@
 #![feature(lint_reasons)]
 #![expect(clippy::identity_op)]
 
-use foyer_intrusive::eviction::fifo::FifoConfig;
+use foyer_intrusive::eviction::fifo::{Fifo, FifoConfig, FifoLink};
 use foyer_storage::{
-    device::fs::FsDeviceConfig,
+    checksum::{ChecksumAlgorithm, VerifyMode},
+    compress::Compression,
+    device::{
+        direct_file::{DirectFileDevice, DirectFileDeviceConfig},
+        fs::FsDeviceConfig,
+    },
+    generic::{GenericStore, GenericStoreConfig, RecoverMode},
     lazy::LazyStore,
+    region_manager::RegionEpItemAdapter,
     runtime::{RuntimeConfig, RuntimeLazyStore, RuntimeStorageConfig, RuntimeStore},
     storage::{Storage, StorageExt},
     store::{FifoFsStoreConfig, Store},
@@ -127,19 +134,120 @@ async fn test_store() {
             file_capacity: 1 * MB,
             align: 4 * KB,
             io_size: 4 * KB,
+            reformat: false,
+            preallocate: false,
         },
         allocator_bits: 0,
         catalog_bits: 1,
         admissions: vec![recorder.clone()],
         reinsertions: vec![recorder.clone()],
         buffer_pool_size: 2 * MB,
+        ring_buffer_capacity: 0,
+        ring_buffer_threshold: 0,
+        small_object_regions: 0,
+        small_object_threshold: 0,
         flushers: 1,
         flush_rate_limit: 0,
         reclaimers: 1,
         reclaim_rate_limit: 0,
+        reclaim_discard: false,
         allocation_timeout: Duration::from_millis(10),
         clean_region_threshold: 1,
         recover_concurrency: 2,
+        recover_mode: RecoverMode::Strict,
+        checkpoint_interval: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        verify_mode: VerifyMode::default(),
+        compression: Compression::None,
+        encryption: None,
+    };
+
+    test_storage::<Store<_, _>>(config.into(), recorder).await;
+}
+
+#[tokio::test]
+async fn test_store_reclaim_discard() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let recorder = Arc::new(JudgeRecorder::default());
+    let config = FifoFsStoreConfig {
+        name: "".to_string(),
+        eviction_config: FifoConfig,
+        device_config: FsDeviceConfig {
+            dir: PathBuf::from(tempdir.path()),
+            capacity: 4 * MB,
+            file_capacity: 1 * MB,
+            align: 4 * KB,
+            io_size: 4 * KB,
+            reformat: false,
+            preallocate: false,
+        },
+        allocator_bits: 0,
+        catalog_bits: 1,
+        admissions: vec![recorder.clone()],
+        reinsertions: vec![recorder.clone()],
+        buffer_pool_size: 2 * MB,
+        ring_buffer_capacity: 0,
+        ring_buffer_threshold: 0,
+        small_object_regions: 0,
+        small_object_threshold: 0,
+        flushers: 1,
+        flush_rate_limit: 0,
+        reclaimers: 1,
+        reclaim_rate_limit: 16 * MB,
+        reclaim_discard: true,
+        allocation_timeout: Duration::from_millis(10),
+        clean_region_threshold: 1,
+        recover_concurrency: 2,
+        recover_mode: RecoverMode::Strict,
+        checkpoint_interval: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        verify_mode: VerifyMode::default(),
+        compression: Compression::None,
+        encryption: None,
+    };
+
+    test_storage::<Store<_, _>>(config.into(), recorder).await;
+}
+
+#[tokio::test]
+async fn test_store_ring_buffer() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let recorder = Arc::new(JudgeRecorder::default());
+    let config = FifoFsStoreConfig {
+        name: "".to_string(),
+        eviction_config: FifoConfig,
+        device_config: FsDeviceConfig {
+            dir: PathBuf::from(tempdir.path()),
+            capacity: 4 * MB,
+            file_capacity: 1 * MB,
+            align: 4 * KB,
+            io_size: 4 * KB,
+            reformat: false,
+            preallocate: false,
+        },
+        allocator_bits: 0,
+        catalog_bits: 1,
+        admissions: vec![recorder.clone()],
+        reinsertions: vec![recorder.clone()],
+        buffer_pool_size: 2 * MB,
+        ring_buffer_capacity: 64 * KB,
+        ring_buffer_threshold: 2 * KB,
+        small_object_regions: 0,
+        small_object_threshold: 0,
+        flushers: 1,
+        flush_rate_limit: 0,
+        reclaimers: 1,
+        reclaim_rate_limit: 0,
+        reclaim_discard: false,
+        allocation_timeout: Duration::from_millis(10),
+        clean_region_threshold: 1,
+        recover_concurrency: 2,
+        recover_mode: RecoverMode::Strict,
+        checkpoint_interval: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        verify_mode: VerifyMode::default(),
+        compression: Compression::None,
+        encryption: None,
     };
 
     test_storage::<Store<_, _>>(config.into(), recorder).await;
@@ -158,19 +266,32 @@ async fn test_lazy_store() {
             file_capacity: 1 * MB,
             align: 4 * KB,
             io_size: 4 * KB,
+            reformat: false,
+            preallocate: false,
         },
         allocator_bits: 0,
         catalog_bits: 1,
         admissions: vec![recorder.clone()],
         reinsertions: vec![recorder.clone()],
         buffer_pool_size: 2 * MB,
+        ring_buffer_capacity: 0,
+        ring_buffer_threshold: 0,
+        small_object_regions: 0,
+        small_object_threshold: 0,
         flushers: 1,
         flush_rate_limit: 0,
         reclaimers: 1,
         reclaim_rate_limit: 0,
+        reclaim_discard: false,
         allocation_timeout: Duration::from_millis(10),
         clean_region_threshold: 1,
         recover_concurrency: 2,
+        recover_mode: RecoverMode::Strict,
+        checkpoint_interval: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        verify_mode: VerifyMode::default(),
+        compression: Compression::None,
+        encryption: None,
     };
 
     test_storage::<LazyStore<_, _>>(config.into(), recorder).await;
@@ -190,19 +311,32 @@ async fn test_runtime_store() {
                 file_capacity: 1 * MB,
                 align: 4 * KB,
                 io_size: 4 * KB,
+                reformat: false,
+                preallocate: false,
             },
             allocator_bits: 0,
             catalog_bits: 1,
             admissions: vec![recorder.clone()],
             reinsertions: vec![recorder.clone()],
             buffer_pool_size: 2 * MB,
+            ring_buffer_capacity: 0,
+            ring_buffer_threshold: 0,
+            small_object_regions: 0,
+            small_object_threshold: 0,
             flushers: 1,
             flush_rate_limit: 0,
             reclaimers: 1,
             reclaim_rate_limit: 0,
+            reclaim_discard: false,
             allocation_timeout: Duration::from_millis(10),
             clean_region_threshold: 1,
             recover_concurrency: 2,
+            recover_mode: RecoverMode::Strict,
+            checkpoint_interval: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            verify_mode: VerifyMode::default(),
+            compression: Compression::None,
+            encryption: None,
         }
         .into(),
         runtime: RuntimeConfig {
@@ -228,19 +362,32 @@ async fn test_runtime_lazy_store() {
                 file_capacity: 1 * MB,
                 align: 4 * KB,
                 io_size: 4 * KB,
+                reformat: false,
+                preallocate: false,
             },
             allocator_bits: 0,
             catalog_bits: 1,
             admissions: vec![recorder.clone()],
             reinsertions: vec![recorder.clone()],
             buffer_pool_size: 2 * MB,
+            ring_buffer_capacity: 0,
+            ring_buffer_threshold: 0,
+            small_object_regions: 0,
+            small_object_threshold: 0,
             flushers: 1,
             flush_rate_limit: 0,
             reclaimers: 1,
             reclaim_rate_limit: 0,
+            reclaim_discard: false,
             allocation_timeout: Duration::from_millis(10),
             clean_region_threshold: 1,
             recover_concurrency: 2,
+            recover_mode: RecoverMode::Strict,
+            checkpoint_interval: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            verify_mode: VerifyMode::default(),
+            compression: Compression::None,
+            encryption: None,
         }
         .into(),
         runtime: RuntimeConfig {
@@ -251,3 +398,376 @@ async fn test_runtime_lazy_store() {
 
     test_storage::<RuntimeLazyStore<_, _>>(config, recorder).await;
 }
+
+#[tokio::test]
+async fn test_direct_file_store() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let recorder = Arc::new(JudgeRecorder::default());
+    let config = GenericStoreConfig {
+        name: "".to_string(),
+        eviction_config: FifoConfig,
+        device_config: DirectFileDeviceConfig {
+            path: tempdir.path().join("foyer-device"),
+            capacity: 4 * MB,
+            region_size: 1 * MB,
+            align: 4 * KB,
+            io_size: 4 * KB,
+            checkpoint_capacity: 1 * MB,
+            reformat: false,
+        },
+        allocator_bits: 0,
+        catalog_bits: 1,
+        admissions: vec![recorder.clone()],
+        reinsertions: vec![recorder.clone()],
+        buffer_pool_size: 2 * MB,
+        ring_buffer_capacity: 0,
+        ring_buffer_threshold: 0,
+        small_object_regions: 0,
+        small_object_threshold: 0,
+        flushers: 1,
+        flush_rate_limit: 0,
+        reclaimers: 1,
+        reclaim_rate_limit: 0,
+        reclaim_discard: false,
+        allocation_timeout: Duration::from_millis(10),
+        clean_region_threshold: 1,
+        recover_concurrency: 2,
+        recover_mode: RecoverMode::Strict,
+        checkpoint_interval: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        verify_mode: VerifyMode::default(),
+        compression: Compression::None,
+        encryption: None,
+    };
+
+    test_storage::<
+        GenericStore<u64, Vec<u8>, DirectFileDevice, Fifo<RegionEpItemAdapter<FifoLink>>, FifoLink>,
+    >(config, recorder)
+    .await;
+}
+
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+#[tokio::test]
+async fn test_uring_store() {
+    use foyer_storage::device::uring::{UringDevice, UringDeviceConfig};
+
+    let tempdir = tempfile::tempdir().unwrap();
+    let recorder = Arc::new(JudgeRecorder::default());
+    let config = GenericStoreConfig {
+        name: "".to_string(),
+        eviction_config: FifoConfig,
+        device_config: UringDeviceConfig {
+            fs: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 4 * MB,
+                file_capacity: 1 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                reformat: false,
+                preallocate: false,
+            },
+            queue_depth: 64,
+            registered_buffers: 4,
+        },
+        allocator_bits: 0,
+        catalog_bits: 1,
+        admissions: vec![recorder.clone()],
+        reinsertions: vec![recorder.clone()],
+        buffer_pool_size: 2 * MB,
+        ring_buffer_capacity: 0,
+        ring_buffer_threshold: 0,
+        small_object_regions: 0,
+        small_object_threshold: 0,
+        flushers: 1,
+        flush_rate_limit: 0,
+        reclaimers: 1,
+        reclaim_rate_limit: 0,
+        reclaim_discard: false,
+        allocation_timeout: Duration::from_millis(10),
+        clean_region_threshold: 1,
+        recover_concurrency: 2,
+        recover_mode: RecoverMode::Strict,
+        checkpoint_interval: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        verify_mode: VerifyMode::default(),
+        compression: Compression::None,
+        encryption: None,
+    };
+
+    test_storage::<
+        GenericStore<u64, Vec<u8>, UringDevice, Fifo<RegionEpItemAdapter<FifoLink>>, FifoLink>,
+    >(config, recorder)
+    .await;
+}
+
+#[tokio::test]
+async fn test_mem_store() {
+    use foyer_storage::device::mem::{MemDevice, MemDeviceConfig, MemDeviceMemory};
+
+    let recorder = Arc::new(JudgeRecorder::default());
+    let config = GenericStoreConfig {
+        name: "".to_string(),
+        eviction_config: FifoConfig,
+        device_config: MemDeviceConfig {
+            capacity: 4 * MB,
+            region_size: 1 * MB,
+            align: 4 * KB,
+            io_size: 4 * KB,
+            memory: MemDeviceMemory::new(),
+        },
+        allocator_bits: 0,
+        catalog_bits: 1,
+        admissions: vec![recorder.clone()],
+        reinsertions: vec![recorder.clone()],
+        buffer_pool_size: 2 * MB,
+        ring_buffer_capacity: 0,
+        ring_buffer_threshold: 0,
+        small_object_regions: 0,
+        small_object_threshold: 0,
+        flushers: 1,
+        flush_rate_limit: 0,
+        reclaimers: 1,
+        reclaim_rate_limit: 0,
+        reclaim_discard: false,
+        allocation_timeout: Duration::from_millis(10),
+        clean_region_threshold: 1,
+        recover_concurrency: 2,
+        recover_mode: RecoverMode::Strict,
+        checkpoint_interval: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        verify_mode: VerifyMode::default(),
+        compression: Compression::None,
+        encryption: None,
+    };
+
+    test_storage::<
+        GenericStore<u64, Vec<u8>, MemDevice, Fifo<RegionEpItemAdapter<FifoLink>>, FifoLink>,
+    >(config, recorder)
+    .await;
+}
+
+fn jbod_store_config(
+    recorder: Arc<JudgeRecorder<u64, Vec<u8>>>,
+) -> GenericStoreConfig<
+    u64,
+    Vec<u8>,
+    foyer_storage::device::jbod::JbodDevice<foyer_storage::device::mem::MemDevice>,
+    Fifo<RegionEpItemAdapter<FifoLink>>,
+> {
+    use foyer_storage::device::{
+        jbod::{JbodDeviceConfig, JbodStrategy},
+        mem::{MemDeviceConfig, MemDeviceMemory},
+    };
+
+    let child = |capacity| MemDeviceConfig {
+        capacity,
+        region_size: 1 * MB,
+        align: 4 * KB,
+        io_size: 4 * KB,
+        memory: MemDeviceMemory::new(),
+    };
+
+    GenericStoreConfig {
+        name: "".to_string(),
+        eviction_config: FifoConfig,
+        device_config: JbodDeviceConfig {
+            devices: vec![child(5 * MB), child(2 * MB)],
+            strategy: JbodStrategy::Weighted,
+            fail_on_error: false,
+        },
+        allocator_bits: 0,
+        catalog_bits: 1,
+        admissions: vec![recorder.clone()],
+        reinsertions: vec![recorder],
+        buffer_pool_size: 2 * MB,
+        ring_buffer_capacity: 0,
+        ring_buffer_threshold: 0,
+        small_object_regions: 0,
+        small_object_threshold: 0,
+        flushers: 1,
+        flush_rate_limit: 0,
+        reclaimers: 1,
+        reclaim_rate_limit: 0,
+        reclaim_discard: false,
+        allocation_timeout: Duration::from_millis(10),
+        clean_region_threshold: 1,
+        recover_concurrency: 2,
+        recover_mode: RecoverMode::Strict,
+        checkpoint_interval: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        verify_mode: VerifyMode::default(),
+        compression: Compression::None,
+        encryption: None,
+    }
+}
+
+#[tokio::test]
+async fn test_jbod_store() {
+    let recorder = Arc::new(JudgeRecorder::default());
+    let config = jbod_store_config(recorder.clone());
+
+    test_storage::<
+        GenericStore<
+            u64,
+            Vec<u8>,
+            foyer_storage::device::jbod::JbodDevice<foyer_storage::device::mem::MemDevice>,
+            Fifo<RegionEpItemAdapter<FifoLink>>,
+            FifoLink,
+        >,
+    >(config, recorder)
+    .await;
+}
+
+#[tokio::test]
+async fn test_jbod_store_child_failure() {
+    type JbodStore = GenericStore<
+        u64,
+        Vec<u8>,
+        foyer_storage::device::jbod::JbodDevice<foyer_storage::device::mem::MemDevice>,
+        Fifo<RegionEpItemAdapter<FifoLink>>,
+        FifoLink,
+    >;
+
+    let recorder = Arc::new(JudgeRecorder::default());
+    let config = jbod_store_config(recorder);
+
+    let store = JbodStore::open(config.clone()).await.unwrap();
+    for key in 0..2000 {
+        assert!(store.insert(key, vec![key as u8; 1 * KB]).await.unwrap());
+    }
+    store.close().await.unwrap();
+    drop(store);
+
+    let store = JbodStore::open(config).await.unwrap();
+    while !store.is_ready() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Entries on the failed child are lost, the others are still readable.
+    store.device().mark_failed(1);
+    let mut hits = 0;
+    for key in 0..2000 {
+        if let Some(value) = store.lookup(&key).await.unwrap() {
+            assert_eq!(value, vec![key as u8; 1 * KB]);
+            hits += 1;
+        }
+    }
+    assert!(hits > 0 && hits < 2000, "hits: {hits}");
+
+    // New entries only go to the healthy child, regions of the failed child are retired on reclamation.
+    for key in 2000..8000 {
+        assert!(store.insert(key, vec![key as u8; 1 * KB]).await.unwrap());
+    }
+    store.close().await.unwrap();
+    for key in 7500..8000 {
+        assert_eq!(
+            store.lookup(&key).await.unwrap(),
+            Some(vec![key as u8; 1 * KB])
+        );
+    }
+}
+
+type TieredMemDevice = foyer_storage::device::tiered::TieredDevice<
+    foyer_storage::device::mem::MemDevice,
+    foyer_storage::device::mem::MemDevice,
+>;
+
+fn tiered_store_config(
+    admissions: Vec<Arc<dyn foyer_storage::admission::AdmissionPolicy<Key = u64, Value = Vec<u8>>>>,
+    reinsertions: Vec<
+        Arc<dyn foyer_storage::reinsertion::ReinsertionPolicy<Key = u64, Value = Vec<u8>>>,
+    >,
+) -> GenericStoreConfig<u64, Vec<u8>, TieredMemDevice, Fifo<RegionEpItemAdapter<FifoLink>>> {
+    use foyer_storage::device::{
+        mem::{MemDeviceConfig, MemDeviceMemory},
+        tiered::TieredDeviceConfig,
+    };
+
+    let tier = |capacity| MemDeviceConfig {
+        capacity,
+        region_size: 1 * MB,
+        align: 4 * KB,
+        io_size: 4 * KB,
+        memory: MemDeviceMemory::new(),
+    };
+
+    GenericStoreConfig {
+        name: "".to_string(),
+        eviction_config: FifoConfig,
+        device_config: TieredDeviceConfig {
+            fast: tier(3 * MB),
+            slow: tier(16 * MB),
+        },
+        allocator_bits: 0,
+        catalog_bits: 1,
+        admissions,
+        reinsertions,
+        buffer_pool_size: 4 * MB,
+        ring_buffer_capacity: 0,
+        ring_buffer_threshold: 0,
+        small_object_regions: 0,
+        small_object_threshold: 0,
+        flushers: 1,
+        flush_rate_limit: 0,
+        reclaimers: 1,
+        reclaim_rate_limit: 0,
+        reclaim_discard: false,
+        allocation_timeout: Duration::from_millis(10),
+        clean_region_threshold: 1,
+        recover_concurrency: 2,
+        recover_mode: RecoverMode::Strict,
+        checkpoint_interval: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        verify_mode: VerifyMode::default(),
+        compression: Compression::None,
+        encryption: None,
+    }
+}
+
+#[tokio::test]
+async fn test_tiered_store() {
+    let recorder = Arc::new(JudgeRecorder::default());
+    let config = tiered_store_config(vec![recorder.clone()], vec![recorder.clone()]);
+
+    test_storage::<
+        GenericStore<u64, Vec<u8>, TieredMemDevice, Fifo<RegionEpItemAdapter<FifoLink>>, FifoLink>,
+    >(config, recorder)
+    .await;
+}
+
+#[tokio::test]
+async fn test_tiered_store_migration() {
+    type TieredStore =
+        GenericStore<u64, Vec<u8>, TieredMemDevice, Fifo<RegionEpItemAdapter<FifoLink>>, FifoLink>;
+
+    // Without reinsertion policies, all entries of reclaimed fast regions are migrated to the slow tier.
+    let config = tiered_store_config(vec![], vec![]);
+
+    let store = TieredStore::open(config.clone()).await.unwrap();
+    for key in 0..3000 {
+        assert!(store.insert(key, vec![key as u8; 1 * KB]).await.unwrap());
+    }
+    store.close().await.unwrap();
+
+    // Entries are aligned to 4 KiB, the fast tier only holds ~750 of them, early entries must have been migrated.
+    for key in 0..1000 {
+        assert_eq!(
+            store.lookup(&key).await.unwrap(),
+            Some(vec![key as u8; 1 * KB]),
+            "key: {key}"
+        );
+    }
+    drop(store);
+
+    let store = TieredStore::open(config).await.unwrap();
+    while !store.is_ready() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    for key in 0..1000 {
+        assert_eq!(
+            store.lookup(&key).await.unwrap(),
+            Some(vec![key as u8; 1 * KB]),
+            "key: {key}"
+        );
+    }
+}